@@ -0,0 +1,256 @@
+use graphql_parser::query as q;
+use linked_hash_map::LinkedHashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use graph::prelude::*;
+
+/// Statistics about how often query results were served from the cache.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryCacheStats {
+    /// Number of queries answered from the cache.
+    pub hits: u64,
+    /// Number of queries that had to be executed.
+    pub misses: u64,
+    /// Number of results dropped because their subgraph advanced or reverted.
+    pub invalidations: u64,
+    /// Number of results dropped to stay within the size limit.
+    pub evictions: u64,
+}
+
+/// Identifies the result of a query against a subgraph at a specific block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QueryCacheKey {
+    subgraph_id: String,
+    query: String,
+    operation_name: Option<String>,
+    variables: String,
+    block: EthereumBlockPointer,
+}
+
+impl QueryCacheKey {
+    /// Creates the key for `query` against the subgraph at `block`. Queries
+    /// that only differ in formatting or variable order share a key.
+    pub fn new(query: &Query, block: EthereumBlockPointer) -> Self {
+        let variables = query.variables.as_ref().map_or(String::new(), |variables| {
+            format!("{:?}", variables.iter().collect::<BTreeMap<_, _>>())
+        });
+
+        QueryCacheKey {
+            subgraph_id: query.schema.id.clone(),
+            query: format!("{}", query.document),
+            operation_name: query.operation_name.clone(),
+            variables,
+            block,
+        }
+    }
+}
+
+struct CacheState {
+    /// Cached result data, least recently used first.
+    results: LinkedHashMap<QueryCacheKey, Option<q::Value>>,
+    /// The block each subgraph's cached results were computed at.
+    blocks: HashMap<String, EthereumBlockPointer>,
+    stats: QueryCacheStats,
+}
+
+impl CacheState {
+    /// Drops the results of a subgraph if it is no longer at the block they
+    /// were computed at.
+    fn invalidate_if_moved(&mut self, subgraph_id: &str, block: EthereumBlockPointer) {
+        if self.blocks.get(subgraph_id) == Some(&block) {
+            return;
+        }
+        self.blocks.insert(subgraph_id.to_owned(), block);
+
+        let stale_keys = self
+            .results
+            .keys()
+            .filter(|key| key.subgraph_id == subgraph_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        self.stats.invalidations += stale_keys.len() as u64;
+        for key in stale_keys {
+            self.results.remove(&key);
+        }
+    }
+}
+
+/// An LRU cache of query results, keyed by the subgraph's latest block.
+///
+/// Results of a subgraph only change when it advances to a new block or
+/// reverts one, at which point all of its cached results are dropped.
+pub struct QueryResultCache {
+    max_entries: usize,
+    state: Mutex<CacheState>,
+}
+
+impl QueryResultCache {
+    /// Creates a cache holding at most `max_entries` results; a size of zero
+    /// disables caching.
+    pub fn new(max_entries: usize) -> Self {
+        QueryResultCache {
+            max_entries,
+            state: Mutex::new(CacheState {
+                results: LinkedHashMap::new(),
+                blocks: HashMap::new(),
+                stats: QueryCacheStats::default(),
+            }),
+        }
+    }
+
+    /// Returns whether results are cached at all.
+    pub fn is_enabled(&self) -> bool {
+        self.max_entries > 0
+    }
+
+    /// Looks up the result for `key`.
+    pub fn get(&self, key: &QueryCacheKey) -> Option<QueryResult> {
+        let mut state = self.state.lock().unwrap();
+        state.invalidate_if_moved(&key.subgraph_id, key.block);
+
+        let data = state.results.get_refresh(key).cloned();
+        match data {
+            Some(data) => {
+                state.stats.hits += 1;
+                Some(QueryResult::new(data))
+            }
+            None => {
+                state.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Caches the result for `key`; results with errors are not cached.
+    pub fn insert(&self, key: QueryCacheKey, result: &QueryResult) {
+        if self.max_entries == 0 || result.errors.is_some() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        // Don't cache results of a block the subgraph has already moved on from
+        if state.blocks.get(&key.subgraph_id) != Some(&key.block) {
+            return;
+        }
+
+        state.results.insert(key, result.data.clone());
+        while state.results.len() > self.max_entries {
+            state.results.pop_front();
+            state.stats.evictions += 1;
+        }
+    }
+
+    /// Returns the cache statistics collected so far.
+    pub fn stats(&self) -> QueryCacheStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser;
+    use graphql_parser::query as q;
+
+    use graph::prelude::*;
+    use graph::web3::types::H256;
+
+    use super::{QueryCacheKey, QueryCacheStats, QueryResultCache};
+
+    fn query(subgraph_id: &str, text: &str) -> Query {
+        Query {
+            schema: Schema {
+                name: subgraph_id.to_owned(),
+                id: subgraph_id.to_owned(),
+                document: Default::default(),
+            },
+            document: Arc::new(graphql_parser::parse_query(text).unwrap()),
+            variables: None,
+            operation_name: None,
+        }
+    }
+
+    fn block(number: u64) -> EthereumBlockPointer {
+        EthereumBlockPointer::from((H256::from([number as u8; 32]), number))
+    }
+
+    fn result(name: &str) -> QueryResult {
+        QueryResult::new(Some(q::Value::String(name.to_owned())))
+    }
+
+    #[test]
+    fn shares_results_of_equivalent_queries() {
+        let cache = QueryResultCache::new(10);
+        let key = QueryCacheKey::new(&query("subgraph", "{ users { name } }"), block(1));
+
+        assert!(cache.get(&key).is_none());
+        cache.insert(key, &result("Jordi"));
+
+        // Formatting doesn't matter
+        let key = QueryCacheKey::new(&query("subgraph", "{\n  users {\n    name\n  }\n}"), block(1));
+        assert_eq!(
+            cache.get(&key).map(|result| result.data),
+            Some(Some(q::Value::String("Jordi".to_owned())))
+        );
+        assert_eq!(
+            cache.stats(),
+            QueryCacheStats {
+                hits: 1,
+                misses: 1,
+                invalidations: 0,
+                evictions: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn drops_results_when_the_subgraph_moves() {
+        let cache = QueryResultCache::new(10);
+        let key = QueryCacheKey::new(&query("subgraph", "{ users { name } }"), block(1));
+        let other_key = QueryCacheKey::new(&query("other", "{ users { name } }"), block(1));
+
+        cache.get(&key);
+        cache.insert(key.clone(), &result("Jordi"));
+        cache.get(&other_key);
+        cache.insert(other_key.clone(), &result("Jorge"));
+
+        // Advancing a block invalidates the results of that subgraph only
+        let advanced_key = QueryCacheKey::new(&query("subgraph", "{ users { name } }"), block(2));
+        assert!(cache.get(&advanced_key).is_none());
+        assert!(cache.get(&other_key).is_some());
+        assert_eq!(cache.stats().invalidations, 1);
+
+        // Results computed at the old block are no longer cached
+        cache.insert(key.clone(), &result("Jordi"));
+        cache.get(&advanced_key);
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn does_not_cache_with_a_size_of_zero() {
+        let cache = QueryResultCache::new(0);
+        let key = QueryCacheKey::new(&query("subgraph", "{ users { name } }"), block(1));
+        assert!(!cache.is_enabled());
+
+        cache.get(&key);
+        cache.insert(key.clone(), &result("Jordi"));
+        assert!(cache.get(&key).is_none());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_results() {
+        let cache = QueryResultCache::new(1);
+        let first = QueryCacheKey::new(&query("subgraph", "{ users { name } }"), block(1));
+        let second = QueryCacheKey::new(&query("subgraph", "{ users { id } }"), block(1));
+
+        cache.get(&first);
+        cache.insert(first.clone(), &result("Jordi"));
+        cache.get(&second);
+        cache.insert(second.clone(), &result("Jorge"));
+
+        assert!(cache.get(&first).is_none());
+        assert!(cache.get(&second).is_some());
+        assert_eq!(cache.stats().evictions, 1);
+    }
+}
@@ -1,3 +1,5 @@
+mod cache;
 mod runner;
 
+pub use self::cache::QueryCacheStats;
 pub use self::runner::GraphQlRunner;
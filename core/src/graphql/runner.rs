@@ -1,25 +1,115 @@
 use futures::future;
-use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use graph::components::metrics::{CounterVec, HistogramVec};
 use graph::prelude::{GraphQlRunner as GraphQlRunnerTrait, *};
+use graph::tokio::timer::Interval;
 use graph_graphql::prelude::*;
 
+use super::cache::{QueryCacheKey, QueryCacheStats, QueryResultCache};
+
 /// GraphQL runner implementation for The Graph.
 pub struct GraphQlRunner<S> {
     logger: Logger,
-    store: Arc<Mutex<S>>,
+    store: Arc<S>,
+    thread_pool: QueryThreadPool,
+    query_cache: Arc<QueryResultCache>,
+    query_count: CounterVec,
+    query_errors: CounterVec,
+    query_duration: HistogramVec,
+    query_cache_hits: CounterVec,
+    query_cache_misses: CounterVec,
 }
 
 impl<S> GraphQlRunner<S>
 where
     S: Store + 'static,
 {
-    /// Creates a new query runner.
-    pub fn new(logger: &Logger, store: Arc<Mutex<S>>) -> Self {
-        GraphQlRunner {
+    /// Creates a new query runner that resolves the root fields of all its
+    /// queries on a shared pool of `query_threads` threads and caches up to
+    /// `query_cache_size` query results.
+    pub fn new(
+        logger: &Logger,
+        store: Arc<S>,
+        query_threads: usize,
+        query_cache_size: usize,
+        metrics: &MetricsRegistry,
+    ) -> Self {
+        let runner = GraphQlRunner {
             logger: logger.new(o!("component" => "GraphQlRunner")),
             store: store,
+            thread_pool: QueryThreadPool::new(query_threads),
+            query_cache: Arc::new(QueryResultCache::new(query_cache_size)),
+            query_count: metrics
+                .new_counter_vec(
+                    "graphql_queries_total",
+                    "Number of GraphQL queries run against a subgraph",
+                    &["subgraph"],
+                ).expect("Failed to register GraphQL query counter"),
+            query_errors: metrics
+                .new_counter_vec(
+                    "graphql_query_errors_total",
+                    "Number of GraphQL queries against a subgraph that returned errors",
+                    &["subgraph"],
+                ).expect("Failed to register GraphQL query error counter"),
+            query_duration: metrics
+                .new_histogram_vec(
+                    "graphql_query_duration_seconds",
+                    "Time it takes to run a GraphQL query against a subgraph",
+                    &["subgraph"],
+                ).expect("Failed to register GraphQL query duration histogram"),
+            query_cache_hits: metrics
+                .new_counter_vec(
+                    "graphql_query_cache_hits_total",
+                    "Number of GraphQL queries against a subgraph answered from the cache",
+                    &["subgraph"],
+                ).expect("Failed to register GraphQL query cache hit counter"),
+            query_cache_misses: metrics
+                .new_counter_vec(
+                    "graphql_query_cache_misses_total",
+                    "Number of cacheable GraphQL queries against a subgraph that had to be run",
+                    &["subgraph"],
+                ).expect("Failed to register GraphQL query cache miss counter"),
+        };
+        if runner.query_cache.is_enabled() {
+            runner.periodically_log_query_cache_stats();
         }
+        runner
+    }
+
+    /// Returns how often query results have been served from the cache so far.
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        self.query_cache.stats()
+    }
+
+    fn periodically_log_query_cache_stats(&self) {
+        let logger = self.logger.clone();
+        let query_cache = self.query_cache.clone();
+
+        // Log the cache hit rate every 60s
+        tokio::spawn(
+            Interval::new(Instant::now(), Duration::from_secs(60))
+                .for_each(move |_| {
+                    let stats = query_cache.stats();
+                    debug!(logger, "Query result cache";
+                           "hits" => stats.hits,
+                           "misses" => stats.misses,
+                           "invalidations" => stats.invalidations,
+                           "evictions" => stats.evictions);
+                    Ok(())
+                }).map_err(|_| unreachable!()),
+        );
+    }
+
+    fn execute(&self, query: &Query) -> QueryResult {
+        execute_query(
+            query,
+            QueryExecutionOptions {
+                logger: self.logger.clone(),
+                resolver: StoreResolver::new(&self.logger, &query.schema, self.store.clone()),
+                thread_pool: self.thread_pool.clone(),
+            },
+        )
     }
 }
 
@@ -28,13 +118,61 @@ where
     S: Store + 'static,
 {
     fn run_query(&self, query: Query) -> QueryResultFuture {
-        let result = execute_query(
-            &query,
-            QueryExecutionOptions {
-                logger: self.logger.clone(),
-                resolver: StoreResolver::new(&self.logger, self.store.clone()),
-            },
-        );
+        let subgraph = query.schema.id.clone();
+        let timer = self
+            .query_duration
+            .with_label_values(&[subgraph.as_str()])
+            .start_timer();
+
+        // Results can only be cached for subgraphs that have indexed a block;
+        // don't bother looking it up if caching is disabled. The health of a
+        // subgraph may change without it advancing to another block, so
+        // results of failed subgraphs are not cached and `_meta` reports the
+        // failure
+        let latest_block = if !self.query_cache.is_enabled() {
+            None
+        } else {
+            match self.store.subgraph_latest_block_and_health(&query.schema.id) {
+                Ok((latest_block, SubgraphHealth::Healthy)) => latest_block,
+                Ok((_, SubgraphHealth::Failed(_))) => None,
+                Err(e) => {
+                    warn!(self.logger, "Failed to look up latest block of subgraph";
+                          "subgraph" => &query.schema.id,
+                          "error" => format!("{}", e));
+                    None
+                }
+            }
+        };
+
+        let result = match latest_block {
+            Some(block) => {
+                let key = QueryCacheKey::new(&query, block);
+                match self.query_cache.get(&key) {
+                    Some(result) => {
+                        self.query_cache_hits
+                            .with_label_values(&[subgraph.as_str()])
+                            .inc();
+                        result
+                    }
+                    None => {
+                        self.query_cache_misses
+                            .with_label_values(&[subgraph.as_str()])
+                            .inc();
+                        let result = self.execute(&query);
+                        self.query_cache.insert(key, &result);
+                        result
+                    }
+                }
+            }
+            None => self.execute(&query),
+        };
+
+        timer.observe_duration();
+        self.query_count.with_label_values(&[subgraph.as_str()]).inc();
+        if result.errors.is_some() {
+            self.query_errors.with_label_values(&[subgraph.as_str()]).inc();
+        }
+
         Box::new(future::ok(result))
     }
 
@@ -43,10 +181,21 @@ where
             &subscription,
             SubscriptionExecutionOptions {
                 logger: self.logger.clone(),
-                resolver: StoreResolver::new(&self.logger, self.store.clone()),
+                resolver: StoreResolver::new(
+                    &self.logger,
+                    &subscription.query.schema,
+                    self.store.clone(),
+                ),
             },
         );
 
         Box::new(future::result(result))
     }
+
+    fn subgraph_latest_block(
+        &self,
+        subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error> {
+        self.store.subgraph_latest_block(subgraph_id)
+    }
 }
@@ -1,13 +1,18 @@
+#[macro_use]
+extern crate failure;
 extern crate futures;
 extern crate graph;
 extern crate graph_graphql;
 extern crate graph_runtime_wasm;
+extern crate graphql_parser;
+extern crate linked_hash_map;
 extern crate serde;
 extern crate serde_yaml;
 
 mod graphql;
 mod subgraph;
 
-pub use graphql::GraphQlRunner;
+pub use graphql::{GraphQlRunner, QueryCacheStats};
 pub use subgraph::RuntimeManager;
 pub use subgraph::SubgraphProvider;
+pub use subgraph::SubgraphStatusTracker;
@@ -0,0 +1,185 @@
+use std::mem;
+
+use graph::components::subgraph::RuntimeHostEvent;
+use graph::prelude::*;
+
+/// Gathers the entity operations and log entries that a runtime host emits
+/// for the events of a block, so the operations can be applied to the store
+/// in a single transaction and the log entries be stored along with them.
+///
+/// The operations and log entries of a block are only emitted once the host
+/// moves on to the next block or when the host's event stream ends, so a
+/// block is never split across several transactions, however long its
+/// handlers take.
+pub struct BlockOperations<S> {
+    events: stream::Fuse<S>,
+    pending: Option<(EthereumBlockPointer, Vec<EntityOperation>, Vec<SubgraphLogEntry>)>,
+}
+
+impl<S> BlockOperations<S>
+where
+    S: Stream<Item = RuntimeHostEvent, Error = ()>,
+{
+    pub fn new(events: S) -> Self {
+        BlockOperations {
+            events: events.fuse(),
+            pending: None,
+        }
+    }
+}
+
+impl<S> Stream for BlockOperations<S>
+where
+    S: Stream<Item = RuntimeHostEvent, Error = ()>,
+{
+    type Item = (EthereumBlockPointer, Vec<EntityOperation>, Vec<SubgraphLogEntry>);
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.events.poll()? {
+                Async::Ready(Some(event)) => {
+                    let (block, operations, log_entries) = match event {
+                        RuntimeHostEvent::EntityOperations(block, operations) => {
+                            (block, operations, vec![])
+                        }
+                        RuntimeHostEvent::LogEntries(block, log_entries) => {
+                            (block, vec![], log_entries)
+                        }
+                    };
+
+                    let same_block = match self.pending {
+                        Some((ref pending_block, _, _)) => *pending_block == block,
+                        None => false,
+                    };
+
+                    if same_block {
+                        // Add the operations and log entries to those of the same block
+                        let pending = self.pending.as_mut().unwrap();
+                        pending.1.extend(operations);
+                        pending.2.extend(log_entries);
+                    } else if let Some(previous) =
+                        mem::replace(&mut self.pending, Some((block, operations, log_entries)))
+                    {
+                        // Start gathering the operations of the next block and
+                        // emit those of the previous one
+                        return Ok(Async::Ready(Some(previous)));
+                    }
+                }
+
+                // Emit the operations of the last block before ending the stream
+                Async::Ready(None) => return Ok(Async::Ready(self.pending.take())),
+
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc::channel;
+    use graph::components::subgraph::RuntimeHostEvent;
+    use graph::prelude::*;
+    use graph::tokio::runtime::Runtime;
+    use graph::tokio::timer::Delay;
+    use graph::web3::types::H256;
+    use std::time::{Duration, Instant};
+
+    use super::BlockOperations;
+
+    fn block(number: u64) -> EthereumBlockPointer {
+        EthereumBlockPointer::from((H256::from(number), number))
+    }
+
+    fn set(id: &str) -> EntityOperation {
+        EntityOperation::Set {
+            key: StoreKey {
+                subgraph: String::from("subgraph"),
+                entity: String::from("Entity"),
+                id: String::from(id),
+            },
+            data: Entity::from(vec![("id", Value::from(id))]),
+        }
+    }
+
+    #[test]
+    fn gathers_the_operations_of_each_block() {
+        let events = stream::iter_ok(vec![
+            RuntimeHostEvent::EntityOperations(block(1), vec![set("a")]),
+            RuntimeHostEvent::EntityOperations(block(1), vec![set("b"), set("c")]),
+            RuntimeHostEvent::EntityOperations(block(2), vec![set("d")]),
+            RuntimeHostEvent::EntityOperations(block(3), vec![]),
+            RuntimeHostEvent::EntityOperations(block(3), vec![set("e")]),
+        ]);
+
+        assert_eq!(
+            BlockOperations::new(events).collect().wait(),
+            Ok(vec![
+                (block(1), vec![set("a"), set("b"), set("c")], vec![]),
+                (block(2), vec![set("d")], vec![]),
+                (block(3), vec![set("e")], vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn gathers_the_log_entries_of_each_block() {
+        let entry = |message: &str, number| {
+            SubgraphLogEntry::new(SubgraphLogLevel::Info, String::from(message), Some(number))
+        };
+        let first = entry("first", 1);
+        let second = entry("second", 1);
+        let third = entry("third", 2);
+
+        let events = stream::iter_ok(vec![
+            RuntimeHostEvent::LogEntries(block(1), vec![first.clone()]),
+            RuntimeHostEvent::EntityOperations(block(1), vec![set("a")]),
+            RuntimeHostEvent::LogEntries(block(1), vec![second.clone()]),
+            RuntimeHostEvent::LogEntries(block(2), vec![third.clone()]),
+        ]);
+
+        assert_eq!(
+            BlockOperations::new(events).collect().wait(),
+            Ok(vec![
+                (block(1), vec![set("a")], vec![first, second]),
+                (block(2), vec![], vec![third]),
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_delayed_events_of_a_block_in_the_same_batch() {
+        let (sender, receiver) = channel(10);
+        let mut runtime = Runtime::new().unwrap();
+
+        // The second event of block 1 only arrives after a pause, e.g. while
+        // a handler waits for a slow `eth_call`
+        runtime.spawn(
+            sender
+                .send(RuntimeHostEvent::EntityOperations(block(1), vec![set("a")]))
+                .map_err(|_| ())
+                .and_then(|sender| {
+                    Delay::new(Instant::now() + Duration::from_millis(100))
+                        .map_err(|_| ())
+                        .map(move |_| sender)
+                }).and_then(|sender| {
+                    sender
+                        .send(RuntimeHostEvent::EntityOperations(block(1), vec![set("b")]))
+                        .map_err(|_| ())
+                }).and_then(|sender| {
+                    sender
+                        .send(RuntimeHostEvent::EntityOperations(block(2), vec![set("c")]))
+                        .map_err(|_| ())
+                }).map(|_| ()),
+        );
+
+        assert_eq!(
+            runtime.block_on(BlockOperations::new(receiver).collect()),
+            Ok(vec![
+                (block(1), vec![set("a"), set("b")], vec![]),
+                (block(2), vec![set("c")], vec![]),
+            ])
+        );
+    }
+}
@@ -1,9 +1,12 @@
 use futures::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::Mutex;
+use std::collections::HashMap;
 
-use graph::components::subgraph::RuntimeHostEvent;
 use graph::components::subgraph::SubgraphProviderEvent;
 use graph::prelude::*;
+use graph_graphql::schema::ast;
+
+use super::block_operations::BlockOperations;
+use super::SubgraphStatusTracker;
 
 pub struct RuntimeManager {
     logger: Logger,
@@ -11,8 +14,14 @@ pub struct RuntimeManager {
 }
 
 impl RuntimeManager where {
-    /// Creates a new runtime manager.
-    pub fn new<S, T>(logger: &Logger, store: Arc<Mutex<S>>, host_builder: T) -> Self
+    /// Creates a new runtime manager that reports the subgraphs it hosts to
+    /// `statuses`.
+    pub fn new<S, T>(
+        logger: &Logger,
+        store: Arc<S>,
+        host_builder: T,
+        statuses: Arc<SubgraphStatusTracker<S>>,
+    ) -> Self
     where
         S: Store + 'static,
         T: RuntimeHostBuilder,
@@ -23,7 +32,13 @@ impl RuntimeManager where {
         let (subgraph_sender, subgraph_receiver) = channel(100);
 
         // Handle incoming events from the subgraph provider.
-        Self::handle_subgraph_events(logger.clone(), store, host_builder, subgraph_receiver);
+        Self::handle_subgraph_events(
+            logger.clone(),
+            store,
+            host_builder,
+            statuses,
+            subgraph_receiver,
+        );
 
         RuntimeManager {
             logger,
@@ -34,31 +49,54 @@ impl RuntimeManager where {
     /// Handle incoming events from subgraph providers.
     fn handle_subgraph_events<S, T>(
         logger: Logger,
-        store: Arc<Mutex<S>>,
+        store: Arc<S>,
         mut host_builder: T,
+        statuses: Arc<SubgraphStatusTracker<S>>,
         receiver: Receiver<SubgraphProviderEvent>,
     ) where
         S: Store + 'static,
         T: RuntimeHostBuilder,
     {
-        // Handles each incoming event from the subgraph.
-        fn handle_event<S: Store + 'static>(store: Arc<Mutex<S>>, event: RuntimeHostEvent) {
-            match event {
-                RuntimeHostEvent::EntitySet(store_key, entity, event_source) => {
-                    store
-                        .lock()
-                        .unwrap()
-                        .set(store_key, entity, event_source)
-                        .expect("Failed to set entity in the store");
-                }
-                RuntimeHostEvent::EntityRemoved(store_key, event_source) => {
-                    store
-                        .lock()
-                        .unwrap()
-                        .delete(store_key, event_source)
-                        .expect("Failed to delete entity from the store");
-                }
+        // Applies the entity operations of a block to the store and appends
+        // the block's log entries to the subgraph's log, all in one
+        // transaction. Records the subgraph as failed and stops handling its
+        // events if the block cannot be stored.
+        fn handle_block<S: Store + 'static>(
+            logger: &Logger,
+            store: Arc<S>,
+            subgraph_id: &str,
+            block: EthereumBlockPointer,
+            operations: Vec<EntityOperation>,
+            log_entries: Vec<SubgraphLogEntry>,
+        ) -> Result<(), ()> {
+            if operations.is_empty() && log_entries.is_empty() {
+                return Ok(());
             }
+
+            let mut block_log_entries = HashMap::new();
+            block_log_entries.insert(subgraph_id.to_owned(), log_entries);
+
+            store
+                .transact_block_operations(block, operations, block_log_entries)
+                .map_err(|e| {
+                    error!(logger, "Failed to apply entity operations to the store";
+                           "subgraph" => subgraph_id,
+                           "error" => format!("{}", e));
+                    let entry = SubgraphLogEntry::new(
+                        SubgraphLogLevel::Critical,
+                        format!("Failed to apply entity operations to the store: {}", e),
+                        Some(block.number),
+                    );
+                    if let Err(e) = store.set_subgraph_health(
+                        subgraph_id,
+                        &SubgraphHealth::Failed(e.to_string()),
+                    ).and_then(|()| store.append_subgraph_logs(subgraph_id, vec![entry]))
+                    {
+                        error!(logger, "Failed to record subgraph failure";
+                               "subgraph" => subgraph_id,
+                               "error" => format!("{}", e));
+                    }
+                })
         }
 
         let mut runtime_hosts = vec![];
@@ -66,6 +104,14 @@ impl RuntimeManager where {
         tokio::spawn(receiver.for_each(move |event| {
             match event {
                 SubgraphProviderEvent::SubgraphAdded(manifest) => {
+                    // Make sure full-text searches declared in the schema are indexed
+                    Self::create_fulltext_indexes(&logger, store.clone(), &manifest);
+
+                    statuses.subgraph_added(&manifest);
+
+                    // A redeployed subgraph starts out healthy again
+                    Self::clear_subgraph_failure(&logger, store.as_ref(), &manifest.id);
+
                     info!(logger, "Host mapping runtimes for subgraph";
                           "location" => &manifest.location);
 
@@ -75,15 +121,25 @@ impl RuntimeManager where {
                         .iter()
                         .map(|d| host_builder.build(manifest.clone(), d.clone()));
 
-                    // Forward events from the runtime host to the store; this
-                    // Tokio task will terminate when the corresponding subgraph
-                    // is removed and the host and its event sender are dropped
+                    // Forward the entity operations of each block from the runtime
+                    // host to the store; this Tokio task will terminate when the
+                    // corresponding subgraph is removed and the host and its
+                    // event sender are dropped
                     for mut new_host in new_hosts {
+                        let logger = logger.clone();
                         let store = store.clone();
-                        tokio::spawn(new_host.take_event_stream().unwrap().for_each(
-                            move |event| {
-                                handle_event(store.clone(), event);
-                                Ok(())
+                        let subgraph_id = manifest.id.clone();
+                        let events = new_host.take_event_stream().unwrap();
+                        tokio::spawn(BlockOperations::new(events).for_each(
+                            move |(block, operations, log_entries)| {
+                                handle_block(
+                                    &logger,
+                                    store.clone(),
+                                    &subgraph_id,
+                                    block,
+                                    operations,
+                                    log_entries,
+                                )
                             },
                         ));
                         // Add the new host to the list of managed runtime hosts
@@ -94,12 +150,51 @@ impl RuntimeManager where {
                     // Destroy all runtime hosts for this subgraph; this will
                     // also terminate the host's event stream
                     runtime_hosts.retain(|host| host.subgraph_manifest().id != id);
+                    statuses.subgraph_removed(&id);
+                    Self::clear_subgraph_failure(&logger, store.as_ref(), &id);
                 }
             }
 
             Ok(())
         }));
     }
+
+    /// Forgets a failure recorded for a subgraph.
+    fn clear_subgraph_failure<S>(logger: &Logger, store: &S, subgraph_id: &str)
+    where
+        S: Store,
+    {
+        if let Err(e) = store.set_subgraph_health(subgraph_id, &SubgraphHealth::Healthy) {
+            error!(logger, "Failed to clear subgraph failure";
+                   "subgraph" => subgraph_id,
+                   "error" => format!("{}", e));
+        }
+    }
+
+    /// Creates store indexes for all `@fulltext` directives in a subgraph's schema.
+    fn create_fulltext_indexes<S>(
+        logger: &Logger,
+        store: Arc<S>,
+        manifest: &SubgraphManifest,
+    ) where
+        S: Store + 'static,
+    {
+        for object_type in ast::get_object_type_definitions(&manifest.schema.document) {
+            for definition in ast::get_fulltext_definitions(object_type) {
+                if let Err(e) = store.create_fulltext_index(
+                    manifest.id.clone(),
+                    object_type.name.clone(),
+                    definition.fields.clone(),
+                ) {
+                    error!(logger, "Failed to create full-text search index";
+                           "subgraph" => &manifest.id,
+                           "entity" => &object_type.name,
+                           "name" => &definition.name,
+                           "error" => format!("{}", e));
+                }
+            }
+        }
+    }
 }
 
 impl EventConsumer<SubgraphProviderEvent> for RuntimeManager {
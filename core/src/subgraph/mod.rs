@@ -1,5 +1,8 @@
+mod block_operations;
 mod manager;
 mod provider;
+mod status;
 
 pub use self::manager::RuntimeManager;
 pub use self::provider::SubgraphProvider;
+pub use self::status::SubgraphStatusTracker;
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use graph::prelude::*;
+use graph_graphql::schema::ast;
+
+/// Keeps track of the subgraphs hosted by the runtime manager. Combines this
+/// with the health, indexing progress and entity counts recorded in the store
+/// to report the status of each subgraph.
+pub struct SubgraphStatusTracker<S> {
+    store: Arc<S>,
+    network_name: String,
+    // Maps a subgraph ID to its manifest.
+    subgraphs: RwLock<BTreeMap<String, SubgraphManifest>>,
+}
+
+impl<S> SubgraphStatusTracker<S>
+where
+    S: Store + 'static,
+{
+    /// Creates a tracker for subgraphs indexing the given Ethereum network.
+    pub fn new(store: Arc<S>, network_name: String) -> Self {
+        SubgraphStatusTracker {
+            store,
+            network_name,
+            subgraphs: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records that a subgraph is now being indexed.
+    pub fn subgraph_added(&self, manifest: &SubgraphManifest) {
+        self.subgraphs
+            .write()
+            .unwrap()
+            .insert(manifest.id.clone(), manifest.clone());
+    }
+
+    /// Forgets a subgraph that is no longer hosted.
+    pub fn subgraph_removed(&self, id: &str) {
+        self.subgraphs.write().unwrap().remove(id);
+    }
+
+    /// Looks up the status of a subgraph in the store.
+    fn status(
+        &self,
+        manifest: SubgraphManifest,
+        chain_head_block: Option<EthereumBlockPointer>,
+    ) -> Result<SubgraphStatus, Error> {
+        let (latest_block, health) = self.store.subgraph_latest_block_and_health(&manifest.id)?;
+        Ok(SubgraphStatus {
+            id: manifest.id,
+            location: manifest.location,
+            data_sources: manifest.data_sources,
+            health,
+            latest_block,
+            chain_head_block,
+        })
+    }
+}
+
+impl<S> SubgraphStatusProvider for SubgraphStatusTracker<S>
+where
+    S: Store + 'static,
+{
+    fn subgraph_statuses(&self) -> Result<Vec<SubgraphStatus>, Error> {
+        let subgraphs = self.subgraphs.read().unwrap().clone();
+        let chain_head_block = self.store.chain_head_ptr(&self.network_name)?;
+
+        subgraphs
+            .into_iter()
+            .map(|(_, manifest)| self.status(manifest, chain_head_block))
+            .collect()
+    }
+
+    fn subgraph_status(&self, id: &str) -> Result<Option<SubgraphStatus>, Error> {
+        let manifest = match self.subgraphs.read().unwrap().get(id) {
+            Some(manifest) => manifest.clone(),
+            None => return Ok(None),
+        };
+        let chain_head_block = self.store.chain_head_ptr(&self.network_name)?;
+
+        self.status(manifest, chain_head_block).map(Some)
+    }
+
+    fn subgraph_entity_counts(&self, id: &str) -> Result<BTreeMap<String, u64>, Error> {
+        let manifest = self
+            .subgraphs
+            .read()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| format_err!("Subgraph {} is not hosted by this node", id))?;
+        let mut counts = BTreeMap::new();
+
+        for object_type in ast::get_object_type_definitions(&manifest.schema.document) {
+            let query = StoreQuery {
+                subgraph: manifest.id.clone(),
+                entity: object_type.name.clone(),
+                filter: None,
+                order_by: vec![],
+                range: None,
+                block: None,
+            };
+
+            let count = match self.store.aggregate(query, vec![StoreAggregate::Count]) {
+                Ok(ref values) if values.len() == 1 => match values[0] {
+                    Value::Int(count) => count as u64,
+                    _ => 0,
+                },
+                _ => {
+                    return Err(format_err!(
+                        "Failed to count entities of type {} in subgraph {}",
+                        object_type.name,
+                        manifest.id
+                    ))
+                }
+            };
+            counts.insert(object_type.name.clone(), count);
+        }
+
+        Ok(counts)
+    }
+}
@@ -17,7 +17,7 @@ use std::time::Instant;
 
 use graph::components::ethereum::*;
 use graph::prelude::*;
-use graph_core::RuntimeManager;
+use graph_core::{RuntimeManager, SubgraphStatusTracker};
 use graph_mock::FakeStore;
 use graph_runtime_wasm::RuntimeHostBuilder;
 
@@ -103,14 +103,19 @@ fn multiple_data_sources_per_subgraph() {
             let eth_adapter = Arc::new(Mutex::new(MockEthereumAdapter {
                 received_subscriptions: vec![],
             }));
-            let fake_store = Arc::new(Mutex::new(FakeStore));
+            let fake_store = Arc::new(FakeStore);
             let host_builder = RuntimeHostBuilder::new(
                 &logger,
                 eth_adapter.clone(),
                 resolver.clone(),
                 fake_store.clone(),
+                &MetricsRegistry::new(),
             );
-            let manager = RuntimeManager::new(&logger, fake_store, host_builder);
+            let statuses = Arc::new(SubgraphStatusTracker::new(
+                fake_store.clone(),
+                String::from("mainnet"),
+            ));
+            let manager = RuntimeManager::new(&logger, fake_store, host_builder, statuses);
 
             // Load a subgraph with two data sets, one listening for `ExampleEvent`
             // and the other for `ExampleEvent2`.
@@ -1,9 +1,9 @@
 use failure::Error;
 use std::fmt::Debug;
-use std::sync::Mutex;
 use std::time::Duration;
 use std::time::Instant;
 
+use graph::components::metrics::Gauge;
 use graph::prelude::*;
 use graph::web3::api::Web3;
 use graph::web3::transports::batch::Batch;
@@ -13,33 +13,35 @@ use graph::web3::Transport;
 
 pub struct BlockIngestor<S, T>
 where
-    S: BlockStore + Send + 'static,
+    S: BlockStore + Send + Sync + 'static,
     T: BatchTransport + Send + Sync + Debug + Clone + 'static,
     <T as Transport>::Out: Send,
     <T as BatchTransport>::Batch: Send,
 {
-    store: Arc<Mutex<S>>,
+    store: Arc<S>,
     network_name: String,
     web3_transport: T,
     ancestor_count: u64,
     logger: slog::Logger,
     polling_interval: Duration,
+    head_lag: Gauge,
 }
 
 impl<S, T> BlockIngestor<S, T>
 where
-    S: BlockStore + Send + 'static,
+    S: BlockStore + Send + Sync + 'static,
     T: BatchTransport + Send + Sync + Debug + Clone + 'static,
     <T as Transport>::Out: Send,
     <T as BatchTransport>::Batch: Send,
 {
     pub fn new(
-        store: Arc<Mutex<S>>,
+        store: Arc<S>,
         network_name: String,
         web3_transport: T,
         ancestor_count: u64,
         logger: slog::Logger,
         polling_interval: Duration,
+        metrics: &MetricsRegistry,
     ) -> Result<BlockIngestor<S, T>, Error> {
         Ok(BlockIngestor {
             store,
@@ -48,6 +50,10 @@ where
             ancestor_count,
             logger: logger.new(o!("component" => "BlockIngestor")),
             polling_interval,
+            head_lag: metrics.new_gauge(
+                "ethereum_chain_head_lag_blocks",
+                "Number of blocks the ingested chain head is behind the Ethereum node",
+            )?,
         })
     }
 
@@ -99,7 +105,7 @@ where
                     .unwrap();
 
                 // Add Ethereum network info to store
-                self.store.lock().unwrap().add_network_if_missing(
+                self.store.add_network_if_missing(
                     &self.network_name,
                     &net_version,
                     gen_block_hash,
@@ -111,6 +117,8 @@ where
         // Ask for latest block from Ethereum node
         self.get_latest_block()
             .and_then(move |latest_block: Block<Transaction>| {
+                self.report_head_lag(&latest_block).map(|()| latest_block)
+            }).and_then(move |latest_block: Block<Transaction>| {
                 // Store latest block in block store.
                 // Might be a no-op if latest block is one that we have seen.
                 // ingest_blocks will return a (potentially incomplete) list of blocks that are
@@ -139,6 +147,22 @@ where
             })
     }
 
+    /// Reports how many blocks the chain head in the store is behind the
+    /// latest block of the Ethereum node.
+    fn report_head_lag(&self, latest_block: &Block<Transaction>) -> Result<(), Error> {
+        let latest_number = match latest_block.number {
+            Some(number) => number.as_u64(),
+            None => return Ok(()),
+        };
+        let head_number = self
+            .store
+            .chain_head_ptr(&self.network_name)?
+            .map_or(0, |head| head.number);
+
+        self.head_lag.set(latest_number.saturating_sub(head_number) as f64);
+        Ok(())
+    }
+
     fn get_latest_block(&self) -> impl Future<Item = Block<Transaction>, Error = Error> {
         let web3 = Web3::new(self.web3_transport.clone());
         web3.eth()
@@ -155,13 +179,9 @@ where
         blocks: B,
     ) -> impl Future<Item = Vec<H256>, Error = Error> + Send + 'a {
         self.store
-            .lock()
-            .unwrap()
             .upsert_blocks(&self.network_name, blocks)
             .and_then(move |()| {
                 self.store
-                    .lock()
-                    .unwrap()
                     .attempt_head_update(&self.network_name, self.ancestor_count)
             })
     }
@@ -7,35 +7,62 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use graph::components::ethereum::{EthereumAdapter as EthereumAdapterTrait, *};
+use graph::prelude::MetricsRegistry;
 use graph::web3;
 use graph::web3::api::{CreateFilter, Eth, Web3};
 use graph::web3::helpers::CallFuture;
 use graph::web3::types::*;
 
+use transport::{MeteredCall, MeteredTransport};
+
 pub struct EthereumAdapterConfig<T: web3::Transport> {
     pub transport: T,
+    /// Registry for the metrics of the JSON-RPC calls made by the adapter.
+    pub metrics: MetricsRegistry,
 }
 
 pub struct EthereumAdapter<T: web3::Transport> {
-    eth_client: Arc<Web3<T>>,
+    eth_client: Arc<Web3<MeteredTransport<T>>>,
 }
 
 impl<T: web3::Transport> EthereumAdapter<T> {
     pub fn new(config: EthereumAdapterConfig<T>) -> Self {
+        let calls = config
+            .metrics
+            .new_counter_vec(
+                "ethereum_rpc_calls_total",
+                "Number of JSON-RPC calls made to the Ethereum node",
+                &["method"],
+            ).expect("Failed to register Ethereum RPC call counter");
+        let call_duration = config
+            .metrics
+            .new_histogram_vec(
+                "ethereum_rpc_call_duration_seconds",
+                "Time it takes the Ethereum node to respond to a JSON-RPC call",
+                &["method"],
+            ).expect("Failed to register Ethereum RPC call duration histogram");
+
         EthereumAdapter {
-            eth_client: Arc::new(Web3::new(config.transport)),
+            eth_client: Arc::new(Web3::new(MeteredTransport::new(
+                config.transport,
+                calls,
+                call_duration,
+            ))),
         }
     }
 
-    pub fn block_number(&self) -> CallFuture<U256, T::Out> {
+    pub fn block_number(&self) -> CallFuture<U256, MeteredCall<T::Out>> {
         self.eth_client.eth().block_number()
     }
 
-    pub fn sha3(&self, data: &str) -> CallFuture<H256, T::Out> {
+    pub fn sha3(&self, data: &str) -> CallFuture<H256, MeteredCall<T::Out>> {
         self.eth_client.web3().sha3(Bytes::from(data))
     }
 
-    pub fn event_filter(&self, subscription: EthereumEventSubscription) -> CreateFilter<T, Log> {
+    pub fn event_filter(
+        &self,
+        subscription: EthereumEventSubscription,
+    ) -> CreateFilter<MeteredTransport<T>, Log> {
         let filter_builder = FilterBuilder::default();
         let eth_filter: Filter = filter_builder
             .address(vec![subscription.address])
@@ -46,18 +73,21 @@ impl<T: web3::Transport> EthereumAdapter<T> {
         self.eth_client.eth_filter().create_logs_filter(eth_filter)
     }
 
-    pub fn block(eth: Eth<T>, block_id: BlockId) -> impl Future<Item = Block<H256>, Error = Error> {
+    pub fn block(
+        eth: Eth<MeteredTransport<T>>,
+        block_id: BlockId,
+    ) -> impl Future<Item = Block<H256>, Error = Error> {
         eth.block(block_id)
             .map_err(|e| format_err!("could not get block from Ethereum: {}", e))
             .and_then(|block| block.ok_or(format_err!("no block returned from Ethereum")))
     }
 
     fn call(
-        eth: Eth<T>,
+        eth: Eth<MeteredTransport<T>>,
         contract_address: Address,
         call_data: Bytes,
         block_number: Option<BlockNumber>,
-    ) -> CallFuture<Bytes, T::Out> {
+    ) -> CallFuture<Bytes, MeteredCall<T::Out>> {
         let req = CallRequest {
             from: None,
             to: contract_address,
@@ -151,7 +181,10 @@ impl<T: web3::Transport + Send + Sync + 'static> EthereumAdapterTrait for Ethere
                 }).map(move |(log, log_data)| EthereumEvent {
                     address: log.address,
                     event_signature: log.topics[0],
-                    block_hash: log.block_hash.unwrap(),
+                    block: EthereumBlockPointer::from((
+                        log.block_hash.unwrap(),
+                        log.block_number.unwrap().as_u64(),
+                    )),
                     params: log_data.params,
                     removed: log.is_removed(),
                 }),
@@ -1,7 +1,9 @@
 use futures::prelude::*;
 use graph::serde_json::Value;
 use jsonrpc_core::types::Call;
+use std::fmt;
 
+use graph::components::metrics::{CounterVec, HistogramTimer, HistogramVec};
 use graph::web3;
 use graph::web3::transports::{http, ipc, ws};
 use graph::web3::RequestId;
@@ -78,3 +80,65 @@ impl web3::BatchTransport for Transport {
         }
     }
 }
+
+/// Wraps a transport to count the JSON-RPC calls made through it and to
+/// measure how long they take, by method.
+#[derive(Clone)]
+pub struct MeteredTransport<T> {
+    transport: T,
+    calls: CounterVec,
+    call_duration: HistogramVec,
+}
+
+impl<T> MeteredTransport<T> {
+    pub fn new(transport: T, calls: CounterVec, call_duration: HistogramVec) -> Self {
+        MeteredTransport {
+            transport,
+            calls,
+            call_duration,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MeteredTransport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MeteredTransport({:?})", self.transport)
+    }
+}
+
+impl<T: web3::Transport> web3::Transport for MeteredTransport<T> {
+    type Out = MeteredCall<T::Out>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let method = match request {
+            Call::MethodCall(ref call) => call.method.clone(),
+            Call::Notification(ref notification) => notification.method.clone(),
+            _ => String::from("invalid"),
+        };
+
+        self.calls.with_label_values(&[method.as_str()]).inc();
+        MeteredCall {
+            timer: self.call_duration.with_label_values(&[method.as_str()]).start_timer(),
+            call: self.transport.send(id, request),
+        }
+    }
+}
+
+/// A JSON-RPC call whose duration is observed once it completes or is dropped.
+pub struct MeteredCall<F> {
+    call: F,
+    timer: HistogramTimer,
+}
+
+impl<F: Future> Future for MeteredCall<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.call.poll()
+    }
+}
@@ -12,7 +12,7 @@ use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
 use graph::components::ethereum::EthereumContractCall;
-use graph::prelude::EthereumAdapter as EthereumAdapterTrait;
+use graph::prelude::{EthereumAdapter as EthereumAdapterTrait, MetricsRegistry};
 use graph::serde_json;
 use graph::web3::error::{Error, ErrorKind};
 use graph::web3::helpers::*;
@@ -126,7 +126,10 @@ fn contract_call() {
         H256::from(100000)
     )));
 
-    let mut adapter = EthereumAdapter::new(EthereumAdapterConfig { transport });
+    let mut adapter = EthereumAdapter::new(EthereumAdapterConfig {
+        transport,
+        metrics: MetricsRegistry::new(),
+    });
     let balance_of = Function {
         name: "balanceOf".to_owned(),
         inputs: vec![Param {
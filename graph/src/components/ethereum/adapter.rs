@@ -107,7 +107,7 @@ pub struct EthereumEventSubscription {
 pub struct EthereumEvent {
     pub address: Address,
     pub event_signature: H256,
-    pub block_hash: H256,
+    pub block: EthereumBlockPointer,
     pub params: Vec<LogParam>,
     pub removed: bool,
 }
@@ -115,7 +115,7 @@ pub struct EthereumEvent {
 /// A block hash and block number from a specific Ethereum block.
 ///
 /// Maximum block number supported: 2^63 - 1
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct EthereumBlockPointer {
     pub hash: H256,
     pub number: u64,
@@ -1,5 +1,7 @@
+use failure::Error;
 use futures::prelude::*;
 
+use components::ethereum::EthereumBlockPointer;
 use data::query::{Query, QueryError, QueryResult};
 use data::subscription::{Subscription, SubscriptionError, SubscriptionResult};
 
@@ -17,4 +19,11 @@ pub trait GraphQlRunner: Send + Sync {
 
     /// Runs a GraphQL subscription and returns a stream of results.
     fn run_subscription(&self, subscription: Subscription) -> SubscriptionResultFuture;
+
+    /// Returns the latest block whose entity changes have been stored for the
+    /// subgraph; query results of the subgraph only change along with it.
+    fn subgraph_latest_block(
+        &self,
+        subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error>;
 }
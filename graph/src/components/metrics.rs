@@ -0,0 +1,92 @@
+use failure::Error;
+use prometheus::{self, Encoder, HistogramOpts, Opts, Registry, TextEncoder};
+
+pub use prometheus::{Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramTimer, HistogramVec};
+
+/// Collects the metrics reported by the components of a node and encodes
+/// them in the Prometheus text format.
+///
+/// Cloning the registry is cheap; all clones share the same metrics.
+#[derive(Clone)]
+pub struct MetricsRegistry {
+    registry: Registry,
+}
+
+impl Default for MetricsRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        MetricsRegistry {
+            registry: Registry::new(),
+        }
+    }
+
+    /// Registers a counter with the given labels.
+    pub fn new_counter_vec(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<CounterVec, Error> {
+        let counters = CounterVec::new(Opts::new(name, help), labels)
+            .map_err(|e| format_err!("Invalid counter `{}`: {}", name, e))?;
+        self.register(name, Box::new(counters.clone()))?;
+        Ok(counters)
+    }
+
+    /// Registers a gauge.
+    pub fn new_gauge(&self, name: &str, help: &str) -> Result<Gauge, Error> {
+        let gauge =
+            Gauge::new(name, help).map_err(|e| format_err!("Invalid gauge `{}`: {}", name, e))?;
+        self.register(name, Box::new(gauge.clone()))?;
+        Ok(gauge)
+    }
+
+    /// Registers a gauge with the given labels.
+    pub fn new_gauge_vec(&self, name: &str, help: &str, labels: &[&str]) -> Result<GaugeVec, Error> {
+        let gauges = GaugeVec::new(Opts::new(name, help), labels)
+            .map_err(|e| format_err!("Invalid gauge `{}`: {}", name, e))?;
+        self.register(name, Box::new(gauges.clone()))?;
+        Ok(gauges)
+    }
+
+    /// Registers a histogram with the given labels and the default buckets,
+    /// which suit durations measured in seconds.
+    pub fn new_histogram_vec(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<HistogramVec, Error> {
+        let histograms = HistogramVec::new(HistogramOpts::new(name, help), labels)
+            .map_err(|e| format_err!("Invalid histogram `{}`: {}", name, e))?;
+        self.register(name, Box::new(histograms.clone()))?;
+        Ok(histograms)
+    }
+
+    fn register(&self, name: &str, collector: Box<prometheus::core::Collector>) -> Result<(), Error> {
+        self.registry
+            .register(collector)
+            .map_err(|e| format_err!("Failed to register metric `{}`: {}", name, e))
+    }
+
+    /// Returns the content type of the encoded metrics.
+    pub fn content_type(&self) -> String {
+        TextEncoder::new().format_type().to_owned()
+    }
+
+    /// Encodes the current values of all metrics.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| format_err!("Failed to encode metrics: {}", e))?;
+        Ok(buffer)
+    }
+}
+
@@ -1,11 +1,16 @@
 use std::io;
 use std::sync::Arc;
 
-use prelude::Logger;
+use prelude::{Logger, SubgraphLogStore};
 
 /// Common trait for JSON-RPC admin server implementations.
 pub trait JsonRpcServer<T> {
     type Server;
 
-    fn serve(port: u16, provider: Arc<T>, logger: Logger) -> Result<Self::Server, io::Error>;
+    fn serve(
+        port: u16,
+        provider: Arc<T>,
+        log_store: Arc<SubgraphLogStore>,
+        logger: Logger,
+    ) -> Result<Self::Server, io::Error>;
 }
@@ -0,0 +1,12 @@
+use futures::prelude::*;
+
+/// Common trait for servers that expose the metrics of a node.
+pub trait MetricsServer {
+    type ServeError;
+
+    /// Creates a new Tokio task that, when spawned, brings up the metrics server.
+    fn serve(
+        &mut self,
+        port: u16,
+    ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError>;
+}
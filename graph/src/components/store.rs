@@ -3,8 +3,12 @@ use futures::Future;
 use futures::Stream;
 use web3::types::{Block, Transaction, H256};
 
+use components::ethereum::EthereumBlockPointer;
+use components::subgraph::SubgraphHealth;
 use data::store::*;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Key by which an individual entity in the store can be accessed.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -38,6 +42,13 @@ pub enum StoreFilter {
     NotStartsWith(Attribute, Value),
     EndsWith(Attribute, Value),
     NotEndsWith(Attribute, Value),
+    /// Matches entities that reference, in an attribute holding one ID or a
+    /// list of IDs, an entity of the given type that matches the nested filter.
+    Child(Attribute, String, Box<StoreFilter>),
+    /// Matches entities whose attributes contain the words of a full-text
+    /// search. Unless the query is ordered explicitly, entities matching a
+    /// top-level full-text filter are returned ranked by relevance.
+    Fulltext(Vec<Attribute>, String),
 }
 
 /// The order in which entities should be restored from a store.
@@ -57,6 +68,13 @@ pub struct StoreRange {
     pub skip: usize,
 }
 
+/// A block at which to query the store, identified by its number or hash.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreBlock {
+    Number(u64),
+    Hash(H256),
+}
+
 /// A query for entities in a store.
 #[derive(Clone, Debug, PartialEq)]
 pub struct StoreQuery {
@@ -69,14 +87,31 @@ pub struct StoreQuery {
     /// Filter to filter entities by.
     pub filter: Option<StoreFilter>,
 
-    /// An optional attribute to order the entities by.
-    pub order_by: Option<String>,
-
-    /// The direction to order entities in.
-    pub order_direction: Option<StoreOrder>,
+    /// Attributes to order the entities by, each with its own direction;
+    /// later attributes break ties between earlier ones.
+    pub order_by: Vec<(String, StoreOrder)>,
 
     /// An optional range to limit the size of the result.
     pub range: Option<StoreRange>,
+
+    /// An optional block at which to query entities; if not set, the
+    /// latest version of each entity is returned.
+    pub block: Option<StoreBlock>,
+}
+
+/// Aggregates that can be computed over the entities matching a store query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreAggregate {
+    /// The number of matching entities.
+    Count,
+    /// The sum of a numeric attribute.
+    Sum(Attribute),
+    /// The smallest value of a numeric attribute.
+    Min(Attribute),
+    /// The largest value of a numeric attribute.
+    Max(Attribute),
+    /// The average value of a numeric attribute.
+    Avg(Attribute),
 }
 
 /// Operation types that lead to entity changes.
@@ -104,13 +139,43 @@ pub struct EntityChange {
     pub operation: EntityChangeOperation,
 }
 
-/// A stream of entity change events.
-pub type EntityChangeStream = Box<Stream<Item = EntityChange, Error = ()> + Send>;
+/// Entity changes that were applied to the store together, e.g. all changes
+/// made while processing an Ethereum event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityChangeBatch {
+    /// The block the changes were made for.
+    pub block: EthereumBlockPointer,
+    /// The changes, in the order in which they were made.
+    pub changes: Vec<EntityChange>,
+}
+
+/// A stream of entity change batches.
+pub type EntityChangeStream = Box<Stream<Item = EntityChangeBatch, Error = ()> + Send>;
+
+/// An operation on an entity that is applied to the store together with
+/// other operations for the same block.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntityOperation {
+    /// Creates an entity or updates the attributes of an existing one.
+    Set { key: StoreKey, data: Entity },
+    /// Removes an entity.
+    Remove { key: StoreKey },
+}
+
+impl EntityOperation {
+    /// The key of the entity the operation applies to.
+    pub fn key(&self) -> &StoreKey {
+        match self {
+            EntityOperation::Set { key, .. } => key,
+            EntityOperation::Remove { key } => key,
+        }
+    }
+}
 
 /// The source of the events being sent to the store
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EventSource {
-    EthereumBlock(H256),
+    EthereumBlock(EthereumBlockPointer),
 }
 
 // Implementing the display trait also provides a ToString trait implementation
@@ -118,30 +183,117 @@ impl fmt::Display for EventSource {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let printable_source = match *self {
             // Use LowerHex to format hash as hex string
-            EventSource::EthereumBlock(hash) => format!("{:x}", hash),
+            EventSource::EthereumBlock(block) => format!("{:x}", block.hash),
         };
         write!(f, "{}", printable_source)
     }
 }
 
 /// Common trait for store implementations that don't require interaction with the system.
-pub trait BasicStore: Send {
+///
+/// Stores are shared between components (and threads) without external locking,
+/// so implementations are responsible for synchronizing access internally.
+pub trait BasicStore: Send + Sync {
     /// Looks up an entity using the given store key.
     fn get(&self, key: StoreKey) -> Result<Entity, ()>;
 
     /// Updates an entity using the given store key and entity data.
-    fn set(&mut self, key: StoreKey, entity: Entity, event_source: EventSource) -> Result<(), ()>;
+    fn set(&self, key: StoreKey, entity: Entity, event_source: EventSource) -> Result<(), ()>;
 
     /// Deletes an entity using the given store key.
-    fn delete(&mut self, key: StoreKey, event_source: EventSource) -> Result<(), ()>;
+    fn delete(&self, key: StoreKey, event_source: EventSource) -> Result<(), ()>;
+
+    /// Applies all entity operations for a block atomically, so that the
+    /// resulting changes are emitted to subscriptions as one batch. The log
+    /// entries that subgraphs emitted for the block, keyed by subgraph ID, are
+    /// appended to their logs in the same transaction.
+    fn transact_block_operations(
+        &self,
+        block: EthereumBlockPointer,
+        operations: Vec<EntityOperation>,
+        log_entries: HashMap<String, Vec<SubgraphLogEntry>>,
+    ) -> Result<(), Error>;
+
+    /// Returns the latest block whose entity changes have been stored for the
+    /// subgraph, or `None` if the subgraph hasn't changed any entities yet.
+    fn subgraph_latest_block(
+        &self,
+        subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error>;
+
+    /// Records whether indexing the subgraph failed, and with which error.
+    fn set_subgraph_health(&self, subgraph_id: &str, health: &SubgraphHealth) -> Result<(), Error>;
+
+    /// Returns the recorded health of the subgraph; subgraphs are healthy
+    /// unless a failure has been recorded for them.
+    fn subgraph_health(&self, subgraph_id: &str) -> Result<SubgraphHealth, Error>;
+
+    /// Returns both the latest block and the health of the subgraph. Stores
+    /// should look both up at once, as this is done for every query.
+    fn subgraph_latest_block_and_health(
+        &self,
+        subgraph_id: &str,
+    ) -> Result<(Option<EthereumBlockPointer>, SubgraphHealth), Error> {
+        Ok((
+            self.subgraph_latest_block(subgraph_id)?,
+            self.subgraph_health(subgraph_id)?,
+        ))
+    }
 
     /// Queries the store for entities that match the store query.
     fn find(&self, query: StoreQuery) -> Result<Vec<Entity>, ()>;
+
+    /// Computes aggregates over all entities that match the store query,
+    /// ignoring its order and range.
+    ///
+    /// Returns one value per aggregate, in the order in which the aggregates
+    /// are passed in. Counts are returned as `Value::Int`. Sums, minimums and
+    /// maximums are returned as `Value::BigInt` if they are integers and as
+    /// `Value::Float` otherwise; averages are always returned as `Value::Float`.
+    /// Aggregates over attributes of zero entities are `Value::Null`.
+    fn aggregate(
+        &self,
+        query: StoreQuery,
+        aggregates: Vec<StoreAggregate>,
+    ) -> Result<Vec<Value>, ()>;
 }
 
 /// A pair of subgraph ID and entity type name.
 pub type SubgraphEntityPair = (String, String);
 
+/// Describes the entity changes a store subscription is interested in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityChangeFilter {
+    /// ID of the subgraph the entities belong to.
+    pub subgraph: String,
+    /// Entity type name.
+    pub entity: String,
+    /// IDs of the entities of interest; `None` matches all entities of the type.
+    pub ids: Option<HashSet<String>>,
+}
+
+impl EntityChangeFilter {
+    /// Returns whether the entity change passes the filter.
+    pub fn matches(&self, change: &EntityChange) -> bool {
+        self.subgraph == change.subgraph
+            && self.entity == change.entity
+            && self
+                .ids
+                .as_ref()
+                .map_or(true, |ids| ids.contains(&change.id))
+    }
+}
+
+impl From<SubgraphEntityPair> for EntityChangeFilter {
+    fn from((subgraph, entity): SubgraphEntityPair) -> Self {
+        EntityChangeFilter {
+            subgraph,
+            entity,
+            ids: None,
+        }
+    }
+}
+
 /// Common trait for block data store implementations.
 pub trait BlockStore {
     /// Add a new network, but only if one with this name does not already exist in the block store
@@ -174,12 +326,110 @@ pub trait BlockStore {
         network_name: &str,
         ancestor_count: u64,
     ) -> Result<Vec<H256>, Error>;
+    /// Returns the head block of the network, or `None` if no head block has
+    /// been determined yet.
+    fn chain_head_ptr(&self, network_name: &str) -> Result<Option<EthereumBlockPointer>, Error>;
+}
+
+/// Common trait for stores that keep the texts of persisted GraphQL queries.
+pub trait PersistedQueryStore: Send + Sync + 'static {
+    /// Looks up the query text with the given SHA-256 hash (hex-encoded).
+    fn persisted_query(&self, hash: &str) -> Result<Option<String>, Error>;
+
+    /// Stores a query text under its SHA-256 hash (hex-encoded).
+    fn persist_query(&self, hash: &str, query: &str) -> Result<(), Error>;
+}
+
+/// Severity of a subgraph log entry, from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubgraphLogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl SubgraphLogLevel {
+    /// Converts the level numbers passed to the `log` host function, which
+    /// count from `0` for critical to `4` for debug.
+    pub fn from_mapping_level(level: i32) -> Option<Self> {
+        match level {
+            0 => Some(SubgraphLogLevel::Critical),
+            1 => Some(SubgraphLogLevel::Error),
+            2 => Some(SubgraphLogLevel::Warning),
+            3 => Some(SubgraphLogLevel::Info),
+            4 => Some(SubgraphLogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// A message logged by a subgraph mapping, or an error that occurred while
+/// indexing the subgraph.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubgraphLogEntry {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub level: SubgraphLogLevel,
+    pub message: String,
+    /// Number of the block that was being indexed, if any.
+    pub block_number: Option<u64>,
+}
+
+impl SubgraphLogEntry {
+    /// Creates an entry timestamped with the current time.
+    pub fn new(level: SubgraphLogLevel, message: String, block_number: Option<u64>) -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        SubgraphLogEntry {
+            timestamp: since_epoch.as_secs() * 1000 + u64::from(since_epoch.subsec_millis()),
+            level,
+            message,
+            block_number,
+        }
+    }
+}
+
+/// Common trait for stores that keep the log entries of subgraphs, so that
+/// subgraph authors can debug their mappings without access to the node.
+pub trait SubgraphLogStore: Send + Sync + 'static {
+    /// Appends entries to the log of the subgraph, in order. Stores may drop
+    /// the oldest entries of a subgraph to keep its log from growing without
+    /// bounds.
+    fn append_subgraph_logs(
+        &self,
+        subgraph_id: &str,
+        entries: Vec<SubgraphLogEntry>,
+    ) -> Result<(), Error>;
+
+    /// Returns the `limit` most recent entries of the subgraph's log with at
+    /// least the given level, most recent first.
+    fn subgraph_logs(
+        &self,
+        subgraph_id: &str,
+        level: SubgraphLogLevel,
+        limit: usize,
+    ) -> Result<Vec<SubgraphLogEntry>, Error>;
 }
 
 /// Common trait for store implementations.
-pub trait Store: BasicStore + BlockStore + Send {
-    /// Subscribe to entity changes for specific subgraphs and entities.
+pub trait Store: BasicStore + BlockStore + SubgraphLogStore + Send + Sync {
+    /// Subscribe to entity changes for specific subgraphs, entities and entity IDs.
     ///
-    /// Returns a stream of entity changes that match the input arguments.
-    fn subscribe(&mut self, entities: Vec<SubgraphEntityPair>) -> EntityChangeStream;
+    /// Returns a stream of entity change batches, one per store transaction,
+    /// with the changes that match at least one of the filters. Transactions
+    /// without matching changes are skipped.
+    fn subscribe(&self, filters: Vec<EntityChangeFilter>) -> EntityChangeStream;
+
+    /// Creates an index that speeds up `StoreFilter::Fulltext` filters over the
+    /// given attributes of an entity type, if it doesn't exist yet.
+    fn create_fulltext_index(
+        &self,
+        subgraph: String,
+        entity: String,
+        attributes: Vec<Attribute>,
+    ) -> Result<(), Error>;
 }
@@ -3,10 +3,14 @@ use prelude::*;
 /// Events emitted by a runtime host.
 #[derive(Debug, Clone, PartialEq)]
 pub enum RuntimeHostEvent {
-    /// An entity should be created or updated.
-    EntitySet(StoreKey, Entity, EventSource),
-    /// An entity should be removed.
-    EntityRemoved(StoreKey, EventSource),
+    /// Entities should be created, updated or removed. The operations result
+    /// from handling one Ethereum event; the runtime manager gathers those of
+    /// a block and applies them to the store together.
+    EntityOperations(EthereumBlockPointer, Vec<EntityOperation>),
+    /// Entries should be appended to the subgraph's log. The entries result
+    /// from handling one Ethereum event; the runtime manager stores them
+    /// along with the entity operations of their block.
+    LogEntries(EthereumBlockPointer, Vec<SubgraphLogEntry>),
 }
 
 /// Common trait for runtime host implementations.
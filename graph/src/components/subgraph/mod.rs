@@ -2,8 +2,10 @@ mod host;
 mod manager;
 mod provider;
 mod registry;
+mod status;
 
 pub use self::host::{RuntimeHost, RuntimeHostBuilder, RuntimeHostEvent};
 pub use self::manager::RuntimeManager;
 pub use self::provider::{SchemaEvent, SubgraphProvider, SubgraphProviderEvent};
 pub use self::registry::SubgraphRegistry;
+pub use self::status::{SubgraphHealth, SubgraphStatus, SubgraphStatusProvider};
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+use prelude::*;
+
+/// Whether a subgraph is still being indexed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubgraphHealth {
+    /// The subgraph is indexed without errors.
+    Healthy,
+    /// Indexing the subgraph stopped because of the given error.
+    Failed(String),
+}
+
+/// The indexing status of a subgraph hosted by this node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubgraphStatus {
+    /// ID of the subgraph.
+    pub id: String,
+    /// Location of the subgraph manifest.
+    pub location: String,
+    /// Data sources defined in the subgraph manifest.
+    pub data_sources: Vec<DataSource>,
+    pub health: SubgraphHealth,
+    /// The latest block whose entity changes have been stored for the subgraph.
+    pub latest_block: Option<EthereumBlockPointer>,
+    /// The head block of the chain the subgraph indexes.
+    pub chain_head_block: Option<EthereumBlockPointer>,
+}
+
+/// Common trait for components that report the status of hosted subgraphs.
+pub trait SubgraphStatusProvider: Send + Sync + 'static {
+    /// Returns the status of every subgraph hosted by this node, ordered by ID.
+    fn subgraph_statuses(&self) -> Result<Vec<SubgraphStatus>, Error>;
+
+    /// Returns the status of the subgraph with the given ID, or `None` if
+    /// this node doesn't host it.
+    fn subgraph_status(&self, id: &str) -> Result<Option<SubgraphStatus>, Error>;
+
+    /// Counts the entities of each type of a hosted subgraph. This is
+    /// expensive, so entity counts are not part of `SubgraphStatus`.
+    fn subgraph_entity_counts(&self, id: &str) -> Result<BTreeMap<String, u64>, Error>;
+}
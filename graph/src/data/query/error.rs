@@ -23,6 +23,9 @@ pub enum QueryExecutionError {
     UnknownField(Pos, String, String),
     EmptyQuery,
     MultipleSubscriptionFields,
+    ValidationError(Pos, String),
+    StoreError(String),
+    ValueOutOfRange(Pos, String),
 }
 
 impl Error for QueryExecutionError {
@@ -78,6 +81,9 @@ impl fmt::Display for QueryExecutionError {
                 f,
                 "Only a single top-level field is allowed in subscriptions"
             ),
+            QueryExecutionError::ValidationError(_, s) => write!(f, "{}", s),
+            QueryExecutionError::StoreError(s) => write!(f, "Store error: {}", s),
+            QueryExecutionError::ValueOutOfRange(_, s) => write!(f, "Value out of range: {}", s),
         }
     }
 }
@@ -176,12 +182,15 @@ impl Serialize for QueryError {
                 parts[1].to_string()
             }
 
-            // Serialize entity resolution errors using their position
+            // Serialize entity resolution and validation errors using their position
             QueryError::ExecutionError(QueryExecutionError::ResolveEntityError(pos, _))
             | QueryError::ExecutionError(QueryExecutionError::NonNullError(pos, _))
             | QueryError::ExecutionError(QueryExecutionError::ListValueError(pos, _))
             | QueryError::ExecutionError(QueryExecutionError::InvalidArgumentError(pos, _, _))
-            | QueryError::ExecutionError(QueryExecutionError::MissingArgumentError(pos, _)) => {
+            | QueryError::ExecutionError(QueryExecutionError::MissingArgumentError(pos, _))
+            | QueryError::ExecutionError(QueryExecutionError::UnknownField(pos, _, _))
+            | QueryError::ExecutionError(QueryExecutionError::ValidationError(pos, _))
+            | QueryError::ExecutionError(QueryExecutionError::ValueOutOfRange(pos, _)) => {
                 let mut location = HashMap::new();
                 location.insert("line", pos.line);
                 location.insert("column", pos.column);
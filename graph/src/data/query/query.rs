@@ -2,6 +2,7 @@ use graphql_parser::query as q;
 use std::cmp::PartialEq;
 use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
 use data::schema::Schema;
 
@@ -75,6 +76,9 @@ impl PartialEq for QueryVariables {
 #[derive(Clone, Debug)]
 pub struct Query {
     pub schema: Schema,
-    pub document: q::Document,
+    /// The parsed query, shared with the cache of parsed documents.
+    pub document: Arc<q::Document>,
     pub variables: Option<QueryVariables>,
+    /// Name of the operation to execute if the document contains several.
+    pub operation_name: Option<String>,
 }
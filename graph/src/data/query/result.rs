@@ -3,6 +3,7 @@ use serde::ser::*;
 
 use super::error::{QueryError, QueryExecutionError};
 use data::graphql::SerializableValue;
+use data::subscription::SubscriptionError;
 
 fn serialize_data<S>(data: &Option<q::Value>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -34,6 +35,14 @@ impl QueryResult {
     }
 }
 
+impl From<QueryError> for QueryResult {
+    fn from(e: QueryError) -> Self {
+        let mut result = Self::new(None);
+        result.add_error(e);
+        result
+    }
+}
+
 impl From<QueryExecutionError> for QueryResult {
     fn from(e: QueryExecutionError) -> Self {
         let mut result = Self::new(None);
@@ -41,3 +50,20 @@ impl From<QueryExecutionError> for QueryResult {
         result
     }
 }
+
+impl From<Vec<QueryExecutionError>> for QueryResult {
+    fn from(e: Vec<QueryExecutionError>) -> Self {
+        let mut result = Self::new(None);
+        result.errors = Some(e.into_iter().map(QueryError::from).collect());
+        result
+    }
+}
+
+impl From<SubscriptionError> for QueryResult {
+    fn from(e: SubscriptionError) -> Self {
+        match e {
+            SubscriptionError::GraphQLError(e) => QueryResult::from(e),
+            SubscriptionError::GraphQLErrors(e) => QueryResult::from(e),
+        }
+    }
+}
@@ -7,6 +7,8 @@ use prelude::QueryExecutionError;
 pub enum SubscriptionError {
     #[fail(display = "GraphQL error: {}", _0)]
     GraphQLError(QueryExecutionError),
+    #[fail(display = "GraphQL errors: {:?}", _0)]
+    GraphQLErrors(Vec<QueryExecutionError>),
 }
 
 impl From<QueryExecutionError> for SubscriptionError {
@@ -15,6 +17,12 @@ impl From<QueryExecutionError> for SubscriptionError {
     }
 }
 
+impl From<Vec<QueryExecutionError>> for SubscriptionError {
+    fn from(e: Vec<QueryExecutionError>) -> Self {
+        SubscriptionError::GraphQLErrors(e)
+    }
+}
+
 impl Serialize for SubscriptionError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -14,8 +14,10 @@ pub extern crate slog;
 extern crate failure;
 extern crate ipfs_api;
 extern crate parity_wasm;
+extern crate prometheus;
 pub extern crate serde_json;
 extern crate slog_async;
+extern crate slog_json;
 extern crate slog_term;
 extern crate tiny_keccak;
 pub extern crate tokio;
@@ -56,17 +58,21 @@ pub mod prelude {
     pub use components::ethereum::{EthereumAdapter, EthereumBlockPointer};
     pub use components::graphql::{GraphQlRunner, QueryResultFuture, SubscriptionResultFuture};
     pub use components::link_resolver::LinkResolver;
+    pub use components::metrics::MetricsRegistry;
     pub use components::server::admin::JsonRpcServer;
+    pub use components::server::metrics::MetricsServer;
     pub use components::server::query::GraphQLServer;
     pub use components::server::subscription::SubscriptionServer;
     pub use components::store::{
-        BasicStore, BlockStore, EntityChange, EntityChangeOperation, EntityChangeStream,
-        EventSource, Store, StoreFilter, StoreKey, StoreOrder, StoreQuery, StoreRange,
-        SubgraphEntityPair,
+        BasicStore, BlockStore, EntityChange, EntityChangeBatch, EntityChangeFilter,
+        EntityChangeOperation, EntityChangeStream, EntityOperation, EventSource, PersistedQueryStore, Store,
+        StoreAggregate, StoreBlock, StoreFilter, StoreKey, StoreOrder, StoreQuery, StoreRange,
+        SubgraphEntityPair, SubgraphLogEntry, SubgraphLogLevel, SubgraphLogStore,
     };
     pub use components::subgraph::{
         RuntimeHost, RuntimeHostBuilder, RuntimeHostEvent, RuntimeManager, SchemaEvent,
-        SubgraphProvider, SubgraphProviderEvent, SubgraphRegistry,
+        SubgraphHealth, SubgraphProvider, SubgraphProviderEvent, SubgraphRegistry, SubgraphStatus,
+        SubgraphStatusProvider,
     };
     pub use components::{EventConsumer, EventProducer};
 
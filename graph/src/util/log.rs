@@ -1,21 +1,203 @@
 use backtrace::Backtrace;
+use failure::Error;
 use slog;
 use slog_async;
+use slog_json;
 use slog_term;
-use std::{env, panic};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::{env, fmt, io, panic};
 
 use slog::Drain;
 
+/// Environment variable that selects the log format, `terminal` or `json`.
+const LOG_FORMAT_VAR: &str = "THEGRAPH_LOG_FORMAT";
+
+/// Environment variable with the log levels, e.g. `info,Store=debug`.
+const LOG_LEVELS_VAR: &str = "THEGRAPH_LOG";
+
+/// Format in which log records are written to stderr.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// Compact, human-readable lines.
+    Terminal,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "terminal" => Ok(LogFormat::Terminal),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format_err!(
+                "Invalid log format `{}`, expected `terminal` or `json`",
+                s
+            )),
+        }
+    }
+}
+
+/// How the node writes its logs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Minimum level of records that don't belong to a component listed in
+    /// `component_levels`.
+    pub default_level: slog::Level,
+    /// Minimum level of records per component, keyed by the value of the
+    /// `component` key of the logger that emits them.
+    pub component_levels: HashMap<String, slog::Level>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Terminal,
+            default_level: slog::Level::Debug,
+            component_levels: HashMap::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Reads the configuration from the `THEGRAPH_LOG_FORMAT` and
+    /// `THEGRAPH_LOG` environment variables, falling back to the defaults
+    /// for variables that are not set.
+    pub fn from_env() -> Result<Self, Error> {
+        let mut config = LogConfig::default();
+        if let Ok(format) = env::var(LOG_FORMAT_VAR) {
+            config.format = format.parse()?;
+        }
+        if let Ok(levels) = env::var(LOG_LEVELS_VAR) {
+            config.parse_levels(&levels)?;
+        }
+        Ok(config)
+    }
+
+    /// Parses a comma-separated list of levels; `<level>` sets the default
+    /// level and `<component>=<level>` the level of a single component.
+    fn parse_levels(&mut self, levels: &str) -> Result<(), Error> {
+        for directive in levels.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let mut parts = directive.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(level), None) => self.default_level = parse_level(level)?,
+                (Some(component), Some(level)) => {
+                    self.component_levels
+                        .insert(component.trim().to_owned(), parse_level(level)?);
+                }
+                _ => unreachable!("splitn always returns at least one part"),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_level(level: &str) -> Result<slog::Level, Error> {
+    slog::Level::from_str(level.trim())
+        .map_err(|()| format_err!("Invalid log level `{}`", level.trim()))
+}
+
+/// Drops records below the level configured for the component that emits
+/// them.
+pub struct ComponentLevelFilter<D> {
+    drain: D,
+    default_level: slog::Level,
+    component_levels: HashMap<String, slog::Level>,
+}
+
+impl<D> ComponentLevelFilter<D> {
+    pub fn new(drain: D, config: &LogConfig) -> Self {
+        ComponentLevelFilter {
+            drain,
+            default_level: config.default_level,
+            component_levels: config.component_levels.clone(),
+        }
+    }
+}
+
+impl<D: Drain> Drain for ComponentLevelFilter<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        values: &slog::OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        let level = if self.component_levels.is_empty() {
+            self.default_level
+        } else {
+            component(record, values)
+                .and_then(|component| self.component_levels.get(&component).cloned())
+                .unwrap_or(self.default_level)
+        };
+
+        if record.level().is_at_least(level) {
+            self.drain.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Returns the innermost `component` value of a logger.
+fn component(record: &slog::Record, values: &slog::OwnedKVList) -> Option<String> {
+    struct ComponentSerializer(Option<String>);
+
+    impl slog::Serializer for ComponentSerializer {
+        fn emit_arguments(&mut self, key: slog::Key, val: &fmt::Arguments) -> slog::Result {
+            // Values of child loggers come before those of their parents.
+            if key == "component" && self.0.is_none() {
+                self.0 = Some(fmt::format(*val));
+            }
+            Ok(())
+        }
+    }
+
+    let mut serializer = ComponentSerializer(None);
+    slog::KV::serialize(values, record, &mut serializer).ok()?;
+    serializer.0
+}
+
+fn format_drain(format: LogFormat) -> Box<Drain<Ok = (), Err = slog::Never> + Send> {
+    match format {
+        LogFormat::Terminal => {
+            let decorator = slog_term::TermDecorator::new().build();
+            Box::new(slog_term::CompactFormat::new(decorator).build().fuse())
+        }
+        LogFormat::Json => Box::new(
+            slog_json::Json::new(io::stderr())
+                .add_default_keys()
+                .build()
+                .fuse(),
+        ),
+    }
+}
+
+fn config_from_env() -> LogConfig {
+    LogConfig::from_env().expect("Invalid logging configuration")
+}
+
+/// Creates the root logger, configured by the `THEGRAPH_LOG_FORMAT` and
+/// `THEGRAPH_LOG` environment variables.
 pub fn logger() -> slog::Logger {
-    let decorator = slog_term::TermDecorator::new().build();
-    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
+    let config = config_from_env();
+
+    // Filter records before they are handed to the async drain, so records
+    // below the configured levels are never sent to the logging thread
+    let drain = ComponentLevelFilter::new(format_drain(config.format), &config).fuse();
     let drain = slog_async::Async::new(drain).build().fuse();
     slog::Logger::root(drain, o!())
 }
 
+/// Like `logger`, but also returns a guard that flushes pending records
+/// when dropped.
 pub fn guarded_logger() -> (slog::Logger, slog_async::AsyncGuard) {
-    let decorator = slog_term::TermDecorator::new().build();
-    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
+    let config = config_from_env();
+    let drain = ComponentLevelFilter::new(format_drain(config.format), &config).fuse();
     let (drain, guard) = slog_async::Async::new(drain).build_with_guard();
     (slog::Logger::root(drain.fuse(), o!()), guard)
 }
@@ -41,3 +223,89 @@ pub fn register_panic_hook(panic_logger: slog::Logger) {
         }
     }));
 }
+
+#[cfg(test)]
+mod tests {
+    use slog::{self, Drain};
+    use std::collections::HashMap;
+    use std::iter::FromIterator;
+    use std::sync::{Arc, Mutex};
+
+    use super::{ComponentLevelFilter, LogConfig};
+
+    fn config(levels: &str) -> LogConfig {
+        let mut config = LogConfig::default();
+        config.parse_levels(levels).expect("Invalid log levels");
+        config
+    }
+
+    /// Collects the messages of the records it receives.
+    #[derive(Clone, Default)]
+    struct Messages(Arc<Mutex<Vec<String>>>);
+
+    impl Drain for Messages {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(&self, record: &slog::Record, _: &slog::OwnedKVList) -> Result<(), slog::Never> {
+            self.0.lock().unwrap().push(format!("{}", record.msg()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parses_a_bare_level_as_the_default_level() {
+        let config = config("info");
+        assert_eq!(config.default_level, slog::Level::Info);
+        assert!(config.component_levels.is_empty());
+    }
+
+    #[test]
+    fn parses_component_levels() {
+        let config = config(" warn, Store=debug ,GraphQlRunner = error,");
+        assert_eq!(config.default_level, slog::Level::Warning);
+        assert_eq!(
+            config.component_levels,
+            HashMap::from_iter(vec![
+                (String::from("Store"), slog::Level::Debug),
+                (String::from("GraphQlRunner"), slog::Level::Error),
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_levels() {
+        for levels in vec!["loud", "Store=loud", "Store=", "info,=debug=x"] {
+            LogConfig::default()
+                .parse_levels(levels)
+                .expect_err(&format!("Should reject `{}`", levels));
+        }
+    }
+
+    #[test]
+    fn filters_records_by_component() {
+        let messages = Messages::default();
+        let drain = ComponentLevelFilter::new(
+            messages.clone(),
+            &config("info,Store=debug,GraphQlRunner=error"),
+        ).fuse();
+
+        let root = slog::Logger::root(drain, o!());
+        let store = root.new(o!("component" => "Store"));
+        let runner = root.new(o!("component" => "GraphQlRunner"));
+        let nested = runner.new(o!("component" => "Store"));
+
+        debug!(root, "root debug");
+        info!(root, "root info");
+        debug!(store, "store debug");
+        warn!(runner, "runner warning");
+        error!(runner, "runner error");
+        debug!(nested, "nested debug");
+
+        // The innermost component of a logger decides its level
+        assert_eq!(
+            *messages.0.lock().unwrap(),
+            vec!["root info", "store debug", "runner error", "nested debug"]
+        );
+    }
+}
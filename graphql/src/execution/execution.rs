@@ -1,8 +1,10 @@
 use graphql_parser::query as q;
 use graphql_parser::schema as s;
 use indexmap::IndexMap;
-use std::cmp;
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+use std::sync::Mutex;
 
 use graph::prelude::*;
 
@@ -46,13 +48,33 @@ where
         ctx.fields.push(field);
         ctx
     }
+
+    /// Creates a derived context with the resolver that `Resolver::for_field`
+    /// returns for the field and its arguments.
+    fn for_field_arguments(
+        &self,
+        field: &q::Field,
+        argument_values: &HashMap<&q::Name, q::Value>,
+    ) -> Result<Self, QueryExecutionError> {
+        let mut ctx = self.clone();
+        if !self.introspecting {
+            if let Some(resolver) = self.resolver.for_field(field, argument_values)? {
+                ctx.resolver = Arc::new(resolver);
+            }
+        }
+        Ok(ctx)
+    }
 }
 
 /// Executes the root selection set of a query.
+///
+/// Root fields are independent of each other, so they are resolved
+/// concurrently on the threads of the given pool.
 pub fn execute_root_selection_set<'a, R1, R2>(
     ctx: ExecutionContext<'a, R1, R2>,
     selection_set: &'a q::SelectionSet,
     initial_value: &Option<q::Value>,
+    thread_pool: &QueryThreadPool,
 ) -> QueryResult
 where
     R1: Resolver,
@@ -64,16 +86,67 @@ where
         None => return QueryResult::from(QueryExecutionError::NoRootQueryObjectType),
     };
 
-    // Execute the root selection set against the root query type
-    execute_selection_set(ctx, selection_set, query_type, initial_value)
-        .unwrap_or_else(QueryResult::from)
+    // Group fields with the same response key, so we can execute them together
+    let field_groups: Vec<_> = collect_fields(ctx.clone(), query_type, selection_set, None)
+        .into_iter()
+        .collect();
+
+    // Execute the root selection set sequentially if there's nothing to parallelize
+    let pool = match thread_pool.pool() {
+        Some(pool) if field_groups.len() > 1 => pool,
+        _ => {
+            return execute_selection_set(ctx, selection_set, query_type, initial_value)
+                .unwrap_or_else(QueryResult::from)
+        }
+    };
+
+    // Queue one job per field group; the pool runs as many of them at once as
+    // it has threads. Results are stored by the index of their field group to
+    // preserve the order
+    let results = Mutex::new(field_groups.iter().map(|_| None).collect::<Vec<_>>());
+
+    pool.scope(|scope| {
+        for (index, (_, fields)) in field_groups.iter().enumerate() {
+            let ctx = ctx.clone();
+            let fields = fields.clone();
+            let results = &results;
+
+            scope.spawn(move |_| {
+                let result = execute_field_group(ctx, query_type, initial_value, fields);
+                results.lock().unwrap()[index] = result;
+            });
+        }
+    });
+
+    let mut result = QueryResult::new(None);
+    let mut result_map: BTreeMap<String, q::Value> = BTreeMap::new();
+
+    for ((response_key, _), field_result) in field_groups
+        .into_iter()
+        .zip(results.into_inner().unwrap().into_iter())
+    {
+        match field_result {
+            Some(Ok(v)) => {
+                result_map.insert(response_key.to_owned(), v);
+            }
+            Some(Err(e)) => result.add_error(QueryError::from(e)),
+            None => (),
+        }
+    }
+
+    // If we have result data, wrap it in an output object
+    if !result_map.is_empty() {
+        result.data = Some(q::Value::Object(result_map));
+    }
+
+    result
 }
 
 /// Executes a selection set, requiring the result to be of the given object type.
 ///
 /// Allows passing in a parent value during recursive processing of objects and their fields.
 pub fn execute_selection_set<'a, R1, R2>(
-    mut ctx: ExecutionContext<'a, R1, R2>,
+    ctx: ExecutionContext<'a, R1, R2>,
     selection_set: &'a q::SelectionSet,
     object_type: &s::ObjectType,
     object_value: &Option<q::Value>,
@@ -91,6 +164,78 @@ where
     // Process all field groups in order
     for (response_key, fields) in grouped_field_set {
         // If the field exists on the object, execute it and add its result to the result map
+        match execute_field_group(ctx.clone(), object_type, object_value, fields) {
+            Some(Ok(v)) => {
+                result_map.insert(response_key.to_owned(), v);
+            }
+            Some(Err(e)) => {
+                result.add_error(QueryError::from(e));
+            }
+            None => (),
+        };
+    }
+
+    // If we have result data, wrap it in an output object
+    if !result_map.is_empty() {
+        result.data = Some(q::Value::Object(result_map));
+    }
+
+    Ok(result)
+}
+
+/// Executes a group of fields with the same response key for an object.
+///
+/// Returns `None` if the field does not exist on the object type.
+fn execute_field_group<'a, R1, R2>(
+    mut ctx: ExecutionContext<'a, R1, R2>,
+    object_type: &s::ObjectType,
+    object_value: &Option<q::Value>,
+    fields: Vec<&'a q::Field>,
+) -> Option<Result<q::Value, QueryExecutionError>>
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    let field = fields[0];
+
+    get_field_type(ctx.clone(), object_type, &field.name).map(|(field_definition, introspecting)| {
+        // Push the new field onto the context's field stack
+        let mut ctx = ctx.for_field(field);
+
+        // Remember whether or not we're introspecting now
+        ctx.introspecting = introspecting;
+
+        execute_field(ctx, object_type, object_value, field, field_definition, fields)
+    })
+}
+
+/// Executes a selection set for several sibling objects of the same object type,
+/// e.g. the items of a list field.
+///
+/// Each field is resolved for all objects together, which allows the resolver to
+/// look up nested entities with a single query rather than one query per object.
+/// Field errors are handled per object, the same way `execute_selection_set`
+/// handles them for nested objects.
+fn execute_selection_set_for_objects<'a, R1, R2>(
+    mut ctx: ExecutionContext<'a, R1, R2>,
+    selection_set: &'a q::SelectionSet,
+    object_type: &s::ObjectType,
+    object_values: Vec<Option<q::Value>>,
+) -> Vec<q::Value>
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    let mut result_maps: Vec<BTreeMap<String, q::Value>> =
+        object_values.iter().map(|_| BTreeMap::new()).collect();
+
+    // Group fields with the same response key, so we can execute them together
+    let grouped_field_set = collect_fields(ctx.clone(), object_type, selection_set, None);
+
+    // Process all field groups in order
+    for (response_key, fields) in grouped_field_set {
+        // If the field exists on the object, execute it for all objects and add
+        // the results to the result maps of the objects
         if let Some((ref field, introspecting)) =
             get_field_type(ctx.clone(), object_type, &fields[0].name)
         {
@@ -100,23 +245,36 @@ where
             // Remember whether or not we're introspecting now
             ctx.introspecting = introspecting;
 
-            match execute_field(ctx, object_type, object_value, &fields[0], field, fields) {
-                Ok(v) => {
+            let values = match execute_field_for_objects(
+                ctx,
+                object_type,
+                &object_values,
+                &fields[0],
+                field,
+                fields,
+            ) {
+                Ok(values) => values,
+                Err(_) => continue,
+            };
+
+            for (result_map, value) in result_maps.iter_mut().zip(values.into_iter()) {
+                if let Ok(v) = value {
                     result_map.insert(response_key.to_owned(), v);
                 }
-                Err(e) => {
-                    result.add_error(QueryError::from(e));
-                }
-            };
+            }
         }
     }
 
-    // If we have result data, wrap it in an output object
-    if !result_map.is_empty() {
-        result.data = Some(q::Value::Object(result_map));
-    }
-
-    Ok(result)
+    // Wrap the result data of each object in an output object
+    result_maps
+        .into_iter()
+        .map(|result_map| {
+            if result_map.is_empty() {
+                q::Value::Null
+            } else {
+                q::Value::Object(result_map)
+            }
+        }).collect()
 }
 
 /// Collects fields of a selection set.
@@ -275,18 +433,77 @@ where
     R1: Resolver,
     R2: Resolver,
 {
-    coerce_argument_values(ctx.clone(), object_type, field)
-        .and_then(|argument_values| {
-            resolve_field_value(
-                ctx.clone(),
-                object_type,
-                object_value,
-                field,
+    let argument_values = coerce_argument_values(ctx.clone(), object_type, field)?;
+    let ctx = ctx.for_field_arguments(field, &argument_values)?;
+
+    resolve_field_value(
+        ctx.clone(),
+        object_type,
+        object_value,
+        field,
+        field_definition,
+        &field_definition.field_type,
+        &argument_values,
+    ).and_then(|value| complete_value(ctx, field, &field_definition.field_type, fields, value))
+}
+
+/// Executes a field for several sibling objects at once.
+///
+/// Lists of objects are resolved through the resolver's batched lookup if it
+/// supports one; all other fields are resolved for each object separately.
+fn execute_field_for_objects<'a, R1, R2>(
+    ctx: ExecutionContext<'a, R1, R2>,
+    object_type: &s::ObjectType,
+    object_values: &Vec<Option<q::Value>>,
+    field: &'a q::Field,
+    field_definition: &s::Field,
+    fields: Vec<&'a q::Field>,
+) -> Result<Vec<Result<q::Value, QueryExecutionError>>, QueryExecutionError>
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    let argument_values = coerce_argument_values(ctx.clone(), object_type, field)?;
+    let ctx = ctx.for_field_arguments(field, &argument_values)?;
+
+    let batched_values = if ctx.introspecting {
+        None
+    } else {
+        list_item_object_type(&ctx, &field_definition.field_type).and_then(|item_type| {
+            ctx.resolver.resolve_objects_for_parents(
+                object_values,
+                &field.name,
                 field_definition,
-                &field_definition.field_type,
+                item_type,
                 &argument_values,
             )
-        }).and_then(|value| complete_value(ctx, field, &field_definition.field_type, fields, value))
+        })
+    };
+
+    let resolved_values = match batched_values {
+        Some(values) => values.into_iter().map(Ok).collect(),
+        None => object_values
+            .iter()
+            .map(|object_value| {
+                resolve_field_value(
+                    ctx.clone(),
+                    object_type,
+                    object_value,
+                    field,
+                    field_definition,
+                    &field_definition.field_type,
+                    &argument_values,
+                )
+            }).collect(),
+    };
+
+    Ok(complete_values(
+        ctx,
+        field,
+        &field_definition.field_type,
+        fields,
+        resolved_values,
+    ))
 }
 
 /// Resolves the value of a field.
@@ -362,21 +579,21 @@ where
         // Let the resolver decide how the field (with the given object type)
         // is resolved into an entity based on the (potential) parent object
         s::TypeDefinition::Object(t) => if ctx.introspecting {
-            Ok(ctx.introspection_resolver.resolve_object(
+            ctx.introspection_resolver.resolve_object(
                 object_value,
-                &field.name,
+                field,
                 field_definition,
                 t,
                 argument_values,
-            ))
+            )
         } else {
-            Ok(ctx.resolver.resolve_object(
+            ctx.resolver.resolve_object(
                 object_value,
-                &field.name,
+                &inline_fragment_spreads(ctx.document, field),
                 field_definition,
                 t,
                 argument_values,
-            ))
+            )
         },
 
         // Let the resolver decide how values in the resolved object value
@@ -452,21 +669,21 @@ where
                 // Let the resolver decide how the list field (with the given item object type)
                 // is resolved into a entities based on the (potential) parent object
                 s::TypeDefinition::Object(t) => if ctx.introspecting {
-                    Ok(ctx.introspection_resolver.resolve_objects(
+                    ctx.introspection_resolver.resolve_objects(
                         object_value,
                         &field.name,
                         field_definition,
                         t,
                         argument_values,
-                    ))
+                    )
                 } else {
-                    Ok(ctx.resolver.resolve_objects(
+                    ctx.resolver.resolve_objects(
                         object_value,
                         &field.name,
                         field_definition,
                         t,
                         argument_values,
-                    ))
+                    )
                 },
 
                 // Let the resolver decide how values in the resolved object value
@@ -537,6 +754,14 @@ where
         return Ok(resolved_value);
     }
 
+    // Complete lists of objects together, so that the fields of all objects
+    // are resolved at once
+    if list_item_object_type(&ctx, field_type).is_some() {
+        return complete_values(ctx, field, field_type, fields, vec![Ok(resolved_value)])
+            .pop()
+            .unwrap();
+    }
+
     // Complete list values
     if let s::Type::ListType(inner_type) = field_type {
         return match resolved_value {
@@ -634,6 +859,143 @@ where
     }
 }
 
+/// Completes the values resolved for a field on several sibling objects.
+///
+/// The items of lists of objects are collected from all values and completed
+/// together; all other values are completed one by one.
+fn complete_values<'a, R1, R2>(
+    ctx: ExecutionContext<'a, R1, R2>,
+    field: &'a q::Field,
+    field_type: &'a s::Type,
+    fields: Vec<&'a q::Field>,
+    resolved_values: Vec<Result<q::Value, QueryExecutionError>>,
+) -> Vec<Result<q::Value, QueryExecutionError>>
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    let object_type = match list_item_object_type(&ctx, field_type) {
+        Some(t) => t,
+        None => {
+            return resolved_values
+                .into_iter()
+                .map(|value| {
+                    value.and_then(|value| {
+                        complete_value(ctx.clone(), field, field_type, fields.clone(), value)
+                    })
+                }).collect()
+        }
+    };
+
+    let (list_non_null, list_type) = match field_type {
+        s::Type::NonNullType(inner_type) => (true, inner_type.as_ref()),
+        t => (false, t),
+    };
+    let items_non_null = match list_type {
+        s::Type::ListType(inner_type) => match inner_type.as_ref() {
+            s::Type::NonNullType(_) => true,
+            _ => false,
+        },
+        _ => false,
+    };
+
+    // Collect the non-null items of all lists, remembering for each list where
+    // its items are and for each item which object it corresponds to
+    let mut objects = vec![];
+    let mut item_objects = vec![];
+    let lists: Vec<Result<Option<(usize, usize)>, QueryExecutionError>> = resolved_values
+        .into_iter()
+        .map(|value| match value? {
+            q::Value::Null => Ok(None),
+            q::Value::List(values) => {
+                let start = item_objects.len();
+                for value in values {
+                    item_objects.push(match value {
+                        q::Value::Null => None,
+                        value => {
+                            objects.push(Some(value));
+                            Some(objects.len() - 1)
+                        }
+                    });
+                }
+                Ok(Some((start, item_objects.len())))
+            }
+            _ => Err(QueryExecutionError::ListValueError(
+                field.position,
+                field.name.to_string(),
+            )),
+        }).collect();
+
+    let mut completed_objects = execute_selection_set_for_objects(
+        ctx.clone(),
+        &merge_selection_sets(fields),
+        object_type,
+        objects,
+    );
+
+    // Distribute the completed objects back to their lists
+    lists
+        .into_iter()
+        .map(|list| match list? {
+            None if list_non_null => Err(QueryExecutionError::NonNullError(
+                field.position,
+                field.name.to_string(),
+            )),
+            None => Ok(q::Value::Null),
+            Some((start, end)) => {
+                let values: Vec<q::Value> = item_objects[start..end]
+                    .iter()
+                    .map(|item_object| match item_object {
+                        Some(i) => mem::replace(&mut completed_objects[*i], q::Value::Null),
+                        None => q::Value::Null,
+                    }).collect();
+
+                if items_non_null && values.iter().any(|value| value == &q::Value::Null) {
+                    Err(QueryExecutionError::NonNullError(
+                        field.position,
+                        field.name.to_string(),
+                    ))
+                } else {
+                    Ok(q::Value::List(values))
+                }
+            }
+        }).collect()
+}
+
+/// Returns the object type of the items of a list type (e.g. `[User!]!`), if
+/// the items are objects.
+fn list_item_object_type<'a, R1, R2>(
+    ctx: &ExecutionContext<'a, R1, R2>,
+    field_type: &s::Type,
+) -> Option<&'a s::ObjectType>
+where
+    R1: Resolver,
+    R2: Resolver,
+{
+    let item_type = match field_type {
+        s::Type::NonNullType(inner_type) => return list_item_object_type(ctx, inner_type),
+        s::Type::ListType(inner_type) => match inner_type.as_ref() {
+            s::Type::NonNullType(inner_type) => inner_type.as_ref(),
+            t => t,
+        },
+        s::Type::NamedType(_) => return None,
+    };
+
+    let schema: &'a s::Document = if ctx.introspecting {
+        ctx.introspection_schema
+    } else {
+        &ctx.schema.document
+    };
+
+    match item_type {
+        s::Type::NamedType(name) => match sast::get_named_type(schema, name) {
+            Some(s::TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Resolves an abstract type (interface, union) into an object type based on the given value.
 fn resolve_abstract_type<'a, R1, R2>(
     ctx: ExecutionContext<'a, R1, R2>,
@@ -660,6 +1022,66 @@ where
         ))
 }
 
+/// Returns the field with the fragment spreads in its selection set replaced
+/// by the selections of the fragments, at any depth. Type conditions are kept
+/// on inline fragments but dropped for spreads, which is fine for telling
+/// which subfields may be selected.
+fn inline_fragment_spreads<'a>(document: &q::Document, field: &'a q::Field) -> Cow<'a, q::Field> {
+    fn has_spreads(selection_set: &q::SelectionSet) -> bool {
+        selection_set.items.iter().any(|selection| match selection {
+            q::Selection::Field(field) => has_spreads(&field.selection_set),
+            q::Selection::FragmentSpread(_) => true,
+            q::Selection::InlineFragment(fragment) => has_spreads(&fragment.selection_set),
+        })
+    }
+
+    fn inline(
+        document: &q::Document,
+        selection_set: &q::SelectionSet,
+        visited: &mut Vec<q::Name>,
+    ) -> q::SelectionSet {
+        let mut items = vec![];
+        for selection in selection_set.items.iter() {
+            match selection {
+                q::Selection::Field(field) => items.push(q::Selection::Field(q::Field {
+                    selection_set: inline(document, &field.selection_set, visited),
+                    ..field.clone()
+                })),
+                q::Selection::FragmentSpread(spread) => {
+                    // Skip cyclic spreads, which validation rejects anyway
+                    if visited.contains(&spread.fragment_name) {
+                        continue;
+                    }
+                    if let Some(fragment) = qast::get_fragment(document, &spread.fragment_name) {
+                        visited.push(spread.fragment_name.clone());
+                        items.extend(inline(document, &fragment.selection_set, visited).items);
+                        visited.pop();
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    items.push(q::Selection::InlineFragment(q::InlineFragment {
+                        selection_set: inline(document, &fragment.selection_set, visited),
+                        ..fragment.clone()
+                    }))
+                }
+            }
+        }
+        q::SelectionSet {
+            span: selection_set.span,
+            items,
+        }
+    }
+
+    if has_spreads(&field.selection_set) {
+        Cow::Owned(q::Field {
+            selection_set: inline(document, &field.selection_set, &mut vec![]),
+            ..field.clone()
+        })
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
 /// Merges the selection sets of several fields into a single selection set.
 fn merge_selection_sets(fields: Vec<&q::Field>) -> q::SelectionSet {
     let (span, items) = fields
@@ -6,7 +6,21 @@ use prelude::*;
 
 /// A GraphQL resolver that can resolve entities, enum values, scalar types and interfaces/unions.
 pub trait Resolver: Clone + Send + Sync {
-    /// Resolves entities referenced by a parent object.
+    /// Returns a resolver for a field and the fields nested in it, if the
+    /// arguments of the field change how they are resolved, or `None` to keep
+    /// using this resolver.
+    ///
+    /// Fails if the arguments are invalid.
+    fn for_field(
+        &self,
+        _field: &q::Field,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<Option<Self>, QueryExecutionError> {
+        Ok(None)
+    }
+
+    /// Resolves entities referenced by a parent object. Fails if the
+    /// entities exist but cannot be resolved.
     fn resolve_objects(
         &self,
         parent: &Option<q::Value>,
@@ -14,17 +28,37 @@ pub trait Resolver: Clone + Send + Sync {
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value;
+    ) -> Result<q::Value, QueryExecutionError>;
+
+    /// Resolves entities referenced by several sibling parent objects at once.
+    ///
+    /// Returns one value per parent, or `None` if the resolver cannot look up
+    /// the entities for all parents together, in which case `resolve_objects`
+    /// is called for each parent instead.
+    fn resolve_objects_for_parents(
+        &self,
+        _parents: &Vec<Option<q::Value>>,
+        _field: &q::Name,
+        _field_definition: &s::Field,
+        _object_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Option<Vec<q::Value>> {
+        None
+    }
 
     /// Resolves an entity referenced by a parent object.
+    ///
+    /// The selection set of the field has its fragment spreads inlined, so
+    /// resolvers can tell which subfields are selected. Fails if the object
+    /// exists but cannot be resolved.
     fn resolve_object(
         &self,
         parent: &Option<q::Value>,
-        field: &q::Name,
+        field: &q::Field,
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value;
+    ) -> Result<q::Value, QueryExecutionError>;
 
     /// Resolves an enum value for a given enum type.
     fn resolve_enum_value(&self, enum_type: &s::EnumType, value: Option<&q::Value>) -> q::Value {
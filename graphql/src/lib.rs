@@ -1,13 +1,17 @@
 extern crate futures;
 extern crate graph;
 extern crate graphql_parser;
+extern crate hex;
 extern crate indexmap;
 extern crate inflector;
+extern crate linked_hash_map;
+extern crate rayon;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate failure;
+extern crate sha2;
 
 /// Utilities for working with GraphQL schemas.
 pub mod schema;
@@ -34,7 +38,9 @@ mod store;
 pub mod prelude {
     pub use super::execution::{ExecutionContext, Resolver};
     pub use super::introspection::{introspection_schema, IntrospectionResolver};
-    pub use super::query::{execute_query, QueryExecutionOptions};
+    pub use super::query::persisted::{PersistedQueries, PersistedQueryError};
+    pub use super::query::validation::validate_query;
+    pub use super::query::{execute_query, QueryExecutionOptions, QueryThreadPool};
     pub use super::schema::{api_schema, validate_schema, APISchemaError, SchemaValidationError};
     pub use super::store::{build_query, StoreResolver};
     pub use super::subscription::{execute_subscription, SubscriptionExecutionOptions};
@@ -1,14 +1,62 @@
 use graphql_parser::query as q;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use graph::prelude::*;
 
 use execution::*;
 use prelude::*;
 use query::ast as qast;
+use query::validation::validate_query;
 
 /// Utilities for working with GraphQL query ASTs.
 pub mod ast;
 
+/// Validation of GraphQL queries against their schema.
+pub mod validation;
+
+/// Caching of parsed queries and automatic persisted queries.
+pub mod persisted;
+
+/// A pool of threads on which the root fields of queries are resolved
+/// concurrently. The pool is meant to be shared by all queries, so that the
+/// number of threads stays bounded no matter how many queries are executed.
+#[derive(Clone)]
+pub struct QueryThreadPool {
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl QueryThreadPool {
+    /// Creates a pool with `size` threads. With fewer than two threads, root
+    /// fields are resolved one by one on the thread executing the query.
+    pub fn new(size: usize) -> Self {
+        QueryThreadPool {
+            pool: if size > 1 {
+                Some(Arc::new(
+                    ThreadPoolBuilder::new()
+                        .num_threads(size)
+                        .thread_name(|index| format!("graphql-query-{}", index))
+                        .build()
+                        .expect("failed to create query thread pool"),
+                ))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Creates a pool that resolves root fields one by one on the thread
+    /// executing the query.
+    pub fn sequential() -> Self {
+        QueryThreadPool { pool: None }
+    }
+
+    /// Returns the underlying thread pool, unless fields are to be resolved
+    /// sequentially.
+    pub(crate) fn pool(&self) -> Option<&ThreadPool> {
+        self.pool.as_ref().map(|pool| pool.as_ref())
+    }
+}
+
 /// Options available for query execution.
 pub struct QueryExecutionOptions<R>
 where
@@ -18,6 +66,8 @@ where
     pub logger: slog::Logger,
     /// The resolver to use.
     pub resolver: R,
+    /// The thread pool to resolve root fields on.
+    pub thread_pool: QueryThreadPool,
 }
 
 /// Executes a query and returns a result.
@@ -27,8 +77,14 @@ where
 {
     info!(options.logger, "Execute query");
 
-    // Obtain the only operation of the query (fail if there is none or more than one)
-    let operation = match qast::get_operation(&query.document, None) {
+    // Validate the query against the schema before executing it
+    if let Err(errors) = validate_query(query) {
+        return QueryResult::from(errors);
+    }
+
+    // Obtain the operation to execute (fail if there is none or it is ambiguous)
+    let operation_name = query.operation_name.as_ref().map(|name| name.as_str());
+    let operation = match qast::get_operation(&query.document, operation_name) {
         Ok(op) => op,
         Err(e) => return QueryResult::from(e),
     };
@@ -53,11 +109,11 @@ where
         // Execute top-level `query { ... }` expressions
         &q::OperationDefinition::Query(q::Query {
             ref selection_set, ..
-        }) => execute_root_selection_set(ctx, selection_set, &None),
+        }) => execute_root_selection_set(ctx, selection_set, &None, &options.thread_pool),
 
         // Execute top-level `{ ... }` expressions
         &q::OperationDefinition::SelectionSet(ref selection_set) => {
-            execute_root_selection_set(ctx, selection_set, &None)
+            execute_root_selection_set(ctx, selection_set, &None, &options.thread_pool)
         }
 
         // Everything else (e.g. mutations) is unsupported
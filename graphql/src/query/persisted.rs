@@ -0,0 +1,352 @@
+use graphql_parser;
+use graphql_parser::query as q;
+use hex;
+use linked_hash_map::LinkedHashMap;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Mutex;
+
+use graph::prelude::*;
+use graph::serde_json;
+
+/// The only version of the `persistedQuery` extension that is supported.
+const PERSISTED_QUERY_VERSION: u64 = 1;
+
+/// Errors that can occur while resolving the document of a query.
+#[derive(Debug)]
+pub enum PersistedQueryError {
+    /// Neither the query text nor a hash of it were provided.
+    MissingQuery,
+    /// The hash is unknown; the client has to send the full query text.
+    NotFound,
+    /// The query text does not match the provided hash.
+    HashMismatch,
+    InvalidExtension(String),
+    UnsupportedVersion(u64),
+    ParseError(QueryError),
+    StoreError(String),
+}
+
+impl fmt::Display for PersistedQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PersistedQueryError::MissingQuery => {
+                write!(f, "The \"query\" field missing in request data")
+            }
+            // Clients retry with the full query text when they see this message
+            PersistedQueryError::NotFound => write!(f, "PersistedQueryNotFound"),
+            PersistedQueryError::HashMismatch => {
+                write!(f, "Provided sha256Hash does not match query")
+            }
+            PersistedQueryError::InvalidExtension(s) => {
+                write!(f, "Invalid persistedQuery extension: {}", s)
+            }
+            PersistedQueryError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported persisted query version: {}", v)
+            }
+            PersistedQueryError::ParseError(e) => write!(f, "{}", e),
+            PersistedQueryError::StoreError(s) => {
+                write!(f, "Failed to look up persisted query: {}", s)
+            }
+        }
+    }
+}
+
+/// An LRU cache of parsed query documents, keyed by the SHA-256 hash of the
+/// query text.
+///
+/// Implements automatic persisted queries: clients may send only the hash of
+/// a query in the `persistedQuery` extension and have to send the full text
+/// only if the hash is unknown. Persisted queries can optionally be backed by
+/// a store, so they survive restarts and are shared between nodes.
+pub struct PersistedQueries {
+    capacity: usize,
+    documents: Mutex<LinkedHashMap<String, Arc<q::Document>>>,
+    store: Option<Arc<PersistedQueryStore>>,
+}
+
+impl PersistedQueries {
+    /// Creates an in-memory cache holding at most `capacity` documents.
+    pub fn new(capacity: usize) -> Self {
+        PersistedQueries {
+            capacity,
+            documents: Mutex::new(LinkedHashMap::new()),
+            store: None,
+        }
+    }
+
+    /// Creates a cache holding at most `capacity` documents that falls back
+    /// to `store` for hashes it doesn't know.
+    pub fn with_store(capacity: usize, store: Arc<PersistedQueryStore>) -> Self {
+        PersistedQueries {
+            store: Some(store),
+            ..Self::new(capacity)
+        }
+    }
+
+    /// Resolves the document of a query from its text and/or the `extensions`
+    /// sent along with it. Query texts are parsed at most once while they
+    /// remain in the cache.
+    pub fn document(
+        &self,
+        query: Option<&str>,
+        extensions: Option<&serde_json::Value>,
+    ) -> Result<Arc<q::Document>, PersistedQueryError> {
+        let requested_hash = Self::requested_hash(extensions)?;
+
+        match (query, requested_hash) {
+            (Some(query), requested_hash) => {
+                let hash = hex::encode(Sha256::digest(query.as_bytes()));
+                if let Some(ref requested_hash) = requested_hash {
+                    if requested_hash.to_lowercase() != hash {
+                        return Err(PersistedQueryError::HashMismatch);
+                    }
+                }
+
+                let document = match self.cached(&hash) {
+                    Some(document) => document,
+                    None => self.parse_and_cache(hash.clone(), query)?,
+                };
+
+                // Only remember queries in the store that clients asked us to
+                // persist, even if they were cached without a hash before
+                if requested_hash.is_some() {
+                    if let Some(ref store) = self.store {
+                        store
+                            .persist_query(&hash, query)
+                            .map_err(|e| PersistedQueryError::StoreError(e.to_string()))?;
+                    }
+                }
+
+                Ok(document)
+            }
+            (None, Some(hash)) => {
+                let hash = hash.to_lowercase();
+                if let Some(document) = self.cached(&hash) {
+                    return Ok(document);
+                }
+
+                let query = match self.store {
+                    Some(ref store) => store
+                        .persisted_query(&hash)
+                        .map_err(|e| PersistedQueryError::StoreError(e.to_string()))?,
+                    None => None,
+                };
+
+                match query {
+                    Some(query) => self.parse_and_cache(hash, &query),
+                    None => Err(PersistedQueryError::NotFound),
+                }
+            }
+            (None, None) => Err(PersistedQueryError::MissingQuery),
+        }
+    }
+
+    /// Extracts the hash from the `persistedQuery` extension, if present.
+    fn requested_hash(
+        extensions: Option<&serde_json::Value>,
+    ) -> Result<Option<String>, PersistedQueryError> {
+        let persisted_query = match extensions.and_then(|e| e.get("persistedQuery")) {
+            None | Some(serde_json::Value::Null) => return Ok(None),
+            Some(persisted_query) => persisted_query,
+        };
+
+        match persisted_query.get("version").and_then(|v| v.as_u64()) {
+            Some(PERSISTED_QUERY_VERSION) => (),
+            Some(version) => return Err(PersistedQueryError::UnsupportedVersion(version)),
+            None => {
+                return Err(PersistedQueryError::InvalidExtension(String::from(
+                    "\"version\" must be a number",
+                )))
+            }
+        }
+
+        persisted_query
+            .get("sha256Hash")
+            .and_then(|hash| hash.as_str())
+            .map(|hash| Some(hash.to_owned()))
+            .ok_or(PersistedQueryError::InvalidExtension(String::from(
+                "\"sha256Hash\" must be a string",
+            )))
+    }
+
+    fn cached(&self, hash: &str) -> Option<Arc<q::Document>> {
+        self.documents
+            .lock()
+            .unwrap()
+            .get_refresh(hash)
+            .map(|document| document.clone())
+    }
+
+    fn parse_and_cache(
+        &self,
+        hash: String,
+        query: &str,
+    ) -> Result<Arc<q::Document>, PersistedQueryError> {
+        let document = graphql_parser::parse_query(query)
+            .map(Arc::new)
+            .map_err(|e| PersistedQueryError::ParseError(QueryError::from(e)))?;
+
+        // Evict the least recently used documents
+        let mut documents = self.documents.lock().unwrap();
+        documents.insert(hash, document.clone());
+        while documents.len() > self.capacity {
+            documents.pop_front();
+        }
+
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::serde_json;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use graph::prelude::*;
+
+    use super::{PersistedQueries, PersistedQueryError};
+
+    const QUERY: &'static str = "{ users { name } }";
+
+    #[derive(Default)]
+    struct TestStore {
+        queries: Mutex<HashMap<String, String>>,
+    }
+
+    impl PersistedQueryStore for TestStore {
+        fn persisted_query(&self, hash: &str) -> Result<Option<String>, Error> {
+            Ok(self.queries.lock().unwrap().get(hash).cloned())
+        }
+
+        fn persist_query(&self, hash: &str, query: &str) -> Result<(), Error> {
+            self.queries
+                .lock()
+                .unwrap()
+                .insert(hash.to_owned(), query.to_owned());
+            Ok(())
+        }
+    }
+
+    fn hash_of(query: &str) -> String {
+        use hex;
+        use sha2::{Digest, Sha256};
+        hex::encode(Sha256::digest(query.as_bytes()))
+    }
+
+    fn extensions(hash: &str) -> serde_json::Value {
+        let mut persisted_query = serde_json::Map::new();
+        persisted_query.insert("version".to_owned(), serde_json::Value::from(1));
+        persisted_query.insert("sha256Hash".to_owned(), serde_json::Value::from(hash));
+        let mut extensions = serde_json::Map::new();
+        extensions.insert(
+            "persistedQuery".to_owned(),
+            serde_json::Value::Object(persisted_query),
+        );
+        serde_json::Value::Object(extensions)
+    }
+
+    #[test]
+    fn requires_full_query_for_unknown_hashes() {
+        let persisted_queries = PersistedQueries::new(10);
+        let hash = hash_of(QUERY);
+
+        match persisted_queries.document(None, Some(&extensions(&hash))) {
+            Err(PersistedQueryError::NotFound) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // Register the query, after which the hash alone is enough
+        let document = persisted_queries
+            .document(Some(QUERY), Some(&extensions(&hash)))
+            .expect("Should accept the query with its hash");
+        assert_eq!(
+            persisted_queries
+                .document(None, Some(&extensions(&hash)))
+                .expect("Should resolve the hash"),
+            document
+        );
+    }
+
+    #[test]
+    fn shares_cached_documents() {
+        let persisted_queries = PersistedQueries::new(10);
+
+        // Cache hits return the document parsed for the first request
+        let first = persisted_queries.document(Some(QUERY), None).unwrap();
+        let second = persisted_queries.document(Some(QUERY), None).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn rejects_mismatching_hashes() {
+        let persisted_queries = PersistedQueries::new(10);
+        let other_hash = hash_of("{ users { id } }");
+        match persisted_queries.document(Some(QUERY), Some(&extensions(&other_hash))) {
+            Err(PersistedQueryError::HashMismatch) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_documents() {
+        let persisted_queries = PersistedQueries::new(1);
+        let first = "{ users { name } }";
+        let second = "{ users { id } }";
+
+        persisted_queries
+            .document(Some(first), Some(&extensions(&hash_of(first))))
+            .unwrap();
+        persisted_queries
+            .document(Some(second), Some(&extensions(&hash_of(second))))
+            .unwrap();
+
+        assert!(
+            persisted_queries
+                .document(None, Some(&extensions(&hash_of(second))))
+                .is_ok()
+        );
+        match persisted_queries.document(None, Some(&extensions(&hash_of(first)))) {
+            Err(PersistedQueryError::NotFound) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_store() {
+        let store = Arc::new(TestStore::default());
+        let hash = hash_of(QUERY);
+
+        PersistedQueries::with_store(10, store.clone())
+            .document(Some(QUERY), Some(&extensions(&hash)))
+            .unwrap();
+
+        // A fresh cache finds the query in the store
+        assert!(
+            PersistedQueries::with_store(10, store)
+                .document(None, Some(&extensions(&hash)))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn persists_queries_cached_without_a_hash() {
+        let store = Arc::new(TestStore::default());
+        let persisted_queries = PersistedQueries::with_store(10, store.clone());
+        let hash = hash_of(QUERY);
+
+        // The query is cached, but not persisted, when seen without a hash
+        persisted_queries.document(Some(QUERY), None).unwrap();
+        assert!(store.queries.lock().unwrap().is_empty());
+
+        // Asking to persist it later stores it despite the cache hit
+        persisted_queries
+            .document(Some(QUERY), Some(&extensions(&hash)))
+            .unwrap();
+        assert_eq!(
+            store.queries.lock().unwrap().get(&hash),
+            Some(&String::from(QUERY))
+        );
+    }
+}
@@ -0,0 +1,1101 @@
+use graphql_parser::query as q;
+use graphql_parser::schema as s;
+use graphql_parser::Pos;
+use std::collections::{HashMap, HashSet};
+
+use graph::prelude::*;
+
+use introspection::introspection_schema;
+use query::ast as qast;
+use schema::ast as sast;
+use values::MaybeCoercible;
+
+/// A use of a variable in an argument value.
+#[derive(Clone, Debug)]
+struct VariableUsage {
+    /// The name of the variable.
+    name: q::Name,
+    /// The type expected where the variable is used.
+    expected_type: s::Type,
+    /// Whether the argument the variable is used in has a default value.
+    has_location_default: bool,
+    /// The position of the field or directive the variable is used in.
+    position: Pos,
+}
+
+/// Validates a query against its schema, following the validation rules of the
+/// GraphQL specification.
+///
+/// All violations are returned at once, each with the position in the query that
+/// it applies to. Overlapping fields are not checked for mergeability yet.
+pub fn validate_query(query: &Query) -> Result<(), Vec<QueryExecutionError>> {
+    let introspection_schema = introspection_schema();
+    let mut validator = Validator {
+        schema: &query.schema.document,
+        introspection_schema: &introspection_schema,
+        document: &query.document,
+        errors: vec![],
+    };
+
+    let fragment_usages = validator.validate_fragments();
+    validator.validate_operations(&fragment_usages, query.variables.as_ref());
+
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+struct Validator<'a> {
+    schema: &'a s::Document,
+    introspection_schema: &'a s::Document,
+    document: &'a q::Document,
+    errors: Vec<QueryExecutionError>,
+}
+
+impl<'a> Validator<'a> {
+    fn error(&mut self, position: Pos, message: String) {
+        self.errors
+            .push(QueryExecutionError::ValidationError(position, message));
+    }
+
+    /// Looks up a named type in the schema, falling back to the introspection
+    /// schema for introspection types and built-in scalars.
+    fn named_type(&self, name: &s::Name) -> Option<&'a s::TypeDefinition> {
+        sast::get_named_type(self.schema, name)
+            .or_else(|| sast::get_named_type(self.introspection_schema, name))
+    }
+
+    /// Returns the root type that an operation is executed against.
+    fn root_type(&self, operation: &q::OperationDefinition) -> Option<&'a s::TypeDefinition> {
+        let name = match operation {
+            q::OperationDefinition::SelectionSet(_) | q::OperationDefinition::Query(_) => "Query",
+            q::OperationDefinition::Mutation(_) => "Mutation",
+            q::OperationDefinition::Subscription(_) => "Subscription",
+        };
+        match sast::get_named_type(self.schema, &name.to_string()) {
+            Some(t @ s::TypeDefinition::Object(_)) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Looks up a field of an object or interface type. The root query type
+    /// also provides the `__schema` and `__type` introspection fields.
+    fn field_definition(
+        &self,
+        parent_type: &'a s::TypeDefinition,
+        name: &q::Name,
+    ) -> Option<&'a s::Field> {
+        match parent_type {
+            s::TypeDefinition::Object(t) => sast::get_field_type(t, name).or_else(|| {
+                if Some(t) == sast::get_root_query_type(self.schema) {
+                    sast::get_root_query_type(self.introspection_schema)
+                        .and_then(|query_type| sast::get_field_type(query_type, name))
+                } else {
+                    None
+                }
+            }),
+            s::TypeDefinition::Interface(t) => t.fields.iter().find(|field| &field.name == name),
+            _ => None,
+        }
+    }
+
+    /// Returns the names of all object types that values of the given type can have.
+    fn possible_types(&self, t: &s::TypeDefinition) -> HashSet<s::Name> {
+        match t {
+            s::TypeDefinition::Object(t) => vec![t.name.to_owned()].into_iter().collect(),
+            s::TypeDefinition::Interface(t) => sast::get_object_type_definitions(self.schema)
+                .into_iter()
+                .chain(sast::get_object_type_definitions(self.introspection_schema))
+                .filter(|object_type| object_type.implements_interfaces.contains(&t.name))
+                .map(|object_type| object_type.name.to_owned())
+                .collect(),
+            s::TypeDefinition::Union(t) => t.types.iter().cloned().collect(),
+            _ => HashSet::new(),
+        }
+    }
+
+    /// Validates all fragment definitions and returns the variables used by
+    /// each of them.
+    fn validate_fragments(&mut self) -> HashMap<&'a q::Name, Vec<VariableUsage>> {
+        let fragments: Vec<&'a q::FragmentDefinition> = self
+            .document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                q::Definition::Fragment(fragment) => Some(fragment),
+                _ => None,
+            }).collect();
+
+        let mut names = HashSet::new();
+        let mut usages = HashMap::new();
+
+        for &fragment in fragments.iter() {
+            // Fragment names must be unique
+            if !names.insert(&fragment.name) {
+                self.error(
+                    fragment.position,
+                    format!("There can be only one fragment named \"{}\"", fragment.name),
+                );
+            }
+
+            let mut fragment_usages = vec![];
+            self.validate_directives(
+                &fragment.directives,
+                "FRAGMENT_DEFINITION",
+                &mut fragment_usages,
+            );
+
+            // Fragments must apply to existing composite types
+            let q::TypeCondition::On(ref type_name) = fragment.type_condition;
+            match self.named_type(type_name) {
+                None => self.error(fragment.position, format!("Unknown type \"{}\"", type_name)),
+                Some(t) if !is_composite_type(t) => self.error(
+                    fragment.position,
+                    format!(
+                        "Fragment \"{}\" cannot condition on non composite type \"{}\"",
+                        fragment.name, type_name
+                    ),
+                ),
+                Some(t) => {
+                    self.validate_selection_set(t, &fragment.selection_set, &mut fragment_usages)
+                }
+            }
+
+            usages.insert(&fragment.name, fragment_usages);
+        }
+
+        // Fragments must not spread themselves, directly or indirectly
+        for &fragment in fragments.iter() {
+            let mut spreads = HashSet::new();
+            self.collect_spreads(&fragment.selection_set, &mut spreads);
+            if spreads.contains(&fragment.name) {
+                self.error(
+                    fragment.position,
+                    format!("Cannot spread fragment \"{}\" within itself", fragment.name),
+                );
+            }
+        }
+
+        // Fragments must be used by at least one operation
+        let mut used = HashSet::new();
+        for operation in qast::get_operations(self.document) {
+            self.collect_spreads(operation_parts(operation).3, &mut used);
+        }
+        for &fragment in fragments.iter() {
+            if !used.contains(&fragment.name) {
+                self.error(
+                    fragment.position,
+                    format!("Fragment \"{}\" is never used", fragment.name),
+                );
+            }
+        }
+
+        usages
+    }
+
+    /// Validates all operations of the document.
+    fn validate_operations(
+        &mut self,
+        fragment_usages: &HashMap<&'a q::Name, Vec<VariableUsage>>,
+        variables: Option<&QueryVariables>,
+    ) {
+        let operations = qast::get_operations(self.document);
+
+        // Operation names must be unique
+        let mut names = HashSet::new();
+        for operation in operations.iter() {
+            if let Some(name) = qast::get_operation_name(operation) {
+                if !names.insert(name) {
+                    self.error(
+                        operation_position(operation),
+                        format!("There can be only one operation named \"{}\"", name),
+                    );
+                }
+            }
+        }
+
+        // Anonymous operations must be the only operation in the document
+        if operations.len() > 1 {
+            for operation in operations.iter() {
+                if qast::get_operation_name(operation).is_none() {
+                    self.error(
+                        operation_position(operation),
+                        String::from(
+                            "This anonymous operation must be the only defined operation",
+                        ),
+                    );
+                }
+            }
+        }
+
+        for operation in operations {
+            self.validate_operation(operation, fragment_usages, variables);
+        }
+    }
+
+    /// Validates an operation, its selection set and its variables.
+    fn validate_operation(
+        &mut self,
+        operation: &'a q::OperationDefinition,
+        fragment_usages: &HashMap<&'a q::Name, Vec<VariableUsage>>,
+        variables: Option<&QueryVariables>,
+    ) {
+        let position = operation_position(operation);
+        let (location, variable_definitions, directives, selection_set) =
+            operation_parts(operation);
+
+        let mut usages = vec![];
+        self.validate_directives(directives, location, &mut usages);
+
+        let root_type = match self.root_type(operation) {
+            Some(t) => t,
+            None => {
+                self.error(
+                    position,
+                    format!(
+                        "Schema does not support {} operations",
+                        location.to_lowercase()
+                    ),
+                );
+                return;
+            }
+        };
+
+        // Subscriptions must select exactly one root field
+        if let q::OperationDefinition::Subscription(_) = operation {
+            let mut keys = HashSet::new();
+            self.collect_response_keys(selection_set, &mut HashSet::new(), &mut keys);
+            if keys.len() != 1 {
+                self.error(
+                    position,
+                    String::from("Subscription operations must select exactly one top-level field"),
+                );
+            }
+        }
+
+        self.validate_selection_set(root_type, selection_set, &mut usages);
+
+        // Include the variables used in all fragments spread by the operation
+        let mut spreads = HashSet::new();
+        self.collect_spreads(selection_set, &mut spreads);
+        for spread in spreads {
+            if let Some(fragment_usages) = fragment_usages.get(spread) {
+                usages.extend(fragment_usages.iter().cloned());
+            }
+        }
+
+        self.validate_variables(operation, variable_definitions, usages, variables);
+    }
+
+    /// Validates the variable definitions of an operation against the variables
+    /// used in it and the variable values provided with the query.
+    fn validate_variables(
+        &mut self,
+        operation: &'a q::OperationDefinition,
+        variable_definitions: &'a [q::VariableDefinition],
+        usages: Vec<VariableUsage>,
+        variables: Option<&QueryVariables>,
+    ) {
+        let mut defined = HashMap::new();
+
+        for definition in variable_definitions {
+            // Variable names must be unique
+            if defined.contains_key(&definition.name) {
+                self.error(
+                    definition.position,
+                    format!(
+                        "There can be only one variable named \"${}\"",
+                        definition.name
+                    ),
+                );
+            }
+            defined.insert(&definition.name, definition);
+
+            // Variables must be of existing input types
+            let type_name = base_type_name(&definition.var_type);
+            match self.named_type(type_name) {
+                None => self.error(
+                    definition.position,
+                    format!("Unknown type \"{}\"", type_name),
+                ),
+                Some(t) if !is_input_type(t) => self.error(
+                    definition.position,
+                    format!(
+                        "Variable \"${}\" cannot be non-input type \"{}\"",
+                        definition.name,
+                        type_to_string(&definition.var_type)
+                    ),
+                ),
+                Some(_) => {
+                    // Default values must be constant and of the variable's type
+                    if let Some(ref default_value) = definition.default_value {
+                        let mut default_usages = vec![];
+                        if !self.is_valid_value(
+                            default_value,
+                            &definition.var_type,
+                            false,
+                            definition.position,
+                            &mut default_usages,
+                        )
+                            || !default_usages.is_empty()
+                        {
+                            self.error(
+                                definition.position,
+                                format!(
+                                    "Variable \"${}\" has an invalid default value",
+                                    definition.name
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+
+            // Values for required variables must be provided
+            if let s::Type::NonNullType(_) = definition.var_type {
+                let provided = variables
+                    .and_then(|variables| variables.get(&definition.name))
+                    .map(|value| **value != q::Value::Null)
+                    .unwrap_or(false);
+                if definition.default_value.is_none() && !provided {
+                    self.error(
+                        definition.position,
+                        format!(
+                            "Variable \"${}\" of required type \"{}\" was not provided",
+                            definition.name,
+                            type_to_string(&definition.var_type)
+                        ),
+                    );
+                }
+            }
+        }
+
+        // Variables must be defined by the operation and be used where their
+        // type is allowed
+        let mut used = HashSet::new();
+        for usage in usages {
+            used.insert(usage.name.clone());
+            match defined.get(&usage.name) {
+                None => self.error(
+                    usage.position,
+                    match qast::get_operation_name(operation) {
+                        Some(name) => format!(
+                            "Variable \"${}\" is not defined by operation \"{}\"",
+                            usage.name, name
+                        ),
+                        None => format!("Variable \"${}\" is not defined", usage.name),
+                    },
+                ),
+                Some(definition) => if !is_variable_usage_allowed(definition, &usage) {
+                    self.error(
+                        usage.position,
+                        format!(
+                            "Variable \"${}\" of type \"{}\" used in position expecting type \"{}\"",
+                            usage.name,
+                            type_to_string(&definition.var_type),
+                            type_to_string(&usage.expected_type)
+                        ),
+                    );
+                },
+            }
+        }
+
+        // Variables must be used
+        for definition in variable_definitions {
+            if !used.contains(&definition.name) {
+                self.error(
+                    definition.position,
+                    format!("Variable \"${}\" is never used", definition.name),
+                );
+            }
+        }
+    }
+
+    /// Validates the selections of a selection set against the given parent type.
+    fn validate_selection_set(
+        &mut self,
+        parent_type: &'a s::TypeDefinition,
+        selection_set: &'a q::SelectionSet,
+        usages: &mut Vec<VariableUsage>,
+    ) {
+        for selection in selection_set.items.iter() {
+            match selection {
+                q::Selection::Field(field) => self.validate_field(parent_type, field, usages),
+
+                q::Selection::FragmentSpread(spread) => {
+                    self.validate_directives(&spread.directives, "FRAGMENT_SPREAD", usages);
+
+                    match qast::get_fragment(self.document, &spread.fragment_name) {
+                        None => self.error(
+                            spread.position,
+                            format!("Unknown fragment \"{}\"", spread.fragment_name),
+                        ),
+                        Some(fragment) => {
+                            let q::TypeCondition::On(ref type_name) = fragment.type_condition;
+                            if let Some(fragment_type) = self.named_type(type_name) {
+                                self.validate_spread_possible(
+                                    spread.position,
+                                    Some(&spread.fragment_name),
+                                    parent_type,
+                                    fragment_type,
+                                );
+                            }
+                        }
+                    }
+                }
+
+                q::Selection::InlineFragment(fragment) => {
+                    self.validate_directives(&fragment.directives, "INLINE_FRAGMENT", usages);
+
+                    let fragment_type = match fragment.type_condition {
+                        Some(q::TypeCondition::On(ref type_name)) => {
+                            match self.named_type(type_name) {
+                                None => {
+                                    self.error(
+                                        fragment.position,
+                                        format!("Unknown type \"{}\"", type_name),
+                                    );
+                                    continue;
+                                }
+                                Some(t) if !is_composite_type(t) => {
+                                    self.error(
+                                        fragment.position,
+                                        format!(
+                                            "Fragment cannot condition on non composite type \"{}\"",
+                                            type_name
+                                        ),
+                                    );
+                                    continue;
+                                }
+                                Some(t) => t,
+                            }
+                        }
+                        None => parent_type,
+                    };
+
+                    self.validate_spread_possible(
+                        fragment.position,
+                        None,
+                        parent_type,
+                        fragment_type,
+                    );
+                    self.validate_selection_set(fragment_type, &fragment.selection_set, usages);
+                }
+            }
+        }
+    }
+
+    /// Validates a field selected on the given parent type.
+    fn validate_field(
+        &mut self,
+        parent_type: &'a s::TypeDefinition,
+        field: &'a q::Field,
+        usages: &mut Vec<VariableUsage>,
+    ) {
+        self.validate_directives(&field.directives, "FIELD", usages);
+
+        // `__typename` can be selected on any composite type
+        if field.name == "__typename" {
+            let context = format!("field \"{}\"", field.name);
+            self.validate_arguments(field.position, &context, &field.arguments, &[], usages);
+            if !field.selection_set.items.is_empty() {
+                self.error(
+                    field.position,
+                    format!(
+                        "Field \"{}\" must not have a selection since type \"String!\" has no subfields",
+                        field.name
+                    ),
+                );
+            }
+            return;
+        }
+
+        // Fields must be defined on the parent type
+        let field_definition = match self.field_definition(parent_type, &field.name) {
+            Some(field_definition) => field_definition,
+            None => {
+                self.errors.push(QueryExecutionError::UnknownField(
+                    field.position,
+                    sast::get_type_name(parent_type).to_owned(),
+                    field.name.to_owned(),
+                ));
+                return;
+            }
+        };
+
+        let context = format!("field \"{}\"", field.name);
+        self.validate_arguments(
+            field.position,
+            &context,
+            &field.arguments,
+            &field_definition.arguments,
+            usages,
+        );
+
+        // Leaf fields must not have selections, all other fields must have them
+        let type_name = base_type_name(&field_definition.field_type);
+        match self.named_type(type_name) {
+            Some(t) if is_composite_type(t) => if field.selection_set.items.is_empty() {
+                self.error(
+                    field.position,
+                    format!(
+                        "Field \"{}\" of type \"{}\" must have a selection of subfields",
+                        field.name,
+                        type_to_string(&field_definition.field_type)
+                    ),
+                );
+            } else {
+                self.validate_selection_set(t, &field.selection_set, usages);
+            },
+            Some(_) => if !field.selection_set.items.is_empty() {
+                self.error(
+                    field.position,
+                    format!(
+                        "Field \"{}\" must not have a selection since type \"{}\" has no subfields",
+                        field.name,
+                        type_to_string(&field_definition.field_type)
+                    ),
+                );
+            },
+            None => self.error(field.position, format!("Unknown type \"{}\"", type_name)),
+        }
+    }
+
+    /// Validates the arguments of a field or directive against their definitions.
+    fn validate_arguments(
+        &mut self,
+        position: Pos,
+        context: &str,
+        arguments: &'a Vec<(q::Name, q::Value)>,
+        definitions: &[s::InputValue],
+        usages: &mut Vec<VariableUsage>,
+    ) {
+        let mut names = HashSet::new();
+
+        for (name, value) in arguments.iter() {
+            // Argument names must be unique
+            if !names.insert(name) {
+                self.error(
+                    position,
+                    format!("There can be only one argument named \"{}\"", name),
+                );
+            }
+
+            // Arguments must be defined and their values must be of the defined type
+            match definitions.iter().find(|definition| &definition.name == name) {
+                None => self.error(
+                    position,
+                    format!("Unknown argument \"{}\" on {}", name, context),
+                ),
+                Some(definition) => if !self.is_valid_value(
+                    value,
+                    &definition.value_type,
+                    definition.default_value.is_some(),
+                    position,
+                    usages,
+                ) {
+                    self.errors.push(QueryExecutionError::InvalidArgumentError(
+                        position,
+                        name.to_owned(),
+                        value.clone(),
+                    ));
+                },
+            }
+        }
+
+        // Required arguments must be provided
+        for definition in definitions {
+            if let s::Type::NonNullType(_) = definition.value_type {
+                if definition.default_value.is_none() && !names.contains(&definition.name) {
+                    self.errors.push(QueryExecutionError::MissingArgumentError(
+                        position,
+                        definition.name.to_owned(),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Validates the directives used at the given location.
+    fn validate_directives(
+        &mut self,
+        directives: &'a [q::Directive],
+        location: &str,
+        usages: &mut Vec<VariableUsage>,
+    ) {
+        let mut names = HashSet::new();
+
+        for directive in directives {
+            // Directives may only be used once per location
+            if !names.insert(&directive.name) {
+                self.error(
+                    directive.position,
+                    format!(
+                        "The directive \"@{}\" can only be used once at this location",
+                        directive.name
+                    ),
+                );
+            }
+
+            match directive.name.as_str() {
+                "skip" | "include" => {
+                    if !["FIELD", "FRAGMENT_SPREAD", "INLINE_FRAGMENT"].contains(&location) {
+                        self.error(
+                            directive.position,
+                            format!(
+                                "Directive \"@{}\" may not be used on {}",
+                                directive.name, location
+                            ),
+                        );
+                    }
+
+                    let context = format!("directive \"@{}\"", directive.name);
+                    self.validate_arguments(
+                        directive.position,
+                        &context,
+                        &directive.arguments,
+                        &[condition_argument()],
+                        usages,
+                    );
+                }
+                _ => self.error(
+                    directive.position,
+                    format!("Unknown directive \"@{}\"", directive.name),
+                ),
+            }
+        }
+    }
+
+    /// Validates that a fragment of the given type can apply to values of the
+    /// parent type.
+    fn validate_spread_possible(
+        &mut self,
+        position: Pos,
+        fragment_name: Option<&q::Name>,
+        parent_type: &s::TypeDefinition,
+        fragment_type: &s::TypeDefinition,
+    ) {
+        if self
+            .possible_types(parent_type)
+            .is_disjoint(&self.possible_types(fragment_type))
+        {
+            let parent_type_name = sast::get_type_name(parent_type);
+            let fragment_type_name = sast::get_type_name(fragment_type);
+            self.error(
+                position,
+                match fragment_name {
+                    Some(name) => format!(
+                        "Fragment \"{}\" cannot be spread here as objects of type \"{}\" can never be of type \"{}\"",
+                        name, parent_type_name, fragment_type_name
+                    ),
+                    None => format!(
+                        "Fragment cannot be spread here as objects of type \"{}\" can never be of type \"{}\"",
+                        parent_type_name, fragment_type_name
+                    ),
+                },
+            );
+        }
+    }
+
+    /// Returns true if a value is valid for the given type. Variables are
+    /// assumed to be valid here and recorded so they can be checked against
+    /// the operation's variable definitions.
+    fn is_valid_value(
+        &self,
+        value: &q::Value,
+        value_type: &s::Type,
+        has_location_default: bool,
+        position: Pos,
+        usages: &mut Vec<VariableUsage>,
+    ) -> bool {
+        if let q::Value::Variable(name) = value {
+            usages.push(VariableUsage {
+                name: name.to_owned(),
+                expected_type: value_type.clone(),
+                has_location_default,
+                position,
+            });
+            return true;
+        }
+
+        match value_type {
+            s::Type::NonNullType(inner_type) => match value {
+                q::Value::Null => false,
+                _ => self.is_valid_value(value, inner_type, false, position, usages),
+            },
+
+            // Single values are accepted where lists are expected
+            s::Type::ListType(inner_type) => match value {
+                q::Value::Null => true,
+                q::Value::List(values) => values.iter().fold(true, |valid, value| {
+                    self.is_valid_value(value, inner_type, false, position, usages) && valid
+                }),
+                _ => self.is_valid_value(value, inner_type, false, position, usages),
+            },
+
+            s::Type::NamedType(name) => match (value, self.named_type(name)) {
+                (q::Value::Null, _) => true,
+                (_, Some(s::TypeDefinition::Scalar(t))) => value.coerce(t).is_some(),
+                (_, Some(s::TypeDefinition::Enum(t))) => value.coerce(t).is_some(),
+                (q::Value::Object(object), Some(s::TypeDefinition::InputObject(t))) => {
+                    // All fields must be defined by the input type
+                    let fields_valid = object.iter().fold(true, |valid, (name, value)| {
+                        match t.fields.iter().find(|field| &field.name == name) {
+                            Some(field) => {
+                                self.is_valid_value(
+                                    value,
+                                    &field.value_type,
+                                    field.default_value.is_some(),
+                                    position,
+                                    usages,
+                                ) && valid
+                            }
+                            None => false,
+                        }
+                    });
+
+                    // Required fields must be provided
+                    let required_fields_present = t.fields.iter().all(|field| match field
+                        .value_type
+                    {
+                        s::Type::NonNullType(_) => {
+                            field.default_value.is_some() || object.contains_key(&field.name)
+                        }
+                        _ => true,
+                    });
+
+                    fields_valid && required_fields_present
+                }
+
+                // Enum values are shorthand for input objects with a single
+                // required field, e.g. `orderBy: name` for `orderBy: { field: name }`
+                (q::Value::Enum(_), Some(s::TypeDefinition::InputObject(t))) => {
+                    let required_fields = t
+                        .fields
+                        .iter()
+                        .filter(|field| match field.value_type {
+                            s::Type::NonNullType(_) => field.default_value.is_none(),
+                            _ => false,
+                        }).collect::<Vec<_>>();
+
+                    match required_fields.as_slice() {
+                        [field] => {
+                            self.is_valid_value(value, &field.value_type, false, position, usages)
+                        }
+                        _ => false,
+                    }
+                }
+                _ => false,
+            },
+        }
+    }
+
+    /// Collects the names of all fragments spread in a selection set, directly
+    /// or through other fragments.
+    fn collect_spreads(
+        &self,
+        selection_set: &'a q::SelectionSet,
+        spreads: &mut HashSet<&'a q::Name>,
+    ) {
+        for selection in selection_set.items.iter() {
+            match selection {
+                q::Selection::Field(field) => self.collect_spreads(&field.selection_set, spreads),
+                q::Selection::FragmentSpread(spread) => {
+                    if spreads.insert(&spread.fragment_name) {
+                        if let Some(fragment) =
+                            qast::get_fragment(self.document, &spread.fragment_name)
+                        {
+                            self.collect_spreads(&fragment.selection_set, spreads);
+                        }
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    self.collect_spreads(&fragment.selection_set, spreads)
+                }
+            }
+        }
+    }
+
+    /// Collects the response keys of the fields selected in a selection set,
+    /// including those selected through fragments.
+    fn collect_response_keys(
+        &self,
+        selection_set: &'a q::SelectionSet,
+        visited_fragments: &mut HashSet<&'a q::Name>,
+        keys: &mut HashSet<&'a q::Name>,
+    ) {
+        for selection in selection_set.items.iter() {
+            match selection {
+                q::Selection::Field(field) => {
+                    keys.insert(qast::get_response_key(field));
+                }
+                q::Selection::FragmentSpread(spread) => {
+                    if visited_fragments.insert(&spread.fragment_name) {
+                        if let Some(fragment) =
+                            qast::get_fragment(self.document, &spread.fragment_name)
+                        {
+                            self.collect_response_keys(
+                                &fragment.selection_set,
+                                visited_fragments,
+                                keys,
+                            );
+                        }
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    self.collect_response_keys(&fragment.selection_set, visited_fragments, keys)
+                }
+            }
+        }
+    }
+}
+
+/// Returns the position of an operation in the query.
+fn operation_position(operation: &q::OperationDefinition) -> Pos {
+    match operation {
+        q::OperationDefinition::SelectionSet(selection_set) => selection_set.span.0,
+        q::OperationDefinition::Query(query) => query.position,
+        q::OperationDefinition::Mutation(mutation) => mutation.position,
+        q::OperationDefinition::Subscription(subscription) => subscription.position,
+    }
+}
+
+/// Returns the directive location, variable definitions, directives and
+/// selection set of an operation.
+fn operation_parts(
+    operation: &q::OperationDefinition,
+) -> (
+    &'static str,
+    &[q::VariableDefinition],
+    &[q::Directive],
+    &q::SelectionSet,
+) {
+    match operation {
+        q::OperationDefinition::SelectionSet(selection_set) => ("QUERY", &[], &[], selection_set),
+        q::OperationDefinition::Query(query) => (
+            "QUERY",
+            &query.variable_definitions,
+            &query.directives,
+            &query.selection_set,
+        ),
+        q::OperationDefinition::Mutation(mutation) => (
+            "MUTATION",
+            &mutation.variable_definitions,
+            &mutation.directives,
+            &mutation.selection_set,
+        ),
+        q::OperationDefinition::Subscription(subscription) => (
+            "SUBSCRIPTION",
+            &subscription.variable_definitions,
+            &subscription.directives,
+            &subscription.selection_set,
+        ),
+    }
+}
+
+/// The `if: Boolean!` argument of the `@skip` and `@include` directives.
+fn condition_argument() -> s::InputValue {
+    s::InputValue {
+        position: Pos::default(),
+        description: None,
+        name: String::from("if"),
+        value_type: s::Type::NonNullType(Box::new(s::Type::NamedType(String::from("Boolean")))),
+        default_value: None,
+        directives: vec![],
+    }
+}
+
+/// Returns the name of the named type wrapped in list and non-null types.
+fn base_type_name(t: &s::Type) -> &s::Name {
+    match t {
+        s::Type::NamedType(name) => name,
+        s::Type::ListType(inner_type) => base_type_name(inner_type),
+        s::Type::NonNullType(inner_type) => base_type_name(inner_type),
+    }
+}
+
+/// Formats a type the way it is written in GraphQL documents (e.g. `[User!]!`).
+fn type_to_string(t: &s::Type) -> String {
+    match t {
+        s::Type::NamedType(name) => name.to_owned(),
+        s::Type::ListType(inner_type) => format!("[{}]", type_to_string(inner_type)),
+        s::Type::NonNullType(inner_type) => format!("{}!", type_to_string(inner_type)),
+    }
+}
+
+/// Returns true for object, interface and union types.
+fn is_composite_type(t: &s::TypeDefinition) -> bool {
+    match t {
+        s::TypeDefinition::Object(_)
+        | s::TypeDefinition::Interface(_)
+        | s::TypeDefinition::Union(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns true for scalar, enum and input object types.
+fn is_input_type(t: &s::TypeDefinition) -> bool {
+    match t {
+        s::TypeDefinition::Scalar(_)
+        | s::TypeDefinition::Enum(_)
+        | s::TypeDefinition::InputObject(_) => true,
+        _ => false,
+    }
+}
+
+/// Returns true if a variable may be used where a value of the usage's
+/// expected type is required.
+fn is_variable_usage_allowed(definition: &q::VariableDefinition, usage: &VariableUsage) -> bool {
+    if let s::Type::NonNullType(ref location_type) = usage.expected_type {
+        if let s::Type::NonNullType(_) = definition.var_type {
+        } else {
+            // Nullable variables may only be used in non-null positions if
+            // either of them provides a default value
+            let has_non_null_default = match definition.default_value {
+                Some(q::Value::Null) | None => false,
+                Some(_) => true,
+            };
+            if !has_non_null_default && !usage.has_location_default {
+                return false;
+            }
+            return are_types_compatible(&definition.var_type, location_type);
+        }
+    }
+    are_types_compatible(&definition.var_type, &usage.expected_type)
+}
+
+/// Returns true if values of the variable type can be used where values of
+/// the location type are expected.
+fn are_types_compatible(variable_type: &s::Type, location_type: &s::Type) -> bool {
+    match (variable_type, location_type) {
+        (s::Type::NonNullType(v), s::Type::NonNullType(l)) => are_types_compatible(v, l),
+        (_, s::Type::NonNullType(_)) => false,
+        (s::Type::NonNullType(v), l) => are_types_compatible(v, l),
+        (s::Type::ListType(v), s::Type::ListType(l)) => are_types_compatible(v, l),
+        (s::Type::ListType(_), _) | (_, s::Type::ListType(_)) => false,
+        (s::Type::NamedType(v), s::Type::NamedType(l)) => v == l,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser;
+
+    use graph::prelude::*;
+    use schema::api::api_schema;
+
+    use super::validate_query;
+
+    fn test_query(query: &str) -> Query {
+        Query {
+            schema: Schema {
+                name: String::from("test"),
+                id: String::from("test"),
+                document: api_schema(
+                    &graphql_parser::parse_schema(
+                        "
+                        type User {
+                            id: ID!
+                            name: String!
+                            friends: [User!]!
+                        }
+                        ",
+                    ).unwrap(),
+                ).unwrap(),
+            },
+            document: Arc::new(graphql_parser::parse_query(query).unwrap()),
+            variables: None,
+            operation_name: None,
+        }
+    }
+
+    fn error_messages(query: &str) -> Vec<String> {
+        match validate_query(&test_query(query)) {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|e| format!("{}", e)).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_queries_pass() {
+        assert_eq!(
+            error_messages(
+                "
+                query Users($skip: Int) {
+                    users(first: 10, skip: $skip, where: { name_in: [\"a\", \"b\"] }) {
+                        ...UserFields
+                        friends @skip(if: false) { id }
+                    }
+                    __schema { types { name } }
+                }
+
+                fragment UserFields on User { __typename id name }
+                ",
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn unknown_fields_and_invalid_selections_are_rejected() {
+        assert_eq!(
+            error_messages("{ users { id email name { first } friends } }"),
+            vec![
+                "Type \"User\" has no field \"email\"",
+                "Field \"name\" must not have a selection since type \"String!\" has no subfields",
+                "Field \"friends\" of type \"[User!]!\" must have a selection of subfields",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        assert_eq!(
+            error_messages("{ users(first: \"ten\", count: 1) { id } user { id } }"),
+            vec![
+                "Invalid value provided for argument \"first\": String(\"ten\")",
+                "Unknown argument \"count\" on field \"users\"",
+                "No value provided for required argument: id",
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_and_unused_fragments_and_variables_are_rejected() {
+        assert_eq!(
+            error_messages(
+                "
+                query Users($unused: Int) {
+                    users(first: $first) { ...Missing }
+                }
+
+                fragment Unused on User { id }
+                ",
+            ),
+            vec![
+                "Fragment \"Unused\" is never used",
+                "Unknown fragment \"Missing\"",
+                "Variable \"$first\" is not defined by operation \"Users\"",
+                "Variable \"$unused\" is never used",
+            ]
+        );
+    }
+
+    #[test]
+    fn required_variables_must_be_provided() {
+        assert_eq!(
+            error_messages("query User($id: ID!) { user(id: $id) { id } }"),
+            vec!["Variable \"$id\" of required type \"ID!\" was not provided"]
+        );
+    }
+
+    #[test]
+    fn anonymous_operations_must_be_alone() {
+        assert_eq!(
+            error_messages("{ users { id } } query Users { users { id } }"),
+            vec!["This anonymous operation must be the only defined operation"]
+        );
+    }
+}
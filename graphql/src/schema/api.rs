@@ -3,7 +3,7 @@ use graphql_parser::Pos;
 use inflector::Inflector;
 use std::error::Error;
 use std::fmt;
-use std::iter::IntoIterator;
+use std::iter::{self, IntoIterator};
 
 use schema::ast;
 
@@ -45,6 +45,8 @@ pub fn api_schema(input_schema: &Document) -> Result<Document, APISchemaError> {
     let mut schema = input_schema.clone();
     add_builtin_scalar_types(&mut schema)?;
     add_order_direction_enum(&mut schema);
+    add_block_height_type(&mut schema)?;
+    add_meta_types(&mut schema)?;
     add_types_for_object_types(&mut schema, &object_types)?;
     add_types_for_interface_types(&mut schema, &interface_types)?;
     add_query_type(&mut schema, &object_types, &interface_types)?;
@@ -93,7 +95,94 @@ fn add_order_direction_enum(schema: &mut Document) {
     schema.definitions.push(def);
 }
 
-/// Adds `*_orderBy` and `*_filter` enum types for the given object types to the schema.
+/// Adds a global `Block_height` input type to the schema, used to query
+/// entities as they were at a given block.
+fn add_block_height_type(schema: &mut Document) -> Result<(), APISchemaError> {
+    let type_name = String::from("Block_height");
+
+    if ast::get_named_type(schema, &type_name).is_some() {
+        return Err(APISchemaError::TypeExists(type_name));
+    }
+
+    let typedef = TypeDefinition::InputObject(InputObjectType {
+        position: Pos::default(),
+        description: None,
+        name: type_name,
+        directives: vec![],
+        fields: vec![
+            input_value(&"hash".to_string(), "", Type::NamedType("Bytes".to_string())),
+            input_value(&"number".to_string(), "", Type::NamedType("Int".to_string())),
+        ],
+    });
+    let def = Definition::TypeDefinition(typedef);
+    schema.definitions.push(def);
+    Ok(())
+}
+
+/// Adds the `_Block_` and `_Meta_` types to the schema, which describe the
+/// block a subgraph has been indexed up to in the `_meta` query field.
+fn add_meta_types(schema: &mut Document) -> Result<(), APISchemaError> {
+    let block_type_name = String::from("_Block_");
+    let meta_type_name = String::from("_Meta_");
+
+    for type_name in [&block_type_name, &meta_type_name].iter() {
+        if ast::get_named_type(schema, type_name).is_some() {
+            return Err(APISchemaError::TypeExists(type_name.to_string()));
+        }
+    }
+
+    let block_type = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: block_type_name.clone(),
+        implements_interfaces: vec![],
+        directives: vec![],
+        fields: vec![
+            meta_field("hash", Type::NamedType("Bytes".to_string())),
+            meta_field(
+                "number",
+                Type::NonNullType(Box::new(Type::NamedType("Int".to_string()))),
+            ),
+        ],
+    });
+    let meta_type = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: meta_type_name,
+        implements_interfaces: vec![],
+        directives: vec![],
+        fields: vec![
+            // The block is unknown until the subgraph has stored entities
+            meta_field("block", Type::NamedType(block_type_name)),
+            meta_field(
+                "deployment",
+                Type::NonNullType(Box::new(Type::NamedType("String".to_string()))),
+            ),
+            meta_field(
+                "hasIndexingErrors",
+                Type::NonNullType(Box::new(Type::NamedType("Boolean".to_string()))),
+            ),
+        ],
+    });
+    schema.definitions.push(Definition::TypeDefinition(block_type));
+    schema.definitions.push(Definition::TypeDefinition(meta_type));
+    Ok(())
+}
+
+/// Generates a field without arguments for the `_meta` field and its types.
+fn meta_field(name: &str, field_type: Type) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: name.to_string(),
+        arguments: vec![],
+        field_type,
+        directives: vec![],
+    }
+}
+
+/// Adds `*_orderBy`, `*_orderField`, `*_filter` and `*_aggregate` types for the given object types
+/// to the schema.
 fn add_types_for_object_types(
     schema: &mut Document,
     object_types: &Vec<&ObjectType>,
@@ -101,11 +190,12 @@ fn add_types_for_object_types(
     for object_type in object_types {
         add_order_by_type(schema, &object_type.name, &object_type.fields)?;
         add_filter_type(schema, &object_type.name, &object_type.fields)?;
+        add_aggregate_types(schema, object_type)?;
     }
     Ok(())
 }
 
-/// Adds `*_orderBy` and `*_filter` enum types for the given interfaces to the schema.
+/// Adds `*_orderBy`, `*_orderField` and `*_filter` types for the given interfaces to the schema.
 fn add_types_for_interface_types(
     schema: &mut Document,
     interface_types: &Vec<&InterfaceType>,
@@ -117,36 +207,64 @@ fn add_types_for_interface_types(
     Ok(())
 }
 
-/// Adds a `<type_name>_orderBy` enum type for the given fields to the schema.
+/// Adds a `<type_name>_orderField` enum type for the given fields and a
+/// `<type_name>_orderBy` input type, pairing one of these fields with an
+/// optional `OrderDirection`, to the schema.
 fn add_order_by_type(
     schema: &mut Document,
     type_name: &Name,
     fields: &Vec<Field>,
 ) -> Result<(), APISchemaError> {
-    let type_name = format!("{}_orderBy", type_name).to_string();
+    let field_type_name = format!("{}_orderField", type_name);
+    let order_by_type_name = format!("{}_orderBy", type_name);
 
-    match ast::get_named_type(schema, &type_name) {
-        None => {
-            let typedef = TypeDefinition::Enum(EnumType {
+    for name in [&field_type_name, &order_by_type_name].into_iter() {
+        if ast::get_named_type(schema, name).is_some() {
+            return Err(APISchemaError::TypeExists(name.to_string()));
+        }
+    }
+
+    let field_typedef = TypeDefinition::Enum(EnumType {
+        position: Pos::default(),
+        description: None,
+        name: field_type_name.clone(),
+        directives: vec![],
+        values: fields
+            .iter()
+            .map(|field| &field.name)
+            .map(|name| EnumValue {
                 position: Pos::default(),
                 description: None,
-                name: type_name,
+                name: name.to_owned(),
                 directives: vec![],
-                values: fields
-                    .iter()
-                    .map(|field| &field.name)
-                    .map(|name| EnumValue {
-                        position: Pos::default(),
-                        description: None,
-                        name: name.to_owned(),
-                        directives: vec![],
-                    }).collect(),
-            });
-            let def = Definition::TypeDefinition(typedef);
-            schema.definitions.push(def);
-        }
-        Some(_) => return Err(APISchemaError::TypeExists(type_name)),
-    }
+            }).collect(),
+    });
+    schema
+        .definitions
+        .push(Definition::TypeDefinition(field_typedef));
+
+    let order_by_typedef = TypeDefinition::InputObject(InputObjectType {
+        position: Pos::default(),
+        description: None,
+        name: order_by_type_name,
+        directives: vec![],
+        fields: vec![
+            input_value(
+                &"field".to_string(),
+                "",
+                Type::NonNullType(Box::new(Type::NamedType(field_type_name))),
+            ),
+            input_value(
+                &"direction".to_string(),
+                "",
+                Type::NamedType("OrderDirection".to_string()),
+            ),
+        ],
+    });
+    schema
+        .definitions
+        .push(Definition::TypeDefinition(order_by_typedef));
+
     Ok(())
 }
 
@@ -176,6 +294,135 @@ fn add_filter_type(
     Ok(())
 }
 
+/// Adds a `<type_name>_aggregate` object type for the given object type to the
+/// schema, along with `<type_name>_aggregateFields` and `<type_name>_avgFields`
+/// types for its numeric fields.
+///
+/// The `<type_name>_aggregate` type is marked with an `@aggregate(entity: ...,
+/// fields: [...])` directive that tells resolvers which entity type and which
+/// numeric fields it aggregates.
+fn add_aggregate_types(
+    schema: &mut Document,
+    object_type: &ObjectType,
+) -> Result<(), APISchemaError> {
+    let type_name = format!("{}_aggregate", object_type.name);
+    let fields_type_name = format!("{}_aggregateFields", object_type.name);
+    let avg_fields_type_name = format!("{}_avgFields", object_type.name);
+
+    for name in [&type_name, &fields_type_name, &avg_fields_type_name].into_iter() {
+        if ast::get_named_type(schema, name).is_some() {
+            return Err(APISchemaError::TypeExists(name.to_string()));
+        }
+    }
+
+    let numeric_fields = ast::get_numeric_fields(object_type);
+
+    let mut fields = vec![aggregate_field(
+        "count",
+        Type::NonNullType(Box::new(Type::NamedType("Int".to_string()))),
+    )];
+
+    // Only offer sums, minimums etc. if there is something to aggregate
+    if !numeric_fields.is_empty() {
+        // Sums, minimums and maximums of `Int` fields may not fit into an `Int`
+        let fields_typedef = TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: fields_type_name.clone(),
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields: numeric_fields
+                .iter()
+                .map(|field| match ast::get_single_value_type_name(&field.field_type) {
+                    Some(name) if name == "Float" => {
+                        aggregate_field(&field.name, Type::NamedType("Float".to_string()))
+                    }
+                    _ => aggregate_field(&field.name, Type::NamedType("BigInt".to_string())),
+                }).collect(),
+        });
+        schema
+            .definitions
+            .push(Definition::TypeDefinition(fields_typedef));
+
+        let avg_fields_typedef = TypeDefinition::Object(ObjectType {
+            position: Pos::default(),
+            description: None,
+            name: avg_fields_type_name.clone(),
+            implements_interfaces: vec![],
+            directives: vec![],
+            fields: numeric_fields
+                .iter()
+                .map(|field| aggregate_field(&field.name, Type::NamedType("Float".to_string())))
+                .collect(),
+        });
+        schema
+            .definitions
+            .push(Definition::TypeDefinition(avg_fields_typedef));
+
+        for name in ["sum", "min", "max"].into_iter() {
+            fields.push(aggregate_field(
+                name,
+                Type::NamedType(fields_type_name.clone()),
+            ));
+        }
+        fields.push(aggregate_field(
+            "avg",
+            Type::NamedType(avg_fields_type_name.clone()),
+        ));
+    }
+
+    // Resolvers need to know which subgraph the aggregated entities belong to
+    let mut directives: Vec<Directive> = object_type
+        .directives
+        .iter()
+        .filter(|directive| directive.name == "subgraphId")
+        .cloned()
+        .collect();
+    directives.push(Directive {
+        position: Pos::default(),
+        name: "aggregate".to_string(),
+        arguments: vec![
+            (
+                "entity".to_string(),
+                Value::String(object_type.name.to_owned()),
+            ),
+            (
+                "fields".to_string(),
+                Value::List(
+                    numeric_fields
+                        .iter()
+                        .map(|field| Value::String(field.name.to_owned()))
+                        .collect(),
+                ),
+            ),
+        ],
+    });
+
+    let typedef = TypeDefinition::Object(ObjectType {
+        position: Pos::default(),
+        description: None,
+        name: type_name,
+        implements_interfaces: vec![],
+        directives,
+        fields,
+    });
+    schema.definitions.push(Definition::TypeDefinition(typedef));
+
+    Ok(())
+}
+
+/// Generates a field without arguments for an aggregate type.
+fn aggregate_field(name: &str, field_type: Type) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: name.to_string(),
+        arguments: vec![],
+        field_type,
+        directives: vec![],
+    }
+}
+
 /// Generates `*_filter` input values for the given set of fields.
 fn field_input_values(schema: &Document, fields: &Vec<Field>) -> Vec<InputValue> {
     fields
@@ -197,6 +444,7 @@ fn field_filter_input_values(
             match named_type {
                 TypeDefinition::Scalar(ref t) => field_scalar_filter_input_values(schema, field, t),
                 TypeDefinition::Enum(ref t) => field_enum_filter_input_values(schema, field, t),
+                TypeDefinition::Object(ref t) => field_child_filter_input_values(schema, field, t),
                 _ => vec![],
             }
         }
@@ -271,11 +519,38 @@ fn field_enum_filter_input_values(
 
 /// Generates `*_filter` input values for the given list field.
 fn field_list_filter_input_values(
+    schema: &Document,
+    field: &Field,
+    field_type: &Type,
+) -> Vec<InputValue> {
+    match ast::get_type_definition_from_type(schema, field_type) {
+        Some(TypeDefinition::Object(ref t)) => field_child_filter_input_values(schema, field, t),
+        _ => vec![],
+    }
+}
+
+/// Generates a `*_` input value for filtering by the entities that the given
+/// reference field points to, e.g. `token_: Token_filter`.
+///
+/// Derived fields are not stored on the entity and can't be filtered by.
+fn field_child_filter_input_values(
     _schema: &Document,
-    _field: &Field,
-    _field_type: &Type,
+    field: &Field,
+    field_type: &ObjectType,
 ) -> Vec<InputValue> {
-    vec![]
+    if field
+        .directives
+        .iter()
+        .any(|directive| directive.name == "derivedFrom")
+    {
+        return vec![];
+    }
+
+    vec![input_value(
+        &format!("{}_", field.name),
+        "",
+        Type::NamedType(format!("{}_filter", field_type.name)),
+    )]
 }
 
 /// Generates a `*_filter` input value for the given field name, suffix and value type.
@@ -317,7 +592,26 @@ fn add_query_type(
             .map(|t| &t.name)
             .chain(interface_types.iter().map(|t| &t.name))
             .flat_map(|name| query_fields_for_type(schema, name))
-            .collect(),
+            .chain(
+                object_types
+                    .iter()
+                    .map(|t| aggregate_query_field_for_type(schema, &t.name)),
+            ).chain(
+                object_types
+                    .iter()
+                    .flat_map(|t| fulltext_query_fields_for_type(schema, t)),
+            ).map(|mut field| {
+                // Allow all root query fields to be resolved at a given block
+                field.arguments.push(input_value(
+                    &"block".to_string(),
+                    "",
+                    Type::NamedType("Block_height".to_string()),
+                ));
+                field
+            }).chain(iter::once(meta_field(
+                "_meta",
+                Type::NonNullType(Box::new(Type::NamedType("_Meta_".to_string()))),
+            ))).collect(),
     });
     let def = Definition::TypeDefinition(typedef);
     schema.definitions.push(def);
@@ -385,7 +679,9 @@ fn query_fields_for_type(_schema: &Document, type_name: &Name) -> Vec<Field> {
                 input_value(
                     &"orderBy".to_string(),
                     "",
-                    Type::NamedType(format!("{}_orderBy", type_name)),
+                    Type::ListType(Box::new(Type::NonNullType(Box::new(Type::NamedType(
+                        format!("{}_orderBy", type_name),
+                    ))))),
                 ),
                 input_value(
                     &"orderDirection".to_string(),
@@ -406,6 +702,72 @@ fn query_fields_for_type(_schema: &Document, type_name: &Name) -> Vec<Field> {
     ]
 }
 
+/// Generates an aggregate `Query` field for the given object type name
+/// (e.g. `usersAggregate`).
+fn aggregate_query_field_for_type(_schema: &Document, type_name: &Name) -> Field {
+    Field {
+        position: Pos::default(),
+        description: None,
+        name: format!("{}Aggregate", type_name.to_plural().to_camel_case()),
+        arguments: vec![input_value(
+            &"where".to_string(),
+            "",
+            Type::NamedType(format!("{}_filter", type_name)),
+        )],
+        field_type: Type::NonNullType(Box::new(Type::NamedType(format!(
+            "{}_aggregate",
+            type_name
+        )))),
+        directives: vec![],
+    }
+}
+
+/// Generates `Query` fields for the full-text searches declared on the given
+/// object type with `@fulltext` directives.
+///
+/// Each field is marked with a `@fulltext(fields: [...])` directive that tells
+/// resolvers which fields to search.
+fn fulltext_query_fields_for_type(_schema: &Document, object_type: &ObjectType) -> Vec<Field> {
+    ast::get_fulltext_definitions(object_type)
+        .into_iter()
+        .map(|definition| Field {
+            position: Pos::default(),
+            description: None,
+            name: definition.name,
+            arguments: vec![
+                input_value(
+                    &"text".to_string(),
+                    "",
+                    Type::NonNullType(Box::new(Type::NamedType("String".to_string()))),
+                ),
+                input_value(&"skip".to_string(), "", Type::NamedType("Int".to_string())),
+                input_value(&"first".to_string(), "", Type::NamedType("Int".to_string())),
+                input_value(
+                    &"where".to_string(),
+                    "",
+                    Type::NamedType(format!("{}_filter", object_type.name)),
+                ),
+            ],
+            field_type: Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+                Box::new(Type::NamedType(object_type.name.to_owned())),
+            ))))),
+            directives: vec![Directive {
+                position: Pos::default(),
+                name: "fulltext".to_string(),
+                arguments: vec![(
+                    "fields".to_string(),
+                    Value::List(
+                        definition
+                            .fields
+                            .into_iter()
+                            .map(|field| Value::String(field))
+                            .collect(),
+                    ),
+                )],
+            }],
+        }).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use graphql_parser::schema::*;
@@ -447,6 +809,52 @@ mod tests {
         assert_eq!(values, [&"asc".to_string(), &"desc".to_string()]);
     }
 
+    #[test]
+    fn api_schema_contains_block_height_input_type() {
+        let input_schema =
+            parse_schema("type User { id: ID! }").expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let block_height = ast::get_named_type(&schema, &"Block_height".to_string())
+            .expect("Block_height type is missing in derived API schema");
+        let input_type = match block_height {
+            TypeDefinition::InputObject(t) => Some(t),
+            _ => None,
+        }.expect("Block_height type is not an input object");
+
+        let fields: Vec<&Name> = input_type.fields.iter().map(|field| &field.name).collect();
+        assert_eq!(fields, [&"hash".to_string(), &"number".to_string()]);
+    }
+
+    #[test]
+    fn api_schema_contains_meta_field() {
+        let input_schema =
+            parse_schema("type User { id: ID! }").expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let query_type = ast::get_root_query_type(&schema).expect("Query type is missing");
+        let meta_field = ast::get_field_type(query_type, &"_meta".to_string())
+            .expect("_meta field is missing on the Query type");
+        assert_eq!(
+            meta_field.field_type,
+            Type::NonNullType(Box::new(Type::NamedType("_Meta_".to_string())))
+        );
+
+        let meta_type = match ast::get_named_type(&schema, &"_Meta_".to_string()) {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("_Meta_ type is missing in derived API schema"),
+        };
+        let fields: Vec<&Name> = meta_type.fields.iter().map(|field| &field.name).collect();
+        assert_eq!(
+            fields,
+            [
+                &"block".to_string(),
+                &"deployment".to_string(),
+                &"hasIndexingErrors".to_string()
+            ]
+        );
+    }
+
     #[test]
     fn api_schema_contains_query_type() {
         let input_schema =
@@ -457,21 +865,47 @@ mod tests {
     }
 
     #[test]
-    fn api_schema_contains_field_order_by_enum() {
+    fn api_schema_contains_field_order_by_types() {
         let input_schema = parse_schema("type User { id: ID!, name: String! }")
             .expect("Failed to parse input schema");
         let schema = api_schema(&input_schema).expect("Failed to derived API schema");
 
-        let user_order_by = ast::get_named_type(&schema, &"User_orderBy".to_string())
-            .expect("User_orderBy type is missing in derived API schema");
+        let user_order_field = ast::get_named_type(&schema, &"User_orderField".to_string())
+            .expect("User_orderField type is missing in derived API schema");
 
-        let enum_type = match user_order_by {
+        let enum_type = match user_order_field {
             TypeDefinition::Enum(t) => Some(t),
             _ => None,
-        }.expect("User_orderBy type is not an enum");
+        }.expect("User_orderField type is not an enum");
 
         let values: Vec<&Name> = enum_type.values.iter().map(|value| &value.name).collect();
         assert_eq!(values, [&"id".to_string(), &"name".to_string()]);
+
+        let user_order_by = ast::get_named_type(&schema, &"User_orderBy".to_string())
+            .expect("User_orderBy type is missing in derived API schema");
+
+        let input_type = match user_order_by {
+            TypeDefinition::InputObject(t) => Some(t),
+            _ => None,
+        }.expect("User_orderBy type is not an input object");
+
+        assert_eq!(
+            input_type
+                .fields
+                .iter()
+                .map(|field| (field.name.to_owned(), field.value_type.to_owned()))
+                .collect::<Vec<(String, Type)>>(),
+            vec![
+                (
+                    "field".to_string(),
+                    Type::NonNullType(Box::new(Type::NamedType("User_orderField".to_string()))),
+                ),
+                (
+                    "direction".to_string(),
+                    Type::NamedType("OrderDirection".to_string()),
+                ),
+            ]
+        );
     }
 
     #[test]
@@ -550,7 +984,7 @@ mod tests {
                 .iter()
                 .map(|input_value| input_value.name.to_owned())
                 .collect::<Vec<String>>(),
-            vec!["id".to_string()],
+            vec!["id".to_string(), "block".to_string()],
         );
 
         let user_plural_field = match query_type {
@@ -580,6 +1014,7 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "block",
             ]
                 .into_iter()
                 .map(|name| name.to_string())
@@ -638,7 +1073,7 @@ mod tests {
                 .iter()
                 .map(|input_value| input_value.name.to_owned())
                 .collect::<Vec<String>>(),
-            vec!["id".to_string()],
+            vec!["id".to_string(), "block".to_string()],
         );
 
         let plural_field = match query_type {
@@ -668,10 +1103,264 @@ mod tests {
                 "orderBy",
                 "orderDirection",
                 "where",
+                "block",
+            ]
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<String>>()
+        );
+    }
+
+    #[test]
+    fn api_schema_contains_aggregate_types() {
+        let input_schema = parse_schema(
+            "type Token { id: ID!, name: String!, decimals: Int!, amount: BigInt, price: Float, tags: [Int!]! }",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let aggregate_type = match ast::get_named_type(&schema, &"Token_aggregate".to_string()) {
+            Some(TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        }.expect("Token_aggregate type is missing in derived API schema");
+
+        assert_eq!(
+            aggregate_type
+                .fields
+                .iter()
+                .map(|field| field.name.to_owned())
+                .collect::<Vec<String>>(),
+            ["count", "sum", "min", "max", "avg"]
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<String>>()
+        );
+
+        let directive = ast::get_object_type_directive(aggregate_type, "aggregate".to_string())
+            .expect("Token_aggregate type is missing the @aggregate directive");
+        assert_eq!(
+            directive.arguments,
+            vec![
+                ("entity".to_string(), Value::String("Token".to_string())),
+                (
+                    "fields".to_string(),
+                    Value::List(vec![
+                        Value::String("decimals".to_string()),
+                        Value::String("amount".to_string()),
+                        Value::String("price".to_string()),
+                    ])
+                ),
             ]
+        );
+
+        let fields_type = match ast::get_named_type(&schema, &"Token_aggregateFields".to_string())
+        {
+            Some(TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        }.expect("Token_aggregateFields type is missing in derived API schema");
+
+        assert_eq!(
+            fields_type
+                .fields
+                .iter()
+                .map(|field| (field.name.to_owned(), field.field_type.clone()))
+                .collect::<Vec<(String, Type)>>(),
+            vec![
+                (
+                    "decimals".to_string(),
+                    Type::NamedType("BigInt".to_string())
+                ),
+                ("amount".to_string(), Type::NamedType("BigInt".to_string())),
+                ("price".to_string(), Type::NamedType("Float".to_string())),
+            ]
+        );
+
+        let avg_fields_type = match ast::get_named_type(&schema, &"Token_avgFields".to_string()) {
+            Some(TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        }.expect("Token_avgFields type is missing in derived API schema");
+
+        assert!(
+            avg_fields_type
+                .fields
+                .iter()
+                .all(|field| field.field_type == Type::NamedType("Float".to_string()))
+        );
+    }
+
+    #[test]
+    fn api_schema_contains_only_count_for_types_without_numeric_fields() {
+        let input_schema = parse_schema("type User { id: ID!, name: String! }")
+            .expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let aggregate_type = match ast::get_named_type(&schema, &"User_aggregate".to_string()) {
+            Some(TypeDefinition::Object(t)) => Some(t),
+            _ => None,
+        }.expect("User_aggregate type is missing in derived API schema");
+
+        assert_eq!(
+            aggregate_type
+                .fields
+                .iter()
+                .map(|field| field.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["count".to_string()]
+        );
+        assert!(ast::get_named_type(&schema, &"User_aggregateFields".to_string()).is_none());
+        assert!(ast::get_named_type(&schema, &"User_avgFields".to_string()).is_none());
+    }
+
+    #[test]
+    fn api_schema_contains_aggregate_fields_on_query_type() {
+        let input_schema = parse_schema(
+            "
+            interface Node { id: ID! }
+            type User implements Node { id: ID!, name: String! }
+            ",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let query_type = ast::get_named_type(&schema, &"Query".to_string())
+            .expect("Query type is missing in derived API schema");
+
+        let aggregate_field = match query_type {
+            TypeDefinition::Object(t) => ast::get_field_type(t, &"usersAggregate".to_string()),
+            _ => None,
+        }.expect("\"usersAggregate\" field is missing on Query type");
+
+        assert_eq!(
+            aggregate_field.field_type,
+            Type::NonNullType(Box::new(Type::NamedType("User_aggregate".to_string())))
+        );
+        assert_eq!(
+            aggregate_field
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.to_owned())
+                .collect::<Vec<String>>(),
+            vec!["where".to_string(), "block".to_string()],
+        );
+
+        // Interfaces are not aggregated
+        match query_type {
+            TypeDefinition::Object(t) => {
+                assert!(ast::get_field_type(t, &"nodesAggregate".to_string()).is_none())
+            }
+            _ => unreachable!(),
+        }
+
+        // Aggregates are not available in subscriptions
+        match ast::get_named_type(&schema, &"Subscription".to_string()) {
+            Some(TypeDefinition::Object(t)) => {
+                assert!(ast::get_field_type(t, &"usersAggregate".to_string()).is_none())
+            }
+            _ => panic!("Subscription type is missing in derived API schema"),
+        }
+    }
+
+    #[test]
+    fn api_schema_contains_fulltext_fields_on_query_type() {
+        let input_schema = parse_schema(
+            "
+            type Token @fulltext(name: \"tokenSearch\", fields: [\"name\", \"symbol\"]) {
+                id: ID!
+                name: String!
+                symbol: String!
+            }
+            ",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let query_type = ast::get_named_type(&schema, &"Query".to_string())
+            .expect("Query type is missing in derived API schema");
+
+        let search_field = match query_type {
+            TypeDefinition::Object(t) => ast::get_field_type(t, &"tokenSearch".to_string()),
+            _ => None,
+        }.expect("\"tokenSearch\" field is missing on Query type");
+
+        assert_eq!(
+            search_field.field_type,
+            Type::NonNullType(Box::new(Type::ListType(Box::new(Type::NonNullType(
+                Box::new(Type::NamedType("Token".to_string()))
+            )))))
+        );
+        assert_eq!(
+            search_field
+                .arguments
+                .iter()
+                .map(|input_value| input_value.name.to_owned())
+                .collect::<Vec<String>>(),
+            ["text", "skip", "first", "where", "block"]
                 .into_iter()
                 .map(|name| name.to_string())
                 .collect::<Vec<String>>()
         );
+        assert_eq!(
+            search_field.directives[0].arguments,
+            vec![(
+                "fields".to_string(),
+                Value::List(vec![
+                    Value::String("name".to_string()),
+                    Value::String("symbol".to_string()),
+                ])
+            )]
+        );
+    }
+
+    #[test]
+    fn api_schema_contains_child_filters_for_reference_fields() {
+        let input_schema = parse_schema(
+            "
+            type Token { id: ID!, symbol: String! }
+            type Transfer {
+                id: ID!
+                token: Token!
+                tokens: [Token!]!
+            }
+            type Account {
+                id: ID!
+                transfers: [Transfer!]! @derivedFrom(field: \"account\")
+            }
+            ",
+        ).expect("Failed to parse input schema");
+        let schema = api_schema(&input_schema).expect("Failed to derive API schema");
+
+        let transfer_filter = match ast::get_named_type(&schema, &"Transfer_filter".to_string()) {
+            Some(TypeDefinition::InputObject(t)) => Some(t),
+            _ => None,
+        }.expect("Transfer_filter type is missing in derived API schema");
+
+        let child_filters = transfer_filter
+            .fields
+            .iter()
+            .filter(|field| field.name.ends_with("_"))
+            .map(|field| (field.name.to_owned(), field.value_type.clone()))
+            .collect::<Vec<(String, Type)>>();
+        assert_eq!(
+            child_filters,
+            vec![
+                (
+                    "token_".to_string(),
+                    Type::NamedType("Token_filter".to_string())
+                ),
+                (
+                    "tokens_".to_string(),
+                    Type::NamedType("Token_filter".to_string())
+                ),
+            ]
+        );
+
+        let account_filter = match ast::get_named_type(&schema, &"Account_filter".to_string()) {
+            Some(TypeDefinition::InputObject(t)) => Some(t),
+            _ => None,
+        }.expect("Account_filter type is missing in derived API schema");
+
+        assert!(
+            account_filter
+                .fields
+                .iter()
+                .all(|field| field.name != "transfers_")
+        );
     }
 }
@@ -104,6 +104,28 @@ pub fn get_field_type<'a>(object_type: &'a ObjectType, name: &Name) -> Option<&'
     object_type.fields.iter().find(|field| &field.name == name)
 }
 
+/// Returns the name of a single-valued field type, e.g. `Int` for `Int` or `Int!`.
+/// Returns `None` for list types.
+pub fn get_single_value_type_name(field_type: &Type) -> Option<&Name> {
+    match field_type {
+        Type::NamedType(name) => Some(name),
+        Type::NonNullType(inner) => get_single_value_type_name(inner),
+        Type::ListType(_) => None,
+    }
+}
+
+/// Returns the fields of an object type that hold a single `Int`, `Float` or
+/// `BigInt` value.
+pub fn get_numeric_fields(object_type: &ObjectType) -> Vec<&Field> {
+    object_type
+        .fields
+        .iter()
+        .filter(|field| match get_single_value_type_name(&field.field_type) {
+            Some(name) => name == "Int" || name == "Float" || name == "BigInt",
+            None => false,
+        }).collect()
+}
+
 /// Returns the type with the given name.
 pub fn get_named_type<'a>(schema: &'a Document, name: &Name) -> Option<&'a TypeDefinition> {
     schema
@@ -181,3 +203,53 @@ pub fn get_object_type_directive(object_type: &ObjectType, name: Name) -> Option
         .iter()
         .find(|directive| directive.name == name)
 }
+
+/// A full-text search over some fields of an object type, declared with a
+/// `@fulltext(name: "...", fields: [...])` directive on the type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FulltextDefinition {
+    /// The name of the root query field that performs the search.
+    pub name: Name,
+
+    /// The fields to search.
+    pub fields: Vec<Name>,
+}
+
+/// Returns the full-text searches declared on an object type. Directives
+/// without a `name` or `fields` argument are ignored.
+pub fn get_fulltext_definitions(object_type: &ObjectType) -> Vec<FulltextDefinition> {
+    object_type
+        .directives
+        .iter()
+        .filter(|directive| directive.name == "fulltext")
+        .filter_map(|directive| {
+            let name = directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "name")
+                .and_then(|(_, value)| match value {
+                    Value::String(name) => Some(name.to_owned()),
+                    _ => None,
+                });
+            let fields = directive
+                .arguments
+                .iter()
+                .find(|(name, _)| name == "fields")
+                .and_then(|(_, value)| match value {
+                    Value::List(values) => Some(
+                        values
+                            .iter()
+                            .filter_map(|value| match value {
+                                Value::String(field) => Some(field.to_owned()),
+                                _ => None,
+                            }).collect(),
+                    ),
+                    _ => None,
+                });
+
+            match (name, fields) {
+                (Some(name), Some(fields)) => Some(FulltextDefinition { name, fields }),
+                _ => None,
+            }
+        }).collect()
+}
@@ -21,11 +21,14 @@ pub enum SchemaValidationError {
         _0
     )]
     EntityDirectivesMissing(Strings),
+    #[fail(display = "Invalid @fulltext directive on type {}: {}", _0, _1)]
+    FulltextDirectiveInvalid(String, String),
 }
 
 /// Validates whether a GraphQL schema is compatible with The Graph.
 pub fn validate_schema(schema: &Document) -> Result<(), Error> {
     validate_schema_types(schema)?;
+    validate_fulltext_directives(schema)?;
     Ok(())
 }
 
@@ -47,3 +50,60 @@ fn validate_schema_types(schema: &Document) -> Result<(), SchemaValidationError>
         )))
     }
 }
+
+/// Validates that all `@fulltext` directives have a unique name and only
+/// search existing `String` fields of their type.
+fn validate_fulltext_directives(schema: &Document) -> Result<(), SchemaValidationError> {
+    use self::SchemaValidationError::*;
+
+    let mut names = vec![];
+
+    for object_type in ast::get_object_type_definitions(schema) {
+        let directive_count = object_type
+            .directives
+            .iter()
+            .filter(|directive| directive.name == "fulltext")
+            .count();
+        let definitions = ast::get_fulltext_definitions(object_type);
+
+        if definitions.len() != directive_count {
+            return Err(FulltextDirectiveInvalid(
+                object_type.name.to_owned(),
+                String::from("a `name` and a list of `fields` are required"),
+            ));
+        }
+
+        for definition in definitions {
+            if names.contains(&definition.name) {
+                return Err(FulltextDirectiveInvalid(
+                    object_type.name.to_owned(),
+                    format!("name `{}` is used more than once", definition.name),
+                ));
+            }
+
+            if definition.fields.is_empty() {
+                return Err(FulltextDirectiveInvalid(
+                    object_type.name.to_owned(),
+                    String::from("at least one field is required"),
+                ));
+            }
+
+            for field_name in definition.fields.iter() {
+                let is_string_field = ast::get_field_type(object_type, field_name)
+                    .and_then(|field| ast::get_single_value_type_name(&field.field_type))
+                    .map_or(false, |type_name| type_name == "String");
+
+                if !is_string_field {
+                    return Err(FulltextDirectiveInvalid(
+                        object_type.name.to_owned(),
+                        format!("`{}` is not a `String` field", field_name),
+                    ));
+                }
+            }
+
+            names.push(definition.name);
+        }
+    }
+
+    Ok(())
+}
@@ -1,18 +1,26 @@
 use graph::prelude::*;
+use graph::web3::types::H256;
 use graphql_parser::{query as q, schema as s};
 use schema::ast as sast;
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::iter;
 
 /// Builds a StoreQuery from GraphQL arguments.
-pub fn build_query(entity: &s::ObjectType, arguments: &HashMap<&q::Name, q::Value>) -> StoreQuery {
+///
+/// The schema is used to look up the entity types referenced in nested filters.
+pub fn build_query(
+    schema: &s::Document,
+    entity: &s::ObjectType,
+    arguments: &HashMap<&q::Name, q::Value>,
+) -> StoreQuery {
     StoreQuery {
         subgraph: parse_subgraph_id(entity)
             .expect(format!("Failed to get subgraph ID from type: {}", entity.name).as_str()),
         entity: entity.name.to_owned(),
         range: build_range(arguments),
-        filter: build_filter(entity, arguments),
+        filter: build_filter(schema, entity, arguments),
         order_by: build_order_by(arguments),
-        order_direction: build_order_direction(arguments),
+        block: build_block(arguments),
     }
 }
 
@@ -42,6 +50,7 @@ fn build_range(arguments: &HashMap<&q::Name, q::Value>) -> Option<StoreRange> {
 
 /// Parses GraphQL arguments into a StoreFilter, if present.
 fn build_filter(
+    schema: &s::Document,
     entity: &s::ObjectType,
     arguments: &HashMap<&q::Name, q::Value>,
 ) -> Option<StoreFilter> {
@@ -50,11 +59,12 @@ fn build_filter(
         .and_then(|value| match value {
             q::Value::Object(object) => Some(object),
             _ => None,
-        }).map(|object| build_filter_from_object(entity, object))
+        }).map(|object| build_filter_from_object(schema, entity, object))
 }
 
 /// Parses a GraphQL input object into a StoreFilter, if present.
 fn build_filter_from_object(
+    schema: &s::Document,
     entity: &s::ObjectType,
     object: &BTreeMap<q::Name, q::Value>,
 ) -> StoreFilter {
@@ -64,6 +74,11 @@ fn build_filter_from_object(
             .map(|(key, value)| {
                 use self::sast::FilterOp::*;
 
+                // Filters on referenced entities, e.g. `token_: { symbol: "DAI" }`
+                if let Some(filter) = build_child_filter(schema, entity, key, value) {
+                    return filter;
+                }
+
                 let (attribute, op) = sast::parse_field_as_filter(key);
 
                 let field = sast::get_field_type(entity, &attribute)
@@ -91,6 +106,35 @@ fn build_filter_from_object(
     )
 }
 
+/// Parses a nested filter on the entities referenced by a field (e.g. `token_`)
+/// into a `StoreFilter::Child`. Returns `None` if the key is not such a filter.
+fn build_child_filter(
+    schema: &s::Document,
+    entity: &s::ObjectType,
+    key: &q::Name,
+    value: &q::Value,
+) -> Option<StoreFilter> {
+    // Fields whose names end in `_` are filtered as usual
+    if !key.ends_with("_") || sast::get_field_type(entity, key).is_some() {
+        return None;
+    }
+
+    let field = sast::get_field_type(entity, &key.trim_right_matches("_").to_owned())?;
+    let child_entity = match sast::get_type_definition_from_field_type(schema, field) {
+        Some(s::TypeDefinition::Object(t)) => t,
+        _ => return None,
+    };
+
+    match value {
+        q::Value::Object(object) => Some(StoreFilter::Child(
+            field.name.to_owned(),
+            child_entity.name.to_owned(),
+            Box::new(build_filter_from_object(schema, child_entity, object)),
+        )),
+        _ => None,
+    }
+}
+
 /// Parses a list of GraphQL values into a vector of entity attribute values.
 fn list_values(value: Value) -> Vec<Value> {
     match value {
@@ -99,25 +143,74 @@ fn list_values(value: Value) -> Vec<Value> {
     }
 }
 
-/// Parses GraphQL arguments into an attribute name to order by, if present.
-fn build_order_by(arguments: &HashMap<&q::Name, q::Value>) -> Option<String> {
-    arguments
-        .get(&"orderBy".to_string())
-        .and_then(|value| match value {
-            q::Value::Enum(name) => Some(name.to_owned()),
+/// Parses GraphQL arguments into the attributes to order by, each with
+/// its direction.
+///
+/// `orderBy` is a list of `{ field, direction }` objects; entries without
+/// a direction fall back to `orderDirection` and then to ascending order.
+/// A bare field name, the form `orderBy` had before it accepted several
+/// attributes, is treated like an entry without a direction.
+fn build_order_by(arguments: &HashMap<&q::Name, q::Value>) -> Vec<(String, StoreOrder)> {
+    let default_direction =
+        build_order_direction(arguments.get(&"orderDirection".to_string()))
+            .unwrap_or(StoreOrder::Ascending);
+
+    let entries = match arguments.get(&"orderBy".to_string()) {
+        Some(q::Value::List(values)) => values.iter().collect(),
+        Some(value @ q::Value::Object(_)) | Some(value @ q::Value::Enum(_)) => vec![value],
+        _ => vec![],
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            q::Value::Object(object) => match object.get("field") {
+                Some(q::Value::Enum(name)) => Some((
+                    name.to_owned(),
+                    build_order_direction(object.get("direction"))
+                        .unwrap_or_else(|| default_direction.clone()),
+                )),
+                _ => None,
+            },
+            q::Value::Enum(name) => Some((name.to_owned(), default_direction.clone())),
             _ => None,
-        })
+        }).collect()
+}
+
+/// Parses an `OrderDirection` value into a StoreOrder, if possible.
+fn build_order_direction(value: Option<&q::Value>) -> Option<StoreOrder> {
+    value.and_then(|value| match value {
+        q::Value::Enum(name) if name == "asc" => Some(StoreOrder::Ascending),
+        q::Value::Enum(name) if name == "desc" => Some(StoreOrder::Descending),
+        _ => None,
+    })
 }
 
-/// Parses GraphQL arguments into a StoreOrder, if present.
-fn build_order_direction(arguments: &HashMap<&q::Name, q::Value>) -> Option<StoreOrder> {
+/// Parses GraphQL arguments into a StoreBlock, if present.
+fn build_block(arguments: &HashMap<&q::Name, q::Value>) -> Option<StoreBlock> {
     arguments
-        .get(&"orderDirection".to_string())
-        .and_then(|value| match value {
-            q::Value::Enum(name) if name == "asc" => Some(StoreOrder::Ascending),
-            q::Value::Enum(name) if name == "desc" => Some(StoreOrder::Descending),
+        .get(&"block".to_string())
+        .and_then(|value| parse_block(value))
+}
+
+/// Parses a `Block_height` input object (e.g. `{ number: 123 }` or
+/// `{ hash: "0x..." }`) into a StoreBlock.
+pub fn parse_block(value: &q::Value) -> Option<StoreBlock> {
+    match value {
+        q::Value::Object(object) => match (object.get("number"), object.get("hash")) {
+            (Some(q::Value::Int(n)), None) => n
+                .as_i64()
+                .and_then(|n| if n >= 0 { Some(n as u64) } else { None })
+                .map(StoreBlock::Number),
+            (None, Some(q::Value::String(hash))) => hash
+                .trim_left_matches("0x")
+                .parse::<H256>()
+                .ok()
+                .map(StoreBlock::Hash),
             _ => None,
-        })
+        },
+        _ => None,
+    }
 }
 
 /// Parses the subgraph ID from the ObjectType directives.
@@ -138,14 +231,19 @@ pub fn parse_subgraph_id(entity: &s::ObjectType) -> Option<String> {
         })
 }
 
-/// Recursively collects entities involved in a query field as `(subgraph ID, name)` tuples.
-pub fn collect_entities_from_query_field(
+/// Recursively collects filters for the entity changes that may affect the
+/// result of a query field.
+///
+/// Changes to an entity type are restricted to specific IDs if every field of
+/// that type in the query selects entities by literal IDs, e.g. `user(id: "1")`
+/// or `users(where: { id_in: ["1", "2"] })`.
+pub fn collect_entity_change_filters(
     schema: &s::Document,
     object_type: &s::ObjectType,
     field: &q::Field,
-) -> Vec<(String, String)> {
-    // Output entities
-    let mut entities = HashSet::new();
+) -> Vec<EntityChangeFilter> {
+    // Output filters, with `None` meaning that changes to any entity of the type matter
+    let mut filters: HashMap<(String, String), Option<HashSet<String>>> = HashMap::new();
 
     // List of objects/fields to visit next
     let mut queue = VecDeque::new();
@@ -169,8 +267,19 @@ pub fn collect_entities_from_query_field(
                     {
                         // Obtain the subgraph ID from the object type
                         if let Some(subgraph_id) = parse_subgraph_id(object_type) {
-                            // Add the (subgraph_id, entity_name) tuple to the result set
-                            entities.insert((subgraph_id, object_type.name.to_owned()));
+                            // Merge the IDs selected by this field into the filter for
+                            // the (subgraph_id, entity_name) tuple
+                            let key = (subgraph_id, object_type.name.to_owned());
+                            let ids = match filters.remove(&key) {
+                                None => collect_entity_ids_from_field(field),
+                                Some(Some(mut ids)) => collect_entity_ids_from_field(field)
+                                    .map(|field_ids| {
+                                        ids.extend(field_ids);
+                                        ids
+                                    }),
+                                Some(None) => None,
+                            };
+                            filters.insert(key, ids);
                         }
                     }
 
@@ -186,7 +295,35 @@ pub fn collect_entities_from_query_field(
         }
     }
 
-    entities.into_iter().collect()
+    filters
+        .into_iter()
+        .map(|((subgraph, entity), ids)| EntityChangeFilter {
+            subgraph,
+            entity,
+            ids,
+        }).collect()
+}
+
+/// Returns the entity IDs a query field is restricted to, if it selects entities
+/// by literal IDs via an `id` argument or an `id`/`id_in` filter.
+fn collect_entity_ids_from_field(field: &q::Field) -> Option<HashSet<String>> {
+    field
+        .arguments
+        .iter()
+        .filter_map(|(name, value)| match (name.as_str(), value) {
+            ("id", value) => Some(value),
+            ("where", q::Value::Object(filter)) => filter.get("id").or_else(|| filter.get("id_in")),
+            _ => None,
+        }).filter_map(|value| match value {
+            q::Value::String(id) => Some(iter::once(id.to_owned()).collect()),
+            q::Value::List(ids) => ids
+                .iter()
+                .map(|id| match id {
+                    q::Value::String(id) => Some(id.to_owned()),
+                    _ => None,
+                }).collect(),
+            _ => None,
+        }).next()
 }
 
 #[cfg(test)]
@@ -196,12 +333,20 @@ mod tests {
         schema::{Directive, Field, ObjectType, Type},
         Pos,
     };
-    use std::collections::{BTreeMap, HashMap};
+    use std::collections::{BTreeMap, HashMap, HashSet};
     use std::iter::FromIterator;
 
     use graph::prelude::*;
+    use graphql_parser;
+
+    use super::{build_query, collect_entity_change_filters};
+    use schema::ast as sast;
 
-    use super::build_query;
+    fn empty_schema() -> s::Document {
+        s::Document {
+            definitions: vec![],
+        }
+    }
 
     fn default_object() -> ObjectType {
         let subgraph_id_argument = (
@@ -244,152 +389,180 @@ mod tests {
     #[test]
     fn build_query_uses_the_entity_name() {
         assert_eq!(
-            build_query(&object("Entity1"), &HashMap::new()).entity,
+            build_query(&empty_schema(), &object("Entity1"), &HashMap::new()).entity,
             "Entity1".to_string()
         );
         assert_eq!(
-            build_query(&object("Entity2"), &HashMap::new()).entity,
+            build_query(&empty_schema(), &object("Entity2"), &HashMap::new()).entity,
             "Entity2".to_string()
         );
     }
 
+    fn order_by_entry(field: q::Value, direction: Option<&str>) -> q::Value {
+        let mut entry = BTreeMap::new();
+        entry.insert("field".to_string(), field);
+        if let Some(direction) = direction {
+            entry.insert(
+                "direction".to_string(),
+                q::Value::Enum(direction.to_string()),
+            );
+        }
+        q::Value::Object(entry)
+    }
+
     #[test]
     fn build_query_yields_no_order_if_order_arguments_are_missing() {
         assert_eq!(
-            build_query(&default_object(), &HashMap::new()).order_by,
-            None,
-        );
-        assert_eq!(
-            build_query(&default_object(), &HashMap::new()).order_direction,
-            None,
+            build_query(&empty_schema(), &default_object(), &HashMap::new()).order_by,
+            vec![],
         );
     }
 
     #[test]
-    fn build_query_parses_order_by_from_enum_values_correctly() {
+    fn build_query_parses_order_by_from_objects_correctly() {
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
-                    vec![(&"orderBy".to_string(), q::Value::Enum("name".to_string()))].into_iter(),
+                    vec![(
+                        &"orderBy".to_string(),
+                        q::Value::List(vec![
+                            order_by_entry(q::Value::Enum("name".to_string()), Some("desc")),
+                            order_by_entry(q::Value::Enum("email".to_string()), Some("asc")),
+                        ]),
+                    )].into_iter(),
                 )
             ).order_by,
-            Some("name".to_string())
+            vec![
+                ("name".to_string(), StoreOrder::Descending),
+                ("email".to_string(), StoreOrder::Ascending),
+            ]
         );
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
-                    vec![(&"orderBy".to_string(), q::Value::Enum("email".to_string()))].into_iter()
+                    vec![(
+                        &"orderBy".to_string(),
+                        order_by_entry(q::Value::Enum("email".to_string()), Some("desc")),
+                    )].into_iter(),
                 )
             ).order_by,
-            Some("email".to_string())
+            vec![("email".to_string(), StoreOrder::Descending)]
         );
     }
 
     #[test]
-    fn build_query_ignores_order_by_from_non_enum_values() {
-        assert_eq!(
-            build_query(
-                &default_object(),
-                &HashMap::from_iter(
-                    vec![(&"orderBy".to_string(), q::Value::String("name".to_string()))]
-                        .into_iter()
-                ),
-            ).order_by,
-            None,
-        );
+    fn build_query_ignores_order_by_from_non_enum_fields() {
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(
                         &"orderBy".to_string(),
-                        q::Value::String("email".to_string()),
+                        q::Value::List(vec![
+                            order_by_entry(q::Value::String("name".to_string()), None),
+                            order_by_entry(q::Value::Enum("email".to_string()), None),
+                        ]),
                     )].into_iter(),
                 )
             ).order_by,
-            None,
+            vec![("email".to_string(), StoreOrder::Ascending)]
         );
     }
 
     #[test]
-    fn build_query_parses_order_direction_from_enum_values_correctly() {
-        assert_eq!(
-            build_query(
-                &default_object(),
-                &HashMap::from_iter(
-                    vec![(
-                        &"orderDirection".to_string(),
-                        q::Value::Enum("asc".to_string()),
-                    )].into_iter(),
-                )
-            ).order_direction,
-            Some(StoreOrder::Ascending)
-        );
+    fn build_query_parses_order_by_from_bare_field_names() {
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
-                    vec![(
-                        &"orderDirection".to_string(),
-                        q::Value::Enum("desc".to_string()),
-                    )].into_iter()
-                )
-            ).order_direction,
-            Some(StoreOrder::Descending)
+                    vec![(&"orderBy".to_string(), q::Value::Enum("name".to_string()))]
+                        .into_iter()
+                ),
+            ).order_by,
+            vec![("name".to_string(), StoreOrder::Ascending)],
         );
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
-                    vec![(
-                        &"orderDirection".to_string(),
-                        q::Value::Enum("ascending...".to_string()),
-                    )].into_iter()
-                )
-            ).order_direction,
-            None,
+                    vec![
+                        (&"orderBy".to_string(), q::Value::Enum("name".to_string())),
+                        (
+                            &"orderDirection".to_string(),
+                            q::Value::Enum("desc".to_string()),
+                        ),
+                    ].into_iter()
+                ),
+            ).order_by,
+            vec![("name".to_string(), StoreOrder::Descending)],
         );
     }
 
     #[test]
-    fn build_query_ignores_order_direction_from_non_enum_values() {
+    fn build_query_falls_back_to_order_direction() {
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
-                    vec![(
-                        &"orderDirection".to_string(),
-                        q::Value::String("asc".to_string()),
-                    )].into_iter()
-                ),
-            ).order_direction,
-            None,
+                    vec![
+                        (
+                            &"orderBy".to_string(),
+                            q::Value::List(vec![
+                                order_by_entry(q::Value::Enum("name".to_string()), None),
+                                order_by_entry(q::Value::Enum("email".to_string()), Some("asc")),
+                            ]),
+                        ),
+                        (
+                            &"orderDirection".to_string(),
+                            q::Value::Enum("desc".to_string()),
+                        ),
+                    ].into_iter(),
+                )
+            ).order_by,
+            vec![
+                ("name".to_string(), StoreOrder::Descending),
+                ("email".to_string(), StoreOrder::Ascending),
+            ]
         );
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
-                    vec![(
-                        &"orderDirection".to_string(),
-                        q::Value::String("desc".to_string()),
-                    )].into_iter(),
+                    vec![
+                        (
+                            &"orderBy".to_string(),
+                            order_by_entry(q::Value::Enum("name".to_string()), Some("ascending...")),
+                        ),
+                        (
+                            &"orderDirection".to_string(),
+                            q::Value::String("desc".to_string()),
+                        ),
+                    ].into_iter(),
                 )
-            ).order_direction,
-            None,
+            ).order_by,
+            vec![("name".to_string(), StoreOrder::Ascending)]
         );
     }
 
     #[test]
     fn build_query_yields_no_range_if_none_is_present() {
-        assert_eq!(build_query(&default_object(), &HashMap::new()).range, None,);
+        assert_eq!(build_query(&empty_schema(), &default_object(), &HashMap::new()).range, None,);
     }
 
     #[test]
     fn build_query_yields_default_first_if_only_skip_is_present() {
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(&"skip".to_string(), q::Value::Int(q::Number::from(50)))].into_iter()
@@ -406,6 +579,7 @@ mod tests {
     fn build_query_yields_default_skip_if_only_first_is_present() {
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &default_object(),
                 &HashMap::from_iter(
                     vec![(&"first".to_string(), q::Value::Int(q::Number::from(70)))].into_iter()
@@ -415,10 +589,79 @@ mod tests {
         );
     }
 
+    #[test]
+    fn build_query_yields_no_block_if_none_is_present() {
+        assert_eq!(build_query(&empty_schema(), &default_object(), &HashMap::new()).block, None);
+    }
+
+    #[test]
+    fn build_query_parses_block_numbers_and_hashes() {
+        assert_eq!(
+            build_query(
+                &empty_schema(),
+                &default_object(),
+                &HashMap::from_iter(
+                    vec![(
+                        &"block".to_string(),
+                        q::Value::Object(BTreeMap::from_iter(vec![(
+                            "number".to_string(),
+                            q::Value::Int(q::Number::from(42)),
+                        )])),
+                    )].into_iter(),
+                )
+            ).block,
+            Some(StoreBlock::Number(42)),
+        );
+        assert_eq!(
+            build_query(
+                &empty_schema(),
+                &default_object(),
+                &HashMap::from_iter(
+                    vec![(
+                        &"block".to_string(),
+                        q::Value::Object(BTreeMap::from_iter(vec![(
+                            "hash".to_string(),
+                            q::Value::String(
+                                "0x8f494c66afc1d3f8ac1b45df21f02a468f494c66afc1d3f8ac1b45df21f02a46"
+                                    .to_string()
+                            ),
+                        )])),
+                    )].into_iter(),
+                )
+            ).block,
+            Some(StoreBlock::Hash(
+                "8f494c66afc1d3f8ac1b45df21f02a468f494c66afc1d3f8ac1b45df21f02a46"
+                    .parse()
+                    .unwrap()
+            )),
+        );
+    }
+
+    #[test]
+    fn build_query_ignores_invalid_blocks() {
+        assert_eq!(
+            build_query(
+                &empty_schema(),
+                &default_object(),
+                &HashMap::from_iter(
+                    vec![(
+                        &"block".to_string(),
+                        q::Value::Object(BTreeMap::from_iter(vec![(
+                            "number".to_string(),
+                            q::Value::Int(q::Number::from(-1)),
+                        )])),
+                    )].into_iter(),
+                )
+            ).block,
+            None,
+        );
+    }
+
     #[test]
     fn build_query_yields_filters() {
         assert_eq!(
             build_query(
+                &empty_schema(),
                 &ObjectType {
                     fields: vec![field("name", Type::NamedType("string".to_owned()))],
                     ..default_object()
@@ -439,4 +682,149 @@ mod tests {
             )]))
         )
     }
+
+    #[test]
+    fn build_query_yields_child_filters() {
+        let schema = graphql_parser::parse_schema(
+            "
+            type Token @subgraphId(id: \"test\") {
+                id: ID!
+                symbol: String!
+            }
+
+            type Transfer @subgraphId(id: \"test\") {
+                id: ID!
+                token: Token!
+                tokens: [Token!]!
+            }
+            ",
+        ).expect("Failed to parse test schema");
+        let transfer = match sast::get_named_type(&schema, &"Transfer".to_string()) {
+            Some(s::TypeDefinition::Object(t)) => t,
+            _ => panic!("Transfer type is missing in test schema"),
+        };
+
+        let symbol_filter = || {
+            q::Value::Object(BTreeMap::from_iter(vec![(
+                "symbol".to_string(),
+                q::Value::String("DAI".to_string()),
+            )]))
+        };
+
+        assert_eq!(
+            build_query(
+                &schema,
+                transfer,
+                &HashMap::from_iter(
+                    vec![(
+                        &"where".to_string(),
+                        q::Value::Object(BTreeMap::from_iter(vec![
+                            ("token_".to_string(), symbol_filter()),
+                            ("tokens_".to_string(), symbol_filter()),
+                        ])),
+                    )].into_iter(),
+                )
+            ).filter,
+            Some(StoreFilter::And(vec![
+                StoreFilter::Child(
+                    "token".to_string(),
+                    "Token".to_string(),
+                    Box::new(StoreFilter::And(vec![StoreFilter::Equal(
+                        "symbol".to_string(),
+                        Value::String("DAI".to_string()),
+                    )])),
+                ),
+                StoreFilter::Child(
+                    "tokens".to_string(),
+                    "Token".to_string(),
+                    Box::new(StoreFilter::And(vec![StoreFilter::Equal(
+                        "symbol".to_string(),
+                        Value::String("DAI".to_string()),
+                    )])),
+                ),
+            ]))
+        )
+    }
+
+    fn entity_change_filters_for_query(query: &str) -> Vec<EntityChangeFilter> {
+        let schema = graphql_parser::parse_schema(
+            "
+            type Query {
+                user(id: ID!): User
+                users(where: User_filter): [User!]!
+            }
+
+            type User @entity @subgraphId(id: \"test\") {
+                id: ID!
+                name: String!
+                friends: [User!]!
+                posts: [Post!]!
+            }
+
+            type Post @entity @subgraphId(id: \"test\") {
+                id: ID!
+                author: User!
+            }
+            ",
+        ).expect("Failed to parse test schema");
+        let query_type = match sast::get_named_type(&schema, &"Query".to_string()) {
+            Some(s::TypeDefinition::Object(t)) => t.clone(),
+            _ => panic!("Query type is missing in test schema"),
+        };
+        let document = graphql_parser::parse_query(query).expect("Failed to parse test query");
+        let field = match &document.definitions[0] {
+            q::Definition::Operation(q::OperationDefinition::SelectionSet(selection_set)) => {
+                match &selection_set.items[0] {
+                    q::Selection::Field(field) => field.clone(),
+                    _ => panic!("Test query must select a field"),
+                }
+            }
+            _ => panic!("Test query must be a selection set"),
+        };
+
+        let mut filters = collect_entity_change_filters(&schema, &query_type, &field);
+        filters.sort_by(|a, b| a.entity.cmp(&b.entity));
+        filters
+    }
+
+    fn entity_change_filter(entity: &str, ids: Option<Vec<&str>>) -> EntityChangeFilter {
+        EntityChangeFilter {
+            subgraph: "test".to_string(),
+            entity: entity.to_string(),
+            ids: ids.map(|ids| HashSet::from_iter(ids.into_iter().map(|id| id.to_string()))),
+        }
+    }
+
+    #[test]
+    fn collect_entity_change_filters_restricts_ids_of_entities_selected_by_id() {
+        assert_eq!(
+            entity_change_filters_for_query("{ user(id: \"1\") { name } }"),
+            vec![entity_change_filter("User", Some(vec!["1"]))]
+        );
+        assert_eq!(
+            entity_change_filters_for_query(
+                "{ users(where: { id_in: [\"1\", \"2\"], name: \"Joe\" }) { name } }"
+            ),
+            vec![entity_change_filter("User", Some(vec!["1", "2"]))]
+        );
+    }
+
+    #[test]
+    fn collect_entity_change_filters_does_not_restrict_ids_of_other_entities() {
+        assert_eq!(
+            entity_change_filters_for_query("{ users(where: { name: \"Joe\" }) { name } }"),
+            vec![entity_change_filter("User", None)]
+        );
+        assert_eq!(
+            entity_change_filters_for_query("{ user(id: \"1\") { posts { id } } }"),
+            vec![
+                entity_change_filter("Post", None),
+                entity_change_filter("User", Some(vec!["1"])),
+            ]
+        );
+        assert_eq!(
+            entity_change_filters_for_query("{ user(id: \"1\") { friends { name } } }"),
+            vec![entity_change_filter("User", None)]
+        );
+    }
 }
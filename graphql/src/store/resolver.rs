@@ -1,8 +1,8 @@
 use graphql_parser::{query as q, schema as s};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
 use std::result;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use graph::components::store::*;
 use graph::prelude::*;
@@ -10,12 +10,17 @@ use graph::prelude::*;
 use prelude::*;
 use query::ast as qast;
 use schema::ast as sast;
-use store::query::{collect_entities_from_query_field, parse_subgraph_id};
+use store::query::{collect_entity_change_filters, parse_block, parse_subgraph_id};
 
 /// A resolver that fetches entities from a `Store`.
 pub struct StoreResolver<S> {
     logger: Logger,
-    store: Arc<Mutex<S>>,
+    subgraph_id: String,
+    schema: Arc<s::Document>,
+    store: Arc<S>,
+    /// The block at which entities are looked up, set by the `block`
+    /// argument of a root query field for that field and its nested fields.
+    block: Option<StoreBlock>,
 }
 
 impl<S> Clone for StoreResolver<S>
@@ -25,7 +30,10 @@ where
     fn clone(&self) -> Self {
         StoreResolver {
             logger: self.logger.clone(),
+            subgraph_id: self.subgraph_id.clone(),
+            schema: self.schema.clone(),
             store: self.store.clone(),
+            block: self.block.clone(),
         }
     }
 }
@@ -34,10 +42,13 @@ impl<S> StoreResolver<S>
 where
     S: Store,
 {
-    pub fn new(logger: &Logger, store: Arc<Mutex<S>>) -> Self {
+    pub fn new(logger: &Logger, schema: &Schema, store: Arc<S>) -> Self {
         StoreResolver {
             logger: logger.new(o!("component" => "StoreResolver")),
+            subgraph_id: schema.id.clone(),
+            schema: Arc::new(schema.document.clone()),
             store,
+            block: None,
         }
     }
 
@@ -50,6 +61,49 @@ where
             .find(|directive| directive.name == s::Name::from("derivedFrom"))
     }
 
+    /// If the field is a derived field, obtain the field in the object type that
+    /// it is derived from.
+    fn get_derived_from_field<'a>(
+        field_definition: &s::Field,
+        object_type: &'a s::ObjectType,
+    ) -> Option<&'a s::Field> {
+        Self::get_derived_from_directive(field_definition)
+            .and_then(|directive| {
+                qast::get_argument_value(&directive.arguments, &q::Name::from("field"))
+            }).and_then(|value| match value {
+                q::Value::String(s) => Some(s),
+                _ => None,
+            }).and_then(|derived_from_field_name| {
+                sast::get_field_type(object_type, derived_from_field_name)
+            })
+    }
+
+    /// Returns true if the given field type is a list type.
+    fn is_list_type(field_type: &s::Type) -> bool {
+        match field_type {
+            s::Type::ListType(_) => true,
+            s::Type::NonNullType(ref inner) => match inner.deref() {
+                s::Type::ListType(_) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Adds a filter to the top-level `And` filter of a query, creating one
+    /// if necessary.
+    fn add_filter(query: &mut StoreQuery, filter: StoreFilter) {
+        let top_level_filter = query.filter.get_or_insert(StoreFilter::And(vec![]));
+        *top_level_filter = match top_level_filter {
+            StoreFilter::And(ref mut filters) => {
+                let mut filters = filters.clone();
+                filters.push(filter);
+                StoreFilter::And(filters)
+            }
+            _ => top_level_filter.clone(),
+        };
+    }
+
     /// Adds a filter for matching entities that correspond to a derived field.
     ///
     /// Returns true if the field is a derived field (i.e., if it is defined with
@@ -60,15 +114,7 @@ where
         field_definition: &s::Field,
         object_type: &s::ObjectType,
     ) -> bool {
-        let derived_from_field = Self::get_derived_from_directive(field_definition)
-            .and_then(|directive| {
-                qast::get_argument_value(&directive.arguments, &q::Name::from("field"))
-            }).and_then(|value| match value {
-                q::Value::String(s) => Some(s),
-                _ => None,
-            }).and_then(|derived_from_field_name| {
-                sast::get_field_type(object_type, derived_from_field_name)
-            });
+        let derived_from_field = Self::get_derived_from_field(field_definition, object_type);
 
         if let Some(derived_from_field) = derived_from_field {
             // This field is derived from a field in the object type that we're trying
@@ -97,26 +143,14 @@ where
             // Depending on whether the field we're deriving from has a list or a
             // single value type, we either create a `Contains` or `Equal`
             // filter argument
-            let filter = match derived_from_field.field_type {
-                s::Type::ListType(_) => StoreFilter::Contains(field_name, parent_id),
-                s::Type::NonNullType(ref inner) => match inner.deref() {
-                    s::Type::ListType(_) => StoreFilter::Contains(field_name, parent_id),
-                    _ => StoreFilter::Equal(field_name, parent_id),
-                },
-                _ => StoreFilter::Equal(field_name, parent_id),
+            let filter = if Self::is_list_type(&derived_from_field.field_type) {
+                StoreFilter::Contains(field_name, parent_id)
+            } else {
+                StoreFilter::Equal(field_name, parent_id)
             };
 
-            // Add the `Contains`/`Equal` filter to the top-level `And` filter, creating one
-            // if necessary
-            let top_level_filter = query.filter.get_or_insert(StoreFilter::And(vec![]));
-            *top_level_filter = match top_level_filter {
-                StoreFilter::And(ref mut filters) => {
-                    let mut filters = filters.clone();
-                    filters.push(filter);
-                    StoreFilter::And(filters)
-                }
-                _ => top_level_filter.clone(),
-            };
+            // Add the `Contains`/`Equal` filter to the top-level `And` filter
+            Self::add_filter(query, filter);
 
             true
         } else {
@@ -156,16 +190,273 @@ where
                     ).as_str(),
                 );
 
-            // Add the `Or` filter to the top-level `And` filter, creating one if necessary
-            let top_level_filter = query.filter.get_or_insert(StoreFilter::And(vec![]));
-            *top_level_filter = match top_level_filter {
-                StoreFilter::And(ref mut filters) => {
-                    let mut filters = filters.clone();
-                    filters.push(filter);
-                    StoreFilter::And(filters)
+            // Add the `Or` filter to the top-level `And` filter
+            Self::add_filter(query, filter);
+        }
+    }
+
+    /// Adds a filter for matching entities found by a full-text search field, i.e.
+    /// a field defined with a `@fulltext(fields: [...])` directive.
+    fn add_filter_for_fulltext_field(
+        query: &mut StoreQuery,
+        field_definition: &s::Field,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) {
+        let fields = field_definition
+            .directives
+            .iter()
+            .find(|directive| directive.name == s::Name::from("fulltext"))
+            .and_then(|directive| {
+                qast::get_argument_value(&directive.arguments, &q::Name::from("fields"))
+            }).and_then(|value| match value {
+                q::Value::List(values) => Some(
+                    values
+                        .iter()
+                        .filter_map(|value| match value {
+                            q::Value::String(field) => Some(field.to_owned()),
+                            _ => None,
+                        }).collect::<Vec<_>>(),
+                ),
+                _ => None,
+            });
+
+        let text = arguments
+            .get(&q::Name::from("text"))
+            .and_then(|value| match value {
+                q::Value::String(text) => Some(text.to_owned()),
+                _ => None,
+            });
+
+        if let (Some(fields), Some(text)) = (fields, text) {
+            let filter = StoreFilter::Fulltext(fields, text);
+
+            // Add the `Fulltext` filter to the top-level `And` filter
+            Self::add_filter(query, filter);
+        }
+    }
+
+    /// Looks up an entity by ID, either in its latest version or as it was at
+    /// the given block.
+    fn get_entity(
+        &self,
+        object_type: &s::ObjectType,
+        id: &String,
+        block: Option<StoreBlock>,
+    ) -> Option<Entity> {
+        let subgraph = parse_subgraph_id(object_type).expect(
+            format!("Failed to get subgraph ID from type: {}", object_type.name).as_str(),
+        );
+        match block {
+            None => self
+                .store
+                .get(StoreKey {
+                    subgraph,
+                    entity: object_type.name.to_owned(),
+                    id: id.to_owned(),
+                }).ok(),
+            Some(block) => self
+                .store
+                .find(StoreQuery {
+                    subgraph,
+                    entity: object_type.name.to_owned(),
+                    filter: Some(StoreFilter::And(vec![StoreFilter::Equal(
+                        String::from("id"),
+                        Value::from(id),
+                    )])),
+                    order_by: vec![],
+                    range: Some(StoreRange { first: 1, skip: 0 }),
+                    block: Some(block),
+                }).ok()
+                .and_then(|entities| entities.into_iter().next()),
+        }
+    }
+
+    /// Resolves a `*_aggregate` object, marked with an `@aggregate(entity: ...,
+    /// fields: [...])` directive, by computing the aggregates selected in the
+    /// field over all entities matching the `where` argument in a single store
+    /// query.
+    fn resolve_aggregate(
+        &self,
+        field: &q::Field,
+        directive: &s::Directive,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> q::Value {
+        let entity_type = qast::get_argument_value(&directive.arguments, &q::Name::from("entity"))
+            .and_then(|value| match value {
+                q::Value::String(name) => sast::get_named_type(&self.schema, name),
+                _ => None,
+            }).and_then(|typedef| match typedef {
+                s::TypeDefinition::Object(t) => Some(t),
+                _ => None,
+            }).expect("Aggregate type does not refer to an entity type");
+
+        let fields = qast::get_argument_value(&directive.arguments, &q::Name::from("fields"))
+            .and_then(|value| match value {
+                q::Value::List(values) => Some(
+                    values
+                        .iter()
+                        .filter_map(|value| match value {
+                            q::Value::String(name) => Some(name.to_owned()),
+                            _ => None,
+                        }).collect::<Vec<_>>(),
+                ),
+                _ => None,
+            }).unwrap_or(vec![]);
+
+        // Collect the selected aggregates, e.g. `Sum("amount")` for
+        // `sum { amount }`, and start with an empty object for each selected
+        // group of aggregates
+        let mut object = BTreeMap::new();
+        let mut aggregates = vec![];
+        for selected in Self::selected_fields(&field.selection_set) {
+            let aggregate: fn(String) -> StoreAggregate = match selected.name.as_str() {
+                "count" => {
+                    if !aggregates.contains(&StoreAggregate::Count) {
+                        aggregates.push(StoreAggregate::Count);
+                    }
+                    continue;
+                }
+                "sum" => StoreAggregate::Sum,
+                "min" => StoreAggregate::Min,
+                "max" => StoreAggregate::Max,
+                "avg" => StoreAggregate::Avg,
+                _ => continue,
+            };
+
+            object
+                .entry(selected.name.clone())
+                .or_insert_with(|| q::Value::Object(BTreeMap::new()));
+            for subfield in Self::selected_fields(&selected.selection_set) {
+                let subfield_aggregate = aggregate(subfield.name.clone());
+                if fields.contains(&subfield.name) && !aggregates.contains(&subfield_aggregate) {
+                    aggregates.push(subfield_aggregate);
+                }
+            }
+        }
+
+        // There's no point in querying the store if nothing is to be computed
+        if aggregates.is_empty() {
+            return q::Value::Object(object);
+        }
+
+        let mut query = build_query(&self.schema, entity_type, arguments);
+        query.block = self.block.clone();
+
+        let values = match self.store.aggregate(query, aggregates.clone()) {
+            Ok(values) => values,
+            Err(_) => return q::Value::Null,
+        };
+
+        for (aggregate, value) in aggregates.into_iter().zip(values.into_iter()) {
+            let (group, attribute, value) = match aggregate {
+                StoreAggregate::Count => {
+                    object.insert(q::Name::from("count"), q::Value::from(value));
+                    continue;
+                }
+                StoreAggregate::Avg(attribute) => ("avg", attribute, q::Value::from(value)),
+                StoreAggregate::Sum(attribute) => {
+                    let value = Self::aggregate_value(entity_type, &attribute, value);
+                    ("sum", attribute, value)
+                }
+                StoreAggregate::Min(attribute) => {
+                    let value = Self::aggregate_value(entity_type, &attribute, value);
+                    ("min", attribute, value)
+                }
+                StoreAggregate::Max(attribute) => {
+                    let value = Self::aggregate_value(entity_type, &attribute, value);
+                    ("max", attribute, value)
                 }
-                _ => top_level_filter.clone(),
             };
+            if let Some(q::Value::Object(values)) = object.get_mut(group) {
+                values.insert(attribute, value);
+            }
+        }
+
+        q::Value::Object(object)
+    }
+
+    /// Returns the fields of a selection set, including those selected in
+    /// inline fragments.
+    fn selected_fields(selection_set: &q::SelectionSet) -> Vec<&q::Field> {
+        selection_set
+            .items
+            .iter()
+            .flat_map(|selection| match selection {
+                q::Selection::Field(field) => vec![field],
+                q::Selection::InlineFragment(fragment) => {
+                    Self::selected_fields(&fragment.selection_set)
+                }
+                q::Selection::FragmentSpread(_) => vec![],
+            }).collect()
+    }
+
+    /// Converts a sum, minimum or maximum into a value of the aggregated
+    /// field's type; the store returns integral results as `BigInt` even for
+    /// `Float` fields.
+    fn aggregate_value(entity_type: &s::ObjectType, attribute: &str, value: Value) -> q::Value {
+        let is_float = sast::get_field_type(entity_type, &attribute.to_owned())
+            .and_then(|field| sast::get_single_value_type_name(&field.field_type))
+            .map_or(false, |name| name == "Float");
+
+        match value {
+            Value::BigInt(number) if is_float => number
+                .to_string()
+                .parse::<f64>()
+                .map(q::Value::Float)
+                .unwrap_or(q::Value::Null),
+            value => q::Value::from(value),
+        }
+    }
+
+    /// Resolves the `_meta` field from the block the subgraph has been indexed
+    /// up to and the health of the subgraph.
+    fn resolve_meta(&self, field: &q::Field) -> result::Result<q::Value, QueryExecutionError> {
+        let (latest_block, health) = self
+            .store
+            .subgraph_latest_block_and_health(&self.subgraph_id)
+            .map_err(|e| QueryExecutionError::StoreError(e.to_string()))?;
+
+        let block = match latest_block {
+            // GraphQL `Int`s have 32 bits
+            Some(block) if block.number > i32::max_value() as u64 => {
+                return Err(QueryExecutionError::ValueOutOfRange(
+                    field.position.clone(),
+                    format!("Block number {} does not fit into an Int", block.number),
+                ))
+            }
+            Some(block) => object_value(vec![
+                ("hash", q::Value::String(format!("{:#x}", block.hash))),
+                (
+                    "number",
+                    q::Value::Int(q::Number::from(block.number as i32)),
+                ),
+            ]),
+            None => q::Value::Null,
+        };
+
+        let has_indexing_errors = match health {
+            SubgraphHealth::Healthy => false,
+            SubgraphHealth::Failed(_) => true,
+        };
+
+        Ok(object_value(vec![
+            ("block", block),
+            ("deployment", q::Value::String(self.subgraph_id.clone())),
+            ("hasIndexingErrors", q::Value::Boolean(has_indexing_errors)),
+        ]))
+    }
+
+    /// Returns the IDs of the given values, which may be a single ID or a list of IDs.
+    fn ids_in_value(value: Option<&Value>) -> Vec<&String> {
+        match value {
+            Some(Value::String(id)) => vec![id],
+            Some(Value::List(values)) => values
+                .iter()
+                .filter_map(|value| match value {
+                    Value::String(id) => Some(id),
+                    _ => None,
+                }).collect(),
+            _ => vec![],
         }
     }
 
@@ -188,6 +479,29 @@ impl<S> Resolver for StoreResolver<S>
 where
     S: Store,
 {
+    fn for_field(
+        &self,
+        field: &q::Field,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> result::Result<Option<Self>, QueryExecutionError> {
+        let value = match arguments.get(&"block".to_string()) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        match parse_block(value) {
+            Some(block) => Ok(Some(StoreResolver {
+                block: Some(block),
+                ..self.clone()
+            })),
+            None => Err(QueryExecutionError::InvalidArgumentError(
+                field.position,
+                String::from("block"),
+                value.clone(),
+            )),
+        }
+    }
+
     fn resolve_objects(
         &self,
         parent: &Option<q::Value>,
@@ -195,8 +509,12 @@ where
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        let mut query = build_query(&object_type, arguments);
+    ) -> result::Result<q::Value, QueryExecutionError> {
+        let mut query = build_query(&self.schema, &object_type, arguments);
+        query.block = self.block.clone();
+
+        // Add matching filter for full-text search fields
+        Self::add_filter_for_fulltext_field(&mut query, field_definition, arguments);
 
         // Add matching filter for derived fields
         let is_derived =
@@ -209,7 +527,7 @@ where
             && parent.is_some()
             && Self::references_field_is_empty(parent, &field_definition.name)
         {
-            return q::Value::List(vec![]);
+            return Ok(q::Value::List(vec![]));
         }
 
         // Add matching filter for reference fields
@@ -217,8 +535,8 @@ where
             Self::add_filter_for_reference_field(&mut query, parent, field_definition, object_type);
         }
 
-        let store = self.store.lock().unwrap();
-        store
+        Ok(self
+            .store
             .find(query)
             .map(|entities| {
                 q::Value::List(
@@ -227,55 +545,180 @@ where
                         .map(|e| e.into())
                         .collect::<Vec<q::Value>>(),
                 )
-            }).unwrap_or(q::Value::Null)
+            }).unwrap_or(q::Value::Null))
+    }
+
+    fn resolve_objects_for_parents(
+        &self,
+        parents: &Vec<Option<q::Value>>,
+        _field: &q::Name,
+        field_definition: &s::Field,
+        object_type: &s::ObjectType,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Option<Vec<q::Value>> {
+        // Only nested fields of two or more parent objects are worth batching
+        let parent_objects = parents
+            .iter()
+            .map(|parent| match parent {
+                Some(q::Value::Object(object)) => Some(object),
+                _ => None,
+            }).collect::<Option<Vec<_>>>()?;
+        if parent_objects.len() < 2 {
+            return None;
+        }
+
+        let mut query = build_query(&self.schema, &object_type, arguments);
+        query.block = self.block.clone();
+
+        // `first` and `skip` apply to each parent separately, which a single
+        // query cannot express
+        if query.range.is_some() {
+            return None;
+        }
+
+        let nulls = || -> Vec<q::Value> { parents.iter().map(|_| q::Value::Null).collect() };
+
+        // Add matching filter for full-text search fields
+        Self::add_filter_for_fulltext_field(&mut query, field_definition, arguments);
+
+        let derived_from_field = Self::get_derived_from_field(field_definition, object_type);
+
+        // For each parent, collect the IDs that entities are matched against:
+        // the parent's own ID for derived fields and the referenced IDs otherwise
+        let parent_ids: Vec<Vec<&String>> = match derived_from_field {
+            Some(_) => parent_objects
+                .iter()
+                .map(|object| match object.get(&q::Name::from("id")) {
+                    Some(q::Value::String(id)) => Some(vec![id]),
+                    _ => None,
+                }).collect::<Option<Vec<_>>>()
+                .expect("Parent object is missing an \"id\""),
+            None => parent_objects
+                .iter()
+                .map(|object| match object.get(&field_definition.name) {
+                    Some(q::Value::List(ids)) => ids
+                        .iter()
+                        .filter_map(|id| match id {
+                            q::Value::String(id) => Some(id),
+                            _ => None,
+                        }).collect(),
+                    _ => vec![],
+                }).collect(),
+        };
+
+        let mut all_ids: Vec<&String> = parent_ids.iter().flat_map(|ids| ids.clone()).collect();
+        all_ids.sort();
+        all_ids.dedup();
+
+        // There's no point in querying the store if no entities can match
+        if all_ids.is_empty() {
+            return Some(parents.iter().map(|_| q::Value::List(vec![])).collect());
+        }
+
+        // Match entities that are derived from or referenced by any of the parents
+        let match_attribute = match derived_from_field {
+            Some(derived_from_field) => {
+                let field_name = derived_from_field.name.clone();
+                if Self::is_list_type(&derived_from_field.field_type) {
+                    Self::add_filter(
+                        &mut query,
+                        StoreFilter::Or(
+                            all_ids
+                                .iter()
+                                .map(|id| StoreFilter::Contains(field_name.clone(), Value::from(*id)))
+                                .collect(),
+                        ),
+                    );
+                } else {
+                    Self::add_filter(
+                        &mut query,
+                        StoreFilter::In(
+                            field_name.clone(),
+                            all_ids.iter().map(|id| Value::from(*id)).collect(),
+                        ),
+                    );
+                }
+                field_name
+            }
+            None => {
+                Self::add_filter(
+                    &mut query,
+                    StoreFilter::In(
+                        String::from("id"),
+                        all_ids.iter().map(|id| Value::from(*id)).collect(),
+                    ),
+                );
+                String::from("id")
+            }
+        };
+
+        let entities = match self.store.find(query) {
+            Ok(entities) => entities,
+            Err(_) => return Some(nulls()),
+        };
+
+        // Distribute the entities to the parents they belong to, preserving the
+        // order in which the store returned them
+        Some(
+            parent_ids
+                .iter()
+                .map(|ids| {
+                    q::Value::List(
+                        entities
+                            .iter()
+                            .filter(|entity| {
+                                Self::ids_in_value(entity.get(&match_attribute))
+                                    .iter()
+                                    .any(|id| ids.contains(id))
+                            }).map(|entity| entity.clone().into())
+                            .collect(),
+                    )
+                }).collect(),
+        )
     }
 
     fn resolve_object(
         &self,
         parent: &Option<q::Value>,
-        field: &q::Name,
+        field: &q::Field,
         field_definition: &s::Field,
         object_type: &s::ObjectType,
         arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
+    ) -> result::Result<q::Value, QueryExecutionError> {
+        if parent.is_none() && field.name == "_meta" {
+            return self.resolve_meta(field);
+        }
+
+        if let Some(directive) =
+            sast::get_object_type_directive(object_type, q::Name::from("aggregate"))
+        {
+            return Ok(self.resolve_aggregate(field, directive, arguments));
+        }
+
         let id = arguments.get(&"id".to_string()).and_then(|id| match id {
             q::Value::String(s) => Some(s),
             _ => None,
         });
 
         if let Some(id) = id {
-            let store = self.store.lock().unwrap();
-            return store
-                .get(StoreKey {
-                    subgraph: parse_subgraph_id(object_type).expect(
-                        format!("Failed to get subgraph ID from type: {}", object_type.name)
-                            .as_str(),
-                    ),
-                    entity: object_type.name.to_owned(),
-                    id: id.to_owned(),
-                }).map(|entity| entity.into())
-                .unwrap_or(q::Value::Null);
+            return Ok(self
+                .get_entity(object_type, id, self.block.clone())
+                .map(|entity| entity.into())
+                .unwrap_or(q::Value::Null));
         }
 
-        match parent {
-            Some(q::Value::Object(parent_object)) => match parent_object.get(field) {
+        Ok(match parent {
+            Some(q::Value::Object(parent_object)) => match parent_object.get(&field.name) {
                 Some(q::Value::String(id)) => self
-                    .store
-                    .lock()
-                    .unwrap()
-                    .get(StoreKey {
-                        subgraph: parse_subgraph_id(object_type).expect(
-                            format!("Failed to get subgraph ID from type: {}", object_type.name)
-                                .as_str(),
-                        ),
-                        entity: object_type.name.to_owned(),
-                        id: id.to_owned(),
-                    }).map(|entity| entity.into())
+                    .get_entity(object_type, id, self.block.clone())
+                    .map(|entity| entity.into())
                     .unwrap_or(q::Value::Null),
+                // Nested objects, e.g. the sums of an aggregate, are resolved already
+                Some(value @ q::Value::Object(_)) => value.clone(),
                 _ => q::Value::Null,
             },
             _ => {
-                let mut query = build_query(&object_type, arguments);
+                let mut query = build_query(&self.schema, &object_type, arguments);
 
                 // Add matching filter for derived fields
                 Self::add_filter_for_derived_field(
@@ -286,10 +729,9 @@ where
                 );
 
                 query.range = Some(StoreRange { first: 1, skip: 0 });
+                query.block = self.block.clone();
 
                 self.store
-                    .lock()
-                    .unwrap()
                     .find(query)
                     .map(|entities| {
                         entities
@@ -299,7 +741,7 @@ where
                             .unwrap_or(q::Value::Null)
                     }).unwrap_or(q::Value::Null)
             }
-        }
+        })
     }
 
     fn resolve_field_stream<'a, 'b>(
@@ -317,10 +759,10 @@ where
             ));
         }
 
-        // Collect all entities involved in the query field
-        let entities = collect_entities_from_query_field(schema, object_type, field);
+        // Collect filters for all entity changes that may affect the query field
+        let filters = collect_entity_change_filters(schema, object_type, field);
 
         // Subscribe to the store and return the entity change stream
-        Ok(self.store.lock().unwrap().subscribe(entities))
+        Ok(self.store.subscribe(filters))
     }
 }
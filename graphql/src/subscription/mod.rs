@@ -8,6 +8,7 @@ use graph::prelude::*;
 use execution::*;
 use prelude::*;
 use query::ast as qast;
+use query::validation::validate_query;
 use schema::ast as sast;
 
 /// Options available for subscription execution.
@@ -30,8 +31,16 @@ where
 {
     info!(options.logger, "Execute subscription");
 
-    // Obtain the only operation of the subscription (fail if there is none or more than one)
-    let operation = qast::get_operation(&subscription.query.document, None)?;
+    // Validate the subscription query against the schema before executing it
+    validate_query(&subscription.query)?;
+
+    // Obtain the operation to execute (fail if there is none or it is ambiguous)
+    let operation_name = subscription
+        .query
+        .operation_name
+        .as_ref()
+        .map(|name| name.as_str());
+    let operation = qast::get_operation(&subscription.query.document, operation_name)?;
 
     // Create an introspection type store and resolver
     let introspection_schema = introspection_schema();
@@ -50,11 +59,15 @@ where
         fields: vec![],
     };
 
+    // Shared by the executions of all subscription events
+    let document = subscription.query.document.clone();
+
     match operation {
         // Execute top-level `subscription { ... }` expressions
         &q::OperationDefinition::Subscription(ref subscription) => {
             let source_stream = create_source_event_stream(&ctx, subscription)?;
-            let response_stream = map_source_to_response_stream(&ctx, subscription, source_stream)?;
+            let response_stream =
+                map_source_to_response_stream(&ctx, document, subscription, source_stream)?;
             Ok(response_stream)
         }
 
@@ -115,6 +128,7 @@ where
 
 fn map_source_to_response_stream<'a, R1, R2>(
     ctx: &ExecutionContext<'a, R1, R2>,
+    document: Arc<q::Document>,
     subscription: &'a q::Subscription,
     source_stream: EntityChangeStream,
 ) -> Result<QueryResultStream, SubscriptionError>
@@ -125,33 +139,51 @@ where
     let logger = ctx.logger.clone();
     let resolver = ctx.resolver.clone();
     let schema = ctx.schema.clone();
-    let document = ctx.document.clone();
     let subscription = subscription.to_owned();
 
-    Ok(Box::new(source_stream.map(move |event| {
-        execute_subscription_event(
-            logger.clone(),
-            resolver.clone(),
-            schema.clone(),
-            document.clone(),
-            subscription.clone(),
-            event,
-        )
-    })))
+    // Data of the last result sent to the subscriber
+    let mut last_data = None;
+
+    Ok(Box::new(
+        ReadyEntityChangeBatches::new(source_stream).filter_map(move |batches| {
+            let result = execute_subscription_event(
+                logger.clone(),
+                resolver.clone(),
+                schema.clone(),
+                document.clone(),
+                subscription.clone(),
+                batches,
+            );
+
+            // Skip results that are identical to the last one sent
+            if result.errors.is_none() {
+                if last_data.as_ref() == result.data.as_ref() {
+                    return None;
+                }
+                last_data = result.data.clone();
+            } else {
+                last_data = None;
+            }
+
+            Some(result)
+        }),
+    ))
 }
 
 fn execute_subscription_event<R1>(
     logger: slog::Logger,
     resolver: Arc<R1>,
     schema: Schema,
-    document: q::Document,
+    document: Arc<q::Document>,
     subscription: q::Subscription,
-    event: EntityChange,
+    batches: Vec<EntityChangeBatch>,
 ) -> QueryResult
 where
     R1: Resolver + 'static,
 {
-    debug!(logger, "Execute subscription event"; "event" => format!("{:?}", event));
+    debug!(logger, "Execute subscription event";
+           "block_number" => batches.last().map(|batch| batch.block.number),
+           "changes" => batches.iter().map(|batch| batch.changes.len()).sum::<usize>());
 
     // Create an introspection type store and resolver
     let introspection_schema = introspection_schema();
@@ -175,3 +207,80 @@ where
     execute_selection_set(ctx, &subscription.selection_set, subscription_type, &None)
         .unwrap_or_else(QueryResult::from)
 }
+
+/// Collects the entity change batches that are available at the same time,
+/// so that they trigger only one re-execution of the subscription query.
+///
+/// Batches are never split, so results are only computed for fully-applied
+/// store transactions.
+struct ReadyEntityChangeBatches {
+    batches: stream::Fuse<EntityChangeStream>,
+}
+
+impl ReadyEntityChangeBatches {
+    fn new(batches: EntityChangeStream) -> Self {
+        ReadyEntityChangeBatches {
+            batches: batches.fuse(),
+        }
+    }
+}
+
+impl Stream for ReadyEntityChangeBatches {
+    type Item = Vec<EntityChangeBatch>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut ready = vec![];
+
+        // Drain all batches that are ready without waiting for more
+        loop {
+            match self.batches.poll()? {
+                Async::Ready(Some(batch)) => ready.push(batch),
+                Async::Ready(None) if ready.is_empty() => return Ok(Async::Ready(None)),
+                Async::NotReady if ready.is_empty() => return Ok(Async::NotReady),
+                Async::Ready(None) | Async::NotReady => return Ok(Async::Ready(Some(ready))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc::channel;
+
+    use graph::prelude::*;
+    use graph::web3::types::H256;
+
+    use super::ReadyEntityChangeBatches;
+
+    fn batch(block_number: u64, ids: Vec<&str>) -> EntityChangeBatch {
+        EntityChangeBatch {
+            block: EthereumBlockPointer::from((H256::from(block_number), block_number)),
+            changes: ids
+                .into_iter()
+                .map(|id| EntityChange {
+                    subgraph: "subgraph".to_string(),
+                    entity: "User".to_string(),
+                    id: id.to_string(),
+                    operation: EntityChangeOperation::Updated,
+                }).collect(),
+        }
+    }
+
+    #[test]
+    fn entity_change_batches_that_are_ready_together_are_collected() {
+        let (sender, receiver) = channel(10);
+        let mut ready = ReadyEntityChangeBatches::new(Box::new(receiver)).wait();
+
+        let sender = sender.send(batch(1, vec!["1", "2"])).wait().unwrap();
+        let sender = sender.send(batch(2, vec!["1"])).wait().unwrap();
+        assert_eq!(
+            ready.next(),
+            Some(Ok(vec![batch(1, vec!["1", "2"]), batch(2, vec!["1"])]))
+        );
+
+        sender.send(batch(3, vec!["3"])).wait().unwrap();
+        assert_eq!(ready.next(), Some(Ok(vec![batch(3, vec!["3"])])));
+        assert_eq!(ready.next(), None);
+    }
+}
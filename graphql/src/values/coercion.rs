@@ -1,5 +1,7 @@
 use graphql_parser::query::Value;
-use graphql_parser::schema::{EnumType, Name, ScalarType, Type, TypeDefinition};
+use graphql_parser::schema::{EnumType, InputObjectType, Name, ScalarType, Type, TypeDefinition};
+use std::collections::BTreeMap;
+use std::iter::{self, FromIterator};
 
 /// A GraphQL value that can be coerced according to a type.
 pub trait MaybeCoercible<T> {
@@ -73,7 +75,14 @@ where
         (Type::NonNullType(t), _) => coerce_value(value, t, resolver),
 
         // Resolve named types, then try to coerce the value into the resolved type
-        (Type::NamedType(name), _) => resolver(name).and_then(|def| value.coerce(def)),
+        (Type::NamedType(name), _) => resolver(name).and_then(|def| match (def, value) {
+            // Enum values are shorthand for input objects with a single required
+            // field, e.g. `orderBy: name` for `orderBy: { field: name }`
+            (TypeDefinition::InputObject(t), Value::Enum(_)) => {
+                coerce_input_object_shorthand(value, t, resolver)
+            }
+            _ => value.coerce(def),
+        }),
 
         // List values may be coercible if they are empty or their values are coercible
         // into the inner type
@@ -95,22 +104,57 @@ where
             Some(Value::List(coerced_values))
         },
 
+        // Null values remain null when coerced into nullable list types
+        (Type::ListType(_), Value::Null) => Some(Value::Null),
+
+        // Single values are coerced into lists of one item
+        (Type::ListType(t), _) => coerce_value(value, t, resolver).map(|v| Value::List(vec![v])),
+
         // Everything else is unsupported for now
         _ => unimplemented!(),
     }
 }
 
+/// Coerces a value into an input object that has exactly one required field,
+/// using the value for that field.
+fn coerce_input_object_shorthand<'a, R>(
+    value: &Value,
+    input_object_type: &InputObjectType,
+    resolver: &R,
+) -> Option<Value>
+where
+    R: Fn(&Name) -> Option<&'a TypeDefinition>,
+{
+    let mut required_fields = input_object_type
+        .fields
+        .iter()
+        .filter(|field| match field.value_type {
+            Type::NonNullType(_) => field.default_value.is_none(),
+            _ => false,
+        });
+
+    match (required_fields.next(), required_fields.next()) {
+        (Some(field), None) => coerce_value(value, &field.value_type, resolver).map(|value| {
+            Value::Object(BTreeMap::from_iter(iter::once((
+                field.name.clone(),
+                value,
+            ))))
+        }),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use graphql_parser::query::Value;
     use graphql_parser::schema::{
-        EnumType, EnumValue, InputObjectType, ScalarType, TypeDefinition,
+        EnumType, EnumValue, InputObjectType, InputValue, ScalarType, Type, TypeDefinition,
     };
     use graphql_parser::Pos;
     use std::collections::BTreeMap;
     use std::iter::FromIterator;
 
-    use super::MaybeCoercible;
+    use super::{coerce_value, MaybeCoercible};
 
     #[test]
     fn coercion_using_enum_type_definitions_is_correct() {
@@ -309,4 +353,102 @@ mod tests {
             Some(Value::String("0x21f".to_string()))
         );
     }
+
+    #[test]
+    fn coerce_enum_values_into_input_objects_with_one_required_field() {
+        let field_type = TypeDefinition::Enum(EnumType {
+            name: "Field".to_string(),
+            description: None,
+            directives: vec![],
+            position: Pos::default(),
+            values: vec![EnumValue {
+                name: "name".to_string(),
+                position: Pos::default(),
+                description: None,
+                directives: vec![],
+            }],
+        });
+        let mut order_by_type = InputObjectType::new("OrderBy".to_owned());
+        order_by_type.fields = vec![
+            InputValue {
+                position: Pos::default(),
+                description: None,
+                name: "field".to_owned(),
+                value_type: Type::NonNullType(Box::new(Type::NamedType("Field".to_owned()))),
+                default_value: None,
+                directives: vec![],
+            },
+            InputValue {
+                position: Pos::default(),
+                description: None,
+                name: "direction".to_owned(),
+                value_type: Type::NamedType("Field".to_owned()),
+                default_value: None,
+                directives: vec![],
+            },
+        ];
+        let order_by_type = TypeDefinition::InputObject(order_by_type);
+        let resolver = |name: &String| match name.as_str() {
+            "Field" => Some(&field_type),
+            "OrderBy" => Some(&order_by_type),
+            _ => None,
+        };
+        let order_by = Type::NamedType("OrderBy".to_owned());
+
+        // An enum value fills in the only required field
+        assert_eq!(
+            coerce_value(&Value::Enum("name".to_owned()), &order_by, &resolver),
+            Some(Value::Object(BTreeMap::from_iter(
+                vec![("field".to_owned(), Value::Enum("name".to_owned()))].into_iter()
+            )))
+        );
+
+        // ...but only if the required field accepts it
+        assert_eq!(
+            coerce_value(&Value::Enum("email".to_owned()), &order_by, &resolver),
+            None
+        );
+
+        // Input objects without exactly one required field don't accept enum values
+        let input_object_type =
+            TypeDefinition::InputObject(InputObjectType::new("InputObject".to_owned()));
+        let resolver = |_: &String| Some(&input_object_type);
+        assert_eq!(
+            coerce_value(
+                &Value::Enum("name".to_owned()),
+                &Type::NamedType("InputObject".to_owned()),
+                &resolver
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn coerce_single_values_into_lists() {
+        let string_type = TypeDefinition::Scalar(ScalarType::new("String".to_owned()));
+        let resolver = |_: &String| Some(&string_type);
+        let list_type = Type::ListType(Box::new(Type::NamedType("String".to_owned())));
+
+        // A single value becomes a list with one item
+        assert_eq!(
+            coerce_value(&Value::String("a".to_owned()), &list_type, &resolver),
+            Some(Value::List(vec![Value::String("a".to_owned())]))
+        );
+
+        // Null remains null
+        assert_eq!(
+            coerce_value(&Value::Null, &list_type, &resolver),
+            Some(Value::Null)
+        );
+
+        // Lists are coerced item by item
+        assert_eq!(
+            coerce_value(
+                &Value::List(vec![Value::String("a".to_owned()), Value::Int(1.into())]),
+                &list_type,
+                &resolver
+            ),
+            None
+        );
+    }
 }
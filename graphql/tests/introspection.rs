@@ -23,19 +23,19 @@ impl Resolver for MockResolver {
         _field_definition: &s::Field,
         _object_type: &s::ObjectType,
         _arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        q::Value::Null
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
     }
 
     fn resolve_object(
         &self,
         _parent: &Option<q::Value>,
-        _field: &q::Name,
+        _field: &q::Field,
         _field_definition: &s::Field,
         _object_type: &s::ObjectType,
         _arguments: &HashMap<&q::Name, q::Value>,
-    ) -> q::Value {
-        q::Value::Null
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
     }
 }
 
@@ -482,8 +482,9 @@ fn introspection_query(schema: Schema, query: &str) -> QueryResult {
     // Create the query
     let query = Query {
         schema: schema,
-        document: graphql_parser::parse_query(query).unwrap(),
+        document: Arc::new(graphql_parser::parse_query(query).unwrap()),
         variables: None,
+        operation_name: None,
     };
 
     // Execute it
@@ -492,6 +493,7 @@ fn introspection_query(schema: Schema, query: &str) -> QueryResult {
         QueryExecutionOptions {
             logger: slog::Logger::root(slog::Discard, o!()),
             resolver: MockResolver,
+            thread_pool: QueryThreadPool::sequential(),
         },
     )
 }
@@ -7,10 +7,16 @@ extern crate graph_core;
 extern crate graph_graphql;
 
 use graphql_parser::query as q;
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
 use graph::components::store::EventSource;
 use graph::prelude::*;
+use graph::serde_json;
 use graph::web3::types::{Block, Transaction, H256};
 use graph_graphql::prelude::*;
 
@@ -39,6 +45,7 @@ fn test_schema() -> Schema {
                 id: ID!
                 title: String!
                 writtenBy: Musician!
+                duration: Float
             }
             ",
             ).expect("Test schema invalid"),
@@ -51,6 +58,18 @@ fn test_schema() -> Schema {
 #[derive(Clone)]
 struct TestStore {
     entities: Vec<Entity>,
+    find_calls: Arc<AtomicUsize>,
+    // The blocks that `find` was called with.
+    find_blocks: Arc<Mutex<Vec<Option<StoreBlock>>>>,
+    // The orders that `find` was called with.
+    find_orders: Arc<Mutex<Vec<Vec<(String, StoreOrder)>>>>,
+    // How long each `find` call takes, so that concurrent calls overlap.
+    find_delay: Option<Duration>,
+    // The number of `find` calls in progress and the most that ever were.
+    finds_in_progress: Arc<AtomicUsize>,
+    max_finds_in_progress: Arc<Mutex<usize>>,
+    // The aggregates that `aggregate` was called with.
+    aggregate_calls: Arc<Mutex<Vec<Vec<StoreAggregate>>>>,
 }
 
 impl TestStore {
@@ -126,6 +145,13 @@ impl TestStore {
                     ("writtenBy", Value::from("m3")),
                 ]),
             ],
+            find_calls: Arc::new(AtomicUsize::new(0)),
+            find_blocks: Arc::new(Mutex::new(vec![])),
+            find_orders: Arc::new(Mutex::new(vec![])),
+            find_delay: None,
+            finds_in_progress: Arc::new(AtomicUsize::new(0)),
+            max_finds_in_progress: Arc::new(Mutex::new(0)),
+            aggregate_calls: Arc::new(Mutex::new(vec![])),
         }
     }
 }
@@ -140,15 +166,59 @@ impl BasicStore for TestStore {
             }).map_or(Err(()), |entity| Ok(entity.clone()))
     }
 
-    fn set(&mut self, _key: StoreKey, _entity: Entity, _source: EventSource) -> Result<(), ()> {
+    fn set(&self, _key: StoreKey, _entity: Entity, _source: EventSource) -> Result<(), ()> {
         unimplemented!()
     }
 
-    fn delete(&mut self, _key: StoreKey, _source: EventSource) -> Result<(), ()> {
+    fn delete(&self, _key: StoreKey, _source: EventSource) -> Result<(), ()> {
         unimplemented!()
     }
 
+    fn transact_block_operations(
+        &self,
+        _block: EthereumBlockPointer,
+        _operations: Vec<EntityOperation>,
+        _log_entries: HashMap<String, Vec<SubgraphLogEntry>>,
+    ) -> Result<(), Error> {
+        unimplemented!()
+    }
+
+    fn subgraph_latest_block(
+        &self,
+        _subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error> {
+        Ok(Some(EthereumBlockPointer::from((H256::zero(), 42u64))))
+    }
+
+    fn set_subgraph_health(
+        &self,
+        _subgraph_id: &str,
+        _health: &SubgraphHealth,
+    ) -> Result<(), Error> {
+        unimplemented!()
+    }
+
+    fn subgraph_health(&self, _subgraph_id: &str) -> Result<SubgraphHealth, Error> {
+        Ok(SubgraphHealth::Healthy)
+    }
+
     fn find(&self, query: StoreQuery) -> Result<Vec<Entity>, ()> {
+        self.find_calls.fetch_add(1, Ordering::SeqCst);
+        self.find_blocks.lock().unwrap().push(query.block.clone());
+        self.find_orders
+            .lock()
+            .unwrap()
+            .push(query.order_by.clone());
+
+        let finds_in_progress = self.finds_in_progress.fetch_add(1, Ordering::SeqCst) + 1;
+        {
+            let mut max_finds_in_progress = self.max_finds_in_progress.lock().unwrap();
+            *max_finds_in_progress = cmp::max(*max_finds_in_progress, finds_in_progress);
+        }
+        if let Some(delay) = self.find_delay {
+            thread::sleep(delay);
+        }
+
         let entity_name = Value::String(query.entity.clone());
 
         let entities = self.entities
@@ -159,7 +229,9 @@ impl BasicStore for TestStore {
             //
             // - And(Contains(...))
             // - And(Equal(...))
+            // - And(In(...))
             // - And(Or([Equal(...), ...]))
+            // - And(Or([Contains(...), ...]))
             .filter(|entity| {
                 query
                     .filter
@@ -174,8 +246,16 @@ impl BasicStore for TestStore {
                             Some(Value::List(values)) => values.contains(v),
                             _ => false,
                         },
+                        StoreFilter::In(k, values) => match entity.get(k) {
+                            Some(v) => values.contains(v),
+                            None => false,
+                        },
                         StoreFilter::Or(filters) => filters.iter().any(|filter| match filter {
                             StoreFilter::Equal(k,v) => entity.get(k) == Some(&v),
+                            StoreFilter::Contains(k, v) => match entity.get(k) {
+                                Some(Value::List(values)) => values.contains(v),
+                                _ => false,
+                            },
                             _ => unimplemented!(),
                         }),
                         _ => unimplemented!(),
@@ -185,8 +265,33 @@ impl BasicStore for TestStore {
             .map(|entity| entity.clone())
             .collect();
 
+        self.finds_in_progress.fetch_sub(1, Ordering::SeqCst);
+
         Ok(entities)
     }
+
+    fn aggregate(
+        &self,
+        query: StoreQuery,
+        aggregates: Vec<StoreAggregate>,
+    ) -> Result<Vec<Value>, ()> {
+        self.aggregate_calls
+            .lock()
+            .unwrap()
+            .push(aggregates.clone());
+        let entities = self.find(query)?;
+
+        // No test entity has a numeric field; pretend every numeric aggregate
+        // is 3, which the store returns as a `BigInt` except for averages
+        Ok(aggregates
+            .into_iter()
+            .map(|aggregate| match aggregate {
+                StoreAggregate::Count => Value::Int(entities.len() as i32),
+                StoreAggregate::Avg(_) => Value::Float(3.0),
+                _ => Value::BigInt("3".parse().unwrap()),
+            })
+            .collect())
+    }
 }
 
 impl BlockStore for TestStore {
@@ -212,28 +317,89 @@ impl BlockStore for TestStore {
     ) -> Result<Vec<H256>, Error> {
         unimplemented!()
     }
+
+    fn chain_head_ptr(&self, _network_name: &str) -> Result<Option<EthereumBlockPointer>, Error> {
+        unimplemented!()
+    }
+}
+
+impl SubgraphLogStore for TestStore {
+    fn append_subgraph_logs(&self, _: &str, _: Vec<SubgraphLogEntry>) -> Result<(), Error> {
+        unimplemented!()
+    }
+
+    fn subgraph_logs(
+        &self,
+        _: &str,
+        _: SubgraphLogLevel,
+        _: usize,
+    ) -> Result<Vec<SubgraphLogEntry>, Error> {
+        unimplemented!()
+    }
 }
 
 impl Store for TestStore {
-    fn subscribe(&mut self, _entities: Vec<SubgraphEntityPair>) -> EntityChangeStream {
+    fn subscribe(&self, _filters: Vec<EntityChangeFilter>) -> EntityChangeStream {
         unimplemented!();
     }
+
+    fn create_fulltext_index(
+        &self,
+        _subgraph: String,
+        _entity: String,
+        _attributes: Vec<Attribute>,
+    ) -> Result<(), Error> {
+        unimplemented!()
+    }
 }
 
 fn execute_query_document(query: q::Document) -> QueryResult {
+    execute_query_document_with_store(query, TestStore::new(), 4)
+}
+
+fn execute_query_document_with_store(
+    query: q::Document,
+    store: TestStore,
+    max_parallel_fields: usize,
+) -> QueryResult {
+    execute_operation_with_store(
+        query,
+        None,
+        store,
+        QueryThreadPool::new(max_parallel_fields),
+    )
+}
+
+fn execute_operation(query: q::Document, operation_name: &str) -> QueryResult {
+    execute_operation_with_store(
+        query,
+        Some(operation_name.to_owned()),
+        TestStore::new(),
+        QueryThreadPool::new(4),
+    )
+}
+
+fn execute_operation_with_store(
+    query: q::Document,
+    operation_name: Option<String>,
+    store: TestStore,
+    thread_pool: QueryThreadPool,
+) -> QueryResult {
     let query = Query {
         schema: test_schema(),
-        document: query,
+        document: Arc::new(query),
         variables: None,
+        operation_name,
     };
 
     let logger = Logger::root(slog::Discard, o!());
-    let store = Arc::new(Mutex::new(TestStore::new()));
-    let store_resolver = StoreResolver::new(&logger, store);
+    let store = Arc::new(store);
+    let store_resolver = StoreResolver::new(&logger, &query.schema, store);
 
     let options = QueryExecutionOptions {
         logger: logger,
         resolver: store_resolver,
+        thread_pool,
     };
 
     execute_query(&query, options)
@@ -478,3 +644,459 @@ fn can_query_many_to_many_relationship() {
         )]))
     );
 }
+
+#[test]
+fn resolves_nested_lists_with_one_query_per_field() {
+    let store = TestStore::new();
+    let find_calls = store.find_calls.clone();
+
+    let result = execute_query_document_with_store(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians {
+                    name
+                    bands {
+                        name
+                        members {
+                            name
+                        }
+                    }
+                    writtenSongs {
+                        title
+                    }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+        store,
+        4,
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    // One query each for the musicians, their bands, the members of these
+    // bands and the songs written by the musicians
+    assert_eq!(find_calls.load(Ordering::SeqCst), 4);
+
+    let songs = |titles: Vec<&str>| {
+        q::Value::List(
+            titles
+                .into_iter()
+                .map(|title| object_value(vec![("title", q::Value::String(String::from(title)))]))
+                .collect(),
+        )
+    };
+
+    let musicians = match result.data {
+        Some(q::Value::Object(ref data)) => data.get("musicians").cloned(),
+        _ => None,
+    }.expect("Musicians missing in result");
+
+    let written_songs = match musicians {
+        q::Value::List(musicians) => musicians
+            .into_iter()
+            .map(|musician| match musician {
+                q::Value::Object(musician) => musician
+                    .get("writtenSongs")
+                    .cloned()
+                    .expect("Written songs missing in result"),
+                _ => panic!("Musician is not an object"),
+            }).collect::<Vec<_>>(),
+        _ => panic!("Musicians are not a list"),
+    };
+
+    assert_eq!(
+        written_songs,
+        vec![
+            songs(vec!["Cheesy Tune", "Pop Tune"]),
+            songs(vec!["Rock Tune"]),
+            songs(vec!["Folk Tune"]),
+            songs(vec![]),
+        ]
+    );
+}
+
+#[test]
+fn resolves_root_fields_concurrently() {
+    let query = graphql_parser::parse_query(
+        "
+        query {
+            musicians { name }
+            bands { name }
+            songs { title }
+            musician(id: \"m2\") { name }
+            band(id: \"unknown\") { name }
+        }
+        ",
+    ).expect("Invalid test query");
+
+    let mut sequential_store = TestStore::new();
+    sequential_store.find_delay = Some(Duration::from_millis(20));
+    let max_sequential_finds = sequential_store.max_finds_in_progress.clone();
+
+    let mut concurrent_store = TestStore::new();
+    concurrent_store.find_delay = Some(Duration::from_millis(20));
+    let max_concurrent_finds = concurrent_store.max_finds_in_progress.clone();
+
+    let sequential_result = execute_query_document_with_store(query.clone(), sequential_store, 1);
+    let concurrent_result = execute_query_document_with_store(query, concurrent_store, 3);
+
+    // Root fields overlap, but never on more threads than the pool has
+    assert_eq!(*max_sequential_finds.lock().unwrap(), 1);
+    assert!(*max_concurrent_finds.lock().unwrap() > 1);
+    assert!(*max_concurrent_finds.lock().unwrap() <= 3);
+
+    assert!(
+        concurrent_result.errors.is_none(),
+        format!(
+            "Unexpected errors return for query: {:#?}",
+            concurrent_result.errors
+        )
+    );
+
+    // Root fields resolved concurrently produce the same data as resolving them one by one
+    assert_eq!(concurrent_result.data, sequential_result.data);
+
+    match concurrent_result.data {
+        Some(q::Value::Object(ref map)) => assert_eq!(
+            map.keys().collect::<Vec<_>>(),
+            vec!["band", "bands", "musician", "musicians", "songs"]
+        ),
+        ref data => panic!("Unexpected result data: {:?}", data),
+    }
+}
+
+#[test]
+fn bounds_concurrent_root_fields_across_queries() {
+    let query = graphql_parser::parse_query(
+        "
+        query {
+            musicians { name }
+            bands { name }
+            songs { title }
+            musician(id: \"m2\") { name }
+        }
+        ",
+    ).expect("Invalid test query");
+
+    // All queries share the same pool and count their `find` calls together
+    let thread_pool = QueryThreadPool::new(2);
+    let finds_in_progress = Arc::new(AtomicUsize::new(0));
+    let max_finds_in_progress = Arc::new(Mutex::new(0));
+
+    let queries = (0..4)
+        .map(|_| {
+            let query = query.clone();
+            let thread_pool = thread_pool.clone();
+            let mut store = TestStore::new();
+            store.find_delay = Some(Duration::from_millis(20));
+            store.finds_in_progress = finds_in_progress.clone();
+            store.max_finds_in_progress = max_finds_in_progress.clone();
+
+            thread::spawn(move || {
+                execute_operation_with_store(query, None, store, thread_pool)
+            })
+        }).collect::<Vec<_>>();
+
+    for query in queries {
+        let result = query.join().expect("Query panicked");
+        assert!(
+            result.errors.is_none(),
+            format!("Unexpected errors return for query: {:#?}", result.errors)
+        );
+    }
+
+    assert_eq!(*max_finds_in_progress.lock().unwrap(), 2);
+}
+
+#[test]
+fn can_query_aggregates() {
+    let result = execute_query_document(
+        graphql_parser::parse_query(
+            "
+            query {
+                musiciansAggregate {
+                    count
+                }
+                songsAggregate(where: { title: \"Cheesy Tune\" }) {
+                    count
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![
+            (
+                "musiciansAggregate",
+                object_value(vec![("count", q::Value::Int(q::Number::from(4)))]),
+            ),
+            (
+                "songsAggregate",
+                object_value(vec![("count", q::Value::Int(q::Number::from(1)))]),
+            ),
+        ]))
+    );
+}
+
+#[test]
+fn computes_only_selected_aggregates_with_the_field_type() {
+    let store = TestStore::new();
+    let aggregate_calls = store.aggregate_calls.clone();
+
+    let result = execute_query_document_with_store(
+        graphql_parser::parse_query(
+            "
+            query {
+                songsAggregate {
+                    min { duration }
+                    ...Max
+                }
+            }
+
+            fragment Max on Song_aggregate {
+                max { duration }
+            }
+            ",
+        ).expect("Invalid test query"),
+        store,
+        4,
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        *aggregate_calls.lock().unwrap(),
+        vec![vec![
+            StoreAggregate::Min(String::from("duration")),
+            StoreAggregate::Max(String::from("duration")),
+        ]]
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "songsAggregate",
+            object_value(vec![
+                ("max", object_value(vec![("duration", q::Value::Float(3.0))])),
+                ("min", object_value(vec![("duration", q::Value::Float(3.0))])),
+            ]),
+        )]))
+    );
+}
+
+#[test]
+fn can_query_meta() {
+    let result = execute_query_document(
+        graphql_parser::parse_query(
+            "
+            query {
+                _meta {
+                    block {
+                        number
+                    }
+                    deployment
+                    hasIndexingErrors
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "_meta",
+            object_value(vec![
+                (
+                    "block",
+                    object_value(vec![("number", q::Value::Int(q::Number::from(42)))]),
+                ),
+                ("deployment", q::Value::String(String::from("test-schema"))),
+                ("hasIndexingErrors", q::Value::Boolean(false)),
+            ]),
+        )]))
+    );
+}
+
+#[test]
+fn resolves_nested_fields_at_the_block_of_the_root_field() {
+    let store = TestStore::new();
+    let find_blocks = store.find_blocks.clone();
+
+    let result = execute_query_document_with_store(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(block: { number: 7 }) {
+                    name
+                    mainBand { name }
+                    writtenSongs { title }
+                }
+            }
+            ",
+        ).expect("Invalid test query"),
+        store,
+        4,
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+
+    let find_blocks = find_blocks.lock().unwrap();
+    assert!(find_blocks.len() > 1);
+    assert!(
+        find_blocks
+            .iter()
+            .all(|block| *block == Some(StoreBlock::Number(7)))
+    );
+}
+
+#[test]
+fn accepts_a_bare_field_name_as_order_by() {
+    let store = TestStore::new();
+    let find_orders = store.find_orders.clone();
+
+    let result = execute_query_document_with_store(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(orderBy: name, orderDirection: desc) { name }
+            }
+            ",
+        ).expect("Invalid test query"),
+        store,
+        4,
+    );
+
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+    assert_eq!(
+        *find_orders.lock().unwrap(),
+        vec![vec![(String::from("name"), StoreOrder::Descending)]]
+    );
+}
+
+#[test]
+fn rejects_invalid_block_arguments() {
+    let result = execute_query_document(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(block: { number: -1 }) { name }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    let errors = result.errors.expect("Invalid block should be an error");
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0]
+            .to_string()
+            .starts_with("Invalid value provided for argument \"block\"")
+    );
+}
+
+#[test]
+fn returns_all_validation_errors_with_positions() {
+    let result = execute_query_document(
+        graphql_parser::parse_query(
+            "
+            query {
+                musicians(first: \"ten\") {
+                    name { first }
+                    age
+                }
+                bands { ...MissingFields }
+            }
+            ",
+        ).expect("Invalid test query"),
+    );
+
+    assert_eq!(result.data, None);
+
+    let errors = serde_json::to_value(result.errors.expect("Query should be invalid"))
+        .expect("Failed to serialize errors");
+    let expected_errors: serde_json::Value = serde_json::from_str(
+        r#"[
+            {
+                "locations": [{ "line": 3, "column": 17 }],
+                "message": "Invalid value provided for argument \"first\": String(\"ten\")"
+            },
+            {
+                "locations": [{ "line": 4, "column": 21 }],
+                "message": "Field \"name\" must not have a selection since type \"String!\" has no subfields"
+            },
+            {
+                "locations": [{ "line": 5, "column": 21 }],
+                "message": "Type \"Musician\" has no field \"age\""
+            },
+            {
+                "locations": [{ "line": 7, "column": 25 }],
+                "message": "Unknown fragment \"MissingFields\""
+            }
+        ]"#,
+    ).unwrap();
+    assert_eq!(errors, expected_errors);
+}
+
+#[test]
+fn executes_the_operation_selected_by_name() {
+    let document = graphql_parser::parse_query(
+        "
+        query Musicians { musiciansAggregate { count } }
+        query Songs { songsAggregate { count } }
+        ",
+    ).expect("Invalid test query");
+
+    let result = execute_operation(document.clone(), "Songs");
+    assert!(
+        result.errors.is_none(),
+        format!("Unexpected errors return for query: {:#?}", result.errors)
+    );
+    assert_eq!(
+        result.data,
+        Some(object_value(vec![(
+            "songsAggregate",
+            object_value(vec![("count", q::Value::Int(q::Number::from(1)))]),
+        )]))
+    );
+
+    // Without an operation name, the operation to execute is ambiguous
+    let result = execute_query_document(document.clone());
+    assert_eq!(result.data, None);
+    let errors = serde_json::to_value(result.errors.expect("Query should be ambiguous"))
+        .expect("Failed to serialize errors");
+    assert_eq!(errors[0]["message"], "Operation name required");
+
+    // Unknown operation names are rejected
+    let result = execute_operation(document, "Bands");
+    assert_eq!(result.data, None);
+    let errors = serde_json::to_value(result.errors.expect("Operation should not exist"))
+        .expect("Failed to serialize errors");
+    assert_eq!(errors[0]["message"], "Operation name not found: Bands");
+}
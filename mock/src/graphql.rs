@@ -43,4 +43,11 @@ impl GraphQlRunner for MockGraphQlRunner {
         let (_, receiver) = channel(2);
         Box::new(future::ok(Box::new(receiver) as SubscriptionResult))
     }
+
+    fn subgraph_latest_block(
+        &self,
+        _subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error> {
+        Ok(None)
+    }
 }
@@ -11,4 +11,4 @@ mod subgraph;
 pub use self::graphql::MockGraphQlRunner;
 pub use self::server::MockGraphQLServer;
 pub use self::store::{FakeStore, MockStore};
-pub use self::subgraph::MockSubgraphProvider;
+pub use self::subgraph::{MockSubgraphProvider, MockSubgraphStatusProvider};
@@ -107,8 +107,9 @@ where
                 let schema = schema.lock().unwrap();
                 Query {
                     schema: schema.clone().unwrap(),
-                    document: graphql_parser::parse_query("{ allUsers { name }}").unwrap(),
+                    document: Arc::new(graphql_parser::parse_query("{ allUsers { name }}").unwrap()),
                     variables: None,
+                    operation_name: None,
                 }
             }).collect::<Vec<Query>>();
 
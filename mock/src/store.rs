@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use graph::components::store::*;
 use graph::prelude::*;
 use graph::web3::types::{Block, Transaction, H256};
@@ -41,17 +43,54 @@ impl BasicStore for MockStore {
         }
     }
 
-    fn set(&mut self, _key: StoreKey, _entity: Entity, _source: EventSource) -> Result<(), ()> {
+    fn set(&self, _key: StoreKey, _entity: Entity, _source: EventSource) -> Result<(), ()> {
+        unimplemented!();
+    }
+
+    fn delete(&self, _key: StoreKey, _source: EventSource) -> Result<(), ()> {
+        unimplemented!();
+    }
+
+    fn transact_block_operations(
+        &self,
+        _block: EthereumBlockPointer,
+        _operations: Vec<EntityOperation>,
+        _log_entries: HashMap<String, Vec<SubgraphLogEntry>>,
+    ) -> Result<(), Error> {
+        unimplemented!();
+    }
+
+    fn subgraph_latest_block(
+        &self,
+        _subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error> {
+        unimplemented!();
+    }
+
+    fn set_subgraph_health(&self, _: &str, _: &SubgraphHealth) -> Result<(), Error> {
         unimplemented!();
     }
 
-    fn delete(&mut self, _key: StoreKey, _source: EventSource) -> Result<(), ()> {
+    fn subgraph_health(&self, _subgraph_id: &str) -> Result<SubgraphHealth, Error> {
         unimplemented!();
     }
 
     fn find(&self, _query: StoreQuery) -> Result<Vec<Entity>, ()> {
         Ok(self.entities.clone())
     }
+
+    fn aggregate(
+        &self,
+        _query: StoreQuery,
+        aggregates: Vec<StoreAggregate>,
+    ) -> Result<Vec<Value>, ()> {
+        Ok(aggregates
+            .iter()
+            .map(|aggregate| match aggregate {
+                StoreAggregate::Count => Value::Int(self.entities.len() as i32),
+                _ => Value::Null,
+            }).collect())
+    }
 }
 
 impl BlockStore for MockStore {
@@ -73,12 +112,35 @@ impl BlockStore for MockStore {
     fn attempt_head_update(&self, _: &str, _: u64) -> Result<Vec<H256>, Error> {
         unimplemented!()
     }
+
+    fn chain_head_ptr(&self, _: &str) -> Result<Option<EthereumBlockPointer>, Error> {
+        unimplemented!()
+    }
+}
+
+impl SubgraphLogStore for MockStore {
+    fn append_subgraph_logs(&self, _: &str, _: Vec<SubgraphLogEntry>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn subgraph_logs(
+        &self,
+        _: &str,
+        _: SubgraphLogLevel,
+        _: usize,
+    ) -> Result<Vec<SubgraphLogEntry>, Error> {
+        Ok(vec![])
+    }
 }
 
 impl Store for MockStore {
-    fn subscribe(&mut self, _entities: Vec<SubgraphEntityPair>) -> EntityChangeStream {
+    fn subscribe(&self, _filters: Vec<EntityChangeFilter>) -> EntityChangeStream {
         unimplemented!();
     }
+
+    fn create_fulltext_index(&self, _: String, _: String, _: Vec<Attribute>) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 pub struct FakeStore;
@@ -88,17 +150,44 @@ impl BasicStore for FakeStore {
         panic!("called FakeStore")
     }
 
-    fn set(&mut self, _: StoreKey, _: Entity, _source: EventSource) -> Result<(), ()> {
+    fn set(&self, _: StoreKey, _: Entity, _source: EventSource) -> Result<(), ()> {
         panic!("called FakeStore")
     }
 
-    fn delete(&mut self, _: StoreKey, _source: EventSource) -> Result<(), ()> {
+    fn delete(&self, _: StoreKey, _source: EventSource) -> Result<(), ()> {
+        panic!("called FakeStore")
+    }
+
+    fn transact_block_operations(
+        &self,
+        _: EthereumBlockPointer,
+        _: Vec<EntityOperation>,
+        _: HashMap<String, Vec<SubgraphLogEntry>>,
+    ) -> Result<(), Error> {
+        panic!("called FakeStore")
+    }
+
+    fn subgraph_latest_block(&self, _: &str) -> Result<Option<EthereumBlockPointer>, Error> {
+        panic!("called FakeStore")
+    }
+
+    fn set_subgraph_health(&self, _: &str, _: &SubgraphHealth) -> Result<(), Error> {
+        // Runtime tests exercise failing handlers, which mark the subgraph
+        // as failed; discard that.
+        Ok(())
+    }
+
+    fn subgraph_health(&self, _: &str) -> Result<SubgraphHealth, Error> {
         panic!("called FakeStore")
     }
 
     fn find(&self, _: StoreQuery) -> Result<Vec<Entity>, ()> {
         panic!("called FakeStore")
     }
+
+    fn aggregate(&self, _: StoreQuery, _: Vec<StoreAggregate>) -> Result<Vec<Value>, ()> {
+        panic!("called FakeStore")
+    }
 }
 
 impl BlockStore for FakeStore {
@@ -120,10 +209,33 @@ impl BlockStore for FakeStore {
     fn attempt_head_update(&self, _: &str, _: u64) -> Result<Vec<H256>, Error> {
         panic!("called FakeStore")
     }
+
+    fn chain_head_ptr(&self, _: &str) -> Result<Option<EthereumBlockPointer>, Error> {
+        panic!("called FakeStore")
+    }
+}
+
+impl SubgraphLogStore for FakeStore {
+    fn append_subgraph_logs(&self, _: &str, _: Vec<SubgraphLogEntry>) -> Result<(), Error> {
+        panic!("called FakeStore")
+    }
+
+    fn subgraph_logs(
+        &self,
+        _: &str,
+        _: SubgraphLogLevel,
+        _: usize,
+    ) -> Result<Vec<SubgraphLogEntry>, Error> {
+        panic!("called FakeStore")
+    }
 }
 
 impl Store for FakeStore {
-    fn subscribe(&mut self, _entities: Vec<SubgraphEntityPair>) -> EntityChangeStream {
+    fn subscribe(&self, _filters: Vec<EntityChangeFilter>) -> EntityChangeStream {
         unimplemented!();
     }
+
+    fn create_fulltext_index(&self, _: String, _: String, _: Vec<Attribute>) -> Result<(), Error> {
+        panic!("called FakeStore")
+    }
 }
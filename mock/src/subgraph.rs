@@ -1,5 +1,6 @@
 use futures::sync::mpsc::{channel, Receiver, Sender};
 use graphql_parser;
+use std::collections::BTreeMap;
 
 use graph::prelude::*;
 use graphql_parser::schema::Document;
@@ -100,3 +101,20 @@ impl EventProducer<SchemaEvent> for MockSubgraphProvider {
             .map(|s| Box::new(s) as Box<Stream<Item = SchemaEvent, Error = ()> + Send>)
     }
 }
+
+/// A mock `SubgraphStatusProvider` that reports no subgraphs.
+pub struct MockSubgraphStatusProvider;
+
+impl SubgraphStatusProvider for MockSubgraphStatusProvider {
+    fn subgraph_statuses(&self) -> Result<Vec<SubgraphStatus>, Error> {
+        Ok(vec![])
+    }
+
+    fn subgraph_status(&self, _: &str) -> Result<Option<SubgraphStatus>, Error> {
+        Ok(None)
+    }
+
+    fn subgraph_entity_counts(&self, _: &str) -> Result<BTreeMap<String, u64>, Error> {
+        Ok(BTreeMap::new())
+    }
+}
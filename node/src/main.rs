@@ -8,10 +8,12 @@ extern crate sentry;
 extern crate graph;
 extern crate graph_core;
 extern crate graph_datasource_ethereum;
+extern crate graph_graphql;
 extern crate graph_mock;
 extern crate graph_runtime_wasm;
 extern crate graph_server_http;
 extern crate graph_server_json_rpc;
+extern crate graph_server_metrics;
 extern crate graph_server_websocket;
 extern crate graph_store_postgres;
 extern crate http;
@@ -34,10 +36,14 @@ use graph::prelude::{JsonRpcServer as JsonRpcServerTrait, *};
 use graph::util::log::{guarded_logger, logger, register_panic_hook};
 use graph_core::SubgraphProvider as IpfsSubgraphProvider;
 use graph_datasource_ethereum::{EventLoopHandle, Transport};
+use graph_graphql::prelude::PersistedQueries;
 use graph_runtime_wasm::RuntimeHostBuilder as WASMRuntimeHostBuilder;
 use graph_server_http::GraphQLServer as GraphQLQueryServer;
 use graph_server_json_rpc::{subgraph_deploy_request, JsonRpcServer};
-use graph_server_websocket::SubscriptionServer as GraphQLSubscriptionServer;
+use graph_server_metrics::PrometheusMetricsServer;
+use graph_server_websocket::{
+    SlowClientPolicy, SubscriptionServer as GraphQLSubscriptionServer, SubscriptionServerConfig,
+};
 use graph_store_postgres::{Store as DieselStore, StoreConfig};
 
 fn main() {
@@ -70,6 +76,27 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .value_name("URL")
                 .help("Location of the Postgres database used for storing entities"),
         )
+        .arg(
+            Arg::with_name("postgres-read-pool-size")
+                .default_value("10")
+                .long("postgres-read-pool-size")
+                .value_name("N")
+                .help("Number of Postgres connections used for GraphQL queries"),
+        )
+        .arg(
+            Arg::with_name("postgres-write-pool-size")
+                .default_value("5")
+                .long("postgres-write-pool-size")
+                .value_name("N")
+                .help("Number of Postgres connections used for indexing subgraphs and blocks"),
+        )
+        .arg(
+            Arg::with_name("subgraph-log-entries")
+                .default_value("10000")
+                .long("subgraph-log-entries")
+                .value_name("N")
+                .help("Number of log entries kept per subgraph"),
+        )
         .arg(
             Arg::with_name("ethereum-rpc")
                 .takes_value(true)
@@ -105,6 +132,75 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .value_name("HOST:PORT")
                 .help("HTTP address of an IPFS node"),
         )
+        .arg(
+            Arg::with_name("query-concurrency")
+                .default_value("4")
+                .long("query-concurrency")
+                .value_name("N")
+                .help("Number of threads, shared by all GraphQL queries, that resolve root query fields concurrently"),
+        )
+        .arg(
+            Arg::with_name("query-cache-size")
+                .default_value("1000")
+                .long("query-cache-size")
+                .value_name("N")
+                .help("Maximum number of GraphQL query results to cache, 0 disables the cache"),
+        )
+        .arg(
+            Arg::with_name("ws-max-connections")
+                .default_value("1000")
+                .long("ws-max-connections")
+                .value_name("N")
+                .help("Maximum number of open GraphQL over WebSocket connections"),
+        )
+        .arg(
+            Arg::with_name("ws-max-operations")
+                .default_value("100")
+                .long("ws-max-operations")
+                .value_name("N")
+                .help("Maximum number of operations running on a single WebSocket connection"),
+        )
+        .arg(
+            Arg::with_name("ws-send-buffer-size")
+                .default_value("100")
+                .long("ws-send-buffer-size")
+                .value_name("N")
+                .help("Number of outgoing messages buffered for each WebSocket connection"),
+        )
+        .arg(
+            Arg::with_name("ws-slow-client-policy")
+                .default_value("disconnect")
+                .possible_values(&["disconnect", "drop-results"])
+                .long("ws-slow-client-policy")
+                .value_name("POLICY")
+                .help("Whether to disconnect WebSocket clients that don't keep up with their results or to drop results"),
+        )
+        .arg(
+            Arg::with_name("ws-connection-init-timeout")
+                .default_value("10")
+                .long("ws-connection-init-timeout")
+                .value_name("SECONDS")
+                .help("Time graphql-transport-ws clients have to initialise their WebSocket connection"),
+        )
+        .arg(
+            Arg::with_name("ws-keep-alive-interval")
+                .default_value("15")
+                .long("ws-keep-alive-interval")
+                .value_name("SECONDS")
+                .help("Time between keep-alive messages sent to WebSocket clients"),
+        )
+        .arg(
+            Arg::with_name("persisted-queries-cache-size")
+                .default_value("1000")
+                .long("persisted-queries-cache-size")
+                .value_name("N")
+                .help("Number of parsed GraphQL queries kept in memory"),
+        )
+        .arg(
+            Arg::with_name("store-persisted-queries")
+                .long("store-persisted-queries")
+                .help("Store persisted GraphQL queries in Postgres so they survive restarts"),
+        )
         .arg(
             Arg::with_name("admin-port")
                 .default_value("8020")
@@ -112,11 +208,35 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
                 .value_name("PORT")
                 .help("port for the admin JSON-RPC server"),
         )
+        .arg(
+            Arg::with_name("metrics-port")
+                .default_value("8040")
+                .long("metrics-port")
+                .value_name("PORT")
+                .help("port for the Prometheus metrics server"),
+        )
         .get_matches();
 
     // Safe to unwrap because a value is required by CLI
     let postgres_url = matches.value_of("postgres-url").unwrap().to_string();
 
+    // Parse the sizes of the Postgres connection pools
+    let postgres_read_pool_size = matches
+        .value_of("postgres-read-pool-size")
+        .unwrap()
+        .parse()
+        .expect("invalid Postgres read pool size");
+    let postgres_write_pool_size = matches
+        .value_of("postgres-write-pool-size")
+        .unwrap()
+        .parse()
+        .expect("invalid Postgres write pool size");
+    let max_subgraph_log_entries = matches
+        .value_of("subgraph-log-entries")
+        .unwrap()
+        .parse()
+        .expect("invalid number of subgraph log entries");
+
     // Obtain subgraph related command-line arguments
     let subgraph = matches.value_of("subgraph");
 
@@ -132,6 +252,72 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         .parse()
         .expect("invalid admin port");
 
+    // Parse metrics port
+    let metrics_port = matches
+        .value_of("metrics-port")
+        .unwrap()
+        .parse()
+        .expect("invalid metrics port");
+
+    // Parse the number of threads to resolve root query fields on
+    let query_concurrency = matches
+        .value_of("query-concurrency")
+        .unwrap()
+        .parse()
+        .expect("invalid query concurrency");
+
+    // Parse the number of query results to cache
+    let query_cache_size = matches
+        .value_of("query-cache-size")
+        .unwrap()
+        .parse()
+        .expect("invalid query cache size");
+
+    // Parse the GraphQL over WebSocket limits
+    let subscription_server_config = SubscriptionServerConfig {
+        max_connections: matches
+            .value_of("ws-max-connections")
+            .unwrap()
+            .parse()
+            .expect("invalid maximum number of WebSocket connections"),
+        max_operations_per_connection: matches
+            .value_of("ws-max-operations")
+            .unwrap()
+            .parse()
+            .expect("invalid maximum number of WebSocket operations"),
+        send_buffer_size: matches
+            .value_of("ws-send-buffer-size")
+            .unwrap()
+            .parse()
+            .expect("invalid WebSocket send buffer size"),
+        slow_client_policy: match matches.value_of("ws-slow-client-policy").unwrap() {
+            "drop-results" => SlowClientPolicy::DropResults,
+            _ => SlowClientPolicy::Disconnect,
+        },
+        connection_init_timeout: Duration::from_secs(
+            matches
+                .value_of("ws-connection-init-timeout")
+                .unwrap()
+                .parse()
+                .expect("invalid WebSocket connection init timeout"),
+        ),
+        keep_alive_interval: Duration::from_secs(
+            matches
+                .value_of("ws-keep-alive-interval")
+                .unwrap()
+                .parse()
+                .expect("invalid WebSocket keep-alive interval"),
+        ),
+    };
+
+    // Parse the persisted query options
+    let persisted_queries_cache_size = matches
+        .value_of("persisted-queries-cache-size")
+        .unwrap()
+        .parse()
+        .expect("invalid persisted queries cache size");
+    let store_persisted_queries = matches.is_present("store-persisted-queries");
+
     debug!(logger, "Setting up Sentry");
 
     // Set up Sentry, with release tracking and panic handling;
@@ -204,15 +390,20 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
 
     let mut subgraph_provider = IpfsSubgraphProvider::new(logger.clone(), ipfs_client.clone());
 
+    // Metrics reported by all components, exposed to Prometheus
+    let metrics_registry = MetricsRegistry::new();
+
     info!(logger, "Connecting to Postgres db...");
-    let store = DieselStore::new(StoreConfig { url: postgres_url }, &logger);
-    let protected_store = Arc::new(Mutex::new(store));
-    let graphql_runner = Arc::new(graph_core::GraphQlRunner::new(
+    let store = Arc::new(DieselStore::new(
+        StoreConfig {
+            url: postgres_url,
+            read_pool_size: postgres_read_pool_size,
+            write_pool_size: postgres_write_pool_size,
+            metrics: metrics_registry.clone(),
+            max_subgraph_log_entries,
+        },
         &logger,
-        protected_store.clone(),
     ));
-    let mut graphql_server = GraphQLQueryServer::new(&logger, graphql_runner.clone());
-    let mut subscription_server = GraphQLSubscriptionServer::new(&logger, graphql_runner.clone());
 
     // Create Ethereum adapter
     let (ethereum_network_name, (transport_event_loop, transport)) = ethereum_ipc
@@ -221,14 +412,47 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         .or(ethereum_rpc.map(|s| new_transport(s, &logger, Transport::new_rpc)))
         .expect("One of --ethereum-ipc, --ethereum-ws or --ethereum-rpc must be provided");
 
+    // Keep track of the status of the subgraphs hosted by this node
+    let subgraph_statuses = Arc::new(graph_core::SubgraphStatusTracker::new(
+        store.clone(),
+        ethereum_network_name.to_owned(),
+    ));
+
+    let graphql_runner = Arc::new(graph_core::GraphQlRunner::new(
+        &logger,
+        store.clone(),
+        query_concurrency,
+        query_cache_size,
+        &metrics_registry,
+    ));
+    let persisted_queries = Arc::new(if store_persisted_queries {
+        PersistedQueries::with_store(persisted_queries_cache_size, store.clone())
+    } else {
+        PersistedQueries::new(persisted_queries_cache_size)
+    });
+    let mut graphql_server = GraphQLQueryServer::new(
+        &logger,
+        graphql_runner.clone(),
+        persisted_queries.clone(),
+        subgraph_statuses.clone(),
+    );
+    let mut subscription_server = GraphQLSubscriptionServer::new(
+        &logger,
+        subscription_server_config,
+        graphql_runner.clone(),
+        persisted_queries.clone(),
+        &metrics_registry,
+    );
+
     // Create Ethereum block ingestor
     let block_ingestor = graph_datasource_ethereum::BlockIngestor::new(
-        protected_store.clone(),
+        store.clone(),
         ethereum_network_name.to_owned(),
         transport.clone(),
         400, // ancestor count, which we could make configuable
         logger.clone(),
         Duration::from_millis(500), // polling interval, which we could make configurable
+        &metrics_registry,
     ).expect("failed to create block ingestor");
     tokio::spawn(block_ingestor.into_polling_stream());
 
@@ -237,7 +461,10 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
     std::mem::forget(transport_event_loop);
 
     let ethereum_watcher = graph_datasource_ethereum::EthereumAdapter::new(
-        graph_datasource_ethereum::EthereumAdapterConfig { transport },
+        graph_datasource_ethereum::EthereumAdapterConfig {
+            transport,
+            metrics: metrics_registry.clone(),
+        },
     );
 
     match ethereum_watcher.block_number().wait() {
@@ -253,10 +480,15 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
         &logger,
         Arc::new(Mutex::new(ethereum_watcher)),
         ipfs_client,
-        protected_store.clone(),
+        store.clone(),
+        &metrics_registry,
+    );
+    let runtime_manager = graph_core::RuntimeManager::new(
+        &logger,
+        store.clone(),
+        runtime_host_builder,
+        subgraph_statuses,
     );
-    let runtime_manager =
-        graph_core::RuntimeManager::new(&logger, protected_store.clone(), runtime_host_builder);
 
     // Forward subgraph events from the subgraph provider to the runtime manager
     tokio::spawn(forward(&mut subgraph_provider, &runtime_manager).unwrap());
@@ -275,9 +507,12 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
     );
 
     // Start admin JSON-RPC server.
-    let json_rpc_server =
-        JsonRpcServer::serve(json_rpc_port, Arc::new(subgraph_provider), logger.clone())
-            .expect("Failed to start admin server");
+    let json_rpc_server = JsonRpcServer::serve(
+        json_rpc_port,
+        Arc::new(subgraph_provider),
+        store.clone(),
+        logger.clone(),
+    ).expect("Failed to start admin server");
 
     // Let the server run forever.
     std::mem::forget(json_rpc_server);
@@ -325,6 +560,14 @@ fn async_main() -> impl Future<Item = (), Error = ()> + Send + 'static {
             .expect("Failed to start GraphQL subscription server"),
     );
 
+    // Serve metrics to Prometheus
+    let mut metrics_server = PrometheusMetricsServer::new(&logger, metrics_registry);
+    tokio::spawn(
+        metrics_server
+            .serve(metrics_port)
+            .expect("Failed to start metrics server"),
+    );
+
     future::empty()
 }
 
@@ -6,6 +6,7 @@ use std::thread;
 use uuid::Uuid;
 
 use graph::components::ethereum::*;
+use graph::components::metrics::HistogramVec;
 use graph::components::store::Store;
 use graph::components::subgraph::RuntimeHostEvent;
 use graph::data::subgraph::DataSource;
@@ -27,7 +28,8 @@ pub struct RuntimeHostBuilder<T, L, S> {
     logger: Logger,
     ethereum_adapter: Arc<Mutex<T>>,
     link_resolver: Arc<L>,
-    store: Arc<Mutex<S>>,
+    store: Arc<S>,
+    handler_execution_time: HistogramVec,
 }
 
 impl<T, L, S> RuntimeHostBuilder<T, L, S>
@@ -40,13 +42,20 @@ where
         logger: &Logger,
         ethereum_adapter: Arc<Mutex<T>>,
         link_resolver: Arc<L>,
-        store: Arc<Mutex<S>>,
+        store: Arc<S>,
+        metrics: &MetricsRegistry,
     ) -> Self {
         RuntimeHostBuilder {
             logger: logger.new(o!("component" => "RuntimeHostBuilder")),
             ethereum_adapter,
             link_resolver,
             store,
+            handler_execution_time: metrics
+                .new_histogram_vec(
+                    "subgraph_handler_execution_time_seconds",
+                    "Time it takes to run an event handler of a subgraph",
+                    &["subgraph", "handler"],
+                ).expect("Failed to register handler execution time histogram"),
         }
     }
 }
@@ -69,6 +78,7 @@ where
             self.ethereum_adapter.clone(),
             self.link_resolver.clone(),
             self.store.clone(),
+            self.handler_execution_time.clone(),
             RuntimeHostConfig {
                 subgraph_manifest,
                 data_source,
@@ -89,7 +99,8 @@ impl RuntimeHost {
         logger: &Logger,
         ethereum_adapter: Arc<Mutex<T>>,
         link_resolver: Arc<L>,
-        store: Arc<Mutex<S>>,
+        store: Arc<S>,
+        handler_execution_time: HistogramVec,
         config: RuntimeHostConfig,
     ) -> Self
     where
@@ -109,6 +120,7 @@ impl RuntimeHost {
             ethereum_adapter: ethereum_adapter.clone(),
             link_resolver: link_resolver.clone(),
             store: store.clone(),
+            handler_execution_time,
         };
 
         let name = config.data_source.name.clone();
@@ -244,7 +256,7 @@ impl RuntimeHost {
 
                 if event.removed {
                     info!(event_logger, "Event removed";
-                          "block" => event.block_hash.to_string());
+                          "block" => event.block.hash.to_string());
                 } else {
                     let event_handler = data_source
                         .mapping
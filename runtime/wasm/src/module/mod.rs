@@ -2,6 +2,7 @@ use futures::sync::mpsc::Sender;
 use nan_preserving_float::F64;
 use std::collections::HashMap;
 use std::fmt;
+use std::mem;
 use std::str::FromStr;
 use std::sync::Mutex;
 use tiny_keccak;
@@ -13,7 +14,8 @@ use wasmi::{
 
 use futures::sync::oneshot;
 use graph::components::ethereum::*;
-use graph::components::store::{EventSource, StoreKey};
+use graph::components::metrics::HistogramVec;
+use graph::components::store::{EntityOperation, StoreKey};
 use graph::components::subgraph::RuntimeHostEvent;
 use graph::data::store::scalar;
 use graph::data::subgraph::DataSource;
@@ -90,6 +92,7 @@ const IPFS_CAT_FUNC_INDEX: usize = 19;
 const STORE_GET_FUNC_INDEX: usize = 20;
 const TYPE_CONVERSION_BIG_INT_FUNC_TO_INT256_INDEX: usize = 21;
 const CRYPTO_KECCAK_256_INDEX: usize = 22;
+const LOG_LOG_INDEX: usize = 23;
 
 pub struct WasmiModuleConfig<T, L, S> {
     pub subgraph: SubgraphManifest,
@@ -97,7 +100,9 @@ pub struct WasmiModuleConfig<T, L, S> {
     pub event_sink: Sender<RuntimeHostEvent>,
     pub ethereum_adapter: Arc<Mutex<T>>,
     pub link_resolver: Arc<L>,
-    pub store: Arc<Mutex<S>>,
+    pub store: Arc<S>,
+    /// Time it takes to run event handlers, by subgraph and handler.
+    pub handler_execution_time: HistogramVec,
 }
 
 impl<T, L, S> Clone for WasmiModuleConfig<T, L, S> {
@@ -109,6 +114,7 @@ impl<T, L, S> Clone for WasmiModuleConfig<T, L, S> {
             ethereum_adapter: self.ethereum_adapter.clone(),
             link_resolver: self.link_resolver.clone(),
             store: self.store.clone(),
+            handler_execution_time: self.handler_execution_time.clone(),
         }
     }
 }
@@ -119,6 +125,7 @@ pub struct WasmiModule<T, L, S, U> {
     pub module: ModuleRef,
     externals: HostExternals<T, L, S, U>,
     heap: WasmiAscHeap,
+    handler_execution_time: HistogramVec,
 }
 
 impl<T, L, S, U> WasmiModule<T, L, S, U>
@@ -149,6 +156,7 @@ where
         imports.push_resolver("json", &JsonModuleResolver);
         imports.push_resolver("ipfs", &IpfsModuleResolver);
         imports.push_resolver("crypto", &CryptoModuleResolver);
+        imports.push_resolver("log", &LogModuleResolver);
 
         // Instantiate the runtime module using hosted functions and import resolver
         let module =
@@ -175,7 +183,9 @@ where
             heap: heap.clone(),
             ethereum_adapter: config.ethereum_adapter.clone(),
             link_resolver: config.link_resolver.clone(),
-            block_hash: H256::zero(),
+            block: EthereumBlockPointer::from((H256::zero(), 0u64)),
+            entity_operations: vec![],
+            log_entries: vec![],
             store: config.store.clone(),
             task_sink,
         };
@@ -189,22 +199,76 @@ where
             module,
             externals,
             heap,
+            handler_execution_time: config.handler_execution_time,
         }
     }
 
     pub fn handle_ethereum_event(&mut self, handler_name: &str, event: EthereumEvent) {
-        self.externals.block_hash = event.block_hash.clone();
-        self.module
-            .invoke_export(
-                handler_name,
-                &[RuntimeValue::from(self.heap.asc_new(&event))],
-                &mut self.externals,
-            ).unwrap_or_else(|e| {
+        let block = event.block;
+        self.externals.block = block;
+        self.externals.entity_operations.clear();
+        self.externals.log_entries.clear();
+
+        let timer = self
+            .handler_execution_time
+            .with_label_values(&[self.externals.subgraph.id.as_str(), handler_name])
+            .start_timer();
+        let result = self.module.invoke_export(
+            handler_name,
+            &[RuntimeValue::from(self.heap.asc_new(&event))],
+            &mut self.externals,
+        );
+        timer.observe_duration();
+
+        // Only apply the entity operations of handlers that succeeded, and
+        // apply all of them at once
+        let operations = mem::replace(&mut self.externals.entity_operations, vec![]);
+        match result {
+            Ok(_) => {
+                if !operations.is_empty() {
+                    self.send_event(RuntimeHostEvent::EntityOperations(block, operations));
+                }
+            }
+            Err(e) => {
                 warn!(self.logger, "Failed to handle Ethereum event";
                       "handler" => &handler_name,
                       "error" => format!("{}", e));
-                None
-            });
+                let message =
+                    format!("Failed to handle Ethereum event with `{}`: {}", handler_name, e);
+                self.externals
+                    .append_subgraph_log(SubgraphLogLevel::Error, message.clone());
+
+                // Report the subgraph as failed, since the entities of the
+                // failed handler are missing from the store
+                if let Err(e) = self.externals.store.set_subgraph_health(
+                    &self.externals.subgraph.id,
+                    &SubgraphHealth::Failed(message),
+                ) {
+                    error!(self.logger, "Failed to record subgraph failure";
+                           "error" => format!("{}", e));
+                }
+            }
+        }
+
+        // Store the log entries of the handler along with the entity
+        // operations of the block, whether or not the handler succeeded
+        let log_entries = mem::replace(&mut self.externals.log_entries, vec![]);
+        if !log_entries.is_empty() {
+            self.send_event(RuntimeHostEvent::LogEntries(block, log_entries));
+        }
+    }
+
+    fn send_event(&self, event: RuntimeHostEvent) {
+        let logger = self.logger.clone();
+        self.externals
+            .event_sink
+            .clone()
+            .send(event)
+            .map_err(move |e| {
+                error!(logger, "Failed to forward runtime host event";
+                       "error" => format!("{}", e));
+            }).wait()
+            .ok();
     }
 }
 
@@ -235,9 +299,13 @@ pub struct HostExternals<T, L, S, U> {
     heap: WasmiAscHeap,
     ethereum_adapter: Arc<Mutex<T>>,
     link_resolver: Arc<L>,
-    // Block hash of the event being mapped.
-    block_hash: H256,
-    store: Arc<Mutex<S>>,
+    // Block of the event being mapped.
+    block: EthereumBlockPointer,
+    // Entity operations made by the event handler so far.
+    entity_operations: Vec<EntityOperation>,
+    // Entries logged by the event handler so far.
+    log_entries: Vec<SubgraphLogEntry>,
+    store: Arc<S>,
     task_sink: U,
 }
 
@@ -250,68 +318,45 @@ where
 {
     /// function store.set(entity: string, id: string, data: Entity): void
     fn store_set(
-        &self,
+        &mut self,
         entity_ptr: AscPtr<AscString>,
         id_ptr: AscPtr<AscString>,
         data_ptr: AscPtr<AscEntity>,
     ) -> Result<Option<RuntimeValue>, Trap> {
-        let block_hash: H256 = self.block_hash.clone();
         let entity: String = self.heap.asc_get(entity_ptr);
         let id: String = self.heap.asc_get(id_ptr);
         let data: HashMap<String, Value> = self.heap.asc_get(data_ptr);
-        let store_key = StoreKey {
+        let key = StoreKey {
             subgraph: self.subgraph.id.clone(),
             entity,
             id,
         };
 
-        let entity_data = Entity::from(data);
-
-        // Send an entity set event
-        let logger = self.logger.clone();
-        self.event_sink
-            .clone()
-            .send(RuntimeHostEvent::EntitySet(
-                store_key,
-                entity_data,
-                EventSource::EthereumBlock(block_hash),
-            )).map_err(move |e| {
-                error!(logger, "Failed to forward runtime host event";
-                        "error" => format!("{}", e));
-            }).wait()
-            .ok();
+        // Record the operation; it is applied once the event handler is done
+        self.entity_operations.push(EntityOperation::Set {
+            key,
+            data: Entity::from(data),
+        });
 
         Ok(None)
     }
 
     /// function store.remove(entity: string, id: string): void
     fn store_remove(
-        &self,
+        &mut self,
         entity_ptr: AscPtr<AscString>,
         id_ptr: AscPtr<AscString>,
     ) -> Result<Option<RuntimeValue>, Trap> {
-        let block_hash: H256 = self.block_hash.clone();
         let entity: String = self.heap.asc_get(entity_ptr);
         let id: String = self.heap.asc_get(id_ptr);
-        let store_key = StoreKey {
+        let key = StoreKey {
             subgraph: self.subgraph.id.clone(),
             entity,
             id,
         };
 
-        // Send an entity removed event
-        let logger = self.logger.clone();
-        self.event_sink
-            .clone()
-            .send(RuntimeHostEvent::EntityRemoved(
-                store_key,
-                EventSource::EthereumBlock(block_hash),
-            )).map_err(move |e| {
-                error!(logger, "Failed to forward runtime host event";
-                        "error" => format!("{}", e));
-            }).and_then(|_| Ok(()))
-            .wait()
-            .ok();
+        // Record the operation; it is applied once the event handler is done
+        self.entity_operations.push(EntityOperation::Remove { key });
 
         Ok(None)
     }
@@ -328,13 +373,28 @@ where
             id: self.heap.asc_get(id_ptr),
         };
 
-        // Retrieve an Entity from the store
-        self.store
-            .lock()
-            .unwrap()
-            .get(store_key)
-            .and_then(|result| Ok(Some(RuntimeValue::from(self.heap.asc_new(&result)))))
-            .or(Ok(Some(RuntimeValue::from(0))))
+        // Retrieve an Entity from the store and apply the operations the event
+        // handler has made to it so far
+        let entity = self
+            .entity_operations
+            .iter()
+            .filter(|operation| operation.key() == &store_key)
+            .fold(
+                self.store.get(store_key.clone()).ok(),
+                |entity, operation| match operation {
+                    EntityOperation::Set { data, .. } => {
+                        let mut entity = entity.unwrap_or_else(Entity::new);
+                        entity.merge(data.clone());
+                        Some(entity)
+                    }
+                    EntityOperation::Remove { .. } => None,
+                },
+            );
+
+        match entity {
+            Some(entity) => Ok(Some(RuntimeValue::from(self.heap.asc_new(&entity)))),
+            None => Ok(Some(RuntimeValue::from(0))),
+        }
     }
 
     /// function ethereum.call(call: SmartContractCall): Array<Token>
@@ -373,7 +433,7 @@ where
 
         let call = EthereumContractCall {
             address: unresolved_call.contract_address.clone(),
-            block_id: BlockId::Hash(self.block_hash.clone()),
+            block_id: BlockId::Hash(self.block.hash),
             function: function.clone(),
             args: unresolved_call.function_args.clone(),
         };
@@ -599,6 +659,30 @@ where
         Ok(Some(RuntimeValue::from(hash_ptr)))
     }
 
+    /// function log.log(level: i32, msg: string): void
+    fn log_log(&mut self, level: i32, msg: AscPtr<AscString>) -> Result<Option<RuntimeValue>, Trap> {
+        let level = SubgraphLogLevel::from_mapping_level(level)
+            .ok_or_else(|| host_error(format!("Invalid log level {}", level)))?;
+        let msg: String = self.heap.asc_get(msg);
+
+        match level {
+            SubgraphLogLevel::Critical => crit!(self.logger, "{}", msg),
+            SubgraphLogLevel::Error => error!(self.logger, "{}", msg),
+            SubgraphLogLevel::Warning => warn!(self.logger, "{}", msg),
+            SubgraphLogLevel::Info => info!(self.logger, "{}", msg),
+            SubgraphLogLevel::Debug => debug!(self.logger, "{}", msg),
+        }
+        self.append_subgraph_log(level, msg);
+        Ok(None)
+    }
+
+    /// Adds an entry to the log of the subgraph. Entries are stored once the
+    /// event handler is done, along with its entity operations.
+    fn append_subgraph_log(&mut self, level: SubgraphLogLevel, message: String) {
+        let entry = SubgraphLogEntry::new(level, message, Some(self.block.number));
+        self.log_entries.push(entry);
+    }
+
     fn block_on<I: Send + 'static, E: Send + 'static>(
         &self,
         future: impl Future<Item = I, Error = E> + Send + 'static,
@@ -666,6 +750,7 @@ where
                 self.big_int_to_int256(args.nth_checked(0)?)
             }
             CRYPTO_KECCAK_256_INDEX => self.crypto_keccak_256(args.nth_checked(0)?),
+            LOG_LOG_INDEX => self.log_log(args.nth_checked(0)?, args.nth_checked(1)?),
             _ => panic!("Unimplemented function at {}", index),
         }
     }
@@ -879,3 +964,22 @@ impl ModuleImportResolver for CryptoModuleResolver {
         })
     }
 }
+
+struct LogModuleResolver;
+
+impl ModuleImportResolver for LogModuleResolver {
+    fn resolve_func(&self, field_name: &str, _signature: &Signature) -> Result<FuncRef, Error> {
+        Ok(match field_name {
+            "log" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32, ValueType::I32][..], None),
+                LOG_LOG_INDEX,
+            ),
+            _ => {
+                return Err(Error::Instantiation(format!(
+                    "Export '{}' not found",
+                    field_name
+                )))
+            }
+        })
+    }
+}
@@ -74,7 +74,10 @@ fn test_module(
                 event_sink: sender,
                 ethereum_adapter: mock_ethereum_adapter,
                 link_resolver: Arc::new(ipfs_api::IpfsClient::default()),
-                store: Arc::new(Mutex::new(FakeStore)),
+                store: Arc::new(FakeStore),
+                handler_execution_time: MetricsRegistry::new()
+                    .new_histogram_vec("handler_execution_time", "Test", &["subgraph", "handler"])
+                    .unwrap(),
             },
             task_sender,
         ),
@@ -131,7 +134,10 @@ fn call_invalid_event_handler_and_dont_crash() {
     let ethereum_event = EthereumEvent {
         address: Address::from("22843e74c59580b3eaf6c233fa67d8b7c561a835"),
         event_signature: util::ethereum::string_to_h256("ExampleEvent(string)"),
-        block_hash: util::ethereum::string_to_h256("example block hash"),
+        block: EthereumBlockPointer::from((
+            util::ethereum::string_to_h256("example block hash"),
+            1u64,
+        )),
         params: vec![LogParam {
             name: String::from("exampleParam"),
             value: Token::String(String::from("some data")),
@@ -147,6 +153,45 @@ fn call_invalid_event_handler_and_dont_crash() {
     );
 }
 
+#[test]
+fn failed_event_handlers_log_an_error() {
+    let (mut module, receiver) =
+        test_module(mock_data_source("wasm_test/example_event_handler.wasm"));
+
+    let block = EthereumBlockPointer::from((
+        util::ethereum::string_to_h256("example block hash"),
+        1u64,
+    ));
+    let ethereum_event = EthereumEvent {
+        address: Address::from("22843e74c59580b3eaf6c233fa67d8b7c561a835"),
+        event_signature: util::ethereum::string_to_h256("ExampleEvent(string)"),
+        block,
+        params: vec![],
+        removed: false,
+    };
+    module.handle_ethereum_event("handleNonExistentExampleEvent", ethereum_event);
+
+    // The error is sent to the runtime manager for storing, rather than
+    // written to the store by the handler
+    let event = receiver
+        .take(1)
+        .into_future()
+        .wait()
+        .expect("No event received from runtime")
+        .0
+        .expect("Event must not be None");
+    match event {
+        RuntimeHostEvent::LogEntries(entry_block, entries) => {
+            assert_eq!(entry_block, block);
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].level, SubgraphLogLevel::Error);
+            assert_eq!(entries[0].block_number, Some(1));
+            assert!(entries[0].message.contains("handleNonExistentExampleEvent"));
+        }
+        event => panic!("Unexpected event: {:?}", event),
+    }
+}
+
 #[test]
 fn call_event_handler_and_receive_store_event() {
     // Load the example_event_handler.wasm test module. All this module does
@@ -163,7 +208,10 @@ fn call_event_handler_and_receive_store_event() {
     let ethereum_event = EthereumEvent {
         address: Address::from("22843e74c59580b3eaf6c233fa67d8b7c561a835"),
         event_signature: util::ethereum::string_to_h256("ExampleEvent(string)"),
-        block_hash: util::ethereum::string_to_h256("example block hash"),
+        block: EthereumBlockPointer::from((
+            util::ethereum::string_to_h256("example block hash"),
+            1u64,
+        )),
         params: vec![LogParam {
             name: String::from("exampleParam"),
             value: Token::String(String::from("some data")),
@@ -175,7 +223,7 @@ fn call_event_handler_and_receive_store_event() {
     module.handle_ethereum_event("handleExampleEvent", ethereum_event);
 
     // Expect a store set call to be made by the handler and a
-    // RuntimeHostEvent::EntitySet event to be written to the event stream
+    // RuntimeHostEvent::EntityOperations event to be written to the event stream
     let work = receiver.take(1).into_future();
     let store_event = work
         .wait()
@@ -186,16 +234,21 @@ fn call_event_handler_and_receive_store_event() {
     // Verify that this event matches what the test module is sending
     assert_eq!(
         store_event,
-        RuntimeHostEvent::EntitySet(
-            StoreKey {
-                subgraph: String::from("example subgraph"),
-                entity: String::from("ExampleEntity"),
-                id: String::from("example id"),
-            },
-            Entity::from(HashMap::from_iter(
-                vec![(String::from("exampleAttribute"), Value::from("some data"))].into_iter()
+        RuntimeHostEvent::EntityOperations(
+            EthereumBlockPointer::from((
+                util::ethereum::string_to_h256("example block hash"),
+                1u64,
             )),
-            EventSource::EthereumBlock(util::ethereum::string_to_h256("example block hash",)),
+            vec![EntityOperation::Set {
+                key: StoreKey {
+                    subgraph: String::from("example subgraph"),
+                    entity: String::from("ExampleEntity"),
+                    id: String::from("example id"),
+                },
+                data: Entity::from(HashMap::from_iter(
+                    vec![(String::from("exampleAttribute"), Value::from("some data"))].into_iter()
+                )),
+            }],
         )
     );
 }
@@ -0,0 +1,35 @@
+use http::header;
+use hyper::{Body, Response, StatusCode};
+
+use graph::components::server::query::GraphQLServerError;
+use graph::prelude::*;
+use graph::serde_json;
+
+/// Formats a single Server-Sent Event.
+fn event(name: &str, data: &str) -> String {
+    format!("event: {}\ndata: {}\n\n", name, data)
+}
+
+/// Creates an HTTP response that streams the results of a subscription to
+/// the client as Server-Sent Events.
+///
+/// Each result is sent as a `next` event with the JSON-encoded result as its
+/// data. A `complete` event is sent once the result stream ends.
+pub fn event_stream_response(results: QueryResultStream) -> Response<Body> {
+    let events = results
+        .map(|result| {
+            let json = serde_json::to_string(&result)
+                .expect("Failed to serialize GraphQL result to JSON");
+            event("next", &json)
+        }).chain(stream::once(Ok(event("complete", ""))))
+        .map_err(|_| GraphQLServerError::from("Failed to stream subscription results"));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Headers", "Content-Type")
+        .body(Body::wrap_stream(events))
+        .unwrap()
+}
@@ -0,0 +1,253 @@
+use graphql_parser;
+use graphql_parser::{query as q, schema as s};
+use std::collections::{BTreeMap, HashMap};
+
+use graph::components::server::query::GraphQLServerError;
+use graph::prelude::*;
+use graph_graphql::prelude::{object_value, Resolver};
+
+/// The schema of the index-node endpoint, which reports the status of the
+/// subgraphs hosted by this node.
+const INDEX_NODE_SCHEMA: &'static str = "
+scalar BigInt
+scalar ID
+scalar String
+
+type Query {
+  # All subgraphs hosted by this node, ordered by ID
+  subgraphs: [Subgraph!]!
+  # Looks up a subgraph by name or ID
+  subgraph(id: ID!): Subgraph
+}
+
+type Subgraph {
+  id: ID!
+  name: String
+  # Location of the subgraph manifest
+  location: String!
+  dataSources: [DataSource!]!
+  health: Health!
+  # The error that made indexing the subgraph fail
+  error: String
+  # The latest block whose entity changes have been stored
+  latestBlock: Block
+  # The head block of the chain the subgraph indexes
+  chainHeadBlock: Block
+  entityCounts: [EntityCount!]!
+}
+
+enum Health {
+  healthy
+  failed
+}
+
+type DataSource {
+  kind: String!
+  name: String!
+  address: String!
+  abi: String!
+}
+
+type Block {
+  hash: String!
+  number: BigInt!
+}
+
+type EntityCount {
+  entity: String!
+  count: BigInt!
+}
+";
+
+/// Returns the schema of the index-node endpoint.
+pub fn index_node_schema() -> Schema {
+    Schema {
+        name: String::from("index-node"),
+        id: String::from("index-node"),
+        document: graphql_parser::parse_schema(INDEX_NODE_SCHEMA)
+            .expect("Failed to parse index-node schema"),
+    }
+}
+
+/// Builds the index-node object for a subgraph status. `names` maps
+/// subgraph IDs to names.
+fn subgraph_object(status: SubgraphStatus, names: &BTreeMap<String, String>) -> q::Value {
+    let (health, error) = match status.health {
+        SubgraphHealth::Healthy => ("healthy", q::Value::Null),
+        SubgraphHealth::Failed(error) => ("failed", q::Value::String(error)),
+    };
+
+    object_value(vec![
+        (
+            "name",
+            names
+                .get(&status.id)
+                .map_or(q::Value::Null, |name| q::Value::String(name.clone())),
+        ),
+        ("id", q::Value::String(status.id)),
+        ("location", q::Value::String(status.location)),
+        (
+            "dataSources",
+            q::Value::List(
+                status
+                    .data_sources
+                    .into_iter()
+                    .map(|data_source| {
+                        object_value(vec![
+                            ("kind", q::Value::String(data_source.kind)),
+                            ("name", q::Value::String(data_source.name)),
+                            ("address", q::Value::String(data_source.source.address)),
+                            ("abi", q::Value::String(data_source.source.abi)),
+                        ])
+                    }).collect(),
+            ),
+        ),
+        ("health", q::Value::Enum(health.to_owned())),
+        ("error", error),
+        ("latestBlock", block_object(status.latest_block)),
+        ("chainHeadBlock", block_object(status.chain_head_block)),
+    ])
+}
+
+fn block_object(block: Option<EthereumBlockPointer>) -> q::Value {
+    block.map_or(q::Value::Null, |block| {
+        object_value(vec![
+            ("hash", q::Value::String(format!("{:#x}", block.hash))),
+            ("number", q::Value::String(block.number.to_string())),
+        ])
+    })
+}
+
+/// Runs an index-node query, looking up only the subgraph statuses that the
+/// query selects.
+pub fn execute_index_node_query(
+    logger: &Logger,
+    query: Query,
+    status_provider: Arc<SubgraphStatusProvider>,
+    names: &BTreeMap<String, String>,
+) -> Result<QueryResult, GraphQLServerError> {
+    Ok(graph_graphql::prelude::execute_query(
+        &query,
+        graph_graphql::prelude::QueryExecutionOptions {
+            logger: logger.clone(),
+            resolver: IndexNodeResolver {
+                status_provider,
+                names: names.clone(),
+            },
+            thread_pool: graph_graphql::prelude::QueryThreadPool::sequential(),
+        },
+    ))
+}
+
+/// Resolves index-node queries by asking the status provider for the
+/// subgraphs and fields that are selected.
+#[derive(Clone)]
+struct IndexNodeResolver {
+    status_provider: Arc<SubgraphStatusProvider>,
+    // Maps IDs to names.
+    names: BTreeMap<String, String>,
+}
+
+impl IndexNodeResolver {
+    /// Looks up the status of a subgraph by ID or, failing that, by name.
+    fn subgraph_status(&self, name_or_id: &str) -> Result<Option<SubgraphStatus>, Error> {
+        if let Some(status) = self.status_provider.subgraph_status(name_or_id)? {
+            return Ok(Some(status));
+        }
+
+        match self.names.iter().find(|(_, name)| *name == name_or_id) {
+            Some((id, _)) => self.status_provider.subgraph_status(id),
+            None => Ok(None),
+        }
+    }
+}
+
+fn object_field<'a>(object: &'a q::Value, field: &str) -> Option<&'a q::Value> {
+    match object {
+        q::Value::Object(data) => data.get(field),
+        _ => None,
+    }
+}
+
+fn parent_field(parent: &Option<q::Value>, field: &str) -> q::Value {
+    parent
+        .as_ref()
+        .and_then(|parent| object_field(parent, field))
+        .cloned()
+        .unwrap_or(q::Value::Null)
+}
+
+fn status_error(e: Error) -> QueryExecutionError {
+    QueryExecutionError::StoreError(format!("Failed to load subgraph status: {}", e))
+}
+
+impl Resolver for IndexNodeResolver {
+    fn resolve_objects(
+        &self,
+        parent: &Option<q::Value>,
+        field: &q::Name,
+        _field_definition: &s::Field,
+        _object_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        match (parent, field.as_str()) {
+            (None, "subgraphs") => Ok(q::Value::List(
+                self.status_provider
+                    .subgraph_statuses()
+                    .map_err(status_error)?
+                    .into_iter()
+                    .map(|status| subgraph_object(status, &self.names))
+                    .collect(),
+            )),
+
+            // Entity counts are expensive, so only count entities if asked to
+            (Some(subgraph), "entityCounts") => {
+                let id = match object_field(subgraph, "id") {
+                    Some(q::Value::String(id)) => id,
+                    _ => return Ok(q::Value::Null),
+                };
+
+                Ok(q::Value::List(
+                    self.status_provider
+                        .subgraph_entity_counts(id)
+                        .map_err(status_error)?
+                        .into_iter()
+                        .map(|(entity, count)| {
+                            object_value(vec![
+                                ("entity", q::Value::String(entity)),
+                                ("count", q::Value::String(count.to_string())),
+                            ])
+                        }).collect(),
+                ))
+            }
+
+            _ => Ok(parent_field(parent, field)),
+        }
+    }
+
+    fn resolve_object(
+        &self,
+        parent: &Option<q::Value>,
+        field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: &s::ObjectType,
+        arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        match (parent, field.name.as_str()) {
+            (None, "subgraph") => {
+                let name_or_id = match arguments.get(&String::from("id")) {
+                    Some(q::Value::String(name_or_id)) => name_or_id,
+                    _ => return Ok(q::Value::Null),
+                };
+
+                Ok(self
+                    .subgraph_status(name_or_id)
+                    .map_err(status_error)?
+                    .map_or(q::Value::Null, |status| {
+                        subgraph_object(status, &self.names)
+                    }))
+            }
+            _ => Ok(parent_field(parent, &field.name)),
+        }
+    }
+}
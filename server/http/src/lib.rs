@@ -6,15 +6,21 @@ extern crate http;
 extern crate hyper;
 extern crate itertools;
 extern crate serde;
+extern crate tokio_threadpool;
+extern crate url;
 
+mod event_stream;
+mod index_node;
 mod request;
 mod response;
 mod server;
 mod service;
+mod stitching;
 
-pub use self::request::GraphQLRequest;
-pub use self::response::GraphQLResponse;
+pub use self::request::{GraphQLRequest, RequestQueries};
+pub use self::response::{GraphQLBatchResponse, GraphQLResponse};
 pub use self::server::GraphQLServer;
 pub use self::service::{GraphQLService, GraphQLServiceResponse};
+pub use self::stitching::StitchedSchema;
 
 pub mod test_utils;
@@ -1,25 +1,180 @@
 use graph::serde_json;
-use graphql_parser;
+use graphql_parser::query as q;
 use hyper::Chunk;
+use url::form_urlencoded;
 
 use graph::components::server::query::GraphQLServerError;
 use graph::prelude::*;
+use graph_graphql::prelude::{PersistedQueries, PersistedQueryError};
+use graph_graphql::query::ast::get_operation;
 
-/// Future for a query parsed from an HTTP request.
+/// The maximum number of queries that can be sent in a single batch.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Queries parsed from an HTTP request; requests contain either a single
+/// query object or an array of them.
+#[derive(Debug)]
+pub enum RequestQueries {
+    Single(Query),
+    Batch(Vec<Query>),
+}
+
+impl RequestQueries {
+    /// Returns the query of a non-batched request.
+    pub fn single(self) -> Result<Query, GraphQLServerError> {
+        match self {
+            RequestQueries::Single(query) => Ok(query),
+            RequestQueries::Batch(_) => Err(GraphQLServerError::ClientError(String::from(
+                "Batched requests are not supported here",
+            ))),
+        }
+    }
+}
+
+/// Future for queries parsed from an HTTP request.
 pub struct GraphQLRequest {
     body: Chunk,
     schema: Schema,
+    persisted_queries: Arc<PersistedQueries>,
 }
 
 impl GraphQLRequest {
     /// Creates a new GraphQLRequest future based on an HTTP request and a result sender.
-    pub fn new(body: Chunk, schema: Schema) -> Self {
-        GraphQLRequest { body, schema }
+    pub fn new(body: Chunk, schema: Schema, persisted_queries: Arc<PersistedQueries>) -> Self {
+        GraphQLRequest {
+            body,
+            schema,
+            persisted_queries,
+        }
+    }
+
+    /// Parses a query from the query string of a GET request, i.e.
+    /// `query=...&variables=...&operationName=...&extensions=...`.
+    ///
+    /// Only query operations are accepted, since responses to GET requests
+    /// may be cached.
+    pub fn from_query_string(
+        query_string: &str,
+        schema: Schema,
+        persisted_queries: &PersistedQueries,
+    ) -> Result<Query, GraphQLServerError> {
+        let query = Self::from_stream_query_string(query_string, schema, persisted_queries)?;
+
+        // Reject mutations and subscriptions
+        let is_query = {
+            let operation_name = query.operation_name.as_ref().map(|name| name.as_str());
+            let operation = get_operation(&query.document, operation_name)
+                .map_err(|e| GraphQLServerError::from(QueryError::from(e)))?;
+
+            match operation {
+                q::OperationDefinition::Query(_) | q::OperationDefinition::SelectionSet(_) => true,
+                _ => false,
+            }
+        };
+
+        if is_query {
+            Ok(query)
+        } else {
+            Err(GraphQLServerError::ClientError(String::from(
+                "Only queries can be sent in GET requests",
+            )))
+        }
+    }
+
+    /// Parses a subscription from the query string of a GET request to an
+    /// event stream, which browsers' `EventSource` can only open with GET.
+    ///
+    /// Event streams are never cached, so unlike `from_query_string`, this
+    /// accepts the same operations as a POST request to the stream.
+    pub fn from_stream_query_string(
+        query_string: &str,
+        schema: Schema,
+        persisted_queries: &PersistedQueries,
+    ) -> Result<Query, GraphQLServerError> {
+        // Collect the parameters into the same shape as a POST request body
+        let mut obj = serde_json::Map::new();
+        for (key, value) in form_urlencoded::parse(query_string.as_bytes()) {
+            let value = match key.as_ref() {
+                "query" | "operationName" => serde_json::Value::String(value.into_owned()),
+                "variables" => serde_json::from_str(&value).map_err(|e| {
+                    GraphQLServerError::ClientError(format!("Invalid query variables: {}", e))
+                })?,
+                "extensions" => serde_json::from_str(&value).map_err(|e| {
+                    GraphQLServerError::ClientError(format!("Invalid query extensions: {}", e))
+                })?,
+                _ => continue,
+            };
+            obj.insert(key.into_owned(), value);
+        }
+
+        query_from_object(&obj, schema, persisted_queries)
     }
 }
 
+/// Converts errors resolving a query document into server errors.
+fn persisted_query_error(e: PersistedQueryError) -> GraphQLServerError {
+    match e {
+        PersistedQueryError::ParseError(e) => GraphQLServerError::from(e),
+        PersistedQueryError::StoreError(_) => GraphQLServerError::InternalError(e.to_string()),
+        _ => GraphQLServerError::ClientError(e.to_string()),
+    }
+}
+
+/// Parses the "query", "variables", "operationName" and "extensions" fields
+/// of a request into a query. The "query" field may be omitted if the
+/// "extensions" field refers to a persisted query.
+fn query_from_object(
+    obj: &serde_json::Map<String, serde_json::Value>,
+    schema: Schema,
+    persisted_queries: &PersistedQueries,
+) -> Result<Query, GraphQLServerError> {
+    // Ensure the "query" field is a string, if present
+    let query_string = match obj.get("query") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(query_value) => Some(query_value.as_str().ok_or(
+            GraphQLServerError::ClientError(String::from("The\"query\" field is not a string")),
+        )?),
+    };
+
+    // Look up or parse the query document
+    let document = persisted_queries
+        .document(query_string, obj.get("extensions"))
+        .map_err(persisted_query_error)?;
+
+    // Parse the "variables" field of the JSON body, if present
+    let variables = match obj.get("variables") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(variables @ serde_json::Value::Object(_)) => {
+            serde_json::from_value(variables.clone())
+                .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))
+                .map(|v| Some(v))
+        }
+        _ => Err(GraphQLServerError::ClientError(format!(
+            "Invalid query variables provided"
+        ))),
+    }?;
+
+    // Parse the "operationName" field of the JSON body, if present
+    let operation_name = match obj.get("operationName") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(name)) => Some(name.clone()),
+        _ => {
+            return Err(GraphQLServerError::ClientError(String::from(
+                "Invalid operation name provided",
+            )))
+        }
+    };
+
+    Ok(Query {
+        document,
+        variables,
+        operation_name,
+        schema,
+    })
+}
+
 impl Future for GraphQLRequest {
-    type Item = Query;
+    type Item = RequestQueries;
     type Error = GraphQLServerError;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
@@ -30,6 +185,34 @@ impl Future for GraphQLRequest {
         let json: serde_json::Value = serde_json::from_slice(&self.body)
             .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
 
+        // Batches are arrays of query objects
+        if let serde_json::Value::Array(ref values) = json {
+            if values.is_empty() {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "Request batch is empty",
+                )));
+            }
+            if values.len() > MAX_BATCH_SIZE {
+                return Err(GraphQLServerError::ClientError(format!(
+                    "Request batch is too large, at most {} queries can be sent at once",
+                    MAX_BATCH_SIZE
+                )));
+            }
+
+            return values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_object()
+                        .ok_or(GraphQLServerError::ClientError(String::from(
+                            "Request data is not an object",
+                        ))).and_then(|obj| {
+                            query_from_object(obj, schema.clone(), &self.persisted_queries)
+                        })
+                }).collect::<Result<Vec<_>, _>>()
+                .map(|queries| Async::Ready(RequestQueries::Batch(queries)));
+        }
+
         // Ensure the JSON data is an object
         let obj = json
             .as_object()
@@ -37,43 +220,8 @@ impl Future for GraphQLRequest {
                 "Request data is not an object",
             )))?;
 
-        // Ensure the JSON data has a "query" field
-        let query_value = obj
-            .get("query")
-            .ok_or(GraphQLServerError::ClientError(String::from(
-                "The \"query\" field missing in request data",
-            )))?;
-
-        // Ensure the "query" field is a string
-        let query_string =
-            query_value
-                .as_str()
-                .ok_or(GraphQLServerError::ClientError(String::from(
-                    "The\"query\" field is not a string",
-                )))?;
-
-        // Parse the "query" field of the JSON body
-        let document = graphql_parser::parse_query(query_string)
-            .map_err(|e| GraphQLServerError::from(QueryError::from(e)))?;
-
-        // Parse the "variables" field of the JSON body, if present
-        let variables = match obj.get("variables") {
-            None | Some(serde_json::Value::Null) => Ok(None),
-            Some(variables @ serde_json::Value::Object(_)) => {
-                serde_json::from_value(variables.clone())
-                    .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))
-                    .map(|v| Some(v))
-            }
-            _ => Err(GraphQLServerError::ClientError(format!(
-                "Invalid query variables provided"
-            ))),
-        }?;
-
-        Ok(Async::Ready(Query {
-            document,
-            variables,
-            schema,
-        }))
+        query_from_object(obj, schema, &self.persisted_queries)
+            .map(|query| Async::Ready(RequestQueries::Single(query)))
     }
 }
 
@@ -83,11 +231,16 @@ mod tests {
     use hyper;
 
     use graph::prelude::*;
+    use graph_graphql::prelude::PersistedQueries;
 
-    use super::GraphQLRequest;
+    use super::{GraphQLRequest, RequestQueries, MAX_BATCH_SIZE};
 
     const EXAMPLE_SCHEMA: &'static str = "type Query { users: [User!] }";
 
+    fn persisted_queries() -> Arc<PersistedQueries> {
+        Arc::new(PersistedQueries::new(10))
+    }
+
     #[test]
     fn rejects_invalid_json() {
         let schema = Schema {
@@ -95,7 +248,7 @@ mod tests {
             id: "test".to_string(),
             document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
         };
-        let request = GraphQLRequest::new(hyper::Chunk::from("!@#)%"), schema);
+        let request = GraphQLRequest::new(hyper::Chunk::from("!@#)%"), schema, persisted_queries());
         request.wait().expect_err("Should reject invalid JSON");
     }
 
@@ -106,7 +259,7 @@ mod tests {
             id: "test".to_string(),
             document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
         };
-        let request = GraphQLRequest::new(hyper::Chunk::from("{}"), schema);
+        let request = GraphQLRequest::new(hyper::Chunk::from("{}"), schema, persisted_queries());
         request
             .wait()
             .expect_err("Should reject JSON without query field");
@@ -119,7 +272,11 @@ mod tests {
             id: "test".to_string(),
             document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
         };
-        let request = GraphQLRequest::new(hyper::Chunk::from("{\"query\": 5}"), schema);
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from("{\"query\": 5}"),
+            schema,
+            persisted_queries(),
+        );
         request
             .wait()
             .expect_err("Should reject JSON with a non-string query field");
@@ -132,7 +289,11 @@ mod tests {
             id: "test".to_string(),
             document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
         };
-        let request = GraphQLRequest::new(hyper::Chunk::from("{\"query\": \"foo\"}"), schema);
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from("{\"query\": \"foo\"}"),
+            schema,
+            persisted_queries(),
+        );
         request.wait().expect_err("Should reject broken queries");
     }
 
@@ -146,10 +307,14 @@ mod tests {
         let request = GraphQLRequest::new(
             hyper::Chunk::from("{\"query\": \"{ user { name } }\"}"),
             schema,
+            persisted_queries(),
         );
-        let query = request.wait().expect("Should accept valid queries");
+        let query = request
+            .and_then(RequestQueries::single)
+            .wait()
+            .expect("Should accept valid queries");
         assert_eq!(
-            query.document,
+            *query.document,
             graphql_parser::parse_query("{ user { name } }").unwrap()
         );
     }
@@ -170,11 +335,15 @@ mod tests {
                  }",
             ),
             schema,
+            persisted_queries(),
         );
-        let query = request.wait().expect("Should accept null variables");
+        let query = request
+            .and_then(RequestQueries::single)
+            .wait()
+            .expect("Should accept null variables");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }").unwrap();
-        assert_eq!(query.document, expected_query);
+        assert_eq!(*query.document, expected_query);
         assert_eq!(query.variables, None);
     }
 
@@ -194,6 +363,7 @@ mod tests {
                  }",
             ),
             schema,
+            persisted_queries(),
         );
         request.wait().expect_err("Should reject non-map variables");
     }
@@ -214,14 +384,150 @@ mod tests {
                  }",
             ),
             schema,
+            persisted_queries(),
         );
-        let query = request.wait().expect("Should accept valid queries");
+        let query = request
+            .and_then(RequestQueries::single)
+            .wait()
+            .expect("Should accept valid queries");
+
+        let expected_query = graphql_parser::parse_query("{ user { name } }").unwrap();
+        let mut expected_variables = QueryVariables::new();
+        expected_variables.insert("foo".to_string(), QueryVariableValue::from("bar"));
+
+        assert_eq!(*query.document, expected_query);
+        assert_eq!(query.variables, Some(expected_variables));
+    }
+    #[test]
+    fn parses_query_strings() {
+        let schema = Schema {
+            name: "test".to_string(),
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let query = GraphQLRequest::from_query_string(
+            "query=%7B%20user%20%7B%20name%20%7D%20%7D&variables=%7B%22foo%22%3A%22bar%22%7D",
+            schema,
+            &persisted_queries(),
+        ).expect("Should accept valid query strings");
 
         let expected_query = graphql_parser::parse_query("{ user { name } }").unwrap();
         let mut expected_variables = QueryVariables::new();
         expected_variables.insert("foo".to_string(), QueryVariableValue::from("bar"));
 
-        assert_eq!(query.document, expected_query);
+        assert_eq!(*query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    #[test]
+    fn rejects_mutations_in_query_strings() {
+        let schema = Schema {
+            name: "test".to_string(),
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        GraphQLRequest::from_query_string(
+            "query=mutation%20%7B%20foo%20%7D",
+            schema,
+            &persisted_queries(),
+        ).expect_err("Should reject mutations in query strings");
+    }
+    #[test]
+    fn parses_operation_name() {
+        let schema = Schema {
+            name: "test".to_string(),
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from(
+                "\
+                 {\
+                 \"query\": \"query A { user { name } } query B { user { id } }\", \
+                 \"operationName\": \"B\" \
+                 }",
+            ),
+            schema,
+            persisted_queries(),
+        );
+        let query = request
+            .and_then(RequestQueries::single)
+            .wait()
+            .expect("Should accept valid queries");
+
+        assert_eq!(query.operation_name, Some(String::from("B")));
+    }
+
+    #[test]
+    fn rejects_non_string_operation_names() {
+        let schema = Schema {
+            name: "test".to_string(),
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from("{\"query\": \"{ user { name } }\", \"operationName\": 5}"),
+            schema,
+            persisted_queries(),
+        );
+        request
+            .wait()
+            .expect_err("Should reject non-string operation names");
+    }
+    #[test]
+    fn parses_batches() {
+        let schema = Schema {
+            name: "test".to_string(),
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from(
+                "\
+                 [\
+                 {\"query\": \"{ user { name } }\"}, \
+                 {\"query\": \"{ user { id } }\"}\
+                 ]",
+            ),
+            schema,
+            persisted_queries(),
+        );
+        let queries = match request.wait().expect("Should accept batches") {
+            RequestQueries::Batch(queries) => queries,
+            RequestQueries::Single(_) => panic!("Should parse a batch"),
+        };
+
+        assert_eq!(queries.len(), 2);
+        assert_eq!(
+            *queries[0].document,
+            graphql_parser::parse_query("{ user { name } }").unwrap()
+        );
+        assert_eq!(
+            *queries[1].document,
+            graphql_parser::parse_query("{ user { id } }").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_batches() {
+        let schema = Schema {
+            name: "test".to_string(),
+            id: "test".to_string(),
+            document: graphql_parser::parse_schema(EXAMPLE_SCHEMA).unwrap(),
+        };
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from("[]"),
+            schema.clone(),
+            persisted_queries(),
+        );
+        request.wait().expect_err("Should reject empty batches");
+
+        let batch = vec!["{\"query\": \"{ user { name } }\"}"; MAX_BATCH_SIZE + 1];
+        let request = GraphQLRequest::new(
+            hyper::Chunk::from(format!("[{}]", batch.join(","))),
+            schema,
+            persisted_queries(),
+        );
+        request.wait().expect_err("Should reject oversized batches");
+    }
 }
@@ -73,6 +73,37 @@ impl Future for GraphQLResponse {
     }
 }
 
+/// Future for HTTP responses to batches of GraphQL queries.
+pub struct GraphQLBatchResponse {
+    results: Vec<QueryResult>,
+}
+
+impl GraphQLBatchResponse {
+    /// Creates a new GraphQLBatchResponse future based on the results of
+    /// running the queries of a batch, in the order of the batch.
+    pub fn new(results: Vec<QueryResult>) -> Self {
+        GraphQLBatchResponse { results }
+    }
+}
+
+impl Future for GraphQLBatchResponse {
+    type Item = Response<Body>;
+    type Error = GraphQLServerError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // Errors are reported per query, the batch itself always succeeds
+        let json = serde_json::to_string(&self.results)
+            .expect("Failed to serialize GraphQL batch response to JSON");
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Headers", "Content-Type")
+            .body(Body::from(json))
+            .unwrap();
+        Ok(Async::Ready(response))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::GraphQLResponse;
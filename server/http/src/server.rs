@@ -10,8 +10,9 @@ use std::sync::RwLock;
 
 use graph::data::schema::Schema;
 use graph::prelude::{GraphQLServer as GraphQLServerTrait, *};
-use graph_graphql::prelude::api_schema;
+use graph_graphql::prelude::{api_schema, PersistedQueries};
 use service::GraphQLService;
+use stitching::StitchedSchema;
 
 /// Errors that may occur when starting the server.
 #[derive(Debug)]
@@ -51,12 +52,21 @@ pub struct GraphQLServer<Q> {
     names: Arc<RwLock<BTreeMap<String, String>>>,
     // Maps a subgraph name to its schema.
     schemas: Arc<RwLock<BTreeMap<String, Schema>>>,
+    // Combines the schemas of all subgraphs.
+    stitched_schema: Arc<RwLock<StitchedSchema>>,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
+    status_provider: Arc<SubgraphStatusProvider>,
 }
 
 impl<Q> GraphQLServer<Q> {
     /// Creates a new GraphQL server.
-    pub fn new(logger: &slog::Logger, graphql_runner: Arc<Q>) -> Self {
+    pub fn new(
+        logger: &slog::Logger,
+        graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        status_provider: Arc<SubgraphStatusProvider>,
+    ) -> Self {
         // Create channel for handling incoming schema events
         let (schema_event_sink, schema_event_stream) = channel(100);
 
@@ -66,7 +76,10 @@ impl<Q> GraphQLServer<Q> {
             schema_event_sink,
             names: Arc::new(RwLock::new(BTreeMap::new())),
             schemas: Arc::new(RwLock::new(BTreeMap::new())),
+            stitched_schema: Arc::new(RwLock::new(StitchedSchema::default())),
             graphql_runner: graphql_runner,
+            persisted_queries,
+            status_provider,
         };
 
         // Spawn tasks to handle incoming schema events
@@ -81,6 +94,7 @@ impl<Q> GraphQLServer<Q> {
         let logger = self.logger.clone();
         let schemas = self.schemas.clone();
         let names = self.names.clone();
+        let stitched_schema = self.stitched_schema.clone();
 
         tokio::spawn(stream.for_each(move |event| {
             info!(logger, "Received schema event");
@@ -108,6 +122,9 @@ impl<Q> GraphQLServer<Q> {
                 }
             }
 
+            // Stitch the schemas of the subgraphs hosted now
+            *stitched_schema.write().unwrap() = StitchedSchema::new(&schemas);
+
             Ok(())
         }));
     }
@@ -136,9 +153,20 @@ where
         let graphql_runner = self.graphql_runner.clone();
         let names = self.names.clone();
         let schemas = self.schemas.clone();
+        let stitched_schema = self.stitched_schema.clone();
+        let persisted_queries = self.persisted_queries.clone();
+        let status_provider = self.status_provider.clone();
+        let service_logger = self.logger.clone();
         let new_service = move || {
-            let service =
-                GraphQLService::new(names.clone(), schemas.clone(), graphql_runner.clone());
+            let service = GraphQLService::new(
+                service_logger.clone(),
+                names.clone(),
+                schemas.clone(),
+                stitched_schema.clone(),
+                graphql_runner.clone(),
+                persisted_queries.clone(),
+                status_provider.clone(),
+            );
             future::ok::<GraphQLService<Q>, hyper::Error>(service)
         };
 
@@ -157,7 +185,7 @@ mod tests {
 
     use std::time::{Duration, Instant};
 
-    use self::graph_mock::MockGraphQlRunner;
+    use self::graph_mock::{MockGraphQlRunner, MockSubgraphStatusProvider};
     use graph_graphql::schema::ast;
 
     use super::*;
@@ -171,7 +199,12 @@ mod tests {
                     // Set up the server
                     let logger = Logger::root(slog::Discard, o!());
                     let graphql_runner = Arc::new(MockGraphQlRunner::new(&logger));
-                    let mut server = GraphQLServer::new(&logger, graphql_runner);
+                    let mut server = GraphQLServer::new(
+                        &logger,
+                        graphql_runner,
+                        Arc::new(PersistedQueries::new(10)),
+                        Arc::new(MockSubgraphStatusProvider),
+                    );
                     let schema_sink = server.schema_event_sink();
 
                     // Create an input schema event
@@ -4,25 +4,45 @@ use hyper::{Body, Method, Request, Response, StatusCode};
 use itertools::Itertools;
 use std::collections::BTreeMap;
 use std::sync::RwLock;
+use tokio_threadpool::blocking;
+use url::form_urlencoded;
 
 use graph::components::server::query::GraphQLServerError;
 use graph::prelude::*;
+use graph_graphql::prelude::{
+    execute_query, validate_query, PersistedQueries, QueryExecutionOptions, QueryThreadPool,
+};
 
-use request::GraphQLRequest;
-use response::GraphQLResponse;
+use event_stream::event_stream_response;
+use index_node::{execute_index_node_query, index_node_schema};
+use request::{GraphQLRequest, RequestQueries};
+use response::{GraphQLBatchResponse, GraphQLResponse};
+use stitching::{merge_results, StitchedResolver, StitchedSchema};
 
 /// An asynchronous response to a GraphQL request.
 pub type GraphQLServiceResponse =
     Box<Future<Item = Response<Body>, Error = GraphQLServerError> + Send>;
 
+/// How long clients may cache results of GET queries, roughly the time
+/// between two Ethereum blocks.
+const GET_QUERY_MAX_AGE_SECS: u64 = 15;
+
+/// The maximum number of queries of a single batch, or subgraph queries of a
+/// single stitched query, that run at the same time.
+const MAX_CONCURRENT_QUERIES: usize = 10;
+
 /// A Hyper Service that serves GraphQL over a POST / endpoint.
-#[derive(Debug)]
 pub struct GraphQLService<Q> {
+    logger: Logger,
     // Maps ids to names.
     names: Arc<RwLock<BTreeMap<String, String>>>,
     // Maps names to schemas.
     schemas: Arc<RwLock<BTreeMap<String, Schema>>>,
+    // Combines the schemas of all subgraphs.
+    stitched_schema: Arc<RwLock<StitchedSchema>>,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
+    status_provider: Arc<SubgraphStatusProvider>,
 }
 
 impl<Q> GraphQLService<Q>
@@ -31,14 +51,22 @@ where
 {
     /// Creates a new GraphQL service.
     pub fn new(
+        logger: Logger,
         names: Arc<RwLock<BTreeMap<String, String>>>,
         schemas: Arc<RwLock<BTreeMap<String, Schema>>>,
+        stitched_schema: Arc<RwLock<StitchedSchema>>,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        status_provider: Arc<SubgraphStatusProvider>,
     ) -> Self {
         GraphQLService {
+            logger,
             names,
             schemas,
+            stitched_schema,
             graphql_runner,
+            persisted_queries,
+            status_provider,
         }
     }
 
@@ -70,41 +98,308 @@ where
         ))
     }
 
-    /// Handles GraphQL queries received via POST /.
+    /// Looks up the schema of a subgraph by name or ID.
+    fn resolve_schema(&self, name_or_id: &str) -> Option<Schema> {
+        lookup_schema(&self.names, &self.schemas, name_or_id)
+    }
+
+    /// Handles GraphQL queries received via POST /; batches of queries are
+    /// executed concurrently and their results returned in order.
     fn handle_graphql_query(
         &self,
         name_or_id: &str,
         request: Request<Body>,
     ) -> GraphQLServiceResponse {
         let graphql_runner = self.graphql_runner.clone();
-        let schemas = self.schemas.read().unwrap();
+        let persisted_queries = self.persisted_queries.clone();
+
+        let schema = if let Some(schema) = self.resolve_schema(name_or_id) {
+            schema
+        } else {
+            return self.handle_not_found();
+        };
+
+        Box::new(
+            request
+                .into_body()
+                .concat2()
+                .map_err(|_| GraphQLServerError::from("Failed to read request body"))
+                .and_then(move |body| GraphQLRequest::new(body, schema.clone(), persisted_queries))
+                .and_then(move |queries| match queries {
+                    // Run the query using the query runner
+                    RequestQueries::Single(query) => future::Either::A(
+                        graphql_runner
+                            .run_query(query)
+                            .map_err(|e| GraphQLServerError::from(e))
+                            .then(|result| GraphQLResponse::new(result)),
+                    ),
 
-        // First try `name_or_id` as a name, if that fails try it as an id.
-        let schema = schemas
-            .get(name_or_id)
-            .or_else(|| schemas.get(self.names.read().unwrap().get(name_or_id)?));
+                    // Run the queries of the batch concurrently; errors are
+                    // reported in the result of the query that caused them
+                    RequestQueries::Batch(queries) => future::Either::B(
+                        stream::iter_ok(queries)
+                            .map(move |query| {
+                                run_query_blocking(graphql_runner.clone(), query).or_else(|e| {
+                                    Ok::<_, GraphQLServerError>(QueryResult::from(e))
+                                })
+                            }).buffered(MAX_CONCURRENT_QUERIES)
+                            .collect()
+                            .and_then(|results| GraphQLBatchResponse::new(results)),
+                    ),
+                }).or_else(|e| GraphQLResponse::new(Err(e))),
+        )
+    }
 
-        let schema = if let Some(schema) = schema {
-            schema.clone()
+    /// Handles GraphQL queries received via GET /?query=...; responses carry an
+    /// ETag derived from the latest block indexed for the subgraph.
+    fn handle_graphql_get_query(
+        &self,
+        name_or_id: &str,
+        request: Request<Body>,
+    ) -> GraphQLServiceResponse {
+        let schema = if let Some(schema) = self.resolve_schema(name_or_id) {
+            schema
         } else {
             return self.handle_not_found();
         };
 
+        let etag = match self.graphql_runner.subgraph_latest_block(&schema.id) {
+            Ok(Some(block)) => Some(format!("\"{}-{:x}\"", block.number, block.hash)),
+            _ => None,
+        };
+
+        let query_string = request.uri().query().unwrap_or("").to_owned();
+        let query = match GraphQLRequest::from_query_string(
+            &query_string,
+            schema,
+            &self.persisted_queries,
+        ) {
+            Ok(query) => query,
+            Err(e) => return Box::new(GraphQLResponse::new(Err(e))),
+        };
+
+        // Results only change when the subgraph advances to a new block
+        if let Some(ref etag) = etag {
+            if etag_matches(&request, etag) {
+                return Box::new(future::ok(
+                    Response::builder()
+                        .status(StatusCode::NOT_MODIFIED)
+                        .header(header::ETAG, etag.as_str())
+                        .header(header::CACHE_CONTROL, cache_control().as_str())
+                        .header("Access-Control-Allow-Origin", "*")
+                        .body(Body::empty())
+                        .unwrap(),
+                ));
+            }
+        }
+
+        Box::new(
+            self.graphql_runner
+                .run_query(query)
+                .map_err(|e| GraphQLServerError::from(e))
+                .then(|result| GraphQLResponse::new(result))
+                .map(move |mut response| {
+                    let cacheable = response.status() == StatusCode::OK;
+                    let headers = response.headers_mut();
+                    match etag {
+                        Some(ref etag) if cacheable => {
+                            headers.insert(
+                                header::ETAG,
+                                header::HeaderValue::from_str(etag).expect("invalid ETag"),
+                            );
+                            headers.insert(
+                                header::CACHE_CONTROL,
+                                header::HeaderValue::from_str(&cache_control())
+                                    .expect("invalid Cache-Control"),
+                            );
+                        }
+                        _ => {
+                            headers.insert(
+                                header::CACHE_CONTROL,
+                                header::HeaderValue::from_static("no-store"),
+                            );
+                        }
+                    }
+                    response
+                }),
+        )
+    }
+
+    /// Handles GraphQL queries received via POST /graphql that select data
+    /// from several subgraphs, with one root field per subgraph, e.g.
+    /// `{ uniswap { pairs { id } } }`. Queries are validated against the
+    /// stitched schema and the subgraph queries run concurrently.
+    fn handle_stitched_graphql_query(&self, request: Request<Body>) -> GraphQLServiceResponse {
+        let logger = self.logger.clone();
+        let graphql_runner = self.graphql_runner.clone();
+        let persisted_queries = self.persisted_queries.clone();
+        let names = self.names.clone();
+        let schemas = self.schemas.clone();
+        let stitched_schema = self.stitched_schema.read().unwrap().clone();
+        let schema = stitched_schema.schema().clone();
+
         Box::new(
             request
                 .into_body()
                 .concat2()
                 .map_err(|_| GraphQLServerError::from("Failed to read request body"))
-                .and_then(move |body| GraphQLRequest::new(body, schema.clone()))
+                .and_then(move |body| GraphQLRequest::new(body, schema, persisted_queries))
+                .and_then(|queries| queries.single())
                 .and_then(move |query| {
-                    // Run the query using the query runner
-                    graphql_runner
-                        .run_query(query)
-                        .map_err(|e| GraphQLServerError::from(e))
+                    if let Err(errors) = validate_query(&query) {
+                        return future::Either::A(future::ok(QueryResult::from(errors)));
+                    }
+
+                    // Split the query into one query per subgraph
+                    let operation_name = query.operation_name.as_ref().map(|name| name.as_str());
+                    let stitched_query =
+                        match stitched_schema.split_query(&query.document, operation_name) {
+                            Ok(stitched_query) => stitched_query,
+                            Err(e) => return future::Either::A(future::err(e)),
+                        };
+
+                    // Resolve introspection fields against the stitched schema
+                    let introspection_result = stitched_query.introspection.map(|document| {
+                        execute_query(
+                            &Query {
+                                schema: query.schema.clone(),
+                                document: Arc::new(document),
+                                variables: query.variables.clone(),
+                                operation_name: None,
+                            },
+                            QueryExecutionOptions {
+                                logger: logger.clone(),
+                                resolver: StitchedResolver,
+                                thread_pool: QueryThreadPool::sequential(),
+                            },
+                        )
+                    });
+
+                    let subgraph_queries = match stitched_query
+                        .subgraph_queries
+                        .into_iter()
+                        .map(|subgraph_query| {
+                            let schema = lookup_schema(&names, &schemas, &subgraph_query.subgraph)
+                                .ok_or(GraphQLServerError::ClientError(format!(
+                                    "Unknown subgraph name or ID: {}",
+                                    subgraph_query.subgraph
+                                )))?;
+                            let query = Query {
+                                schema,
+                                document: subgraph_query.document.clone(),
+                                variables: query.variables.clone(),
+                                operation_name: None,
+                            };
+                            Ok((subgraph_query, query))
+                        }).collect::<Result<Vec<_>, GraphQLServerError>>()
+                    {
+                        Ok(subgraph_queries) => subgraph_queries,
+                        Err(e) => return future::Either::A(future::err(e)),
+                    };
+
+                    // Run the subgraph queries concurrently and combine their results
+                    future::Either::B(
+                        stream::iter_ok(subgraph_queries)
+                            .map(move |(subgraph_query, query)| {
+                                run_query_blocking(graphql_runner.clone(), query)
+                                    .or_else(|e| {
+                                        Ok::<_, GraphQLServerError>(QueryResult::from(e))
+                                    }).map(move |result| {
+                                        let result = subgraph_query.stitch_result(result);
+                                        (subgraph_query.response_key, result)
+                                    })
+                            }).buffered(MAX_CONCURRENT_QUERIES)
+                            .collect()
+                            .map(move |results| merge_results(introspection_result, results)),
+                    )
                 }).then(|result| GraphQLResponse::new(result)),
         )
     }
 
+    /// Handles queries about the subgraphs hosted by this node, received via
+    /// POST /index-node/graphql.
+    fn handle_index_node_query(&self, request: Request<Body>) -> GraphQLServiceResponse {
+        let logger = self.logger.clone();
+        let persisted_queries = self.persisted_queries.clone();
+        let status_provider = self.status_provider.clone();
+        let names = self.names.clone();
+
+        Box::new(
+            request
+                .into_body()
+                .concat2()
+                .map_err(|_| GraphQLServerError::from("Failed to read request body"))
+                .and_then(move |body| {
+                    GraphQLRequest::new(body, index_node_schema(), persisted_queries)
+                }).and_then(|queries| queries.single())
+                .and_then(move |query| {
+                    execute_index_node_query(
+                        &logger,
+                        query,
+                        status_provider,
+                        &*names.read().unwrap(),
+                    )
+                }).then(|result| GraphQLResponse::new(result)),
+        )
+    }
+
+    /// Handles GraphQL subscriptions received via POST /graphql/stream or
+    /// GET /graphql/stream?query=...; streams the results back as
+    /// Server-Sent Events.
+    fn handle_graphql_subscription(
+        &self,
+        name_or_id: &str,
+        request: Request<Body>,
+    ) -> GraphQLServiceResponse {
+        let graphql_runner = self.graphql_runner.clone();
+        let persisted_queries = self.persisted_queries.clone();
+
+        let schema = if let Some(schema) = self.resolve_schema(name_or_id) {
+            schema
+        } else {
+            return self.handle_not_found();
+        };
+
+        // `EventSource` can only send GET requests, which pass the
+        // subscription in the query string rather than the body
+        let query = if *request.method() == Method::GET {
+            let query_string = request.uri().query().unwrap_or("");
+            future::Either::A(future::result(GraphQLRequest::from_stream_query_string(
+                query_string,
+                schema,
+                &persisted_queries,
+            )))
+        } else {
+            future::Either::B(
+                request
+                    .into_body()
+                    .concat2()
+                    .map_err(|_| GraphQLServerError::from("Failed to read request body"))
+                    .and_then(move |body| GraphQLRequest::new(body, schema, persisted_queries))
+                    .and_then(|queries| queries.single()),
+            )
+        };
+
+        Box::new(
+            query
+                .and_then(move |query| {
+                    // Run the subscription using the query runner; errors that
+                    // prevent the subscription from starting are returned as a
+                    // regular GraphQL response
+                    graphql_runner
+                        .run_subscription(Subscription { query })
+                        .then(|result| match result {
+                            Ok(results) => future::Either::A(future::ok(
+                                event_stream_response(results),
+                            )),
+                            Err(e) => {
+                                future::Either::B(GraphQLResponse::new(Ok(QueryResult::from(e))))
+                            }
+                        })
+                }),
+        )
+    }
+
     // Handles OPTIONS requests
     fn handle_graphql_options(&self, _request: Request<Body>) -> GraphQLServiceResponse {
         Box::new(future::ok(
@@ -128,6 +423,76 @@ where
     }
 }
 
+/// Runs a query without tying up the thread that serves requests.
+///
+/// `GraphQlRunner::run_query` does all of its work before returning, so the
+/// query runs in a blocking section of the Tokio thread pool, which lets the
+/// pool hand its other work to a new thread. Outside of a thread pool, e.g.
+/// in tests, the query runs on the current thread.
+fn run_query_blocking<Q>(
+    graphql_runner: Arc<Q>,
+    query: Query,
+) -> impl Future<Item = QueryResult, Error = QueryError>
+where
+    Q: GraphQlRunner,
+{
+    let mut query = Some(query);
+    future::poll_fn(move || {
+        let result = blocking(|| {
+            graphql_runner
+                .run_query(query.take().expect("query was already run"))
+                .wait()
+        });
+
+        match result {
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(_) => graphql_runner
+                .run_query(query.take().expect("query was already run"))
+                .wait()
+                .map(Async::Ready),
+        }
+    })
+}
+
+/// Looks up the schema of a subgraph by name or ID.
+fn lookup_schema(
+    names: &RwLock<BTreeMap<String, String>>,
+    schemas: &RwLock<BTreeMap<String, Schema>>,
+    name_or_id: &str,
+) -> Option<Schema> {
+    let schemas = schemas.read().unwrap();
+
+    // First try `name_or_id` as a name, if that fails try it as an id.
+    schemas
+        .get(name_or_id)
+        .or_else(|| schemas.get(names.read().unwrap().get(name_or_id)?))
+        .cloned()
+}
+
+/// Returns whether the request's If-None-Match header includes `etag`.
+fn etag_matches(request: &Request<Body>, etag: &str) -> bool {
+    request
+        .headers()
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|candidate| candidate.trim())
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+fn cache_control() -> String {
+    format!("public, max-age={}", GET_QUERY_MAX_AGE_SECS)
+}
+
+/// Returns whether the request passes a GraphQL query in its query string.
+fn has_query_param(request: &Request<Body>) -> bool {
+    request.uri().query().map_or(false, |query_string| {
+        form_urlencoded::parse(query_string.as_bytes()).any(|(key, _)| key == "query")
+    })
+}
+
 impl<Q> Service for GraphQLService<Q>
 where
     Q: GraphQlRunner + 'static,
@@ -150,6 +515,14 @@ where
                 self.serve_file(include_str!("../assets/graphiql.min.js"))
             }
 
+            // POST /graphql receives GraphQL queries across subgraphs
+            (Method::POST, "/graphql") => self.handle_stitched_graphql_query(req),
+            (Method::OPTIONS, "/graphql") => self.handle_graphql_options(req),
+
+            // POST /index-node/graphql receives queries about the hosted subgraphs
+            (Method::POST, "/index-node/graphql") => self.handle_index_node_query(req),
+            (Method::OPTIONS, "/index-node/graphql") => self.handle_graphql_options(req),
+
             // Request is relative to a subgraph.
             (method, path) => {
                 let mut path = path.split('/');
@@ -158,6 +531,14 @@ where
                 let rest = path.join("/");
 
                 match (method, name_or_id, rest.as_str()) {
+                    // GET /?query=... receives GraphQL queries
+                    (Method::GET, Some(name_or_id), "") if has_query_param(&req) => {
+                        self.handle_graphql_get_query(&name_or_id, req)
+                    }
+                    (Method::GET, Some(name_or_id), "graphql") => {
+                        self.handle_graphql_get_query(&name_or_id, req)
+                    }
+
                     // GraphiQL
                     (Method::GET, Some(_), "") => {
                         self.serve_file(include_str!("../assets/index.html"))
@@ -168,8 +549,17 @@ where
                         self.handle_graphql_query(&name_or_id, req)
                     }
 
+                    // GET and POST /graphql/stream receive GraphQL subscriptions
+                    (Method::GET, Some(name_or_id), "graphql/stream")
+                    | (Method::POST, Some(name_or_id), "graphql/stream") => {
+                        self.handle_graphql_subscription(&name_or_id, req)
+                    }
+
                     // OPTIONS / allows to check for GraphQL HTTP features
-                    (Method::OPTIONS, Some(_), "graphql") => self.handle_graphql_options(req),
+                    (Method::OPTIONS, Some(_), "graphql")
+                    | (Method::OPTIONS, Some(_), "graphql/stream") => {
+                        self.handle_graphql_options(req)
+                    }
 
                     // Everything else results in a 404
                     _ => self.handle_not_found(),
@@ -185,15 +575,25 @@ mod tests {
     use graphql_parser::query as q;
     use http::status::StatusCode;
     use hyper::service::Service;
+    use hyper::header;
     use hyper::{Body, Method, Request};
     use std::collections::BTreeMap;
     use std::iter::once;
     use std::iter::FromIterator;
-    use std::sync::RwLock;
+    use std::cmp;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Mutex, RwLock};
+    use std::thread;
+    use std::time::Duration;
 
     use graph::prelude::*;
+    use graph::serde_json;
+    use graph::tokio::runtime::Runtime;
+    use graph_graphql::prelude::PersistedQueries;
+    use graph::web3::types::H256;
 
-    use super::GraphQLService;
+    use super::{GraphQLService, MAX_CONCURRENT_QUERIES};
+    use stitching::StitchedSchema;
     use test_utils;
 
     /// A simple stupid query runner for testing.
@@ -212,8 +612,137 @@ mod tests {
         }
 
         fn run_subscription(&self, _subscription: Subscription) -> SubscriptionResultFuture {
-            unimplemented!();
+            let results = vec!["Jordi", "Jorge"].into_iter().map(|name| {
+                QueryResult::new(Some(q::Value::Object(BTreeMap::from_iter(
+                    vec![(String::from("name"), q::Value::String(String::from(name)))]
+                        .into_iter(),
+                ))))
+            });
+            Box::new(future::ok(
+                Box::new(stream::iter_ok(results.collect::<Vec<_>>())) as QueryResultStream
+            ))
         }
+
+        fn subgraph_latest_block(
+            &self,
+            _subgraph_id: &str,
+        ) -> Result<Option<EthereumBlockPointer>, Error> {
+            Ok(Some(EthereumBlockPointer::from((H256::zero(), 1u64))))
+        }
+    }
+
+    /// A query runner that takes a while for every query and returns the
+    /// operation name of the query. Keeps track of how many queries run at
+    /// the same time.
+    #[derive(Default)]
+    pub struct SlowGraphQlRunner {
+        queries_in_progress: AtomicUsize,
+        max_queries_in_progress: Mutex<usize>,
+    }
+
+    impl GraphQlRunner for SlowGraphQlRunner {
+        fn run_query(&self, query: Query) -> QueryResultFuture {
+            let in_progress = self.queries_in_progress.fetch_add(1, Ordering::SeqCst) + 1;
+            {
+                let mut max_in_progress = self.max_queries_in_progress.lock().unwrap();
+                *max_in_progress = cmp::max(*max_in_progress, in_progress);
+            }
+
+            thread::sleep(Duration::from_millis(50));
+            self.queries_in_progress.fetch_sub(1, Ordering::SeqCst);
+
+            Box::new(future::ok(QueryResult::new(Some(q::Value::Object(
+                BTreeMap::from_iter(
+                    vec![(
+                        String::from("name"),
+                        q::Value::String(query.operation_name.unwrap_or_default()),
+                    )].into_iter(),
+                ),
+            )))))
+        }
+
+        fn run_subscription(&self, _subscription: Subscription) -> SubscriptionResultFuture {
+            unimplemented!()
+        }
+
+        fn subgraph_latest_block(
+            &self,
+            _subgraph_id: &str,
+        ) -> Result<Option<EthereumBlockPointer>, Error> {
+            Ok(None)
+        }
+    }
+
+    /// A status provider that reports a single failed subgraph and records
+    /// which of its methods are called.
+    #[derive(Default)]
+    struct TestStatusProvider {
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl TestStatusProvider {
+        fn status() -> SubgraphStatus {
+            SubgraphStatus {
+                id: String::from("test-id"),
+                location: String::from("/ipfs/test-id"),
+                data_sources: vec![],
+                health: SubgraphHealth::Failed(String::from("Mapping aborted")),
+                latest_block: Some(EthereumBlockPointer::from((H256::zero(), 1u64))),
+                chain_head_block: Some(EthereumBlockPointer::from((H256::zero(), 2u64))),
+            }
+        }
+    }
+
+    impl SubgraphStatusProvider for TestStatusProvider {
+        fn subgraph_statuses(&self) -> Result<Vec<SubgraphStatus>, Error> {
+            self.calls.lock().unwrap().push("subgraph_statuses");
+            Ok(vec![Self::status()])
+        }
+
+        fn subgraph_status(&self, id: &str) -> Result<Option<SubgraphStatus>, Error> {
+            self.calls.lock().unwrap().push("subgraph_status");
+            Ok(Some(Self::status()).filter(|status| status.id == id))
+        }
+
+        fn subgraph_entity_counts(&self, _id: &str) -> Result<BTreeMap<String, u64>, Error> {
+            self.calls.lock().unwrap().push("subgraph_entity_counts");
+            Ok(BTreeMap::from_iter(once((String::from("User"), 3))))
+        }
+    }
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    fn persisted_queries() -> Arc<PersistedQueries> {
+        Arc::new(PersistedQueries::new(10))
+    }
+
+    fn status_provider() -> Arc<SubgraphStatusProvider> {
+        Arc::new(TestStatusProvider::default())
+    }
+
+    fn test_schema(id: &str) -> Arc<RwLock<BTreeMap<String, Schema>>> {
+        Arc::new(RwLock::new(BTreeMap::from_iter(once((
+            id.to_string(),
+            Schema {
+                name: id.to_string(),
+                id: id.to_string(),
+                document: graphql_parser::parse_schema(
+                    "\
+                     scalar String \
+                     type Query { name: String } \
+                     type Mutation { setName: String } \
+                     ",
+                ).unwrap(),
+            },
+        )))))
+    }
+
+    fn stitched_schema(
+        schemas: &Arc<RwLock<BTreeMap<String, Schema>>>,
+    ) -> Arc<RwLock<StitchedSchema>> {
+        Arc::new(RwLock::new(StitchedSchema::new(&schemas.read().unwrap())))
     }
 
     #[test]
@@ -233,7 +762,15 @@ mod tests {
             },
         )))));
         let graphql_runner = Arc::new(TestGraphQlRunner);
-        let mut service = GraphQLService::new(Default::default(), schema, graphql_runner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            schema,
+            Default::default(),
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
 
         let request = Request::builder()
             .method(Method::POST)
@@ -280,8 +817,15 @@ mod tests {
                         },
                     )))));
 
-                    let mut service =
-                        GraphQLService::new(Default::default(), schema, graphql_runner);
+                    let mut service = GraphQLService::new(
+                        logger(),
+                        Default::default(),
+                        schema,
+                        Default::default(),
+                        graphql_runner,
+                        persisted_queries(),
+                        status_provider(),
+                    );
 
                     let request = Request::builder()
                         .method(Method::POST)
@@ -307,4 +851,545 @@ mod tests {
                 res
             })).unwrap()
     }
+
+    #[test]
+    fn posting_subscriptions_yields_event_stream() {
+        let id = "test-schema".to_string();
+        let schema = Arc::new(RwLock::new(BTreeMap::from_iter(once((
+            id.clone(),
+            Schema {
+                name: id.clone(),
+                id: id.clone(),
+                document: graphql_parser::parse_schema(
+                    "\
+                     scalar String \
+                     type Query { name: String } \
+                     type Subscription { name: String } \
+                     ",
+                ).unwrap(),
+            },
+        )))));
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            schema,
+            Default::default(),
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/{}/graphql/stream", id))
+            .body(Body::from("{\"query\": \"subscription { name }\"}"))
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "text/event-stream"
+        );
+
+        // Every result is sent as a `next` event, followed by `complete`
+        let body = response
+            .into_body()
+            .concat2()
+            .wait()
+            .expect("Failed to read event stream");
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            "event: next\ndata: {\"data\":{\"name\":\"Jordi\"}}\n\n\
+             event: next\ndata: {\"data\":{\"name\":\"Jorge\"}}\n\n\
+             event: complete\ndata: \n\n"
+        );
+    }
+
+    #[test]
+    fn getting_subscriptions_yields_event_stream() {
+        let id = "test-schema".to_string();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            test_schema(&id),
+            Default::default(),
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "http://localhost:8000/{}/graphql/stream?query=subscription%20%7B%20name%20%7D",
+                id
+            )).body(Body::empty())
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "text/event-stream"
+        );
+
+        let body = response
+            .into_body()
+            .concat2()
+            .wait()
+            .expect("Failed to read event stream");
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            "event: next\ndata: {\"data\":{\"name\":\"Jordi\"}}\n\n\
+             event: next\ndata: {\"data\":{\"name\":\"Jorge\"}}\n\n\
+             event: complete\ndata: \n\n"
+        );
+    }
+
+    #[test]
+    fn getting_valid_queries_yields_cacheable_result_response() {
+        let id = "test-schema".to_string();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            test_schema(&id),
+            Default::default(),
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!("http://localhost:8000/{}?query=%7B%20name%20%7D", id))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .expect("Response has no ETag")
+            .clone();
+        assert_eq!(etag, format!("\"1-{:x}\"", H256::zero()).as_str());
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=15"
+        );
+
+        let data = test_utils::assert_successful_response(response);
+        let name = data
+            .get("name")
+            .expect("Query result data has no \"name\" field")
+            .as_str()
+            .expect("Query result field \"name\" is not a string");
+        assert_eq!(name, "Jordi".to_string());
+
+        // Asking again with the ETag yields a 304
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!("http://localhost:8000/{}/graphql?query=%7B%20name%20%7D", id))
+            .header(header::IF_NONE_MATCH, etag)
+            .body(Body::empty())
+            .unwrap();
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn getting_mutations_yields_error_response() {
+        let id = "test-schema".to_string();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            test_schema(&id),
+            Default::default(),
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "http://localhost:8000/{}/graphql?query=mutation%20%7B%20setName%20%7D",
+                id
+            )).body(Body::empty())
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+        let message = errors[0]
+            .as_object()
+            .expect("Query error is not an object")
+            .get("message")
+            .expect("Error contains no message")
+            .as_str()
+            .expect("Error message is not a string");
+        assert_eq!(message, "Only queries can be sent in GET requests");
+    }
+
+    #[test]
+    fn posting_batches_yields_array_of_results() {
+        let id = "test-schema".to_string();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            test_schema(&id),
+            Default::default(),
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/{}/graphql", id))
+            .body(Body::from(
+                "[{\"query\": \"{ name }\"}, {\"query\": \"query Name { name }\"}]",
+            )).unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Every query of the batch yields a result, in order
+        let body = response
+            .into_body()
+            .concat2()
+            .wait()
+            .expect("Failed to read response");
+        let results: serde_json::Value =
+            serde_json::from_slice(&body).expect("Batch response is not valid JSON");
+        let expected_results: serde_json::Value = serde_json::from_str(
+            r#"[{ "data": { "name": "Jordi" } }, { "data": { "name": "Jordi" } }]"#,
+        ).unwrap();
+        assert_eq!(results, expected_results);
+    }
+
+    #[test]
+    fn runs_batched_queries_concurrently() {
+        let id = "test-schema".to_string();
+        let graphql_runner = Arc::new(SlowGraphQlRunner::default());
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            test_schema(&id),
+            Default::default(),
+            graphql_runner.clone(),
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let names = Vec::from_iter((0..15).map(|i| format!("Q{}", i)));
+        let queries = Vec::from_iter(names.iter().map(|name| {
+            format!(
+                r#"{{"query": "query {} {{ name }}", "operationName": "{}"}}"#,
+                name, name
+            )
+        }));
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/{}/graphql", id))
+            .body(Body::from(format!("[{}]", queries.join(", "))))
+            .unwrap();
+
+        // Run the request on a thread pool, like the server does
+        let mut runtime = Runtime::new().unwrap();
+        let response = runtime
+            .block_on(service.call(request))
+            .expect("Should return a response");
+        let body = runtime
+            .block_on(response.into_body().concat2())
+            .expect("Failed to read response");
+
+        // The results are in the order of the queries
+        let results: serde_json::Value =
+            serde_json::from_slice(&body).expect("Batch response is not valid JSON");
+        let expected_results = serde_json::Value::Array(Vec::from_iter(names.iter().map(|name| {
+            serde_json::from_str(&format!(r#"{{"data": {{"name": "{}"}}}}"#, name)).unwrap()
+        })));
+        assert_eq!(results, expected_results);
+
+        // The queries ran concurrently, but no more than the limit at a time
+        let max_queries_in_progress = *graphql_runner.max_queries_in_progress.lock().unwrap();
+        assert!(max_queries_in_progress > 1);
+        assert!(max_queries_in_progress <= MAX_CONCURRENT_QUERIES);
+    }
+
+    #[test]
+    fn posting_persisted_query_hashes_requires_registration() {
+        let id = "test-schema".to_string();
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            test_schema(&id),
+            Default::default(),
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        // SHA-256 hash of "{ name }"
+        let extensions = "\"extensions\": {\"persistedQuery\": {\"version\": 1, \
+                          \"sha256Hash\": \"30166fc3298853f22709fce1e4a00e98f1b6a3160eaaaf9cb3b7db6a16073b07\"}}";
+        let post = |body: String| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!("http://localhost:8000/{}/graphql", id))
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        // Unknown hashes are rejected so the client sends the full query
+        let response = service
+            .call(post(format!("{{{}}}", extensions)))
+            .wait()
+            .expect("Should return a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+        assert_eq!(errors[0]["message"], "PersistedQueryNotFound");
+
+        // Sending the query along with its hash registers it
+        let response = service
+            .call(post(format!("{{\"query\": \"{{ name }}\", {}}}", extensions)))
+            .wait()
+            .expect("Should return a response");
+        test_utils::assert_successful_response(response);
+
+        // After that, the hash alone is enough
+        let response = service
+            .call(post(format!("{{{}}}", extensions)))
+            .wait()
+            .expect("Should return a response");
+        let data = test_utils::assert_successful_response(response);
+        assert_eq!(data.get("name").and_then(|name| name.as_str()), Some("Jordi"));
+    }
+
+    #[test]
+    fn posting_stitched_queries_yields_results_per_subgraph() {
+        let schemas = test_schema("a");
+        let other_schema = test_schema("b").read().unwrap()["b"].clone();
+        schemas
+            .write()
+            .unwrap()
+            .insert("b".to_string(), other_schema);
+        let stitched_schema = stitched_schema(&schemas);
+
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            schemas,
+            stitched_schema,
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/graphql")
+            .body(Body::from("{\"query\": \"{ a { name } other: b { name } }\"}"))
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let data = test_utils::assert_successful_response(response);
+
+        // Every subgraph's result is returned under its root field
+        let expected_data: serde_json::Value = serde_json::from_str(
+            r#"{ "a": { "name": "Jordi" }, "other": { "name": "Jordi" } }"#,
+        ).unwrap();
+        assert_eq!(serde_json::Value::Object(data), expected_data);
+    }
+
+    #[test]
+    fn posting_index_node_queries_yields_subgraph_statuses() {
+        let names = Arc::new(RwLock::new(BTreeMap::from_iter(once((
+            String::from("test-id"),
+            String::from("test-name"),
+        )))));
+        let mut service = GraphQLService::new(
+            logger(),
+            names,
+            Default::default(),
+            Default::default(),
+            Arc::new(TestGraphQlRunner),
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let query = "{ \
+                     subgraphs { \
+                       id name location health error \
+                       latestBlock { number } chainHeadBlock { number } \
+                       entityCounts { entity count } \
+                     } \
+                     byName: subgraph(id: \\\"test-name\\\") { id } \
+                     unknown: subgraph(id: \\\"other\\\") { id } \
+                     }";
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/index-node/graphql")
+            .body(Body::from(format!("{{\"query\": \"{}\"}}", query)))
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let data = test_utils::assert_successful_response(response);
+
+        let expected_data: serde_json::Value = serde_json::from_str(
+            r#"{
+                "subgraphs": [{
+                    "id": "test-id",
+                    "name": "test-name",
+                    "location": "/ipfs/test-id",
+                    "health": "failed",
+                    "error": "Mapping aborted",
+                    "latestBlock": { "number": "1" },
+                    "chainHeadBlock": { "number": "2" },
+                    "entityCounts": [{ "entity": "User", "count": "3" }]
+                }],
+                "byName": { "id": "test-id" },
+                "unknown": null
+            }"#,
+        ).unwrap();
+        assert_eq!(serde_json::Value::Object(data), expected_data);
+    }
+
+    #[test]
+    fn index_node_queries_only_look_up_selected_statuses() {
+        let status_provider = Arc::new(TestStatusProvider::default());
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Arc::new(TestGraphQlRunner),
+            persisted_queries(),
+            status_provider.clone(),
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/index-node/graphql")
+            .body(Body::from(
+                "{\"query\": \"{ subgraph(id: \\\"test-id\\\") { health } }\"}",
+            )).unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let data = test_utils::assert_successful_response(response);
+        let expected_data: serde_json::Value =
+            serde_json::from_str(r#"{ "subgraph": { "health": "failed" } }"#).unwrap();
+        assert_eq!(serde_json::Value::Object(data), expected_data);
+
+        // Neither the other subgraphs nor the entity counts are looked up
+        assert_eq!(*status_provider.calls.lock().unwrap(), vec!["subgraph_status"]);
+    }
+
+    #[test]
+    fn posting_stitched_queries_for_unknown_subgraphs_yields_error() {
+        let schemas = test_schema("a");
+        let stitched_schema = stitched_schema(&schemas);
+
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            schemas,
+            stitched_schema,
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/graphql")
+            .body(Body::from("{\"query\": \"{ a { name } c { name } }\"}"))
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST);
+        assert_eq!(errors[0]["message"], "Type \"Query\" has no field \"c\"");
+    }
+
+    #[test]
+    fn posting_stitched_introspection_queries_yields_stitched_schema() {
+        let schemas = test_schema("org/a");
+        let stitched_schema = stitched_schema(&schemas);
+
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+        let mut service = GraphQLService::new(
+            logger(),
+            Default::default(),
+            schemas,
+            stitched_schema,
+            graphql_runner,
+            persisted_queries(),
+            status_provider(),
+        );
+
+        let query = "{ \
+                     org_a { name } \
+                     query: __type(name: \\\"Query\\\") { fields { name type { name } } } \
+                     }";
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:8000/graphql")
+            .body(Body::from(format!("{{\"query\": \"{}\"}}", query)))
+            .unwrap();
+
+        let response = service
+            .call(request)
+            .wait()
+            .expect("Should return a response");
+        let data = test_utils::assert_successful_response(response);
+
+        // The subgraph is selected through a sanitised root field whose type
+        // is prefixed with the field name
+        let expected_data: serde_json::Value = serde_json::from_str(
+            r#"{
+                "org_a": { "name": "Jordi" },
+                "query": {
+                    "fields": [{ "name": "org_a", "type": { "name": "org_a_Query" } }]
+                }
+            }"#,
+        ).unwrap();
+        assert_eq!(serde_json::Value::Object(data), expected_data);
+    }
 }
@@ -0,0 +1,922 @@
+use graphql_parser::{query as q, schema as s, Pos};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use graph::components::server::query::GraphQLServerError;
+use graph::prelude::*;
+use graph_graphql::prelude::Resolver;
+use graph_graphql::query::ast::get_operation;
+use graph_graphql::schema::ast as sast;
+
+/// A subgraph as seen through the stitched schema.
+#[derive(Debug)]
+struct Namespace {
+    /// Name of the subgraph.
+    subgraph: String,
+    /// Prefix of the subgraph's types in the stitched schema.
+    type_prefix: String,
+    /// Maps the names of the subgraph's types in the stitched schema to
+    /// their names in the subgraph's schema.
+    types: HashMap<String, String>,
+}
+
+impl Namespace {
+    /// Returns the name a type of the subgraph has in the subgraph's schema.
+    fn original_type_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.types.get(name).map_or(name, |original| original.as_str())
+    }
+
+    /// Returns the name a type of the subgraph has in the stitched schema.
+    fn stitched_type_name(&self, name: &str) -> Option<String> {
+        let stitched_name = format!("{}{}", self.type_prefix, name);
+        if self.types.contains_key(&stitched_name) {
+            Some(stitched_name)
+        } else {
+            None
+        }
+    }
+}
+
+/// A schema that combines the API schemas of all subgraphs.
+///
+/// Every subgraph is selected through a root field named after it, e.g.
+/// `org_name` for subgraph `org/name`, and its types are prefixed with that
+/// name, e.g. `org_name_Pair`. Scalars are shared by all subgraphs.
+#[derive(Clone, Debug)]
+pub struct StitchedSchema {
+    schema: Schema,
+    /// Maps root fields to the subgraphs they select from.
+    namespaces: BTreeMap<String, Arc<Namespace>>,
+}
+
+impl Default for StitchedSchema {
+    fn default() -> Self {
+        StitchedSchema::new(&BTreeMap::new())
+    }
+}
+
+impl StitchedSchema {
+    /// Stitches together the API schemas of the given subgraphs, keyed by
+    /// subgraph name.
+    pub fn new(schemas: &BTreeMap<String, Schema>) -> Self {
+        let mut namespaces = BTreeMap::new();
+        let mut type_names = HashSet::new();
+        let mut definitions = vec![];
+        let mut root_fields = vec![];
+
+        for (subgraph, schema) in schemas.iter() {
+            let query_type = match sast::get_root_query_type(&schema.document) {
+                Some(query_type) => query_type.name.clone(),
+                None => continue,
+            };
+
+            // Scalars are shared, all other types are prefixed
+            let subgraph_type_names = sast::get_type_definitions(&schema.document)
+                .into_iter()
+                .filter(|type_definition| match type_definition {
+                    s::TypeDefinition::Scalar(_) => false,
+                    _ => true,
+                }).map(|type_definition| sast::get_type_name(type_definition).clone())
+                .collect::<Vec<_>>();
+
+            // Pick a root field whose type prefix doesn't clash with the
+            // types of other subgraphs
+            let base_field_name = field_name(subgraph);
+            let (field_name, type_prefix) = (1..)
+                .map(|n| match n {
+                    1 => base_field_name.clone(),
+                    n => format!("{}_{}", base_field_name, n),
+                }).map(|field_name| {
+                    let type_prefix = format!("{}_", field_name);
+                    (field_name, type_prefix)
+                }).find(|(field_name, type_prefix)| {
+                    !namespaces.contains_key(field_name) && subgraph_type_names
+                        .iter()
+                        .all(|name| !type_names.contains(&format!("{}{}", type_prefix, name)))
+                }).unwrap();
+
+            let renames = subgraph_type_names
+                .into_iter()
+                .map(|name| (name.clone(), format!("{}{}", type_prefix, name)))
+                .collect::<HashMap<_, _>>();
+
+            for definition in schema.document.definitions.iter() {
+                let mut type_definition = match definition {
+                    s::Definition::TypeDefinition(type_definition) => type_definition.clone(),
+                    _ => continue,
+                };
+
+                // Only queries are supported across subgraphs
+                if let s::TypeDefinition::Object(ref object_type) = type_definition {
+                    if object_type.name == "Mutation" || object_type.name == "Subscription" {
+                        continue;
+                    }
+                }
+
+                rename_types(&mut type_definition, &renames);
+                if type_names.insert(sast::get_type_name(&type_definition).clone()) {
+                    definitions.push(s::Definition::TypeDefinition(type_definition));
+                }
+            }
+
+            root_fields.push(s::Field {
+                position: Pos::default(),
+                description: Some(format!("Selects data from subgraph \"{}\"", subgraph)),
+                name: field_name.clone(),
+                arguments: vec![],
+                field_type: s::Type::NamedType(renames[&query_type].clone()),
+                directives: vec![],
+            });
+            namespaces.insert(
+                field_name,
+                Arc::new(Namespace {
+                    subgraph: subgraph.clone(),
+                    type_prefix,
+                    types: renames
+                        .into_iter()
+                        .map(|(original, renamed)| (renamed, original))
+                        .collect(),
+                }),
+            );
+        }
+
+        definitions.push(s::Definition::TypeDefinition(s::TypeDefinition::Object(
+            s::ObjectType {
+                position: Pos::default(),
+                description: None,
+                name: String::from("Query"),
+                implements_interfaces: vec![],
+                directives: vec![],
+                fields: root_fields,
+            },
+        )));
+
+        StitchedSchema {
+            schema: Schema {
+                name: String::from("stitched"),
+                id: String::from("stitched"),
+                document: s::Document { definitions },
+            },
+            namespaces,
+        }
+    }
+
+    /// The schema that stitched queries are validated against.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Splits a query whose root fields select subgraphs, e.g.
+    /// `{ uniswap { pairs { id } } }`, into one query per subgraph, and a
+    /// query for the introspection fields at the root, if there are any.
+    pub fn split_query(
+        &self,
+        document: &q::Document,
+        operation_name: Option<&str>,
+    ) -> Result<StitchedQuery, GraphQLServerError> {
+        let operation = get_operation(document, operation_name)
+            .map_err(|e| GraphQLServerError::from(QueryError::from(e)))?;
+
+        let (variable_definitions, selection_set) = match operation {
+            q::OperationDefinition::SelectionSet(selection_set) => (vec![], selection_set),
+            q::OperationDefinition::Query(query) => {
+                (query.variable_definitions.clone(), &query.selection_set)
+            }
+            _ => {
+                return Err(GraphQLServerError::ClientError(String::from(
+                    "Only queries are supported across subgraphs",
+                )))
+            }
+        };
+
+        let fragments = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                q::Definition::Fragment(fragment) => Some((fragment.name.clone(), fragment)),
+                _ => None,
+            }).collect::<BTreeMap<_, _>>();
+
+        let mut response_keys = HashSet::new();
+        let mut introspection_selections = vec![];
+        let mut subgraph_queries = vec![];
+
+        for selection in selection_set.items.iter() {
+            let field = match selection {
+                q::Selection::Field(field) => field,
+                _ => {
+                    return Err(GraphQLServerError::ClientError(String::from(
+                        "Root selections must be subgraph fields",
+                    )))
+                }
+            };
+
+            // Fields like `__schema` are resolved against the stitched schema
+            if field.name.starts_with("__") {
+                introspection_selections.push(selection.clone());
+                continue;
+            }
+
+            let namespace = self.namespaces.get(&field.name).ok_or_else(|| {
+                GraphQLServerError::ClientError(format!(
+                    "Unknown subgraph field: {}",
+                    field.name
+                ))
+            })?;
+
+            if !field.arguments.is_empty() || !field.directives.is_empty() {
+                return Err(GraphQLServerError::ClientError(format!(
+                    "Subgraph field \"{}\" takes no arguments or directives",
+                    field.name
+                )));
+            }
+
+            let response_key = field.alias.clone().unwrap_or(field.name.clone());
+            if !response_keys.insert(response_key.clone()) {
+                return Err(GraphQLServerError::ClientError(format!(
+                    "Subgraph field \"{}\" is selected more than once",
+                    response_key
+                )));
+            }
+
+            let mut subgraph_document = extract_query(
+                field.position,
+                &field.selection_set,
+                &variable_definitions,
+                &fragments,
+            );
+            rename_query_types(&mut subgraph_document, namespace);
+
+            subgraph_queries.push(SubgraphQuery {
+                response_key,
+                subgraph: namespace.subgraph.clone(),
+                document: Arc::new(subgraph_document),
+                namespace: namespace.clone(),
+            });
+        }
+
+        let introspection = if introspection_selections.is_empty() {
+            None
+        } else {
+            Some(extract_query(
+                Pos::default(),
+                &q::SelectionSet {
+                    span: selection_set.span,
+                    items: introspection_selections,
+                },
+                &variable_definitions,
+                &fragments,
+            ))
+        };
+
+        Ok(StitchedQuery {
+            subgraph_queries,
+            introspection,
+        })
+    }
+}
+
+/// A stitched query, split into the parts resolved by the subgraphs and
+/// by the stitched schema.
+#[derive(Debug)]
+pub struct StitchedQuery {
+    pub subgraph_queries: Vec<SubgraphQuery>,
+    /// A query for the introspection fields at the root, e.g. `__schema`.
+    pub introspection: Option<q::Document>,
+}
+
+/// The part of a stitched query that is sent to a single subgraph.
+#[derive(Debug)]
+pub struct SubgraphQuery {
+    /// The key under which the subgraph's result is returned, i.e. the alias
+    /// or name of the root field.
+    pub response_key: String,
+    /// Name of the subgraph.
+    pub subgraph: String,
+    /// A query document with the selections of the root field, along with
+    /// the fragments and variable definitions they use, in terms of the
+    /// subgraph's schema.
+    pub document: Arc<q::Document>,
+    namespace: Arc<Namespace>,
+}
+
+impl SubgraphQuery {
+    /// Translates `__typename` values in the subgraph's result to the type
+    /// names of the stitched schema.
+    pub fn stitch_result(&self, mut result: QueryResult) -> QueryResult {
+        let fragments = self
+            .document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                q::Definition::Fragment(fragment) => Some((fragment.name.clone(), fragment)),
+                _ => None,
+            }).collect::<BTreeMap<_, _>>();
+
+        let selection_set = self
+            .document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                q::Definition::Operation(q::OperationDefinition::Query(query)) => {
+                    Some(&query.selection_set)
+                }
+                _ => None,
+            }).next();
+
+        if let (Some(data), Some(selection_set)) = (result.data.as_mut(), selection_set) {
+            stitch_typenames(data, selection_set, &fragments, &self.namespace);
+        }
+        result
+    }
+}
+
+/// Resolves the introspection fields of stitched queries; there is no data
+/// to resolve otherwise.
+#[derive(Clone)]
+pub struct StitchedResolver;
+
+impl Resolver for StitchedResolver {
+    fn resolve_objects(
+        &self,
+        _parent: &Option<q::Value>,
+        _field: &q::Name,
+        _field_definition: &s::Field,
+        _object_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
+    }
+
+    fn resolve_object(
+        &self,
+        _parent: &Option<q::Value>,
+        _field: &q::Field,
+        _field_definition: &s::Field,
+        _object_type: &s::ObjectType,
+        _arguments: &HashMap<&q::Name, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        Ok(q::Value::Null)
+    }
+}
+
+/// Combines the results of the introspection query and the subgraph queries
+/// into the result of the stitched query.
+pub fn merge_results(
+    introspection_result: Option<QueryResult>,
+    subgraph_results: Vec<(String, QueryResult)>,
+) -> QueryResult {
+    let mut data = BTreeMap::new();
+    let mut errors = vec![];
+
+    if let Some(result) = introspection_result {
+        if let Some(q::Value::Object(fields)) = result.data {
+            data.extend(fields);
+        }
+        errors.extend(result.errors.unwrap_or(vec![]));
+    }
+
+    for (response_key, result) in subgraph_results {
+        data.insert(response_key, result.data.unwrap_or(q::Value::Null));
+        errors.extend(result.errors.unwrap_or(vec![]));
+    }
+
+    let mut result = QueryResult::new(Some(q::Value::Object(data)));
+    for error in errors {
+        result.add_error(error);
+    }
+    result
+}
+
+/// Turns a subgraph name into a valid GraphQL name, e.g. `org/name` into
+/// `org_name`.
+fn field_name(subgraph: &str) -> String {
+    let name = subgraph
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+
+    // Names starting with `__` are reserved for introspection
+    let name = if name.starts_with("__") {
+        name.trim_left_matches('_').to_owned()
+    } else {
+        name
+    };
+
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => name,
+        _ => format!("_{}", name),
+    }
+}
+
+/// Builds a query with the given selections, along with the fragments and
+/// variable definitions they use.
+fn extract_query(
+    position: Pos,
+    selection_set: &q::SelectionSet,
+    variable_definitions: &[q::VariableDefinition],
+    fragments: &BTreeMap<q::Name, &q::FragmentDefinition>,
+) -> q::Document {
+    let mut used = Usage::default();
+    used.collect_selection_set(selection_set, fragments);
+
+    let mut definitions = vec![q::Definition::Operation(q::OperationDefinition::Query(
+        q::Query {
+            position,
+            name: None,
+            variable_definitions: variable_definitions
+                .iter()
+                .filter(|definition| used.variables.contains(&definition.name))
+                .cloned()
+                .collect(),
+            directives: vec![],
+            selection_set: selection_set.clone(),
+        },
+    ))];
+    definitions.extend(
+        fragments
+            .iter()
+            .filter(|(name, _)| used.fragments.contains(*name))
+            .map(|(_, fragment)| q::Definition::Fragment((*fragment).clone())),
+    );
+
+    q::Document { definitions }
+}
+
+/// Renames the types a type definition refers to, including itself.
+fn rename_types(type_definition: &mut s::TypeDefinition, renames: &HashMap<String, String>) {
+    let rename = |name: &mut String| {
+        if let Some(renamed) = renames.get(name) {
+            *name = renamed.clone();
+        }
+    };
+
+    match type_definition {
+        s::TypeDefinition::Scalar(_) => (),
+        s::TypeDefinition::Object(object_type) => {
+            rename(&mut object_type.name);
+            for interface in object_type.implements_interfaces.iter_mut() {
+                rename(interface);
+            }
+            for field in object_type.fields.iter_mut() {
+                rename_field_types(field, renames);
+            }
+        }
+        s::TypeDefinition::Interface(interface_type) => {
+            rename(&mut interface_type.name);
+            for field in interface_type.fields.iter_mut() {
+                rename_field_types(field, renames);
+            }
+        }
+        s::TypeDefinition::Union(union_type) => {
+            rename(&mut union_type.name);
+            for member in union_type.types.iter_mut() {
+                rename(member);
+            }
+        }
+        s::TypeDefinition::Enum(enum_type) => rename(&mut enum_type.name),
+        s::TypeDefinition::InputObject(input_object_type) => {
+            rename(&mut input_object_type.name);
+            for field in input_object_type.fields.iter_mut() {
+                rename_type(&mut field.value_type, renames);
+            }
+        }
+    }
+}
+
+fn rename_field_types(field: &mut s::Field, renames: &HashMap<String, String>) {
+    rename_type(&mut field.field_type, renames);
+    for argument in field.arguments.iter_mut() {
+        rename_type(&mut argument.value_type, renames);
+    }
+}
+
+fn rename_type(t: &mut s::Type, renames: &HashMap<String, String>) {
+    match t {
+        s::Type::NamedType(name) => {
+            if let Some(renamed) = renames.get(name) {
+                *name = renamed.clone();
+            }
+        }
+        s::Type::ListType(t) | s::Type::NonNullType(t) => rename_type(t, renames),
+    }
+}
+
+/// Translates the type names of the stitched schema in a subgraph query to
+/// those of the subgraph's schema.
+fn rename_query_types(document: &mut q::Document, namespace: &Namespace) {
+    for definition in document.definitions.iter_mut() {
+        match definition {
+            q::Definition::Operation(q::OperationDefinition::Query(query)) => {
+                for variable_definition in query.variable_definitions.iter_mut() {
+                    rename_variable_type(&mut variable_definition.var_type, namespace);
+                }
+                rename_selection_set_types(&mut query.selection_set, namespace);
+            }
+            q::Definition::Operation(_) => (),
+            q::Definition::Fragment(fragment) => {
+                rename_type_condition(&mut fragment.type_condition, namespace);
+                rename_selection_set_types(&mut fragment.selection_set, namespace);
+            }
+        }
+    }
+}
+
+fn rename_selection_set_types(selection_set: &mut q::SelectionSet, namespace: &Namespace) {
+    for selection in selection_set.items.iter_mut() {
+        match selection {
+            q::Selection::Field(field) => {
+                rename_selection_set_types(&mut field.selection_set, namespace)
+            }
+            q::Selection::FragmentSpread(_) => (),
+            q::Selection::InlineFragment(fragment) => {
+                if let Some(ref mut type_condition) = fragment.type_condition {
+                    rename_type_condition(type_condition, namespace);
+                }
+                rename_selection_set_types(&mut fragment.selection_set, namespace);
+            }
+        }
+    }
+}
+
+fn rename_type_condition(type_condition: &mut q::TypeCondition, namespace: &Namespace) {
+    let q::TypeCondition::On(name) = type_condition;
+    *name = namespace.original_type_name(name).to_owned();
+}
+
+fn rename_variable_type(t: &mut q::Type, namespace: &Namespace) {
+    match t {
+        q::Type::NamedType(name) => *name = namespace.original_type_name(name).to_owned(),
+        q::Type::ListType(t) | q::Type::NonNullType(t) => rename_variable_type(t, namespace),
+    }
+}
+
+/// Translates the `__typename` values in a subgraph's result data to the
+/// type names of the stitched schema.
+fn stitch_typenames(
+    value: &mut q::Value,
+    selection_set: &q::SelectionSet,
+    fragments: &BTreeMap<q::Name, &q::FragmentDefinition>,
+    namespace: &Namespace,
+) {
+    match value {
+        q::Value::List(values) => {
+            for value in values.iter_mut() {
+                stitch_typenames(value, selection_set, fragments, namespace);
+            }
+        }
+        q::Value::Object(object) => {
+            stitch_object_typenames(object, selection_set, fragments, namespace)
+        }
+        _ => (),
+    }
+}
+
+fn stitch_object_typenames(
+    object: &mut BTreeMap<q::Name, q::Value>,
+    selection_set: &q::SelectionSet,
+    fragments: &BTreeMap<q::Name, &q::FragmentDefinition>,
+    namespace: &Namespace,
+) {
+    for selection in selection_set.items.iter() {
+        match selection {
+            q::Selection::Field(field) => {
+                let response_key = field.alias.as_ref().unwrap_or(&field.name);
+                let value = match object.get_mut(response_key) {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                if field.name == "__typename" {
+                    let stitched_name = match value {
+                        q::Value::String(name) => namespace.stitched_type_name(name),
+                        _ => None,
+                    };
+                    if let Some(stitched_name) = stitched_name {
+                        *value = q::Value::String(stitched_name);
+                    }
+                } else {
+                    stitch_typenames(value, &field.selection_set, fragments, namespace);
+                }
+            }
+            q::Selection::FragmentSpread(spread) => {
+                if let Some(fragment) = fragments.get(&spread.fragment_name) {
+                    stitch_object_typenames(object, &fragment.selection_set, fragments, namespace);
+                }
+            }
+            q::Selection::InlineFragment(fragment) => {
+                stitch_object_typenames(object, &fragment.selection_set, fragments, namespace)
+            }
+        }
+    }
+}
+
+/// Fragments and variables used by a selection set.
+#[derive(Default)]
+struct Usage {
+    fragments: HashSet<q::Name>,
+    variables: HashSet<q::Name>,
+}
+
+impl Usage {
+    fn collect_selection_set(
+        &mut self,
+        selection_set: &q::SelectionSet,
+        fragments: &BTreeMap<q::Name, &q::FragmentDefinition>,
+    ) {
+        for selection in selection_set.items.iter() {
+            match selection {
+                q::Selection::Field(field) => {
+                    for (_, value) in field.arguments.iter() {
+                        self.collect_value(value);
+                    }
+                    self.collect_directives(&field.directives);
+                    self.collect_selection_set(&field.selection_set, fragments);
+                }
+                q::Selection::FragmentSpread(spread) => {
+                    self.collect_directives(&spread.directives);
+
+                    // Visit every fragment only once, in case they are cyclic
+                    if self.fragments.insert(spread.fragment_name.clone()) {
+                        if let Some(fragment) = fragments.get(&spread.fragment_name) {
+                            self.collect_directives(&fragment.directives);
+                            self.collect_selection_set(&fragment.selection_set, fragments);
+                        }
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    self.collect_directives(&fragment.directives);
+                    self.collect_selection_set(&fragment.selection_set, fragments);
+                }
+            }
+        }
+    }
+
+    fn collect_directives(&mut self, directives: &[q::Directive]) {
+        for directive in directives {
+            for (_, value) in directive.arguments.iter() {
+                self.collect_value(value);
+            }
+        }
+    }
+
+    fn collect_value(&mut self, value: &q::Value) {
+        match value {
+            q::Value::Variable(name) => {
+                self.variables.insert(name.clone());
+            }
+            q::Value::List(values) => {
+                for value in values {
+                    self.collect_value(value);
+                }
+            }
+            q::Value::Object(fields) => {
+                for value in fields.values() {
+                    self.collect_value(value);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graphql_parser;
+    use graphql_parser::query as q;
+    use graphql_parser::schema as s;
+    use std::collections::BTreeMap;
+    use std::iter::FromIterator;
+
+    use graph::prelude::*;
+    use graph_graphql::schema::ast as sast;
+
+    use super::{field_name, StitchedSchema};
+
+    fn schema(name: &str, document: &str) -> (String, Schema) {
+        (
+            name.to_string(),
+            Schema {
+                name: name.to_string(),
+                id: name.to_string(),
+                document: graphql_parser::parse_schema(document).unwrap(),
+            },
+        )
+    }
+
+    fn named_type(name: &str) -> s::Type {
+        s::Type::NamedType(name.to_string())
+    }
+
+    fn stitched_schema() -> StitchedSchema {
+        StitchedSchema::new(&BTreeMap::from_iter(vec![
+            schema(
+                "uniswap",
+                "scalar ID \
+                 enum Pair_orderBy { id } \
+                 type Pair { id: ID! } \
+                 type Query { pairs(orderBy: Pair_orderBy): [Pair!]! } \
+                 type Subscription { pairs: [Pair!]! }",
+            ),
+            schema(
+                "org/erc20",
+                "scalar ID \
+                 scalar String \
+                 input Token_filter { name: String } \
+                 type Token { id: ID! name: String } \
+                 type Query { tokens(where: Token_filter): [Token!]! }",
+            ),
+        ]))
+    }
+
+    #[test]
+    fn sanitises_subgraph_names() {
+        assert_eq!(field_name("uniswap"), "uniswap");
+        assert_eq!(field_name("org/name-v2"), "org_name_v2");
+        assert_eq!(field_name("2pairs"), "_2pairs");
+        assert_eq!(field_name("__schema"), "schema");
+        assert_eq!(field_name(""), "_");
+    }
+
+    #[test]
+    fn stitches_schemas_with_prefixed_types() {
+        let stitched_schema = stitched_schema();
+        let document = &stitched_schema.schema().document;
+
+        let query_type = sast::get_root_query_type(document).expect("Query type missing");
+        let root_fields = query_type
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.field_type.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            root_fields,
+            vec![
+                ("org_erc20", named_type("org_erc20_Query")),
+                ("uniswap", named_type("uniswap_Query")),
+            ]
+        );
+
+        // References between types are prefixed, scalars are shared
+        let pairs = match sast::get_named_type(document, &String::from("uniswap_Query")) {
+            Some(s::TypeDefinition::Object(object_type)) => {
+                sast::get_field_type(object_type, &String::from("pairs"))
+                    .expect("Field missing in stitched type")
+            }
+            _ => panic!("Stitched type missing"),
+        };
+        assert_eq!(
+            pairs.field_type,
+            s::Type::NonNullType(Box::new(s::Type::ListType(Box::new(s::Type::NonNullType(
+                Box::new(named_type("uniswap_Pair"))
+            )))))
+        );
+        assert_eq!(
+            pairs.arguments[0].value_type,
+            named_type("uniswap_Pair_orderBy")
+        );
+        assert!(sast::get_named_type(document, &String::from("ID")).is_some());
+        assert!(sast::get_named_type(document, &String::from("uniswap_ID")).is_none());
+
+        // Subscriptions are not stitched
+        assert!(sast::get_named_type(document, &String::from("uniswap_Subscription")).is_none());
+    }
+
+    #[test]
+    fn avoids_clashing_names() {
+        // `org`'s type `a_Pair` is prefixed to `org_a_Pair`, which rules out
+        // `org_a` as the root field of the other subgraphs
+        let stitched_schema = StitchedSchema::new(&BTreeMap::from_iter(vec![
+            schema("org", "type Query { pair: a_Pair } type a_Pair { id: String }"),
+            schema("org-a", "type Query { pair: Pair } type Pair { id: String }"),
+            schema("org/a", "type Query { pair: Pair } type Pair { id: String }"),
+        ]));
+
+        let subgraphs = stitched_schema
+            .namespaces
+            .iter()
+            .map(|(field, namespace)| (field.as_str(), namespace.subgraph.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subgraphs,
+            vec![("org", "org"), ("org_a_2", "org-a"), ("org_a_3", "org/a")]
+        );
+
+        let document = &stitched_schema.schema().document;
+        for type_name in vec!["org_a_Pair", "org_a_2_Pair", "org_a_3_Pair"] {
+            assert!(sast::get_named_type(document, &String::from(type_name)).is_some());
+        }
+    }
+
+    #[test]
+    fn splits_queries_by_subgraph() {
+        let document = graphql_parser::parse_query(
+            "
+            query Pairs($order: uniswap_Pair_orderBy, $where: org_erc20_Token_filter) {
+                uniswap { pairs(orderBy: $order) { ...PairFields } }
+                tokens: org_erc20 { tokens(where: $where) { ... on org_erc20_Token { id } } }
+                __typename
+            }
+            fragment PairFields on uniswap_Pair { id }
+            ",
+        ).unwrap();
+
+        let stitched_query = stitched_schema()
+            .split_query(&document, None)
+            .expect("Should split the query");
+        let queries = stitched_query.subgraph_queries;
+        assert_eq!(queries.len(), 2);
+
+        // Types are referred to by their names in the subgraph's schema
+        assert_eq!(queries[0].response_key, "uniswap");
+        assert_eq!(queries[0].subgraph, "uniswap");
+        assert_eq!(
+            format!("{}", queries[0].document),
+            format!(
+                "{}",
+                graphql_parser::parse_query(
+                    "
+                    query ($order: Pair_orderBy) { pairs(orderBy: $order) { ...PairFields } }
+                    fragment PairFields on Pair { id }
+                    "
+                ).unwrap()
+            )
+        );
+
+        assert_eq!(queries[1].response_key, "tokens");
+        assert_eq!(queries[1].subgraph, "org/erc20");
+        assert_eq!(
+            format!("{}", queries[1].document),
+            format!(
+                "{}",
+                graphql_parser::parse_query(
+                    "query ($where: Token_filter) { tokens(where: $where) { ... on Token { id } } }"
+                ).unwrap()
+            )
+        );
+
+        // Introspection fields are resolved against the stitched schema
+        assert_eq!(
+            format!("{}", stitched_query.introspection.expect("No introspection query")),
+            format!("{}", graphql_parser::parse_query("query { __typename }").unwrap())
+        );
+    }
+
+    #[test]
+    fn stitches_typenames_in_results() {
+        let document = graphql_parser::parse_query(
+            "
+            { uniswap { pairs { kind: __typename ...PairFields } } }
+            fragment PairFields on uniswap_Pair { __typename }
+            ",
+        ).unwrap();
+        let stitched_query = stitched_schema().split_query(&document, None).unwrap();
+
+        let pair = q::Value::Object(BTreeMap::from_iter(vec![
+            (String::from("kind"), q::Value::String(String::from("Pair"))),
+            (
+                String::from("__typename"),
+                q::Value::String(String::from("Pair")),
+            ),
+        ]));
+        let result = QueryResult::new(Some(q::Value::Object(BTreeMap::from_iter(vec![(
+            String::from("pairs"),
+            q::Value::List(vec![pair]),
+        )]))));
+
+        let result = stitched_query.subgraph_queries[0].stitch_result(result);
+        let expected_pair = q::Value::Object(BTreeMap::from_iter(vec![
+            (
+                String::from("kind"),
+                q::Value::String(String::from("uniswap_Pair")),
+            ),
+            (
+                String::from("__typename"),
+                q::Value::String(String::from("uniswap_Pair")),
+            ),
+        ]));
+        assert_eq!(
+            result.data,
+            Some(q::Value::Object(BTreeMap::from_iter(vec![(
+                String::from("pairs"),
+                q::Value::List(vec![expected_pair]),
+            )])))
+        );
+    }
+
+    #[test]
+    fn rejects_mutations_and_root_arguments() {
+        let stitched_schema = stitched_schema();
+
+        let document = graphql_parser::parse_query("mutation { uniswap { pairs { id } } }")
+            .expect("Invalid test query");
+        stitched_schema
+            .split_query(&document, None)
+            .expect_err("Should reject mutations");
+
+        let document = graphql_parser::parse_query("{ uniswap(first: 5) { pairs { id } } }")
+            .expect("Invalid test query");
+        stitched_schema
+            .split_query(&document, None)
+            .expect_err("Should reject arguments on subgraph fields");
+    }
+}
@@ -1,5 +1,7 @@
 extern crate futures;
 extern crate graph;
+extern crate graph_graphql;
+extern crate graph_mock;
 extern crate graph_server_http;
 extern crate graphql_parser;
 extern crate http;
@@ -12,6 +14,8 @@ use std::collections::BTreeMap;
 use std::iter::FromIterator;
 
 use graph::prelude::*;
+use graph_graphql::prelude::PersistedQueries;
+use graph_mock::MockSubgraphStatusProvider;
 
 use graph_server_http::test_utils;
 use graph_server_http::GraphQLServer as HyperGraphQLServer;
@@ -34,6 +38,13 @@ impl GraphQlRunner for TestGraphQlRunner {
     fn run_subscription(&self, _subscription: Subscription) -> SubscriptionResultFuture {
         unimplemented!();
     }
+
+    fn subgraph_latest_block(
+        &self,
+        _subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error> {
+        Ok(None)
+    }
 }
 
 #[cfg(test)]
@@ -56,7 +67,13 @@ mod test {
                 let logger = slog::Logger::root(slog::Discard, o!());
 
                 let query_runner = Arc::new(TestGraphQlRunner);
-                let mut server = HyperGraphQLServer::new(&logger, query_runner);
+                let persisted_queries = Arc::new(PersistedQueries::new(10));
+                let mut server = HyperGraphQLServer::new(
+                    &logger,
+                    query_runner,
+                    persisted_queries,
+                    Arc::new(MockSubgraphStatusProvider),
+                );
                 let http_server = server.serve(8001).expect("Failed to start GraphQL server");
 
                 // Create a simple schema and send it to the server
@@ -104,7 +121,13 @@ mod test {
                 let logger = slog::Logger::root(slog::Discard, o!());
 
                 let query_runner = Arc::new(TestGraphQlRunner);
-                let mut server = HyperGraphQLServer::new(&logger, query_runner);
+                let persisted_queries = Arc::new(PersistedQueries::new(10));
+                let mut server = HyperGraphQLServer::new(
+                    &logger,
+                    query_runner,
+                    persisted_queries,
+                    Arc::new(MockSubgraphStatusProvider),
+                );
                 let http_server = server.serve(8002).expect("Failed to start GraphQL server");
 
                 // Launch the server to handle a single request
@@ -186,7 +209,13 @@ mod test {
                 let logger = slog::Logger::root(slog::Discard, o!());
 
                 let query_runner = Arc::new(TestGraphQlRunner);
-                let mut server = HyperGraphQLServer::new(&logger, query_runner);
+                let persisted_queries = Arc::new(PersistedQueries::new(10));
+                let mut server = HyperGraphQLServer::new(
+                    &logger,
+                    query_runner,
+                    persisted_queries,
+                    Arc::new(MockSubgraphStatusProvider),
+                );
                 let http_server = server.serve(8003).expect("Failed to start GraphQL server");
 
                 // Launch the server to handle a single request
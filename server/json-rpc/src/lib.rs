@@ -24,6 +24,12 @@ const GRAPH_MASTER_TOKEN_VAR: &str = "GRAPH_MASTER_TOKEN";
 const JSON_RPC_DEPLOY_ERROR: i64 = 0;
 const JSON_RPC_REMOVE_ERROR: i64 = 1;
 const JSON_RPC_UNAUTHORIZED_ERROR: i64 = 2;
+const JSON_RPC_LOGS_ERROR: i64 = 3;
+
+/// Number of log entries returned by `subgraph_logs` unless `first` is given.
+const DEFAULT_LOG_ENTRIES: usize = 100;
+/// Maximum number of log entries returned by `subgraph_logs`.
+const MAX_LOG_ENTRIES: usize = 1000;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SubgraphDeployParams {
@@ -48,6 +54,21 @@ impl fmt::Display for SubgraphRemoveParams {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct SubgraphLogsParams {
+    name_or_id: String,
+    /// Only return entries with at least this level.
+    level: Option<SubgraphLogLevel>,
+    /// Number of most recent entries to return.
+    first: Option<usize>,
+}
+
+impl fmt::Display for SubgraphLogsParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?}", self)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct SubgraphAuthorizeParams {
     subgraph_api_keys: BTreeMap<String, String>,
@@ -68,6 +89,7 @@ impl Metadata for AuthorizationHeader {}
 
 pub struct JsonRpcServer<T> {
     provider: Arc<T>,
+    log_store: Arc<SubgraphLogStore>,
     logger: Logger,
     // Maps auth tokens to authorized subgraph name.
     subgraph_api_keys: Arc<RwLock<BTreeMap<String, String>>>,
@@ -149,6 +171,45 @@ impl<T: SubgraphProvider> JsonRpcServer<T> {
         )
     }
 
+    /// Handler for the `subgraph_logs` endpoint.
+    ///
+    /// Returns the most recent log entries of a subgraph, most recent first.
+    fn logs_handler(
+        &self,
+        params: SubgraphLogsParams,
+        auth: AuthorizationHeader,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        info!(self.logger, "Received subgraph_logs request"; "params" => params.to_string());
+
+        let name_or_id = params.name_or_id;
+        // As with `subgraph_remove`, auth requires `name_or_id` to be a name.
+        if should_check_auth()
+            && Some(&auth.bearer_token) != self.subgraph_api_keys.read().unwrap().get(&name_or_id)
+        {
+            return Err(json_rpc_error(
+                JSON_RPC_UNAUTHORIZED_ERROR,
+                "API key is invalid".to_owned(),
+            ));
+        }
+
+        let subgraph_id = self
+            .provider
+            .list()
+            .into_iter()
+            .find(|(name, _)| *name == name_or_id)
+            .map_or(name_or_id, |(_, id)| id);
+        let level = params.level.unwrap_or(SubgraphLogLevel::Debug);
+        let first = params
+            .first
+            .unwrap_or(DEFAULT_LOG_ENTRIES)
+            .min(MAX_LOG_ENTRIES);
+
+        self.log_store
+            .subgraph_logs(&subgraph_id, level, first)
+            .and_then(|entries| serde_json::to_value(entries).map_err(Error::from))
+            .map_err(|e| json_rpc_error(JSON_RPC_LOGS_ERROR, e.to_string()))
+    }
+
     /// Handler for the `subgraph_authorize` endpoint.
     ///
     /// Taken subgraph name and returns an API key that can be used to
@@ -183,13 +244,19 @@ impl<T: SubgraphProvider> JsonRpcServer<T> {
 impl<T: SubgraphProvider> JsonRpcServerTrait<T> for JsonRpcServer<T> {
     type Server = Server;
 
-    fn serve(port: u16, provider: Arc<T>, logger: Logger) -> Result<Self::Server, io::Error> {
+    fn serve(
+        port: u16,
+        provider: Arc<T>,
+        log_store: Arc<SubgraphLogStore>,
+        logger: Logger,
+    ) -> Result<Self::Server, io::Error> {
         let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port);
 
         let mut handler = MetaIoHandler::with_compatibility(Compatibility::Both);
 
         let arc_self = Arc::new(JsonRpcServer {
             provider,
+            log_store,
             logger: logger.new(o!("component" => "JsonRpcServer")),
             subgraph_api_keys: Arc::new(RwLock::new(BTreeMap::new())),
         });
@@ -223,6 +290,16 @@ impl<T: SubgraphProvider> JsonRpcServerTrait<T> for JsonRpcServer<T> {
                 .and_then(move |params| me.authorize_handler(params, auth))
         });
 
+        // `subgraph_logs` handler.
+        let me = arc_self.clone();
+        handler.add_method_with_meta("subgraph_logs", move |params: Params, auth| {
+            let me = me.clone();
+            params
+                .parse()
+                .into_future()
+                .and_then(move |params| me.logs_handler(params, auth))
+        });
+
         // `subgraph_list` handler.
         let me = arc_self.clone();
         handler.add_method_with_meta("subgraph_list", move |_, _| me.list_handler());
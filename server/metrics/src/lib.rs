@@ -0,0 +1,7 @@
+extern crate futures;
+extern crate graph;
+extern crate hyper;
+
+mod server;
+
+pub use self::server::{PrometheusMetricsServeError, PrometheusMetricsServer};
@@ -0,0 +1,104 @@
+use hyper::header;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::error::Error;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use graph::prelude::{MetricsServer as MetricsServerTrait, *};
+
+/// Errors that may occur when starting the server.
+#[derive(Debug)]
+pub enum PrometheusMetricsServeError {
+    BindError(hyper::Error),
+}
+
+impl Error for PrometheusMetricsServeError {
+    fn description(&self) -> &str {
+        "Failed to start the server"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+impl fmt::Display for PrometheusMetricsServeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrometheusMetricsServeError::BindError(e) => {
+                write!(f, "Failed to bind metrics server: {}", e)
+            }
+        }
+    }
+}
+
+impl From<hyper::Error> for PrometheusMetricsServeError {
+    fn from(err: hyper::Error) -> Self {
+        PrometheusMetricsServeError::BindError(err)
+    }
+}
+
+/// A server that exposes the metrics of a node to Prometheus under `/metrics`.
+pub struct PrometheusMetricsServer {
+    logger: Logger,
+    registry: MetricsRegistry,
+}
+
+impl PrometheusMetricsServer {
+    /// Creates a new metrics server for the metrics in `registry`.
+    pub fn new(logger: &Logger, registry: MetricsRegistry) -> Self {
+        PrometheusMetricsServer {
+            logger: logger.new(o!("component" => "MetricsServer")),
+            registry,
+        }
+    }
+
+    fn handle_request(registry: &MetricsRegistry, request: Request<Body>) -> Response<Body> {
+        match (request.method(), request.uri().path()) {
+            (&Method::GET, "/metrics") => match registry.encode() {
+                Ok(metrics) => Response::builder()
+                    .status(StatusCode::OK)
+                    .header(header::CONTENT_TYPE, registry.content_type().as_str())
+                    .body(Body::from(metrics))
+                    .unwrap(),
+                Err(e) => Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(e.to_string()))
+                    .unwrap(),
+            },
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Not found"))
+                .unwrap(),
+        }
+    }
+}
+
+impl MetricsServerTrait for PrometheusMetricsServer {
+    type ServeError = PrometheusMetricsServeError;
+
+    fn serve(
+        &mut self,
+        port: u16,
+    ) -> Result<Box<Future<Item = (), Error = ()> + Send>, Self::ServeError> {
+        let logger = self.logger.clone();
+
+        let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port);
+
+        let registry = self.registry.clone();
+        let new_service = move || {
+            let registry = registry.clone();
+            service_fn(move |request| {
+                Ok::<_, hyper::Error>(Self::handle_request(&registry, request))
+            })
+        };
+
+        // Create a task to run the server and handle HTTP requests
+        let task = Server::try_bind(&addr.into())?
+            .serve(new_service)
+            .map_err(move |e| error!(logger, "Server error"; "error" => format!("{}", e)));
+
+        Ok(Box::new(task))
+    }
+}
@@ -0,0 +1,69 @@
+extern crate futures;
+extern crate graph;
+extern crate graph_server_metrics;
+extern crate hyper;
+
+use hyper::{Body, Client, Request, StatusCode};
+
+use graph::prelude::*;
+use graph_server_metrics::PrometheusMetricsServer;
+
+#[test]
+fn serves_registered_metrics() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(futures::lazy(|| {
+            let logger = Logger::root(slog::Discard, o!());
+
+            let registry = MetricsRegistry::new();
+            let calls = registry
+                .new_counter_vec("test_calls_total", "Number of test calls", &["method"])
+                .expect("Failed to register test counter");
+            calls.with_label_values(&["get"]).inc();
+
+            let mut server = PrometheusMetricsServer::new(&logger, registry);
+            let metrics_server = server.serve(8051).expect("Failed to start metrics server");
+
+            // Launch the server to handle a single request
+            tokio::spawn(metrics_server.fuse());
+
+            let client = Client::new();
+            let request = Request::get("http://localhost:8051/metrics")
+                .body(Body::empty())
+                .unwrap();
+
+            client
+                .request(request)
+                .and_then(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                    response.into_body().concat2()
+                }).map(|body| {
+                    let metrics = String::from_utf8(body.to_vec()).unwrap();
+                    assert!(metrics.contains("test_calls_total{method=\"get\"} 1"));
+                })
+        })).unwrap()
+}
+
+#[test]
+fn rejects_unknown_paths() {
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(futures::lazy(|| {
+            let logger = Logger::root(slog::Discard, o!());
+
+            let mut server = PrometheusMetricsServer::new(&logger, MetricsRegistry::new());
+            let metrics_server = server.serve(8052).expect("Failed to start metrics server");
+
+            // Launch the server to handle a single request
+            tokio::spawn(metrics_server.fuse());
+
+            let client = Client::new();
+            let request = Request::get("http://localhost:8052/graphql")
+                .body(Body::empty())
+                .unwrap();
+
+            client.request(request).map(|response| {
+                assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            })
+        })).unwrap()
+}
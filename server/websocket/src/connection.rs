@@ -1,27 +1,79 @@
 use futures::future::IntoFuture;
 use futures::stream::SplitStream;
 use futures::sync::mpsc;
-use graphql_parser::parse_query;
+use graphql_parser::query as q;
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::str;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
 use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
 
+use graph::components::metrics::Gauge;
 use graph::prelude::*;
 use graph::serde_json;
+use graph::tokio::timer::{Delay, Interval};
+use graph_graphql::prelude::{PersistedQueries, PersistedQueryError};
+use graph_graphql::query::ast::get_operation;
+
+use server::{GuardedSchema, SlowClientPolicy, SubscriptionServerConfig};
+
+/// GraphQL over WebSocket protocols supported by the server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WsProtocol {
+    /// The legacy Apollo `subscriptions-transport-ws` protocol.
+    GraphQlWs,
+    /// The newer `graphql-transport-ws` protocol.
+    GraphQlTransportWs,
+}
 
-use server::GuardedSchema;
+impl WsProtocol {
+    /// Picks the protocol to use from the `Sec-WebSocket-Protocol` header of
+    /// the handshake request. Prefers `graphql-transport-ws` and falls back
+    /// to the legacy protocol if the client didn't ask for it.
+    pub fn negotiate(header: Option<&[u8]>) -> Self {
+        let requested = header
+            .and_then(|value| str::from_utf8(value).ok())
+            .unwrap_or("");
+
+        if requested
+            .split(',')
+            .any(|protocol| protocol.trim() == WsProtocol::GraphQlTransportWs.name())
+        {
+            WsProtocol::GraphQlTransportWs
+        } else {
+            WsProtocol::GraphQlWs
+        }
+    }
+
+    /// The name of the protocol as used in `Sec-WebSocket-Protocol`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WsProtocol::GraphQlWs => "graphql-ws",
+            WsProtocol::GraphQlTransportWs => "graphql-transport-ws",
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct StartPayload {
-    query: String,
+    /// May be omitted if `extensions` refers to a persisted query.
+    query: Option<String>,
     variables: Option<serde_json::Value>,
     operation_name: Option<String>,
+    extensions: Option<serde_json::Value>,
 }
 
 /// GraphQL/WebSocket message received from a client.
+///
+/// `ConnectionTerminate`, `Start` and `Stop` are only valid in the legacy
+/// protocol; `Subscribe`, `Complete`, `Ping` and `Pong` only in
+/// `graphql-transport-ws`.
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum IncomingMessage {
@@ -29,39 +81,128 @@ enum IncomingMessage {
     ConnectionTerminate,
     Start { id: String, payload: StartPayload },
     Stop { id: String },
+    Subscribe { id: String, payload: StartPayload },
+    Complete { id: String },
+    Ping { payload: Option<serde_json::Value> },
+    Pong { payload: Option<serde_json::Value> },
 }
 
 impl IncomingMessage {
-    pub fn from_ws_message(msg: WsMessage) -> Result<Self, WsError> {
+    pub fn from_ws_message(msg: WsMessage, protocol: WsProtocol) -> Result<Self, WsError> {
         let text = msg.into_text()?;
-        serde_json::from_str(text.as_str()).map_err(|e| {
+        let msg: IncomingMessage = serde_json::from_str(text.as_str()).map_err(|e| {
             WsError::Protocol(
                 format!("Invalid GraphQL over WebSocket message: {}: {}", text, e).into(),
             )
-        })
+        })?;
+
+        if msg.is_supported_by(protocol) {
+            Ok(msg)
+        } else {
+            Err(WsError::Protocol(
+                format!(
+                    "Message not supported by the {} protocol: {}",
+                    protocol.name(),
+                    text
+                ).into(),
+            ))
+        }
+    }
+
+    fn is_supported_by(&self, protocol: WsProtocol) -> bool {
+        use self::IncomingMessage::*;
+
+        match (self, protocol) {
+            (ConnectionInit { .. }, _) => true,
+            (ConnectionTerminate, WsProtocol::GraphQlWs) => true,
+            (Start { .. }, WsProtocol::GraphQlWs) => true,
+            (Stop { .. }, WsProtocol::GraphQlWs) => true,
+            (Subscribe { .. }, WsProtocol::GraphQlTransportWs) => true,
+            (Complete { .. }, WsProtocol::GraphQlTransportWs) => true,
+            (Ping { .. }, WsProtocol::GraphQlTransportWs) => true,
+            (Pong { .. }, WsProtocol::GraphQlTransportWs) => true,
+            _ => false,
+        }
     }
 }
 
 /// GraphQL/WebSocket message to be sent to the client.
+///
+/// `KeepAlive` and `Data` are only used in the legacy protocol; `Ping`,
+/// `Pong` and `Next` only in `graphql-transport-ws`.
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum OutgoingMessage {
     ConnectionAck,
-    Error { id: String, payload: String },
+    #[serde(rename = "ka")]
+    KeepAlive,
+    Ping {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        payload: Option<serde_json::Value>,
+    },
+    Pong {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        payload: Option<serde_json::Value>,
+    },
+    Error { id: String, payload: serde_json::Value },
     Data { id: String, payload: QueryResult },
+    Next { id: String, payload: QueryResult },
     Complete { id: String },
 }
 
 impl OutgoingMessage {
-    pub fn from_query_result(id: String, result: QueryResult) -> Self {
-        OutgoingMessage::Data {
-            id: id,
-            payload: result,
+    pub fn from_query_result(protocol: WsProtocol, id: String, result: QueryResult) -> Self {
+        match protocol {
+            WsProtocol::GraphQlWs => OutgoingMessage::Data {
+                id: id,
+                payload: result,
+            },
+            WsProtocol::GraphQlTransportWs => OutgoingMessage::Next {
+                id: id,
+                payload: result,
+            },
+        }
+    }
+
+    pub fn from_error_string(protocol: WsProtocol, id: String, s: String) -> Self {
+        let payload = match protocol {
+            WsProtocol::GraphQlWs => serde_json::Value::String(s),
+            WsProtocol::GraphQlTransportWs => {
+                // `graphql-transport-ws` expects a list of GraphQL errors
+                let mut error = serde_json::Map::new();
+                error.insert(String::from("message"), serde_json::Value::String(s));
+                serde_json::Value::Array(vec![serde_json::Value::Object(error)])
+            }
+        };
+        OutgoingMessage::Error { id, payload }
+    }
+
+    /// Creates the message reporting a subscription that failed before
+    /// producing any results. The legacy protocol reports these as data
+    /// with errors, `graphql-transport-ws` as an `error` message.
+    pub fn from_subscription_error(
+        protocol: WsProtocol,
+        id: String,
+        e: SubscriptionError,
+    ) -> Self {
+        let result = QueryResult::from(e);
+
+        match protocol {
+            WsProtocol::GraphQlWs => Self::from_query_result(protocol, id, result),
+            WsProtocol::GraphQlTransportWs => OutgoingMessage::Error {
+                id,
+                payload: serde_json::to_value(result.errors.unwrap_or_default())
+                    .expect("invalid GraphQL errors"),
+            },
         }
     }
 
-    pub fn from_error_string(id: String, s: String) -> Self {
-        OutgoingMessage::Error { id, payload: s }
+    /// The message to send periodically to keep the connection alive.
+    pub fn keep_alive(protocol: WsProtocol) -> Self {
+        match protocol {
+            WsProtocol::GraphQlWs => OutgoingMessage::KeepAlive,
+            WsProtocol::GraphQlTransportWs => OutgoingMessage::Ping { payload: None },
+        }
     }
 }
 
@@ -71,75 +212,190 @@ impl From<OutgoingMessage> for WsMessage {
     }
 }
 
+/// Sending end of the bounded buffer of outgoing messages of a connection.
+///
+/// The connection is dropped if the client doesn't read its messages fast
+/// enough and the buffer fills up. Depending on the `SlowClientPolicy`,
+/// operation results are dropped instead.
+#[derive(Clone)]
+struct MessageSink {
+    sender: mpsc::Sender<WsMessage>,
+    slow_client_policy: SlowClientPolicy,
+    disconnect: SharedCancelGuard,
+}
+
+impl MessageSink {
+    fn send(&mut self, msg: WsMessage) -> Result<(), WsError> {
+        let disconnect = &self.disconnect;
+        self.sender.try_send(msg).map_err(|e| {
+            if e.is_full() {
+                disconnect.cancel();
+            }
+            WsError::Http(500)
+        })
+    }
+
+    fn send_result(&mut self, msg: WsMessage) -> Result<(), WsError> {
+        match (self.sender.try_send(msg), self.slow_client_policy) {
+            (Err(ref e), SlowClientPolicy::DropResults) if e.is_full() => Ok(()),
+            (Err(e), _) => {
+                if e.is_full() {
+                    self.disconnect.cancel();
+                }
+                Err(WsError::Http(500))
+            }
+            (Ok(()), _) => Ok(()),
+        }
+    }
+}
+
 /// Helper function to send outgoing messages.
-fn send_message(
-    sink: &mpsc::UnboundedSender<WsMessage>,
-    msg: OutgoingMessage,
-) -> Result<(), WsError> {
-    sink.unbounded_send(msg.into())
-        .map_err(|_| WsError::Http(500))
+fn send_message(sink: &mut MessageSink, msg: OutgoingMessage) -> Result<(), WsError> {
+    sink.send(msg.into())
+}
+
+/// Helper function to send operation results, which are subject to the
+/// `SlowClientPolicy`.
+fn send_result(sink: &mut MessageSink, msg: OutgoingMessage) -> Result<(), WsError> {
+    sink.send_result(msg.into())
 }
 
 /// Helper function to send error messages.
 fn send_error_string(
-    sink: &mpsc::UnboundedSender<WsMessage>,
+    sink: &mut MessageSink,
+    protocol: WsProtocol,
     operation_id: String,
     error: String,
 ) -> Result<(), WsError> {
-    sink.unbounded_send(OutgoingMessage::from_error_string(operation_id, error).into())
-        .map_err(|_| WsError::Http(500))
+    sink.send(OutgoingMessage::from_error_string(protocol, operation_id, error).into())
+}
+
+/// Helper function to close the connection with a close code and reason.
+fn send_close(sink: &mut MessageSink, code: u16, reason: &str) -> Result<(), WsError> {
+    sink.send(WsMessage::Close(Some(CloseFrame {
+        code: CloseCode::Library(code),
+        reason: String::from(reason).into(),
+    })))
+}
+
+/// Returns whether the document is a subscription. Queries and mutations
+/// produce a single result, subscriptions a stream of results.
+fn is_subscription(query: &Query) -> bool {
+    let operation_name = query.operation_name.as_ref().map(|name| name.as_str());
+    match get_operation(&query.document, operation_name) {
+        Ok(q::OperationDefinition::Subscription(_)) => true,
+        _ => false,
+    }
+}
+
+/// Where a connection is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ConnectionState {
+    /// Waiting for the client to send `connection_init`.
+    AwaitingInit,
+    /// The connection was acknowledged.
+    Initialized,
+    /// We have asked the client to close the connection; any further
+    /// messages are ignored.
+    Closing,
+}
+
+/// Counts a subscription as active for as long as it is alive.
+struct ActiveSubscription {
+    subscriptions: Gauge,
+}
+
+impl ActiveSubscription {
+    fn new(subscriptions: Gauge) -> Self {
+        subscriptions.inc();
+        ActiveSubscription { subscriptions }
+    }
+}
+
+impl Drop for ActiveSubscription {
+    fn drop(&mut self) {
+        self.subscriptions.dec();
+    }
 }
 
+/// Running operations by ID. Shared with the operations themselves so they
+/// can remove themselves once they are done.
+type OperationGuards = Arc<Mutex<HashMap<String, CancelGuard>>>;
+
 /// Responsible for recording operation ids and stopping them.
 /// On drop, cancels all operations.
 struct Operations {
-    operations: HashMap<String, CancelGuard>,
-    msg_sink: mpsc::UnboundedSender<WsMessage>,
+    protocol: WsProtocol,
+    max_operations: usize,
+    operations: OperationGuards,
+    msg_sink: MessageSink,
 }
 
 impl Operations {
-    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
+    fn new(protocol: WsProtocol, max_operations: usize, msg_sink: MessageSink) -> Self {
         Self {
-            operations: HashMap::new(),
+            protocol,
+            max_operations,
+            operations: Arc::new(Mutex::new(HashMap::new())),
             msg_sink,
         }
     }
 
+    fn is_full(&self) -> bool {
+        self.operations.lock().unwrap().len() >= self.max_operations
+    }
+
+    fn guards(&self) -> OperationGuards {
+        self.operations.clone()
+    }
+
     fn contains(&self, id: &str) -> bool {
-        self.operations.contains_key(id)
+        self.operations.lock().unwrap().contains_key(id)
     }
 
     fn insert(&mut self, id: String, guard: CancelGuard) {
-        self.operations.insert(id, guard);
+        self.operations.lock().unwrap().insert(id, guard);
     }
 
     fn stop(&mut self, operation_id: String) -> Result<(), WsError> {
         // Remove the operation with this ID from the known operations.
-        match self.operations.remove(&operation_id) {
-            Some(stopper) => {
+        let stopper = self.operations.lock().unwrap().remove(&operation_id);
+
+        match (stopper, self.protocol) {
+            (Some(stopper), WsProtocol::GraphQlWs) => {
                 // Cancel the subscription result stream.
                 stopper.cancel();
 
                 // Send a GQL_COMPLETE to indicate the operation is been completed.
                 send_message(
-                    &self.msg_sink,
+                    &mut self.msg_sink,
                     OutgoingMessage::Complete {
                         id: operation_id.clone(),
                     },
                 )
             }
-            None => send_error_string(
-                &self.msg_sink,
+            (None, WsProtocol::GraphQlWs) => send_error_string(
+                &mut self.msg_sink,
+                self.protocol,
                 operation_id.clone(),
                 format!("Unknown operation ID: {}", operation_id),
             ),
+
+            // In `graphql-transport-ws`, the client's `complete` needs no
+            // response and may race with the operation finishing by itself
+            (stopper, WsProtocol::GraphQlTransportWs) => {
+                if let Some(stopper) = stopper {
+                    stopper.cancel();
+                }
+                Ok(())
+            }
         }
     }
 }
 
 impl Drop for Operations {
     fn drop(&mut self) {
-        let ids = Vec::from_iter(self.operations.keys().cloned());
+        let ids = Vec::from_iter(self.operations.lock().unwrap().keys().cloned());
         for id in ids {
             // Discard errors, the connection is being shutdown anyways.
             let _ = self.stop(id);
@@ -155,6 +411,10 @@ pub struct GraphQlConnection<Q, S> {
     stream: WebSocketStream<S>,
     subgraphs: SubgraphRegistry<GuardedSchema>,
     subgraph: String,
+    protocol: WsProtocol,
+    config: SubscriptionServerConfig,
+    persisted_queries: Arc<PersistedQueries>,
+    subscriptions: Gauge,
 }
 
 impl<Q, S> GraphQlConnection<Q, S>
@@ -163,12 +423,17 @@ where
     S: AsyncRead + AsyncWrite + Send + 'static,
 {
     /// Creates a new GraphQL subscription service.
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub(crate) fn new(
         logger: &Logger,
         subgraphs: SubgraphRegistry<GuardedSchema>,
         subgraph: String,
+        protocol: WsProtocol,
+        config: SubscriptionServerConfig,
         stream: WebSocketStream<S>,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        subscriptions: Gauge,
     ) -> Self {
         GraphQlConnection {
             id: Uuid::new_v4().to_string(),
@@ -177,62 +442,267 @@ where
             stream,
             subgraphs,
             subgraph,
+            protocol,
+            config,
+            persisted_queries,
+            subscriptions,
         }
     }
 
+    /// Closes the connection if the client doesn't send `connection_init`
+    /// in time.
+    fn enforce_connection_init_timeout(
+        mut msg_sink: MessageSink,
+        state: Arc<Mutex<ConnectionState>>,
+        timeout: Duration,
+        logger: Logger,
+    ) -> impl Future<Item = (), Error = ()> {
+        Delay::new(Instant::now() + timeout)
+            .map_err(|_| ())
+            .and_then(move |_| {
+                let mut state = state.lock().unwrap();
+                if *state == ConnectionState::AwaitingInit {
+                    debug!(logger, "Connection initialisation timeout");
+
+                    *state = ConnectionState::Closing;
+
+                    // Discard errors, the connection may be gone already
+                    let _ = send_close(&mut msg_sink, 4408, "Connection initialisation timeout");
+                }
+                Ok(())
+            })
+    }
+
+    /// Sends keep-alive messages to the client until the connection is closed.
+    fn send_keep_alives(
+        mut msg_sink: MessageSink,
+        protocol: WsProtocol,
+        interval: Duration,
+    ) -> impl Future<Item = (), Error = ()> {
+        // The legacy protocol expects a keep-alive right after the
+        // connection was acknowledged
+        let start = match protocol {
+            WsProtocol::GraphQlWs => Instant::now(),
+            WsProtocol::GraphQlTransportWs => Instant::now() + interval,
+        };
+
+        // Sending fails once the connection is closed, which ends the stream
+        Interval::new(start, interval)
+            .map_err(|_| ())
+            .for_each(move |_| {
+                send_message(&mut msg_sink, OutgoingMessage::keep_alive(protocol)).map_err(|_| ())
+            })
+    }
+
+    /// Runs a query or mutation and sends its single result to the client.
+    fn run_query(
+        graphql_runner: Arc<Q>,
+        query: Query,
+        mut msg_sink: MessageSink,
+        protocol: WsProtocol,
+        id: String,
+    ) -> Box<Future<Item = (), Error = ()> + Send> {
+        Box::new(graphql_runner.run_query(query).then(move |result| {
+            let result = result.unwrap_or_else(|e| {
+                let mut result = QueryResult::new(None);
+                result.add_error(e);
+                result
+            });
+            send_result(
+                &mut msg_sink,
+                OutgoingMessage::from_query_result(protocol, id, result),
+            ).map_err(|_| ())
+        }))
+    }
+
+    /// Runs a subscription and sends its results to the client until the
+    /// result stream ends. Fails if the subscription could not be started.
+    fn run_subscription(
+        graphql_runner: Arc<Q>,
+        subscription: Subscription,
+        mut msg_sink: MessageSink,
+        protocol: WsProtocol,
+        id: String,
+        subscriptions: Gauge,
+    ) -> Box<Future<Item = (), Error = ()> + Send> {
+        let mut error_sink = msg_sink.clone();
+        let err_id = id.clone();
+        let active_subscription = ActiveSubscription::new(subscriptions);
+
+        Box::new(
+            graphql_runner
+                .run_subscription(subscription)
+                .map_err(move |e| {
+                    // Send errors back to the client as GQL_DATA (legacy)
+                    // or as an `error` message (`graphql-transport-ws`)
+                    let msg = OutgoingMessage::from_subscription_error(protocol, err_id, e);
+                    let _ = send_message(&mut error_sink, msg);
+                }).and_then(move |result_stream| {
+                    // Send results back to the client as GQL_DATA or `next`
+                    result_stream.for_each(move |result| {
+                        send_result(
+                            &mut msg_sink,
+                            OutgoingMessage::from_query_result(protocol, id.clone(), result),
+                        ).map_err(|_| ())
+                    })
+                }).then(move |result| {
+                    drop(active_subscription);
+                    result
+                }),
+        )
+    }
+
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     fn handle_incoming_messages(
         ws_stream: SplitStream<WebSocketStream<S>>,
-        mut msg_sink: mpsc::UnboundedSender<WsMessage>,
+        mut msg_sink: MessageSink,
         logger: Logger,
-        connection_id: String,
         subgraphs: SubgraphRegistry<GuardedSchema>,
         subgraph: String,
+        mut operations: Operations,
+        config: SubscriptionServerConfig,
         graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        subscriptions: Gauge,
     ) -> impl Future<Item = (), Error = WsError> {
-        let mut operations = Operations::new(msg_sink.clone());
+        let protocol = operations.protocol;
+
+        // Close the connection if a `graphql-transport-ws` client doesn't
+        // initialize it in time; the legacy protocol has no such deadline
+        let state = Arc::new(Mutex::new(ConnectionState::AwaitingInit));
+        if protocol == WsProtocol::GraphQlTransportWs {
+            tokio::spawn(Self::enforce_connection_init_timeout(
+                msg_sink.clone(),
+                state.clone(),
+                config.connection_init_timeout,
+                logger.clone(),
+            ));
+        }
 
         // Process incoming messages as long as the WebSocket is open
         ws_stream.for_each(move |ws_msg| {
             use self::IncomingMessage::*;
-            use self::OutgoingMessage::*;
 
             debug!(logger, "Received message";
-                   "connection" => &connection_id,
                    "msg" => format!("{}", ws_msg).as_str());
 
-            let msg = IncomingMessage::from_ws_message(ws_msg.clone())?;
+            // Ignore everything the client sends after we closed the connection
+            if *state.lock().unwrap() == ConnectionState::Closing {
+                return Ok(());
+            }
+
+            let msg = match IncomingMessage::from_ws_message(ws_msg.clone(), protocol) {
+                Ok(msg) => msg,
+                Err(e) => match protocol {
+                    WsProtocol::GraphQlWs => return Err(e),
+
+                    // `graphql-transport-ws` closes the connection with a
+                    // dedicated close code on invalid messages
+                    WsProtocol::GraphQlTransportWs => {
+                        debug!(logger, "Invalid message received";
+                               "error" => format!("{}", e));
+                        *state.lock().unwrap() = ConnectionState::Closing;
+                        return send_close(&mut msg_sink, 4400, "Invalid message received");
+                    }
+                },
+            };
 
             debug!(logger, "GraphQL/WebSocket message";
-                   "connection" => &connection_id,
                    "msg" => format!("{:?}", msg).as_str());
 
             match msg {
-                // Always accept connection init requests
-                ConnectionInit { payload: _ } => send_message(&msg_sink, ConnectionAck),
+                ConnectionInit { payload: _ } => {
+                    let previous_state = {
+                        let mut state = state.lock().unwrap();
+                        let previous_state = *state;
+                        if previous_state == ConnectionState::AwaitingInit {
+                            *state = ConnectionState::Initialized;
+                        }
+                        previous_state
+                    };
+
+                    match (previous_state, protocol) {
+                        // `graphql-transport-ws` only allows a single init request
+                        (ConnectionState::Initialized, WsProtocol::GraphQlTransportWs) => {
+                            *state.lock().unwrap() = ConnectionState::Closing;
+                            send_close(&mut msg_sink, 4429, "Too many initialisation requests")
+                        }
+
+                        // Always accept connection init requests otherwise
+                        _ => {
+                            send_message(&mut msg_sink, OutgoingMessage::ConnectionAck)?;
+
+                            // Start sending keep-alives after the first acknowledgement
+                            if previous_state == ConnectionState::AwaitingInit {
+                                tokio::spawn(Self::send_keep_alives(
+                                    msg_sink.clone(),
+                                    protocol,
+                                    config.keep_alive_interval,
+                                ));
+                            }
+                            Ok(())
+                        }
+                    }
+                }
 
                 // When receiving a connection termination request
                 ConnectionTerminate => {
-                    // Close the message sink
-                    msg_sink.close().unwrap();
-
                     // Return an error here to terminate the connection
                     Err(WsError::ConnectionClosed(None))
                 }
 
-                // When receiving a stop request
-                Stop { id } => operations.stop(id),
+                // Respond to pings from the client
+                Ping { payload } => send_message(&mut msg_sink, OutgoingMessage::Pong { payload }),
+
+                // Pongs only tell us that the client is still there
+                Pong { payload: _ } => Ok(()),
+
+                // When receiving a stop or complete request
+                Stop { id } | Complete { id } => operations.stop(id),
+
+                // When receiving a start or subscribe request
+                Start { id, payload } | Subscribe { id, payload } => {
+                    if protocol == WsProtocol::GraphQlTransportWs {
+                        // Operations require an acknowledged connection
+                        if *state.lock().unwrap() != ConnectionState::Initialized {
+                            *state.lock().unwrap() = ConnectionState::Closing;
+                            return send_close(&mut msg_sink, 4401, "Unauthorized");
+                        }
+
+                        // Operation IDs must be unique
+                        if operations.contains(&id) {
+                            *state.lock().unwrap() = ConnectionState::Closing;
+                            return send_close(
+                                &mut msg_sink,
+                                4409,
+                                &format!("Subscriber for {} already exists", id),
+                            );
+                        }
+                    }
 
-                // When receiving a start request
-                Start { id, payload } => {
                     // Respond with a GQL_ERROR if we already have an operation with this ID
                     if operations.contains(&id) {
                         return send_error_string(
-                            &msg_sink,
+                            &mut msg_sink,
+                            protocol,
                             id.clone(),
                             format!("Operation with ID already started: {}", id),
                         );
                     }
 
+                    // Respond with a GQL_ERROR if the connection runs too many operations
+                    if operations.is_full() {
+                        return send_error_string(
+                            &mut msg_sink,
+                            protocol,
+                            id.clone(),
+                            format!(
+                                "Too many operations, at most {} can run on a connection",
+                                operations.max_operations
+                            ),
+                        );
+                    }
+
                     // Respond with a GQL_ERROR if the subgraph name or ID is unknown
                     let schema = if let Some(schema) =
                         subgraphs.resolve_map(&subgraph, |s| s.schema.clone())
@@ -240,78 +710,120 @@ where
                         schema
                     } else {
                         return send_error_string(
-                            &msg_sink,
+                            &mut msg_sink,
+                            protocol,
                             id.clone(),
                             format!("Unknown subgraph name or ID: {}", subgraph),
                         );
                     };
 
-                    // Parse the GraphQL query document; respond with a GQL_ERROR if
-                    // the query is invalid
-                    let query = match parse_query(&payload.query) {
+                    // Look up or parse the GraphQL query document; respond with a
+                    // GQL_ERROR if the query is invalid or unknown
+                    let query_text = payload.query.as_ref().map(|query| query.as_str());
+                    let query = match persisted_queries
+                        .document(query_text, payload.extensions.as_ref())
+                    {
                         Ok(query) => query,
+                        Err(PersistedQueryError::ParseError(e)) => {
+                            return send_error_string(
+                                &mut msg_sink,
+                                protocol,
+                                id.clone(),
+                                format!("Invalid query: {}: {}", query_text.unwrap_or(""), e),
+                            );
+                        }
                         Err(e) => {
                             return send_error_string(
-                                &msg_sink,
+                                &mut msg_sink,
+                                protocol,
                                 id.clone(),
-                                format!("Invalid query: {}: {}", payload.query, e),
+                                e.to_string(),
                             );
                         }
                     };
 
-                    // TODO Parse query variables and operation name
+                    // Parse the query variables; respond with a GQL_ERROR if they
+                    // are not an object of variable values
+                    let variables = match payload.variables {
+                        None | Some(serde_json::Value::Null) => None,
+                        Some(variables @ serde_json::Value::Object(_)) => {
+                            match serde_json::from_value(variables) {
+                                Ok(variables) => Some(variables),
+                                Err(e) => {
+                                    return send_error_string(
+                                        &mut msg_sink,
+                                        protocol,
+                                        id.clone(),
+                                        format!("Invalid query variables provided: {}", e),
+                                    );
+                                }
+                            }
+                        }
+                        _ => {
+                            return send_error_string(
+                                &mut msg_sink,
+                                protocol,
+                                id.clone(),
+                                String::from("Invalid query variables provided"),
+                            );
+                        }
+                    };
 
-                    // Construct a subscription
-                    let subscription = Subscription {
-                        query: Query {
-                            schema,
-                            document: query,
-                            variables: None,
-                        },
+                    let query = Query {
+                        schema,
+                        document: query,
+                        variables,
+                        operation_name: payload.operation_name.clone(),
                     };
 
-                    debug!(logger, "Start operation";
-                           "connection" => &connection_id,
-                           "id" => &id);
-
-                    // Execute the GraphQL subscription
-                    let graphql_runner = graphql_runner.clone();
-                    let error_sink = msg_sink.clone();
-                    let result_sink = msg_sink.clone();
-                    let result_id = id.clone();
-                    let err_id = id.clone();
-                    let run_subscription = graphql_runner
-                        .run_subscription(subscription)
-                        .map_err(move |e| {
-                            // Send errors back to the client as GQL_DATA
-                            match e {
-                                SubscriptionError::GraphQLError(e) => {
-                                    let result = QueryResult::from(e);
-                                    let msg =
-                                        OutgoingMessage::from_query_result(err_id.clone(), result);
-                                    error_sink.unbounded_send(msg.into()).unwrap();
-                                }
-                            };
-                        }).and_then(move |result_stream| {
-                            // Send results back to the client as GQL_DATA
-                            result_stream
-                                .map(move |result| {
-                                    OutgoingMessage::from_query_result(result_id.clone(), result)
-                                }).map(WsMessage::from)
-                                .forward(result_sink.sink_map_err(|_| ()))
-                                .map(|_| ())
-                        });
+                    debug!(logger, "Start operation"; "id" => &id);
+
+                    // Execute the GraphQL operation
+                    let run_operation = if is_subscription(&query) {
+                        Self::run_subscription(
+                            graphql_runner.clone(),
+                            Subscription { query },
+                            msg_sink.clone(),
+                            protocol,
+                            id.clone(),
+                            subscriptions.clone(),
+                        )
+                    } else {
+                        Self::run_query(
+                            graphql_runner.clone(),
+                            query,
+                            msg_sink.clone(),
+                            protocol,
+                            id.clone(),
+                        )
+                    };
+
+                    let mut complete_sink = msg_sink.clone();
+                    let complete_id = id.clone();
+                    let guards = operations.guards();
+                    let run_operation = run_operation.then(move |result| {
+                        // The operation is done; forget about it so that its ID
+                        // can be reused
+                        guards.lock().unwrap().remove(&complete_id);
+
+                        // An `error` message already terminates the operation
+                        // in `graphql-transport-ws`
+                        if result.is_ok() || protocol == WsProtocol::GraphQlWs {
+                            let _ = send_message(
+                                &mut complete_sink,
+                                OutgoingMessage::Complete { id: complete_id },
+                            );
+                        }
+                        Ok::<(), ()>(())
+                    });
 
                     let logger = logger.clone();
                     let cancel_id = id.clone();
-                    let connection_id = connection_id.clone();
-                    let (run_subscription, guard) = run_subscription.cancelable(move || {
-                        debug!(logger, "Stopped operation";
-                                       "connection" => &connection_id,
-                                       "id" => &cancel_id)
+                    let (run_operation, guard) = run_operation.cancelable(move || {
+                        debug!(logger, "Stopped operation"; "id" => &cancel_id)
                     });
                     operations.insert(id, guard);
-                    tokio::spawn(run_subscription);
+                    tokio::spawn(run_operation);
                     Ok(())
                 }
             }
@@ -329,23 +841,45 @@ where
     type Error = ();
 
     fn into_future(self) -> Self::Future {
-        debug!(self.logger, "GraphQL over WebSocket connection opened"; "id" => &self.id);
+        debug!(self.logger, "GraphQL over WebSocket connection opened";
+               "id" => &self.id,
+               "protocol" => self.protocol.name());
 
         // Obtain sink/stream pair to send and receive WebSocket messages
         let (ws_sink, ws_stream) = self.stream.split();
 
-        // Allocate a channel for writing
-        let (msg_sink, msg_stream) = mpsc::unbounded();
+        // Allocate a bounded channel for writing; the connection is dropped
+        // when the client can't keep up with reading our messages
+        let (sender, msg_stream) = mpsc::channel(self.config.send_buffer_size);
+        let disconnect_logger = self.logger.clone();
+        let disconnect_id = self.id.clone();
+        let (disconnect, disconnect_guard) = future::empty::<(), ()>().cancelable(move || {
+            debug!(disconnect_logger, "Disconnecting slow client";
+                   "connection" => &disconnect_id)
+        });
+        let msg_sink = MessageSink {
+            sender,
+            slow_client_policy: self.config.slow_client_policy,
+            disconnect: disconnect_guard.shared(),
+        };
 
         // Handle incoming messages asynchronously
+        let operations = Operations::new(
+            self.protocol,
+            self.config.max_operations_per_connection,
+            msg_sink.clone(),
+        );
         let ws_reader = Self::handle_incoming_messages(
             ws_stream,
             msg_sink,
-            self.logger.clone(),
-            self.id.clone(),
+            self.logger.new(o!("connection" => self.id.clone())),
             self.subgraphs.clone(),
             self.subgraph.clone(),
+            operations,
+            self.config,
             self.graphql_runner.clone(),
+            self.persisted_queries.clone(),
+            self.subscriptions.clone(),
         );
 
         // Send outgoing messages asynchronously
@@ -366,12 +900,65 @@ where
         let ws_reader = ws_reader.map(|_| ()).map_err(|_| ());
 
         // Return a future that is fulfilled when either we or the client close
-        // our/their end of the WebSocket stream
+        // our/their end of the WebSocket stream, or when we drop the client
         let logger = self.logger.clone();
         let id = self.id.clone();
-        Box::new(ws_reader.select(ws_writer).then(move |_| {
+        Box::new(ws_reader.select(ws_writer).select2(disconnect).then(move |_| {
             debug!(logger, "GraphQL over WebSocket connection closed"; "connection" => id);
             Ok(())
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MessageSink;
+    use futures::sync::mpsc;
+    use graph::prelude::*;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    use server::SlowClientPolicy;
+
+    /// Creates a sink with room for a single message, along with the
+    /// receiving end of its buffer.
+    fn message_sink(policy: SlowClientPolicy) -> (MessageSink, mpsc::Receiver<WsMessage>) {
+        let (sender, receiver) = mpsc::channel(0);
+        let (_, guard) = future::empty::<(), ()>().cancelable(|| ());
+        let sink = MessageSink {
+            sender,
+            slow_client_policy: policy,
+            disconnect: guard.shared(),
+        };
+        (sink, receiver)
+    }
+
+    #[test]
+    fn disconnects_slow_clients() {
+        let (mut sink, _receiver) = message_sink(SlowClientPolicy::Disconnect);
+
+        assert!(sink.send_result(WsMessage::text("1")).is_ok());
+        assert!(!sink.disconnect.is_canceled());
+
+        // The buffer is full, so the client is disconnected
+        assert!(sink.send_result(WsMessage::text("2")).is_err());
+        assert!(sink.disconnect.is_canceled());
+    }
+
+    #[test]
+    fn drops_results_for_slow_clients() {
+        let (mut sink, receiver) = message_sink(SlowClientPolicy::DropResults);
+
+        assert!(sink.send_result(WsMessage::text("1")).is_ok());
+
+        // Results that don't fit into the buffer are dropped
+        assert!(sink.send_result(WsMessage::text("2")).is_ok());
+        assert!(!sink.disconnect.is_canceled());
+
+        // Other messages can't be dropped, so the client is disconnected
+        assert!(sink.send(WsMessage::text("3")).is_err());
+        assert!(sink.disconnect.is_canceled());
+
+        drop(sink);
+        assert_eq!(receiver.collect().wait(), Ok(vec![WsMessage::text("1")]));
+    }
+}
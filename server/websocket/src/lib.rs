@@ -11,4 +11,4 @@ extern crate uuid;
 mod connection;
 mod server;
 
-pub use self::server::SubscriptionServer;
+pub use self::server::{SlowClientPolicy, SubscriptionServer, SubscriptionServerConfig};
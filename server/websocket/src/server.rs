@@ -2,15 +2,86 @@ use futures::prelude::*;
 use futures::sync::mpsc::{channel, Receiver, Sender};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
+use std::time::Duration;
 use tokio_tungstenite::accept_hdr_async;
 use tokio_tungstenite::tungstenite::{handshake::server::Request, Error as WsError};
 
+use graph::components::metrics::GaugeVec;
 use graph::prelude::{SubscriptionServer as SubscriptionServerTrait, *};
 use graph::tokio::net::TcpListener;
-use graph_graphql::prelude::api_schema;
+use graph_graphql::prelude::{api_schema, PersistedQueries};
 
-use connection::GraphQlConnection;
+use connection::{GraphQlConnection, WsProtocol};
+
+/// What to do with a client that doesn't read its messages fast enough to
+/// keep up with the results of its operations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlowClientPolicy {
+    /// Drop operation results that don't fit into the send buffer.
+    DropResults,
+    /// Disconnect the client once its send buffer is full.
+    Disconnect,
+}
+
+/// Configuration for the GraphQL over WebSocket server.
+#[derive(Clone, Copy, Debug)]
+pub struct SubscriptionServerConfig {
+    /// Maximum number of open connections; further connections are rejected.
+    pub max_connections: usize,
+    /// Maximum number of operations running on a single connection.
+    pub max_operations_per_connection: usize,
+    /// Number of outgoing messages buffered for each connection.
+    pub send_buffer_size: usize,
+    /// What to do when the send buffer of a connection is full.
+    pub slow_client_policy: SlowClientPolicy,
+    /// How long `graphql-transport-ws` clients have to send `connection_init`
+    /// after opening the WebSocket before the connection is closed. The
+    /// legacy protocol has no such deadline.
+    pub connection_init_timeout: Duration,
+    /// Time between keep-alive messages sent to the client.
+    pub keep_alive_interval: Duration,
+}
+
+impl Default for SubscriptionServerConfig {
+    fn default() -> Self {
+        SubscriptionServerConfig {
+            max_connections: 1000,
+            max_operations_per_connection: 100,
+            send_buffer_size: 100,
+            slow_client_policy: SlowClientPolicy::Disconnect,
+            connection_init_timeout: Duration::from_secs(10),
+            keep_alive_interval: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Counts a connection towards the connection limit for as long as it
+/// is alive.
+struct ConnectionSlot {
+    connections: Arc<AtomicUsize>,
+}
+
+impl ConnectionSlot {
+    /// Takes a slot unless `max_connections` are open already.
+    fn acquire(connections: &Arc<AtomicUsize>, max_connections: usize) -> Option<Self> {
+        if connections.fetch_add(1, Ordering::SeqCst) < max_connections {
+            Some(ConnectionSlot {
+                connections: connections.clone(),
+            })
+        } else {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
 
 /// On drop, cancels all connections to this subgraph.
 pub(crate) struct GuardedSchema {
@@ -30,25 +101,44 @@ impl GuardedSchema {
 /// A GraphQL subscription server based on Hyper / Websockets.
 pub struct SubscriptionServer<Q> {
     logger: Logger,
+    config: SubscriptionServerConfig,
     graphql_runner: Arc<Q>,
+    persisted_queries: Arc<PersistedQueries>,
     schema_event_sink: Sender<SchemaEvent>,
     subgraphs: SubgraphRegistry<GuardedSchema>,
+    connections: Arc<AtomicUsize>,
+    subscriptions: GaugeVec,
 }
 
 impl<Q> SubscriptionServer<Q>
 where
     Q: GraphQlRunner + 'static,
 {
-    pub fn new(logger: &Logger, graphql_runner: Arc<Q>) -> Self {
+    pub fn new(
+        logger: &Logger,
+        config: SubscriptionServerConfig,
+        graphql_runner: Arc<Q>,
+        persisted_queries: Arc<PersistedQueries>,
+        metrics: &MetricsRegistry,
+    ) -> Self {
         let logger = logger.new(o!("component" => "SubscriptionServer"));
 
         let (schema_event_sink, schema_event_stream) = channel(100);
 
         let mut server = SubscriptionServer {
             logger,
+            config,
             graphql_runner,
+            persisted_queries,
             schema_event_sink,
             subgraphs: SubgraphRegistry::new(),
+            connections: Arc::new(AtomicUsize::new(0)),
+            subscriptions: metrics
+                .new_gauge_vec(
+                    "websocket_subscriptions",
+                    "Number of active GraphQL subscriptions over WebSockets",
+                    &["subgraph"],
+                ).expect("Failed to register WebSocket subscription gauge"),
         };
 
         // Spawn task to handle incoming schema events
@@ -115,9 +205,13 @@ where
         let logger = self.logger.clone();
         let error_logger = self.logger.clone();
         let subgraphs = self.subgraphs.clone();
+        let config = self.config;
+        let connections = self.connections.clone();
+        let subscriptions = self.subscriptions.clone();
 
         let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
         let graphql_runner = self.graphql_runner.clone();
+        let persisted_queries = self.persisted_queries.clone();
 
         let socket = TcpListener::bind(&addr).expect("Failed to bind WebSocket port");
 
@@ -128,38 +222,62 @@ where
             }).for_each(move |stream| {
                 let logger = logger.clone();
                 let graphql_runner = graphql_runner.clone();
+                let persisted_queries = persisted_queries.clone();
+                let subscriptions = subscriptions.clone();
 
                 // Clone subgraph registry to pass it on to connections
                 let subgraphs = subgraphs.clone();
 
+                // Reject the connection if there are too many open connections already
+                let slot = ConnectionSlot::acquire(&connections, config.max_connections);
+                let accept_connection = slot.is_some();
+
                 // Subgraph that the request is resolved to (if any)
                 let subgraph_id_or_name = Arc::new(Mutex::new(None));
                 let accept_subgraph_id_or_name = subgraph_id_or_name.clone();
 
+                // GraphQL over WebSocket protocol negotiated with the client
+                let protocol = Arc::new(Mutex::new(WsProtocol::GraphQlWs));
+                let accept_protocol = protocol.clone();
+
                 accept_hdr_async(stream, move |request: &Request| {
+                    if !accept_connection {
+                        return Err(WsError::Http(503));
+                    }
+
                     // Try to obtain the subgraph ID or name from the URL path.
                     // Return a 404 if the URL path contains no name/ID segment.
                     let path = PathBuf::from(&request.path);
                     *accept_subgraph_id_or_name.lock().unwrap() =
                         Some(Self::subgraph_from_url_path(path).ok_or(WsError::Http(404))?);
 
+                    // Pick the protocol from the ones requested by the client
+                    let negotiated_protocol =
+                        WsProtocol::negotiate(request.headers.find_first("Sec-WebSocket-Protocol"));
+                    *accept_protocol.lock().unwrap() = negotiated_protocol;
+
                     Ok(Some(vec![(
                         String::from("Sec-WebSocket-Protocol"),
-                        String::from("graphql-ws"),
+                        String::from(negotiated_protocol.name()),
                     )]))
                 }).then(move |result| {
                     match result {
                         Ok(ws_stream) => {
                             // Obtain the subgraph ID or name that we resolved the request to
                             let subgraph = subgraph_id_or_name.lock().unwrap().clone().unwrap();
+                            let protocol = *protocol.lock().unwrap();
 
                             // Spawn a GraphQL over WebSocket connection
                             let service = GraphQlConnection::new(
                                 &logger,
                                 subgraphs.clone(),
                                 subgraph.clone(),
+                                protocol,
+                                config,
                                 ws_stream,
                                 graphql_runner.clone(),
+                                persisted_queries.clone(),
+                                subscriptions.with_label_values(&[subgraph.as_str()]),
                             );
 
                             let cancel_subgraph = subgraph.clone();
@@ -172,7 +290,12 @@ where
                             subgraphs.mutate(&subgraph, |subgraph| {
                                 subgraph.connection_guards.push(guard)
                             });
-                            tokio::spawn(connection);
+
+                            // Free up the connection slot once the connection is closed
+                            tokio::spawn(connection.then(move |result| {
+                                drop(slot);
+                                result
+                            }));
                         }
                         Err(e) => {
                             // We gracefully skip over failed connection attempts rather
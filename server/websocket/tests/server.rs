@@ -0,0 +1,431 @@
+extern crate futures;
+extern crate graph;
+extern crate graph_graphql;
+extern crate graph_server_websocket;
+extern crate graphql_parser;
+extern crate tokio_tungstenite;
+extern crate url;
+
+use graphql_parser::query as q;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::iter::FromIterator;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::thread;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+use tokio_tungstenite::{client_async, WebSocketStream};
+use url::Url;
+
+use graph::prelude::{SubscriptionServer as SubscriptionServerTrait, *};
+use graph::serde_json;
+use graph::tokio::net::TcpStream;
+use graph::tokio::runtime::Runtime;
+use graph_graphql::prelude::PersistedQueries;
+use graph_server_websocket::{SubscriptionServer, SubscriptionServerConfig};
+
+/// A query runner whose queries return the `name` variable (or a default
+/// name) and whose subscriptions never produce any results.
+pub struct TestGraphQlRunner;
+
+impl GraphQlRunner for TestGraphQlRunner {
+    fn run_query(&self, query: Query) -> QueryResultFuture {
+        let name = query
+            .variables
+            .as_ref()
+            .and_then(|variables| variables.get("name"))
+            .map(|name| (**name).clone())
+            .unwrap_or(q::Value::String(String::from("Jordi")));
+
+        Box::new(future::ok(QueryResult::new(Some(q::Value::Object(
+            BTreeMap::from_iter(vec![(String::from("name"), name)].into_iter()),
+        )))))
+    }
+
+    fn run_subscription(&self, _subscription: Subscription) -> SubscriptionResultFuture {
+        Box::new(future::ok(
+            Box::new(stream::poll_fn(|| Ok(Async::NotReady))) as QueryResultStream
+        ))
+    }
+
+    fn subgraph_latest_block(
+        &self,
+        _subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error> {
+        Ok(None)
+    }
+}
+
+type Client = WebSocketStream<TcpStream>;
+
+/// Starts a subscription server for a `test-schema` subgraph on `port`. The
+/// server runs for as long as the returned runtime is alive.
+fn start_server(port: u16, config: SubscriptionServerConfig) -> Runtime {
+    let mut runtime = Runtime::new().unwrap();
+
+    runtime
+        .block_on(future::lazy(move || {
+            let logger = Logger::root(slog::Discard, o!());
+            let mut server = SubscriptionServer::new(
+                &logger,
+                config,
+                Arc::new(TestGraphQlRunner),
+                Arc::new(PersistedQueries::new(10)),
+                &MetricsRegistry::new(),
+            );
+
+            let schema = Schema {
+                name: String::from("test-schema"),
+                id: String::from("test-schema"),
+                document: graphql_parser::parse_schema("type User { id: ID!, name: String }")
+                    .unwrap(),
+            };
+            server
+                .event_sink()
+                .send(SchemaEvent::SchemaAdded(schema))
+                .wait()
+                .expect("Failed to send schema to server");
+
+            tokio::spawn(server.serve(port).expect("Failed to start WebSocket server"));
+            Ok::<_, ()>(())
+        })).unwrap();
+
+    // Give the server time to bind its port and register the schema
+    thread::sleep(Duration::from_millis(100));
+
+    runtime
+}
+
+/// Opens a WebSocket connection to the test subgraph, asking for the given
+/// subprotocols. Returns the connection and the protocol picked by the server.
+fn connect(port: u16, protocols: Option<&str>) -> (Client, Option<String>) {
+    try_connect(port, protocols).unwrap_or_else(|e| panic!("Handshake failed: {}", e))
+}
+
+fn try_connect(port: u16, protocols: Option<&str>) -> Result<(Client, Option<String>), WsError> {
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port);
+    let request = Request {
+        url: Url::parse(&format!("ws://localhost:{}/test-schema", port)).unwrap(),
+        extra_headers: protocols.map(|protocols| {
+            vec![(
+                Cow::from("Sec-WebSocket-Protocol"),
+                Cow::from(protocols.to_owned()),
+            )]
+        }),
+    };
+
+    let stream = TcpStream::connect(&addr)
+        .wait()
+        .unwrap_or_else(|e| panic!("Failed to connect: {}", e));
+    let (client, response) = client_async(request, stream).wait()?;
+
+    let protocol = response
+        .headers
+        .find_first("Sec-WebSocket-Protocol")
+        .map(|value| String::from_utf8(value.to_vec()).unwrap());
+
+    Ok((client, protocol))
+}
+
+fn send(client: Client, msg: &str) -> Client {
+    client
+        .send(WsMessage::text(msg))
+        .wait()
+        .expect("Failed to send message")
+}
+
+/// Receives the next message that isn't a keep-alive.
+fn receive(mut client: Client) -> (Option<WsMessage>, Client) {
+    loop {
+        let (msg, next_client) = client
+            .into_future()
+            .wait()
+            .map_err(|(e, _)| e)
+            .expect("Failed to receive message");
+        client = next_client;
+
+        match msg {
+            Some(WsMessage::Text(ref text)) if text == "{\"type\":\"ka\"}" => continue,
+            msg => return (msg, client),
+        }
+    }
+}
+
+/// Receives the next message and parses it as JSON.
+fn receive_json(client: Client) -> (serde_json::Value, Client) {
+    match receive(client) {
+        (Some(WsMessage::Text(text)), client) => (serde_json::from_str(&text).unwrap(), client),
+        (msg, _) => panic!("Expected a text message, got {:?}", msg),
+    }
+}
+
+/// Asserts that the server closes the connection with the given close code.
+fn assert_closed_with(client: Client, code: u16) {
+    match receive(client) {
+        (Some(WsMessage::Close(Some(frame))), _) => assert_eq!(u16::from(frame.code), code),
+        (msg, _) => panic!("Expected close code {}, got {:?}", code, msg),
+    }
+}
+
+fn json(s: &str) -> serde_json::Value {
+    serde_json::from_str(s).unwrap()
+}
+
+#[test]
+fn negotiates_the_subprotocol() {
+    let _runtime = start_server(8101, SubscriptionServerConfig::default());
+
+    let (_, protocol) = connect(8101, Some("graphql-ws, graphql-transport-ws"));
+    assert_eq!(protocol, Some(String::from("graphql-transport-ws")));
+
+    let (_, protocol) = connect(8101, Some("graphql-ws"));
+    assert_eq!(protocol, Some(String::from("graphql-ws")));
+
+    // Clients that don't ask for a protocol get the legacy one
+    let (_, protocol) = connect(8101, None);
+    assert_eq!(protocol, Some(String::from("graphql-ws")));
+}
+
+#[test]
+fn closes_graphql_transport_ws_connections_on_invalid_messages() {
+    let _runtime = start_server(8102, SubscriptionServerConfig::default());
+
+    let (client, _) = connect(8102, Some("graphql-transport-ws"));
+    let client = send(client, "not json");
+    assert_closed_with(client, 4400);
+
+    // Messages of the legacy protocol are invalid in `graphql-transport-ws`
+    let (client, _) = connect(8102, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (ack, client) = receive_json(client);
+    assert_eq!(ack, json(r#"{"type": "connection_ack"}"#));
+    let client = send(
+        client,
+        r#"{"type": "start", "id": "1", "payload": {"query": "{ users { id } }"}}"#,
+    );
+    assert_closed_with(client, 4400);
+}
+
+#[test]
+fn rejects_operations_before_connection_init() {
+    let _runtime = start_server(8103, SubscriptionServerConfig::default());
+
+    let (client, _) = connect(8103, Some("graphql-transport-ws"));
+    let client = send(
+        client,
+        r#"{"type": "subscribe", "id": "1", "payload": {"query": "{ users { id } }"}}"#,
+    );
+    assert_closed_with(client, 4401);
+}
+
+#[test]
+fn closes_graphql_transport_ws_connections_without_connection_init() {
+    let mut config = SubscriptionServerConfig::default();
+    config.connection_init_timeout = Duration::from_millis(100);
+    let _runtime = start_server(8104, config);
+
+    let (client, _) = connect(8104, Some("graphql-transport-ws"));
+    assert_closed_with(client, 4408);
+
+    // The legacy protocol doesn't require a timely `connection_init`
+    let (client, _) = connect(8104, Some("graphql-ws"));
+    thread::sleep(Duration::from_millis(300));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (ack, _) = receive_json(client);
+    assert_eq!(ack, json(r#"{"type": "connection_ack"}"#));
+}
+
+#[test]
+fn rejects_duplicate_operation_ids() {
+    let _runtime = start_server(8105, SubscriptionServerConfig::default());
+
+    let (client, _) = connect(8105, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (_, client) = receive_json(client);
+
+    // The first subscription never completes, so its ID remains in use
+    let subscribe =
+        r#"{"type": "subscribe", "id": "1", "payload": {"query": "subscription { users { id } }"}}"#;
+    let client = send(client, subscribe);
+    let client = send(client, subscribe);
+    assert_closed_with(client, 4409);
+}
+
+#[test]
+fn rejects_repeated_connection_init() {
+    let _runtime = start_server(8106, SubscriptionServerConfig::default());
+
+    let (client, _) = connect(8106, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (ack, client) = receive_json(client);
+    assert_eq!(ack, json(r#"{"type": "connection_ack"}"#));
+
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    assert_closed_with(client, 4429);
+}
+
+#[test]
+fn sends_keep_alives_at_the_configured_interval() {
+    let mut config = SubscriptionServerConfig::default();
+    config.keep_alive_interval = Duration::from_millis(100);
+    let _runtime = start_server(8107, config);
+
+    // `graphql-transport-ws` uses pings to keep the connection alive
+    let (client, _) = connect(8107, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (_, client) = receive_json(client);
+    let (ping, _) = receive_json(client);
+    assert_eq!(ping, json(r#"{"type": "ping"}"#));
+}
+
+#[test]
+fn runs_queries_and_mutations() {
+    let _runtime = start_server(8108, SubscriptionServerConfig::default());
+
+    // Queries over `graphql-transport-ws` are answered with `next`
+    let (client, _) = connect(8108, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (_, client) = receive_json(client);
+    let client = send(
+        client,
+        r#"{"type": "subscribe", "id": "1", "payload": {"query": "{ name }"}}"#,
+    );
+    let (next, client) = receive_json(client);
+    assert_eq!(
+        next,
+        json(r#"{"type": "next", "id": "1", "payload": {"data": {"name": "Jordi"}}}"#)
+    );
+    let (complete, _) = receive_json(client);
+    assert_eq!(complete, json(r#"{"type": "complete", "id": "1"}"#));
+
+    // Mutations over the legacy protocol are answered with `data`
+    let (client, _) = connect(8108, Some("graphql-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (_, client) = receive_json(client);
+    let client = send(
+        client,
+        r#"{"type": "start", "id": "1", "payload": {"query": "mutation { name }"}}"#,
+    );
+    let (data, client) = receive_json(client);
+    assert_eq!(
+        data,
+        json(r#"{"type": "data", "id": "1", "payload": {"data": {"name": "Jordi"}}}"#)
+    );
+    let (complete, _) = receive_json(client);
+    assert_eq!(complete, json(r#"{"type": "complete", "id": "1"}"#));
+}
+
+#[test]
+fn passes_query_variables_to_the_runner() {
+    let _runtime = start_server(8109, SubscriptionServerConfig::default());
+
+    let (client, _) = connect(8109, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (_, client) = receive_json(client);
+    let client = send(
+        client,
+        r#"{
+            "type": "subscribe",
+            "id": "1",
+            "payload": {
+                "query": "query Name($name: String) { name(name: $name) }",
+                "variables": {"name": "Ana"}
+            }
+        }"#,
+    );
+    let (next, _) = receive_json(client);
+    assert_eq!(
+        next,
+        json(r#"{"type": "next", "id": "1", "payload": {"data": {"name": "Ana"}}}"#)
+    );
+}
+
+#[test]
+fn rejects_invalid_query_variables() {
+    let _runtime = start_server(8110, SubscriptionServerConfig::default());
+
+    let (client, _) = connect(8110, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (_, client) = receive_json(client);
+    let client = send(
+        client,
+        r#"{
+            "type": "subscribe",
+            "id": "1",
+            "payload": {"query": "{ name }", "variables": ["Ana"]}
+        }"#,
+    );
+    let (error, _) = receive_json(client);
+    assert_eq!(error["type"], json(r#""error""#));
+    assert_eq!(error["id"], json(r#""1""#));
+    assert_eq!(
+        error["payload"][0]["message"],
+        json(r#""Invalid query variables provided""#)
+    );
+}
+
+#[test]
+fn rejects_connections_beyond_the_limit() {
+    let mut config = SubscriptionServerConfig::default();
+    config.max_connections = 1;
+    let _runtime = start_server(8111, config);
+
+    let (client, _) = connect(8111, Some("graphql-transport-ws"));
+
+    // The only connection slot is taken
+    match try_connect(8111, Some("graphql-transport-ws")) {
+        Err(WsError::Http(code)) => assert_eq!(code, 503),
+        Err(e) => panic!("Expected a 503 response, got {}", e),
+        Ok(_) => panic!("Expected a 503 response, got a connection"),
+    }
+
+    // Closing the connection frees up its slot
+    drop(client);
+    thread::sleep(Duration::from_millis(100));
+    assert!(try_connect(8111, Some("graphql-transport-ws")).is_ok());
+}
+
+#[test]
+fn limits_the_operations_per_connection() {
+    let mut config = SubscriptionServerConfig::default();
+    config.max_operations_per_connection = 1;
+    let _runtime = start_server(8112, config);
+
+    let (client, _) = connect(8112, Some("graphql-transport-ws"));
+    let client = send(client, r#"{"type": "connection_init"}"#);
+    let (_, client) = receive_json(client);
+
+    // The first subscription never completes and takes up the only slot
+    let client = send(
+        client,
+        r#"{"type": "subscribe", "id": "1", "payload": {"query": "subscription { users { id } }"}}"#,
+    );
+    let client = send(
+        client,
+        r#"{"type": "subscribe", "id": "2", "payload": {"query": "subscription { users { id } }"}}"#,
+    );
+    let (error, client) = receive_json(client);
+    assert_eq!(
+        error,
+        json(
+            r#"{
+                "type": "error",
+                "id": "2",
+                "payload": [{"message": "Too many operations, at most 1 can run on a connection"}]
+            }"#
+        )
+    );
+
+    // Completing the first subscription frees up its slot
+    let client = send(client, r#"{"type": "complete", "id": "1"}"#);
+    let client = send(
+        client,
+        r#"{"type": "subscribe", "id": "3", "payload": {"query": "{ name }"}}"#,
+    );
+    let (next, _) = receive_json(client);
+    assert_eq!(
+        next,
+        json(r#"{"type": "next", "id": "3", "payload": {"data": {"name": "Jordi"}}}"#)
+    );
+}
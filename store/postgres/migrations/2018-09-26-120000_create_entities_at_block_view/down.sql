@@ -0,0 +1,39 @@
+/**************************************************************
+* DROP VIEWS
+**************************************************************/
+DROP VIEW entities_at_block;
+
+/**************************************************************
+* RESTORE REVERT BLOCK
+**************************************************************/
+CREATE OR REPLACE FUNCTION revert_block(block_hash_to_revert VARCHAR, subgraph_id VARCHAR)
+    RETURNS VOID AS
+$$
+DECLARE
+    event_row RECORD;
+    entity_row RECORD;
+BEGIN
+    FOR event_row IN
+        -- Get all events associated with the given block
+        SELECT
+            entity_history.event_id as event_id
+        FROM entity_history
+        JOIN event_meta_data ON
+            entity_history.event_id = event_meta_data.id
+        WHERE event_meta_data.source = block_hash_to_revert AND
+            entity_history.subgraph = subgraph_id
+        GROUP BY
+            entity_history.event_id
+        ORDER BY entity_history.event_id DESC
+    -- For each event perform the reverse operation
+    LOOP
+        PERFORM revert_transaction(event_row.event_id::integer);
+    END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+/**************************************************************
+* REMOVE event_meta_data COLUMNS
+**************************************************************/
+ALTER TABLE event_meta_data
+    DROP COLUMN reverted;
@@ -0,0 +1,84 @@
+/**************************************************************
+* ADD event_meta_data COLUMNS
+**************************************************************/
+-- Marks events that have been reverted (e.g. as part of a block reorg)
+ALTER TABLE event_meta_data
+    ADD COLUMN reverted BOOLEAN NOT NULL DEFAULT FALSE;
+
+/**************************************************************
+* REVERT BLOCK
+*
+* Revert the row store events related to a particular block
+* and mark the events as reverted
+* Parameters: block_hash, subgraph
+**************************************************************/
+CREATE OR REPLACE FUNCTION revert_block(block_hash_to_revert VARCHAR, subgraph_id VARCHAR)
+    RETURNS VOID AS
+$$
+DECLARE
+    event_row RECORD;
+BEGIN
+    FOR event_row IN
+        -- Get all events associated with the given block
+        SELECT
+            entity_history.event_id as event_id
+        FROM entity_history
+        JOIN event_meta_data ON
+            entity_history.event_id = event_meta_data.id
+        WHERE event_meta_data.source = block_hash_to_revert AND
+            entity_history.subgraph = subgraph_id
+        GROUP BY
+            entity_history.event_id
+        ORDER BY entity_history.event_id DESC
+    -- For each event perform the reverse operation
+    LOOP
+        PERFORM revert_transaction(event_row.event_id::integer);
+
+        UPDATE event_meta_data
+        SET reverted = TRUE
+        WHERE id = event_row.event_id;
+    END LOOP;
+END;
+$$ LANGUAGE plpgsql;
+
+/**************************************************************
+* CREATE VIEWS
+**************************************************************/
+-- Reconstructs entities as they were at the block number stored in the
+-- `vars.query_block_number` session setting, from the latest change to
+-- each entity made in that block or before. Changes from reverted events
+-- are ignored, as are deleted entities.
+CREATE OR REPLACE VIEW entities_at_block AS
+    SELECT
+        id,
+        subgraph,
+        entity,
+        data,
+        event_source
+    FROM (
+        SELECT DISTINCT ON (
+            entity_history.subgraph,
+            entity_history.entity,
+            entity_history.entity_id
+        )
+            entity_history.entity_id AS id,
+            entity_history.subgraph AS subgraph,
+            entity_history.entity AS entity,
+            entity_history.data_after AS data,
+            event_meta_data.source AS event_source
+        FROM entity_history
+        JOIN event_meta_data ON
+            event_meta_data.id = entity_history.event_id
+        JOIN ethereum_blocks ON
+            ethereum_blocks.hash = '0x' || event_meta_data.source
+        WHERE
+            entity_history.reversion = FALSE AND
+            event_meta_data.reverted = FALSE AND
+            ethereum_blocks.number <= current_setting('vars.query_block_number')::BIGINT
+        ORDER BY
+            entity_history.subgraph,
+            entity_history.entity,
+            entity_history.entity_id,
+            entity_history.id DESC
+    ) AS latest_changes
+    WHERE data IS NOT NULL;
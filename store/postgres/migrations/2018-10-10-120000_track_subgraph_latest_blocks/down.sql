@@ -0,0 +1,6 @@
+/**************************************************************
+* RESTORE TABLE
+**************************************************************/
+DELETE FROM subgraphs WHERE network_name IS NULL;
+ALTER TABLE subgraphs
+    ALTER COLUMN network_name SET NOT NULL;
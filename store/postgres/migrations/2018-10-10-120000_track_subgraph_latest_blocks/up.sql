@@ -0,0 +1,7 @@
+/**************************************************************
+* ALTER TABLE
+**************************************************************/
+-- The latest block of a subgraph is recorded whenever its entities change,
+-- which happens without knowledge of the network the subgraph indexes
+ALTER TABLE subgraphs
+    ALTER COLUMN network_name DROP NOT NULL;
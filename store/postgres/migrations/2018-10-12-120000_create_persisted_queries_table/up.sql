@@ -0,0 +1,8 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- Stores the texts of persisted GraphQL queries by their SHA-256 hash
+CREATE TABLE IF NOT EXISTS persisted_queries (
+    sha256_hash VARCHAR PRIMARY KEY,
+    query TEXT NOT NULL
+);
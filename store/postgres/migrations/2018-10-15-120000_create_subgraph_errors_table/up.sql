@@ -0,0 +1,9 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- Stores the error that made indexing a subgraph fail; subgraphs without
+-- an error are healthy
+CREATE TABLE IF NOT EXISTS subgraph_errors (
+    subgraph VARCHAR PRIMARY KEY,
+    error TEXT NOT NULL
+);
@@ -0,0 +1,17 @@
+/**************************************************************
+* CREATE TABLE
+**************************************************************/
+-- Stores the messages logged by subgraph mappings and the errors that
+-- occurred while indexing subgraphs. Levels count from 0 (debug) to
+-- 4 (critical), timestamps are milliseconds since the Unix epoch
+CREATE TABLE IF NOT EXISTS subgraph_logs (
+    id BIGSERIAL PRIMARY KEY,
+    subgraph VARCHAR NOT NULL,
+    timestamp BIGINT NOT NULL,
+    level SMALLINT NOT NULL,
+    message TEXT NOT NULL,
+    block_number BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS subgraph_logs_subgraph_id_idx
+    ON subgraph_logs (subgraph, id DESC);
@@ -0,0 +1,93 @@
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use graph::prelude::*;
+
+/// A connection checked out from a `ConnectionPool`.
+pub type PgPooledConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
+/// Statistics about how long callers had to wait for connections from a pool.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolWaitStats {
+    /// Number of connections handed out by the pool.
+    pub checkouts: u64,
+    /// Number of requests that timed out before a connection became available.
+    pub timeouts: u64,
+    /// Total time spent waiting for connections.
+    pub total_wait: Duration,
+    /// Longest time spent waiting for a single connection.
+    pub max_wait: Duration,
+}
+
+impl PoolWaitStats {
+    /// Average time spent waiting for a connection.
+    pub fn average_wait(&self) -> Duration {
+        if self.checkouts == 0 {
+            Duration::from_secs(0)
+        } else {
+            self.total_wait / self.checkouts as u32
+        }
+    }
+}
+
+/// A pool of Postgres connections that keeps track of how long it takes to
+/// obtain connections from it.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    name: &'static str,
+    pool: Pool<ConnectionManager<PgConnection>>,
+    wait_stats: Arc<Mutex<PoolWaitStats>>,
+}
+
+impl ConnectionPool {
+    /// Creates a pool of up to `size` connections to the database at `url`.
+    pub fn create(logger: &Logger, name: &'static str, url: &str, size: u32) -> Self {
+        let pool = Pool::builder()
+            .max_size(size)
+            .build(ConnectionManager::<PgConnection>::new(url))
+            .expect(&format!("failed to create {} connection pool", name));
+
+        info!(logger, "Created Postgres connection pool";
+              "pool" => name,
+              "size" => size);
+
+        ConnectionPool {
+            name,
+            pool,
+            wait_stats: Arc::new(Mutex::new(PoolWaitStats::default())),
+        }
+    }
+
+    /// The name of the pool, e.g. `"read"` or `"write"`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Obtains a connection from the pool, waiting for one to become available
+    /// if all of them are in use.
+    pub fn get(&self) -> Result<PgPooledConnection, PoolError> {
+        let start = Instant::now();
+        let result = self.pool.get();
+        let wait = start.elapsed();
+
+        let mut wait_stats = self.wait_stats.lock().unwrap();
+        match result {
+            Ok(_) => {
+                wait_stats.checkouts += 1;
+                wait_stats.total_wait += wait;
+                wait_stats.max_wait = cmp::max(wait_stats.max_wait, wait);
+            }
+            Err(_) => wait_stats.timeouts += 1,
+        }
+
+        result
+    }
+
+    /// Returns the wait-time statistics of the pool collected so far.
+    pub fn wait_stats(&self) -> PoolWaitStats {
+        self.wait_stats.lock().unwrap().clone()
+    }
+}
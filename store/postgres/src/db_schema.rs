@@ -8,6 +8,18 @@ table! {
     }
 }
 
+// A view of entities as they were at the block number set in the
+// `vars.query_block_number` session setting
+table! {
+    entities_at_block (id, subgraph, entity) {
+        id -> Varchar,
+        subgraph -> Varchar,
+        entity -> Varchar,
+        data -> Jsonb,
+        event_source -> Varchar,
+    }
+}
+
 table! {
     ethereum_networks (name) {
         name -> Varchar,
@@ -31,10 +43,35 @@ table! {
 table! {
     subgraphs (id) {
         id -> Varchar,
-        network_name -> Varchar,
+        network_name -> Nullable<Varchar>,
         latest_block_hash -> Varchar,
         latest_block_number -> BigInt,
     }
 }
+table! {
+    persisted_queries (sha256_hash) {
+        sha256_hash -> Varchar,
+        query -> Text,
+    }
+}
+
+table! {
+    subgraph_errors (subgraph) {
+        subgraph -> Varchar,
+        error -> Text,
+    }
+}
+
+table! {
+    subgraph_logs (id) {
+        id -> BigInt,
+        subgraph -> Varchar,
+        timestamp -> BigInt,
+        level -> SmallInt,
+        message -> Text,
+        block_number -> Nullable<BigInt>,
+    }
+}
+
 allow_tables_to_appear_in_same_query!(entities, subgraphs);
 joinable!(entities -> subgraphs (subgraph));
@@ -1,5 +1,6 @@
 use fallible_iterator::FallibleIterator;
 use postgres::{Connection, TlsMode};
+use std::mem;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Barrier};
 use std::thread;
@@ -9,8 +10,20 @@ use futures::sync::mpsc::{channel, Receiver};
 use graph::prelude::*;
 use graph::serde_json;
 
+/// Builds the payload of the notification that marks the end of the entity
+/// changes made in a transaction. All entity changes received since the
+/// previous such notification are emitted as one batch for `block`.
+pub fn entity_changes_complete_payload(block: &EthereumBlockPointer) -> String {
+    let mut payload = serde_json::Map::new();
+    payload.insert(
+        String::from("block"),
+        serde_json::to_value(block).expect("Failed to serialize block pointer"),
+    );
+    serde_json::Value::Object(payload).to_string()
+}
+
 pub struct EntityChangeListener {
-    output: Option<Receiver<EntityChange>>,
+    output: Option<Receiver<EntityChangeBatch>>,
     worker_handle: Option<thread::JoinHandle<()>>,
     terminate_worker: Arc<AtomicBool>,
     worker_barrier: Arc<Barrier>,
@@ -42,7 +55,7 @@ impl EntityChangeListener {
     fn listen(
         url: String,
     ) -> (
-        Receiver<EntityChange>,
+        Receiver<EntityChangeBatch>,
         thread::JoinHandle<()>,
         Arc<AtomicBool>,
         Arc<Barrier>,
@@ -54,7 +67,7 @@ impl EntityChangeListener {
         let barrier = Arc::new(Barrier::new(2));
         let worker_barrier = barrier.clone();
 
-        // Create a channel for entity changes
+        // Create a channel for batches of entity changes
         let (sender, receiver) = channel(100);
 
         let worker_handle = thread::spawn(move || {
@@ -69,6 +82,10 @@ impl EntityChangeListener {
             // Wait until the listener has been started
             barrier.wait();
 
+            // Entity changes of the transaction whose notifications are being read;
+            // Postgres delivers the notifications of a transaction together, in order
+            let mut changes = vec![];
+
             // Read notifications until the thread is to be terminated
             while !terminate.load(Ordering::SeqCst) {
                 // Obtain a notifications iterator from Postgres
@@ -86,22 +103,36 @@ impl EntityChangeListener {
                         break;
                     }
 
-                    // Parse payload into an entity change
+                    // Parse payload into an entity change or the end of a transaction
                     let value: serde_json::Value =
                         serde_json::from_str(notification.payload.as_str())
                             .expect("Invalid JSON entity change data received from database");
-                    let change: EntityChange = serde_json::from_value(value.clone()).expect(
-                        format!(
-                            "Invalid entity change received from the database: {:?}",
-                            value
-                        ).as_str(),
-                    );
-
-                    // We'll assume here that if sending fails, this means that the
-                    // entity change listener has already been dropped, the receiving
-                    // is gone and we should terminate the listener loop
-                    if sender.clone().send(change).wait().is_err() {
-                        break;
+
+                    if let Some(block) = value.get("block") {
+                        let block: EthereumBlockPointer = serde_json::from_value(block.clone())
+                            .expect(
+                                format!("Invalid block received from the database: {:?}", block)
+                                    .as_str(),
+                            );
+                        let batch = EntityChangeBatch {
+                            block,
+                            changes: mem::replace(&mut changes, vec![]),
+                        };
+
+                        // We'll assume here that if sending fails, this means that the
+                        // entity change listener has already been dropped, the receiving
+                        // is gone and we should terminate the listener loop
+                        if sender.clone().send(batch).wait().is_err() {
+                            break;
+                        }
+                    } else {
+                        let change: EntityChange = serde_json::from_value(value.clone()).expect(
+                            format!(
+                                "Invalid entity change received from the database: {:?}",
+                                value
+                            ).as_str(),
+                        );
+                        changes.push(change);
                     }
                 }
             }
@@ -124,10 +155,12 @@ impl Drop for EntityChangeListener {
     }
 }
 
-impl EventProducer<EntityChange> for EntityChangeListener {
-    fn take_event_stream(&mut self) -> Option<Box<Stream<Item = EntityChange, Error = ()> + Send>> {
+impl EventProducer<EntityChangeBatch> for EntityChangeListener {
+    fn take_event_stream(
+        &mut self,
+    ) -> Option<Box<Stream<Item = EntityChangeBatch, Error = ()> + Send>> {
         self.output
             .take()
-            .map(|s| Box::new(s) as Box<Stream<Item = EntityChange, Error = ()> + Send>)
+            .map(|s| Box::new(s) as Box<Stream<Item = EntityChangeBatch, Error = ()> + Send>)
     }
 }
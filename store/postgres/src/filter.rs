@@ -1,13 +1,12 @@
 use std::str::FromStr;
 
 use bigdecimal::BigDecimal;
-use db_schema::entities;
 use diesel::dsl::{self, sql};
 use diesel::expression::NonAggregate;
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::{BoxedSelectStatement, QueryFragment};
-use diesel::sql_types::{Array, Bool, Float, Integer, Jsonb, Numeric, Text};
+use diesel::sql_types::{Array, Bool, Float, Integer, Numeric, Text};
 use diesel::AppearsOnTable;
 
 use graph::components::store::StoreFilter;
@@ -25,21 +24,35 @@ enum FilterMode {
     Or,
 }
 
-/// Adds `filter` to a `SELECT data FROM entities` statement.
-pub(crate) fn store_filter<'a>(
-    query: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+/// Where the entities referenced in nested filters are looked up.
+pub(crate) struct FilterScope {
+    /// The subgraph that the filtered entities belong to.
+    pub subgraph: String,
+
+    /// Whether entities are filtered as they were at a block, i.e. in
+    /// `entities_at_block` instead of `entities`.
+    pub at_block: bool,
+}
+
+/// Adds `filter` to a `SELECT ... FROM entities` statement.
+///
+/// The statement may select from any table or view with a `data` column,
+/// e.g. `entities` or `entities_at_block`.
+pub(crate) fn store_filter<'a, ST, QS>(
+    query: BoxedSelectStatement<'a, ST, QS, Pg>,
+    scope: &FilterScope,
     filter: StoreFilter,
-) -> Result<BoxedSelectStatement<'a, Jsonb, entities::table, Pg>, UnsupportedFilter> {
-    store_filter_by_mode(query, filter, FilterMode::And)
+) -> Result<BoxedSelectStatement<'a, ST, QS, Pg>, UnsupportedFilter> {
+    store_filter_by_mode(query, scope, filter, FilterMode::And)
 }
 
-fn add_filter<'a, P: 'a>(
-    query: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+fn add_filter<'a, ST, QS, P: 'a>(
+    query: BoxedSelectStatement<'a, ST, QS, Pg>,
     filter_mode: FilterMode,
     predicate: P,
-) -> BoxedSelectStatement<'a, Jsonb, entities::table, Pg>
+) -> BoxedSelectStatement<'a, ST, QS, Pg>
 where
-    P: AppearsOnTable<entities::table>
+    P: AppearsOnTable<QS>
         + NonAggregate
         + QueryFragment<Pg>
         + Expression<SqlType = Bool>,
@@ -50,19 +63,20 @@ where
     }
 }
 
-/// Adds `filter` to a `SELECT data FROM entities` statement.
-fn store_filter_by_mode<'a>(
-    query: BoxedSelectStatement<'a, Jsonb, entities::table, Pg>,
+/// Adds `filter` to a `SELECT ... FROM entities` statement.
+fn store_filter_by_mode<'a, ST, QS>(
+    query: BoxedSelectStatement<'a, ST, QS, Pg>,
+    scope: &FilterScope,
     filter: StoreFilter,
     filter_mode: FilterMode,
-) -> Result<BoxedSelectStatement<'a, Jsonb, entities::table, Pg>, UnsupportedFilter> {
+) -> Result<BoxedSelectStatement<'a, ST, QS, Pg>, UnsupportedFilter> {
     Ok(match filter {
         StoreFilter::And(filters) => filters
             .into_iter()
-            .try_fold(query, |q, f| store_filter_by_mode(q, f, FilterMode::And))?,
+            .try_fold(query, |q, f| store_filter_by_mode(q, scope, f, FilterMode::And))?,
         StoreFilter::Or(filters) => filters
             .into_iter()
-            .try_fold(query, |q, f| store_filter_by_mode(q, f, FilterMode::Or))?,
+            .try_fold(query, |q, f| store_filter_by_mode(q, scope, f, FilterMode::Or))?,
         StoreFilter::Contains(..) | StoreFilter::NotContains(..) => {
             let (attribute, not, value) = match filter {
                 StoreFilter::Contains(attribute, value) => (attribute, false, value),
@@ -341,7 +355,12 @@ fn store_filter_by_mode<'a>(
         // Is `attribute` different from all `query_values`?
         StoreFilter::NotIn(attribute, query_values) => {
             query_values.into_iter().try_fold(query, |q, v| {
-                store_filter_by_mode(q, StoreFilter::Not(attribute.clone(), v), FilterMode::And)
+                store_filter_by_mode(
+                    q,
+                    scope,
+                    StoreFilter::Not(attribute.clone(), v),
+                    FilterMode::And,
+                )
             })?
         }
         StoreFilter::StartsWith(..) | StoreFilter::NotStartsWith(..) => {
@@ -411,5 +430,99 @@ fn store_filter_by_mode<'a>(
                 }
             }
         }
+
+        // Does `attribute` reference an entity that matches `child_filter`?
+        StoreFilter::Child(attribute, child_entity, child_filter) => {
+            // The attribute may hold a single ID or a list of IDs; treat both
+            // as a list and look for any ID of a matching child entity
+            let child_ids = format!(
+                "EXISTS (SELECT 1 FROM jsonb_array_elements_text(\
+                 CASE jsonb_typeof(data -> {attribute}) \
+                 WHEN 'array' THEN data -> {attribute} \
+                 ELSE jsonb_build_array(data -> {attribute}) END) AS child_id WHERE ",
+                attribute = quote_literal(&attribute)
+            );
+
+            // Child entities are selected in a sub-select, from the same
+            // relation and subgraph as the entities we're filtering
+            if scope.at_block {
+                use db_schema::entities_at_block::dsl::*;
+
+                let children = entities_at_block
+                    .select(id)
+                    .filter(entity.eq(child_entity))
+                    .filter(subgraph.eq(scope.subgraph.clone()))
+                    .into_boxed::<Pg>();
+                let children = store_filter(children, scope, *child_filter)?;
+                add_filter(
+                    query,
+                    filter_mode,
+                    sql(&child_ids)
+                        .bind::<Bool, _>(sql::<Text>("child_id").eq_any(children))
+                        .sql(")"),
+                )
+            } else {
+                use db_schema::entities::dsl::*;
+
+                let children = entities
+                    .select(id)
+                    .filter(entity.eq(child_entity))
+                    .filter(subgraph.eq(scope.subgraph.clone()))
+                    .into_boxed::<Pg>();
+                let children = store_filter(children, scope, *child_filter)?;
+                add_filter(
+                    query,
+                    filter_mode,
+                    sql(&child_ids)
+                        .bind::<Bool, _>(sql::<Text>("child_id").eq_any(children))
+                        .sql(")"),
+                )
+            }
+        }
+
+        StoreFilter::Fulltext(attributes, text) => {
+            if attributes.is_empty() {
+                return Err(UnsupportedFilter {
+                    filter: "fulltext".to_owned(),
+                    value: Value::String(text),
+                });
+            }
+            add_filter(
+                query,
+                filter_mode,
+                sql(&format!("{} @@ plainto_tsquery('english', ", fulltext_document(&attributes)))
+                    .bind::<Text, _>(text)
+                    .sql(")"),
+            )
+        }
     })
 }
+
+/// Returns the `tsvector` expression that full-text searches over the given
+/// attributes match against.
+///
+/// Indexes created for full-text searches use the exact same expression, as
+/// Postgres only uses an expression index if a query repeats its expression.
+pub(crate) fn fulltext_document(attributes: &[Attribute]) -> String {
+    let text = attributes
+        .iter()
+        .map(|attribute| format!("coalesce(data ->> {}, '')", quote_literal(attribute)))
+        .collect::<Vec<_>>()
+        .join(" || ' ' || ");
+    format!("to_tsvector('english', {})", text)
+}
+
+/// Returns the search text of a top-level full-text filter, along with the
+/// attributes it searches, if there is one.
+pub(crate) fn fulltext_search(filter: &StoreFilter) -> Option<(&Vec<Attribute>, &String)> {
+    match filter {
+        StoreFilter::Fulltext(attributes, text) => Some((attributes, text)),
+        StoreFilter::And(filters) => filters.iter().filter_map(fulltext_search).next(),
+        _ => None,
+    }
+}
+
+/// Quotes a string so it can be used as a literal in SQL statements.
+pub(crate) fn quote_literal(s: &str) -> String {
+    format!("'{}'", s.replace("'", "''"))
+}
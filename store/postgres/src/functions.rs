@@ -11,6 +11,11 @@ sql_function! {
     CurrentSetting,
     (setting_name: Text, missing_ok: Bool)
 }
+sql_function! {
+    pg_notify,
+    PgNotify,
+    (channel: Text, payload: Text)
+}
 sql_function! {
     set_config,
     SetConfig,
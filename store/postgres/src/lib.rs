@@ -12,6 +12,7 @@ extern crate postgres;
 extern crate serde;
 extern crate uuid;
 
+mod connection_pool;
 pub mod db_schema;
 mod entity_changes;
 mod filter;
@@ -19,4 +20,5 @@ pub mod functions;
 pub mod models;
 pub mod store;
 
+pub use self::connection_pool::PoolWaitStats;
 pub use self::store::{Store, StoreConfig};
@@ -1,7 +1,7 @@
 use bigdecimal::BigDecimal;
 use diesel::pg::Pg;
 use diesel::serialize::{self, Output, ToSql};
-use diesel::sql_types::{Bool, Float, Integer, Jsonb, Numeric, Text, VarChar};
+use diesel::sql_types::{BigInt, Bool, Float, Integer, Jsonb, Nullable, Numeric, Text, VarChar};
 use graph::serde_json;
 use std::io::Write;
 use std::str::FromStr;
@@ -24,6 +24,18 @@ pub struct EntityTable {
     pub event_source: String,
 }
 
+/// The latest block and the recorded error of a subgraph, either of which
+/// may be missing.
+#[derive(QueryableByName, Debug)]
+pub struct SubgraphStateRow {
+    #[sql_type = "Nullable<VarChar>"]
+    pub latest_block_hash: Option<String>,
+    #[sql_type = "Nullable<BigInt>"]
+    pub latest_block_number: Option<i64>,
+    #[sql_type = "Nullable<Text>"]
+    pub error: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, AsExpression)]
 pub struct SqlValue(Value);
 
@@ -2,30 +2,37 @@ use diesel::dsl::sql;
 use diesel::pg::Pg;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use diesel::sql_types::Text;
-use diesel::{debug_query, delete, insert_into, result, select, update};
-use filter::store_filter;
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::query_dsl::LoadQuery;
+use diesel::sql_types::{Jsonb, Text};
+use diesel::{debug_query, delete, insert_into, result, select, sql_query, update};
+use filter::{fulltext_document, fulltext_search, quote_literal, store_filter, FilterScope};
 use futures::sync::mpsc::{channel, Sender};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+use graph::components::metrics::HistogramVec;
 use graph::components::store::{EventSource, Store as StoreTrait};
+use graph::data::store::scalar::BigInt;
 use graph::prelude::*;
 use graph::serde_json;
 use graph::web3::types::{Block, Transaction, H256};
 use graph::{tokio, tokio::timer::Interval};
 
-use entity_changes::EntityChangeListener;
-use functions::{attempt_head_update, revert_block, set_config};
+use connection_pool::{ConnectionPool, PgPooledConnection, PoolWaitStats};
+use entity_changes::{entity_changes_complete_payload, EntityChangeListener};
+use functions::{attempt_head_update, pg_notify, revert_block, set_config};
+use models::SubgraphStateRow;
 
 embed_migrations!("./migrations");
 
 /// Internal representation of a Store subscription.
 struct Subscription {
-    pub entities: Vec<SubgraphEntityPair>,
-    pub sender: Sender<EntityChange>,
+    pub filters: Vec<EntityChangeFilter>,
+    pub sender: Sender<EntityChangeBatch>,
 }
 
 /// Run all initial schema migrations.
@@ -50,14 +57,41 @@ fn initiate_schema(logger: &slog::Logger, conn: &PgConnection) {
 /// Configuration for the Diesel/Postgres store.
 pub struct StoreConfig {
     pub url: String,
+    /// Number of connections used for reading entities, e.g. in GraphQL queries.
+    pub read_pool_size: u32,
+    /// Number of connections used for writing entities and blocks.
+    pub write_pool_size: u32,
+    /// Registry for the metrics reported by the store.
+    pub metrics: MetricsRegistry,
+    /// Number of log entries kept per subgraph; older entries are deleted.
+    pub max_subgraph_log_entries: usize,
+}
+
+impl StoreConfig {
+    /// Creates a configuration with default connection pool sizes.
+    pub fn new(url: String) -> Self {
+        StoreConfig {
+            url,
+            read_pool_size: 10,
+            write_pool_size: 5,
+            metrics: MetricsRegistry::new(),
+            max_subgraph_log_entries: 10_000,
+        }
+    }
 }
 
 /// A Store based on Diesel and Postgres.
+///
+/// Reads and writes use separate connection pools, so that expensive queries
+/// can't starve subgraph indexing and block ingestion of connections.
 pub struct Store {
     logger: slog::Logger,
     subscriptions: Arc<RwLock<HashMap<String, Subscription>>>,
     change_listener: EntityChangeListener,
-    pub conn: Arc<Mutex<PgConnection>>,
+    read_pool: ConnectionPool,
+    write_pool: ConnectionPool,
+    find_duration: HistogramVec,
+    max_subgraph_log_entries: usize,
 }
 
 impl Store {
@@ -66,13 +100,20 @@ impl Store {
         let logger = logger.new(o!("component" => "Store"));
 
         // Connect to Postgres
-        let conn =
-            PgConnection::establish(config.url.as_str()).expect("failed to connect to Postgres");
+        let read_pool =
+            ConnectionPool::create(&logger, "read", &config.url, config.read_pool_size);
+        let write_pool =
+            ConnectionPool::create(&logger, "write", &config.url, config.write_pool_size);
 
         info!(logger, "Connected to Postgres"; "url" => &config.url);
 
         // Create the entities table (if necessary)
-        initiate_schema(&logger, &conn);
+        initiate_schema(
+            &logger,
+            &*write_pool
+                .get()
+                .expect("failed to obtain a connection to Postgres"),
+        );
 
         // Listen to entity changes in Postgres
         let mut change_listener = EntityChangeListener::new(config.url.clone());
@@ -85,12 +126,22 @@ impl Store {
             logger: logger.clone(),
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
             change_listener,
-            conn: Arc::new(Mutex::new(conn)),
+            read_pool,
+            write_pool,
+            find_duration: config
+                .metrics
+                .new_histogram_vec(
+                    "store_find_duration_seconds",
+                    "Time it takes to find the entities of a subgraph in the store",
+                    &["subgraph"],
+                ).expect("Failed to register store find duration histogram"),
+            max_subgraph_log_entries: config.max_subgraph_log_entries,
         };
 
         // Deal with store subscriptions
         store.handle_entity_changes(entity_changes);
         store.periodically_clean_up_stale_subscriptions();
+        store.periodically_log_pool_wait_stats();
 
         // We're ready for processing entity changes
         store.change_listener.start();
@@ -99,42 +150,62 @@ impl Store {
         store
     }
 
-    /// Handles entity changes emitted by Postgres.
+    /// Handles batches of entity changes emitted by Postgres.
     fn handle_entity_changes(
         &mut self,
-        entity_changes: Box<Stream<Item = EntityChange, Error = ()> + Send>,
+        entity_changes: Box<Stream<Item = EntityChangeBatch, Error = ()> + Send>,
     ) {
         let logger = self.logger.clone();
         let subscriptions = self.subscriptions.clone();
 
-        tokio::spawn(entity_changes.for_each(move |change| {
-            debug!(logger, "Entity change";
-                           "subgraph" => &change.subgraph,
-                           "entity" => &change.entity,
-                           "id" => &change.id);
+        tokio::spawn(entity_changes.for_each(move |batch| {
+            debug!(logger, "Entity changes";
+                           "block_number" => batch.block.number,
+                           "block_hash" => format!("{:x}", batch.block.hash),
+                           "changes" => batch.changes.len());
 
-            // Obtain IDs and senders of subscriptions matching the entity change
+            // Obtain IDs and senders of subscriptions matching any of the entity
+            // changes, together with the changes that match
             let matches = subscriptions
                 .read()
                 .unwrap()
                 .iter()
-                .filter(|(_, subscription)| {
-                    subscription
-                        .entities
-                        .contains(&(change.subgraph.clone(), change.entity.clone()))
-                }).map(|(id, subscription)| (id.clone(), subscription.sender.clone()))
-                .collect::<Vec<_>>();
+                .filter_map(|(id, subscription)| {
+                    let changes = batch
+                        .changes
+                        .iter()
+                        .filter(|change| {
+                            subscription
+                                .filters
+                                .iter()
+                                .any(|filter| filter.matches(change))
+                        }).cloned()
+                        .collect::<Vec<_>>();
+
+                    if changes.is_empty() {
+                        None
+                    } else {
+                        Some((
+                            id.clone(),
+                            subscription.sender.clone(),
+                            EntityChangeBatch {
+                                block: batch.block,
+                                changes,
+                            },
+                        ))
+                    }
+                }).collect::<Vec<_>>();
 
             let subscriptions = subscriptions.clone();
             let logger = logger.clone();
 
-            // Write change to all matching subscription streams; remove subscriptions
-            // whose receiving end has been dropped
-            stream::iter_ok::<_, ()>(matches).for_each(move |(id, sender)| {
+            // Write the changes to all matching subscription streams; remove
+            // subscriptions whose receiving end has been dropped
+            stream::iter_ok::<_, ()>(matches).for_each(move |(id, sender, batch)| {
                 let logger = logger.clone();
                 let subscriptions = subscriptions.clone();
                 sender
-                    .send(change.clone())
+                    .send(batch)
                     .map_err(move |_| {
                         debug!(logger, "Unsubscribe"; "id" => &id);
                         subscriptions.write().unwrap().remove(&id);
@@ -174,158 +245,682 @@ impl Store {
         );
     }
 
+    fn periodically_log_pool_wait_stats(&mut self) {
+        let logger = self.logger.clone();
+        let pools = vec![self.read_pool.clone(), self.write_pool.clone()];
+
+        // Log how long it took to obtain connections every 60s
+        tokio::spawn(
+            Interval::new(Instant::now(), Duration::from_secs(60))
+                .for_each(move |_| {
+                    for pool in pools.iter() {
+                        let wait_stats = pool.wait_stats();
+                        debug!(logger, "Connection pool wait times";
+                               "pool" => pool.name(),
+                               "checkouts" => wait_stats.checkouts,
+                               "timeouts" => wait_stats.timeouts,
+                               "average_wait_ms" => duration_as_millis(wait_stats.average_wait()),
+                               "max_wait_ms" => duration_as_millis(wait_stats.max_wait));
+                    }
+                    Ok(())
+                }).map_err(|_| unreachable!()),
+        );
+    }
+
+    /// Obtains a connection for reading from the store.
+    pub fn read_conn(&self) -> Result<PgPooledConnection, Error> {
+        self.read_pool.get().map_err(Error::from)
+    }
+
+    /// Obtains a connection for writing to the store.
+    pub fn write_conn(&self) -> Result<PgPooledConnection, Error> {
+        self.write_pool.get().map_err(Error::from)
+    }
+
+    /// Returns how long obtaining connections for reading has taken so far.
+    pub fn read_pool_wait_stats(&self) -> PoolWaitStats {
+        self.read_pool.wait_stats()
+    }
+
+    /// Returns how long obtaining connections for writing has taken so far.
+    pub fn write_pool_wait_stats(&self) -> PoolWaitStats {
+        self.write_pool.wait_stats()
+    }
+
+    /// Obtains a connection for reading, logging failures to do so.
+    fn read_conn_or_log(&self) -> Result<PgPooledConnection, ()> {
+        self.read_conn().map_err(|e| {
+            error!(self.logger, "failed to obtain a connection for reading";
+                   "error" => format!("{}", e))
+        })
+    }
+
+    /// Obtains a connection for writing, logging failures to do so.
+    fn write_conn_or_log(&self) -> Result<PgPooledConnection, ()> {
+        self.write_conn().map_err(|e| {
+            error!(self.logger, "failed to obtain a connection for writing";
+                   "error" => format!("{}", e))
+        })
+    }
+
+    /// Queries the latest version of all entities matching the query.
+    fn find_latest(&self, query: StoreQuery) -> Result<Vec<Entity>, ()> {
+        use db_schema::entities::dsl::*;
+
+        // Create base boxed query; this will be added to based on the
+        // query parameters provided
+        let diesel_query = entities
+            .filter(entity.eq(query.entity.clone()))
+            .filter(subgraph.eq(query.subgraph.clone()))
+            .select(data)
+            .into_boxed::<Pg>();
+        let diesel_query = apply_store_query(&self.logger, diesel_query, query)?;
+
+        debug!(self.logger, "find";
+                "sql" => format!("{:?}", debug_query::<Pg, _>(&diesel_query)));
+
+        // Process results; deserialize JSON data
+        diesel_query
+            .load::<serde_json::Value>(&*self.read_conn_or_log()?)
+            .map(entities_from_json)
+            .map_err(|_| ())
+    }
+
+    /// Queries entities matching the query as they were at the given block.
+    fn find_at_block(&self, query: StoreQuery, block: StoreBlock) -> Result<Vec<Entity>, ()> {
+        use db_schema::entities_at_block::dsl::*;
+
+        let conn = self.read_conn_or_log()?;
+        let block_number = self.block_number(&*conn, block)?;
+
+        let diesel_query = entities_at_block
+            .filter(entity.eq(query.entity.clone()))
+            .filter(subgraph.eq(query.subgraph.clone()))
+            .select(data)
+            .into_boxed::<Pg>();
+        let diesel_query = apply_store_query(&self.logger, diesel_query, query)?;
+
+        debug!(self.logger, "find";
+                "block" => block_number,
+                "sql" => format!("{:?}", debug_query::<Pg, _>(&diesel_query)));
+
+        load_at_block(&*conn, block_number, diesel_query)
+            .map(entities_from_json)
+            .map_err(|e| {
+                error!(self.logger, "failed to query entities at block";
+                       "block" => block_number,
+                       "error" => format!("{}", e))
+            })
+    }
+
+    /// Resolves a block into its block number.
+    fn block_number(&self, conn: &PgConnection, block: StoreBlock) -> Result<i64, ()> {
+        use db_schema::ethereum_blocks::dsl::*;
+
+        match block {
+            StoreBlock::Number(block_number) => Ok(block_number as i64),
+            StoreBlock::Hash(block_hash) => ethereum_blocks
+                .filter(hash.eq(format!("{:#x}", block_hash)))
+                .select(number)
+                .first::<i64>(conn)
+                .map_err(|e| {
+                    error!(self.logger, "failed to look up block";
+                           "hash" => format!("{:#x}", block_hash),
+                           "error" => format!("{}", e))
+                }),
+        }
+    }
+
     /// Handles block reorganizations.
     /// Revert all store events related to the given block
-    pub fn revert_events(&self, block_hash: String, subgraph_id: String) {
-        select(revert_block(block_hash, subgraph_id))
-            .execute(&*self.conn.lock().unwrap())
-            .unwrap();
+    pub fn revert_events(
+        &self,
+        block: EthereumBlockPointer,
+        subgraph_id: String,
+    ) -> Result<(), Error> {
+        let input_event_source = EventSource::EthereumBlock(block);
+
+        let conn = self.write_conn()?;
+        conn.transaction::<(), result::Error, _>(|| {
+            revert_subgraph_latest_block(&*conn, &subgraph_id, &block)?;
+            select(revert_block(input_event_source.to_string(), subgraph_id)).execute(&*conn)?;
+            notify_entity_changes_complete(&*conn, &input_event_source)
+        }).map_err(Error::from)
     }
 }
 
+/// Adds the filter, order and range of `query` to a `SELECT data FROM ...` statement.
+fn apply_store_query<'a, QS>(
+    logger: &Logger,
+    diesel_query: BoxedSelectStatement<'a, Jsonb, QS, Pg>,
+    query: StoreQuery,
+) -> Result<BoxedSelectStatement<'a, Jsonb, QS, Pg>, ()> {
+    // Rank the results of full-text searches unless an order is given explicitly
+    let fulltext_rank = if query.order_by.is_empty() {
+        query
+            .filter
+            .as_ref()
+            .and_then(fulltext_search)
+            .map(|(attributes, text)| {
+                sql::<Text>(&format!(
+                    "ts_rank({}, plainto_tsquery('english', ",
+                    fulltext_document(attributes)
+                )).bind::<Text, _>(text.to_owned())
+                .sql(")) DESC")
+            })
+    } else {
+        None
+    };
+
+    // Add specified filter to query
+    let scope = FilterScope {
+        subgraph: query.subgraph,
+        at_block: query.block.is_some(),
+    };
+    let mut diesel_query = apply_store_filter(logger, diesel_query, &scope, query.filter)?;
+
+    if let Some(rank) = fulltext_rank {
+        diesel_query = diesel_query.order(rank);
+    }
+
+    // Add order by filters to query
+    if !query.order_by.is_empty() {
+        let order = query
+            .order_by
+            .iter()
+            .map(|(order_attribute, direction)| {
+                let direction = match direction {
+                    StoreOrder::Ascending => "ASC",
+                    StoreOrder::Descending => "DESC",
+                };
+                format!("data ->> {} {}", quote_literal(order_attribute), direction)
+            }).collect::<Vec<_>>()
+            .join(", ");
+
+        diesel_query = diesel_query.order(sql::<Text>(&order))
+    }
+
+    // Add range filter to query
+    if let Some(range) = query.range {
+        diesel_query = diesel_query
+            .limit(range.first as i64)
+            .offset(range.skip as i64);
+    }
+
+    Ok(diesel_query)
+}
+
+/// Adds an optional filter to a `SELECT ... FROM ...` statement.
+fn apply_store_filter<'a, QS>(
+    logger: &Logger,
+    diesel_query: BoxedSelectStatement<'a, Jsonb, QS, Pg>,
+    scope: &FilterScope,
+    filter: Option<StoreFilter>,
+) -> Result<BoxedSelectStatement<'a, Jsonb, QS, Pg>, ()> {
+    match filter {
+        Some(filter) => store_filter(diesel_query, scope, filter).map_err(|e| {
+            error!(logger, "value does not support this filter";
+                           "value" => format!("{:?}", e.value),
+                           "filter" => e.filter)
+        }),
+        None => Ok(diesel_query),
+    }
+}
+
+/// Runs a query against the `entities_at_block` view at the given block number.
+fn load_at_block<Q>(
+    conn: &PgConnection,
+    block_number: i64,
+    diesel_query: Q,
+) -> result::QueryResult<Vec<serde_json::Value>>
+where
+    Q: LoadQuery<PgConnection, serde_json::Value>,
+{
+    // The `entities_at_block` view reads the block number from a setting
+    // that is local to the transaction
+    conn.transaction::<_, result::Error, _>(|| {
+        select(set_config(
+            "vars.query_block_number",
+            block_number.to_string(),
+            true,
+        )).execute(conn)?;
+        diesel_query.load::<serde_json::Value>(conn)
+    })
+}
+
+/// Builds a `jsonb_build_array(...)` clause that computes all `aggregates`
+/// in a single row. Numeric aggregates are returned as text to avoid losing
+/// precision when going through JSON.
+fn aggregate_clause(aggregates: &[StoreAggregate]) -> String {
+    let numeric = |attribute: &String| format!("(data ->> {})::numeric", quote_literal(attribute));
+
+    let columns = aggregates
+        .iter()
+        .map(|aggregate| match aggregate {
+            StoreAggregate::Count => String::from("COUNT(*)"),
+            StoreAggregate::Sum(attribute) => format!("SUM({})::text", numeric(attribute)),
+            StoreAggregate::Min(attribute) => format!("MIN({})::text", numeric(attribute)),
+            StoreAggregate::Max(attribute) => format!("MAX({})::text", numeric(attribute)),
+            StoreAggregate::Avg(attribute) => format!("AVG({})::text", numeric(attribute)),
+        }).collect::<Vec<_>>();
+
+    format!("jsonb_build_array({})", columns.join(", "))
+}
+
+/// Converts a row computed with `aggregate_clause` into store values.
+fn aggregates_from_json(aggregates: &[StoreAggregate], row: serde_json::Value) -> Vec<Value> {
+    let columns = match row {
+        serde_json::Value::Array(columns) => columns,
+        _ => vec![],
+    };
+
+    aggregates
+        .iter()
+        .zip(columns.into_iter().chain(iter::repeat(serde_json::Value::Null)))
+        .map(|(aggregate, column)| match (aggregate, column) {
+            (StoreAggregate::Count, serde_json::Value::Number(n)) => {
+                Value::Int(n.as_i64().unwrap_or(0) as i32)
+            }
+            (StoreAggregate::Avg(_), serde_json::Value::String(s)) => {
+                s.parse::<f32>().map(Value::Float).unwrap_or(Value::Null)
+            }
+            (_, serde_json::Value::String(s)) => match s.parse::<BigInt>() {
+                Ok(n) => Value::BigInt(n),
+                Err(_) => s.parse::<f32>().map(Value::Float).unwrap_or(Value::Null),
+            },
+            _ => Value::Null,
+        }).collect()
+}
+
+/// Deserializes the JSON data of entities loaded from the database.
+fn entities_from_json(values: Vec<serde_json::Value>) -> Vec<Entity> {
+    values
+        .into_iter()
+        .map(|value| serde_json::from_value::<Entity>(value).expect("Error to deserialize entity"))
+        .collect()
+}
+
+/// Looks up an entity using the given store key and connection.
+fn get_entity(conn: &PgConnection, key: StoreKey) -> Result<Entity, ()> {
+    use db_schema::entities::dsl::*;
+
+    // Use primary key fields to get the entity; deserialize the result JSON
+    entities
+        .find((key.id, key.subgraph, key.entity))
+        .select(data)
+        .first::<serde_json::Value>(conn)
+        .map(|value| serde_json::from_value::<Entity>(value).expect("Failed to deserialize entity"))
+        .map_err(|_| ())
+}
+
+/// Creates an entity or merges the input entity into an existing one.
+///
+/// The merge happens in the upsert itself, so concurrent updates of the same
+/// entity can't overwrite each other's attributes. Like `Entity::merge`,
+/// attributes set to null in the input entity are removed.
+fn set_entity(
+    conn: &PgConnection,
+    key: StoreKey,
+    input_entity: Entity,
+    input_event_source: &EventSource,
+) -> Result<(), result::Error> {
+    use db_schema::entities::dsl::*;
+
+    // Convert Entity hashmap to serde_json::Value for insert
+    let entity_json: serde_json::Value =
+        serde_json::to_value(&input_entity).expect("Failed to serialize entity");
+
+    // Insert entity, merge it into the existing one in case of a primary key conflict
+    insert_into(entities)
+        .values((
+            id.eq(&key.id),
+            entity.eq(&key.entity),
+            subgraph.eq(&key.subgraph),
+            data.eq(&entity_json),
+            event_source.eq(&input_event_source.to_string()),
+        )).on_conflict((id, entity, subgraph))
+        .do_update()
+        .set((
+            data.eq(sql::<Jsonb>(
+                "jsonb_strip_nulls(entities.data || excluded.data)",
+            )),
+            event_source.eq(&input_event_source.to_string()),
+        )).execute(conn)
+        .map(|_| ())
+}
+
+/// Removes an entity; must be called inside a transaction.
+fn delete_entity(
+    conn: &PgConnection,
+    key: StoreKey,
+    input_event_source: &EventSource,
+) -> Result<(), result::Error> {
+    use db_schema::entities::dsl::*;
+
+    // Set a transaction-local variable to store the source of the event; a
+    // session variable would leak to other users of the pooled connection
+    select(set_config(
+        "vars.current_event_source",
+        input_event_source.to_string(),
+        true,
+    )).execute(conn)?;
+
+    // Delete from DB where rows match the subgraph ID, entity name and ID
+    delete(
+        entities
+            .filter(subgraph.eq(&key.subgraph))
+            .filter(entity.eq(&key.entity))
+            .filter(id.eq(&key.id)),
+    ).execute(conn)
+    .map(|_| ())
+}
+
+/// Records `block` as the latest block whose entity changes are stored for the
+/// subgraph.
+fn set_subgraph_latest_block(
+    conn: &PgConnection,
+    subgraph_id: &str,
+    block: &EthereumBlockPointer,
+) -> Result<(), result::Error> {
+    use db_schema::subgraphs::dsl::*;
+
+    insert_into(subgraphs)
+        .values((
+            id.eq(subgraph_id),
+            latest_block_hash.eq(format!("{:x}", block.hash)),
+            latest_block_number.eq(block.number as i64),
+        )).on_conflict(id)
+        .do_update()
+        .set((
+            latest_block_hash.eq(format!("{:x}", block.hash)),
+            latest_block_number.eq(block.number as i64),
+        )).execute(conn)
+        .map(|_| ())
+}
+
+/// Makes the parent of a reverted block the latest block of the subgraph.
+/// Forgets the latest block of the subgraph if the parent is unknown.
+fn revert_subgraph_latest_block(
+    conn: &PgConnection,
+    subgraph_id: &str,
+    block: &EthereumBlockPointer,
+) -> Result<(), result::Error> {
+    use db_schema::{ethereum_blocks, subgraphs};
+
+    let parent_hash = ethereum_blocks::table
+        .select(ethereum_blocks::parent_hash)
+        .filter(ethereum_blocks::hash.eq(format!("{:#x}", block.hash)))
+        .first::<Option<String>>(conn)
+        .optional()?
+        .and_then(|parent_hash| parent_hash)
+        .and_then(|parent_hash| parent_hash.trim_left_matches("0x").parse().ok());
+
+    match parent_hash {
+        Some(parent_hash) => set_subgraph_latest_block(
+            conn,
+            subgraph_id,
+            &EthereumBlockPointer {
+                hash: parent_hash,
+                number: block.number - 1,
+            },
+        ),
+        None => delete(subgraphs::table.filter(subgraphs::id.eq(subgraph_id)))
+            .execute(conn)
+            .map(|_| ()),
+    }
+}
+
+/// Marks the end of the entity changes made in the current transaction, which
+/// makes the entity change listener emit them as one batch for the block of
+/// the event source. Every transaction that changes entities has to end with this.
+fn notify_entity_changes_complete(
+    conn: &PgConnection,
+    input_event_source: &EventSource,
+) -> Result<(), result::Error> {
+    let block = match input_event_source {
+        EventSource::EthereumBlock(block) => block,
+    };
+
+    select(pg_notify(
+        "entity_changes",
+        entity_changes_complete_payload(block),
+    )).execute(conn)
+    .map(|_| ())
+}
+
 impl BasicStore for Store {
     fn get(&self, key: StoreKey) -> Result<Entity, ()> {
         debug!(self.logger, "get"; "key" => format!("{:?}", key));
 
-        use db_schema::entities::dsl::*;
-
-        // Use primary key fields to get the entity; deserialize the result JSON
-        entities
-            .find((key.id, key.subgraph, key.entity))
-            .select(data)
-            .first::<serde_json::Value>(&*self.conn.lock().unwrap())
-            .map(|value| {
-                serde_json::from_value::<Entity>(value).expect("Failed to deserialize entity")
-            }).map_err(|_| ())
+        get_entity(&*self.read_conn_or_log()?, key)
     }
 
     fn set(
-        &mut self,
+        &self,
         key: StoreKey,
         input_entity: Entity,
         input_event_source: EventSource,
     ) -> Result<(), ()> {
         debug!(self.logger, "set"; "key" => format!("{:?}", key));
 
-        use db_schema::entities::dsl::*;
+        let conn = self.write_conn_or_log()?;
+        conn.transaction::<(), result::Error, _>(|| {
+            let EventSource::EthereumBlock(ref block) = input_event_source;
+            set_subgraph_latest_block(&*conn, &key.subgraph, block)?;
+            set_entity(&*conn, key, input_entity, &input_event_source)?;
+            notify_entity_changes_complete(&*conn, &input_event_source)
+        }).map_err(|_| ())
+    }
+
+    fn delete(&self, key: StoreKey, input_event_source: EventSource) -> Result<(), ()> {
+        debug!(self.logger, "delete"; "key" => format!("{:?}", key));
+
+        let conn = self.write_conn_or_log()?;
+        conn.transaction::<(), result::Error, _>(|| {
+            let EventSource::EthereumBlock(ref block) = input_event_source;
+            set_subgraph_latest_block(&*conn, &key.subgraph, block)?;
+            delete_entity(&*conn, key, &input_event_source)?;
+            notify_entity_changes_complete(&*conn, &input_event_source)
+        }).map_err(|_| ())
+    }
 
-        // Update the existing entity, if necessary
-        let updated_entity = match self.get(key.clone()) {
-            Ok(mut existing_entity) => {
-                existing_entity.merge(input_entity);
-                existing_entity
+    fn transact_block_operations(
+        &self,
+        block: EthereumBlockPointer,
+        operations: Vec<EntityOperation>,
+        log_entries: HashMap<String, Vec<SubgraphLogEntry>>,
+    ) -> Result<(), Error> {
+        debug!(self.logger, "transact block operations";
+               "block_number" => block.number,
+               "block_hash" => format!("{:x}", block.hash),
+               "operations" => operations.len(),
+               "log_entries" => log_entries.values().map(Vec::len).sum::<usize>());
+
+        let input_event_source = EventSource::EthereumBlock(block);
+
+        // Subgraphs whose latest block advances with these operations
+        let subgraph_ids = operations
+            .iter()
+            .map(|operation| operation.key().subgraph.clone())
+            .collect::<HashSet<_>>();
+
+        let conn = self.write_conn()?;
+        conn.transaction::<(), result::Error, _>(|| {
+            for subgraph_id in subgraph_ids.iter() {
+                set_subgraph_latest_block(&*conn, subgraph_id, &block)?;
             }
-            Err(_) => input_entity,
-        };
+            for operation in operations {
+                match operation {
+                    EntityOperation::Set { key, data } => {
+                        set_entity(&*conn, key, data, &input_event_source)?
+                    }
+                    EntityOperation::Remove { key } => {
+                        delete_entity(&*conn, key, &input_event_source)?
+                    }
+                }
+            }
+            for (subgraph_id, entries) in log_entries.iter() {
+                insert_subgraph_logs(
+                    &*conn,
+                    subgraph_id,
+                    entries,
+                    self.max_subgraph_log_entries,
+                )?;
+            }
+            notify_entity_changes_complete(&*conn, &input_event_source)
+        }).map_err(Error::from)
+    }
 
-        // Convert Entity hashmap to serde_json::Value for insert
-        let entity_json: serde_json::Value =
-            serde_json::to_value(&updated_entity).expect("Failed to serialize entity");
-
-        // Insert entity, perform an update in case of a primary key conflict
-        insert_into(entities)
-            .values((
-                id.eq(&key.id),
-                entity.eq(&key.entity),
-                subgraph.eq(&key.subgraph),
-                data.eq(&entity_json),
-                event_source.eq(&input_event_source.to_string()),
-            )).on_conflict((id, entity, subgraph))
-            .do_update()
-            .set((
-                id.eq(&key.id),
-                entity.eq(&key.entity),
-                subgraph.eq(&key.subgraph),
-                data.eq(&entity_json),
-                event_source.eq(&input_event_source.to_string()),
-            )).execute(&*self.conn.lock().unwrap())
-            .map(|_| ())
-            .map_err(|_| ())
+    fn subgraph_latest_block(
+        &self,
+        subgraph_id: &str,
+    ) -> Result<Option<EthereumBlockPointer>, Error> {
+        use db_schema::subgraphs::dsl::*;
+
+        let latest_block = subgraphs
+            .select((latest_block_hash, latest_block_number))
+            .filter(id.eq(subgraph_id))
+            .first::<(String, i64)>(&*self.read_conn()?)
+            .optional()?;
+
+        match latest_block {
+            Some((block_hash, block_number)) => Ok(Some(EthereumBlockPointer {
+                hash: block_hash.parse()?,
+                number: block_number as u64,
+            })),
+            None => Ok(None),
+        }
     }
 
-    fn delete(&mut self, key: StoreKey, input_event_source: EventSource) -> Result<(), ()> {
-        debug!(self.logger, "delete"; "key" => format!("{:?}", key));
+    fn set_subgraph_health(&self, subgraph_id: &str, health: &SubgraphHealth) -> Result<(), Error> {
+        use db_schema::subgraph_errors::dsl::*;
 
-        use db_schema::entities::dsl::*;
+        let conn = self.write_conn()?;
+        match health {
+            SubgraphHealth::Healthy => {
+                delete(subgraph_errors.filter(subgraph.eq(subgraph_id))).execute(&*conn)?;
+            }
+            SubgraphHealth::Failed(message) => {
+                insert_into(subgraph_errors)
+                    .values((subgraph.eq(subgraph_id), error.eq(message)))
+                    .on_conflict(subgraph)
+                    .do_update()
+                    .set(error.eq(message))
+                    .execute(&*conn)?;
+            }
+        }
+        Ok(())
+    }
 
-        let conn = self.conn.lock().unwrap();
-        conn.transaction::<usize, result::Error, _>(|| {
-            // Set session variable to store the source of the event
-            select(set_config(
-                "vars.current_event_source",
-                input_event_source.to_string(),
-                false,
-            )).execute(&*conn)
-            .unwrap();
-
-            // Delete from DB where rows match the subgraph ID, entity name and ID
-            delete(
-                entities
-                    .filter(subgraph.eq(&key.subgraph))
-                    .filter(entity.eq(&key.entity))
-                    .filter(id.eq(&key.id)),
-            ).execute(&*conn)
-        }).map(|_| ())
-        .map_err(|_| ())
+    fn subgraph_health(&self, subgraph_id: &str) -> Result<SubgraphHealth, Error> {
+        use db_schema::subgraph_errors::dsl::*;
+
+        let message = subgraph_errors
+            .select(error)
+            .filter(subgraph.eq(subgraph_id))
+            .first::<String>(&*self.read_conn()?)
+            .optional()?;
+
+        Ok(message.map_or(SubgraphHealth::Healthy, SubgraphHealth::Failed))
     }
 
-    fn find(&self, query: StoreQuery) -> Result<Vec<Entity>, ()> {
-        use db_schema::entities::dsl::*;
+    fn subgraph_latest_block_and_health(
+        &self,
+        subgraph_id: &str,
+    ) -> Result<(Option<EthereumBlockPointer>, SubgraphHealth), Error> {
+        // A subgraph may have failed before storing any block, so neither
+        // table is guaranteed to have a row for it
+        let row = sql_query(
+            "SELECT s.latest_block_hash, s.latest_block_number, e.error \
+             FROM (SELECT $1::varchar AS id) q \
+             LEFT JOIN subgraphs s ON s.id = q.id \
+             LEFT JOIN subgraph_errors e ON e.subgraph = q.id",
+        ).bind::<Text, _>(subgraph_id)
+        .get_result::<SubgraphStateRow>(&*self.read_conn()?)?;
+
+        let latest_block = match (row.latest_block_hash, row.latest_block_number) {
+            (Some(block_hash), Some(block_number)) => Some(EthereumBlockPointer {
+                hash: block_hash.parse()?,
+                number: block_number as u64,
+            }),
+            _ => None,
+        };
+        let health = row
+            .error
+            .map_or(SubgraphHealth::Healthy, SubgraphHealth::Failed);
 
-        // Create base boxed query; this will be added to based on the
-        // query parameters provided
-        let mut diesel_query = entities
-            .filter(entity.eq(query.entity))
-            .filter(subgraph.eq(query.subgraph))
-            .select(data)
-            .into_boxed::<Pg>();
+        Ok((latest_block, health))
+    }
 
-        // Add specified filter to query
-        if let Some(filter) = query.filter {
-            diesel_query = store_filter(diesel_query, filter).map_err(|e| {
-                error!(self.logger, "value does not support this filter";
-                                    "value" => format!("{:?}", e.value),
-                                    "filter" => e.filter)
-            })?;
+    fn find(&self, query: StoreQuery) -> Result<Vec<Entity>, ()> {
+        let _timer = self
+            .find_duration
+            .with_label_values(&[query.subgraph.as_str()])
+            .start_timer();
+
+        match query.block.clone() {
+            Some(block) => self.find_at_block(query, block),
+            None => self.find_latest(query),
         }
+    }
 
-        // Add order by filters to query
-        if let Some(order_attribute) = query.order_by {
-            let direction = query
-                .order_direction
-                .map(|direction| match direction {
-                    StoreOrder::Ascending => String::from("ASC"),
-                    StoreOrder::Descending => String::from("DESC"),
-                }).unwrap_or(String::from("ASC"));
-
-            diesel_query = diesel_query.order(
-                sql::<Text>("data ->> ")
-                    .bind::<Text, _>(order_attribute)
-                    .sql(&format!(" {} ", direction)),
-            )
-        }
+    fn aggregate(
+        &self,
+        query: StoreQuery,
+        aggregates: Vec<StoreAggregate>,
+    ) -> Result<Vec<Value>, ()> {
+        let conn = self.read_conn_or_log()?;
+        let clause = aggregate_clause(&aggregates);
+        let scope = FilterScope {
+            subgraph: query.subgraph.clone(),
+            at_block: query.block.is_some(),
+        };
 
-        // Add range filter to query
-        if let Some(range) = query.range {
-            diesel_query = diesel_query
-                .limit(range.first as i64)
-                .offset(range.skip as i64);
-        }
+        let rows = match query.block.clone() {
+            Some(block) => {
+                use db_schema::entities_at_block::dsl::*;
 
-        debug!(self.logger, "find";
-                "sql" => format!("{:?}", debug_query::<Pg, _>(&diesel_query)));
+                let block_number = self.block_number(&*conn, block)?;
+                let diesel_query = entities_at_block
+                    .filter(entity.eq(query.entity.clone()))
+                    .filter(subgraph.eq(query.subgraph.clone()))
+                    .select(sql::<Jsonb>(&clause))
+                    .into_boxed::<Pg>();
+                let diesel_query =
+                    apply_store_filter(&self.logger, diesel_query, &scope, query.filter)?;
 
-        // Process results; deserialize JSON data
-        diesel_query
-            .load::<serde_json::Value>(&*self.conn.lock().unwrap())
-            .map(|values| {
-                values
-                    .into_iter()
-                    .map(|value| {
-                        serde_json::from_value::<Entity>(value)
-                            .expect("Error to deserialize entity")
-                    }).collect()
-            }).map_err(|_| ())
+                debug!(self.logger, "aggregate";
+                        "block" => block_number,
+                        "sql" => format!("{:?}", debug_query::<Pg, _>(&diesel_query)));
+
+                load_at_block(&*conn, block_number, diesel_query)
+            }
+            None => {
+                use db_schema::entities::dsl::*;
+
+                let diesel_query = entities
+                    .filter(entity.eq(query.entity.clone()))
+                    .filter(subgraph.eq(query.subgraph.clone()))
+                    .select(sql::<Jsonb>(&clause))
+                    .into_boxed::<Pg>();
+                let diesel_query =
+                    apply_store_filter(&self.logger, diesel_query, &scope, query.filter)?;
+
+                debug!(self.logger, "aggregate";
+                        "sql" => format!("{:?}", debug_query::<Pg, _>(&diesel_query)));
+
+                diesel_query.load::<serde_json::Value>(&*conn)
+            }
+        }.map_err(|e| {
+            error!(self.logger, "failed to aggregate entities";
+                   "error" => format!("{}", e))
+        })?;
+
+        // Aggregating always yields exactly one row
+        rows.into_iter()
+            .next()
+            .map(|row| aggregates_from_json(&aggregates, row))
+            .ok_or(())
     }
 }
 
@@ -338,10 +933,12 @@ impl BlockStore for Store {
     ) -> Result<(), Error> {
         use db_schema::ethereum_networks::dsl::*;
 
+        let conn = self.write_conn()?;
+
         let network_identifiers_opt = ethereum_networks
             .select((net_version, genesis_block_hash))
             .filter(name.eq(new_network_name))
-            .first::<(Option<String>, Option<String>)>(&*self.conn.lock().unwrap())
+            .first::<(Option<String>, Option<String>)>(&*conn)
             .optional()?;
 
         match network_identifiers_opt {
@@ -357,7 +954,7 @@ impl BlockStore for Store {
                             .eq::<Option<String>>(Some(format!("{:x}", new_genesis_block_hash))),
                     )).on_conflict(name)
                     .do_nothing()
-                    .execute(&*self.conn.lock().unwrap())?;
+                    .execute(&*conn)?;
             }
 
             // Network is in database and has identifiers
@@ -389,7 +986,7 @@ impl BlockStore for Store {
                         genesis_block_hash
                             .eq::<Option<String>>(Some(format!("{:x}", new_genesis_block_hash))),
                     )).filter(name.eq(new_network_name))
-                    .execute(&*self.conn.lock().unwrap())?;
+                    .execute(&*conn)?;
             }
         }
 
@@ -403,7 +1000,7 @@ impl BlockStore for Store {
     ) -> Box<Future<Item = (), Error = Error> + Send + 'a> {
         use db_schema::ethereum_blocks::dsl::*;
 
-        let conn = self.conn.clone();
+        let write_pool = self.write_pool.clone();
         let net_name = net_name.to_owned();
         Box::new(blocks.for_each(move |block| {
             let json_blob = serde_json::to_value(&block).expect("Failed to serialize block");
@@ -420,7 +1017,7 @@ impl BlockStore for Store {
                 .on_conflict(hash)
                 .do_update()
                 .set(values)
-                .execute(&*conn.lock().unwrap())
+                .execute(&*write_pool.get()?)
                 .map_err(Error::from)
                 .map(|_| ())
         }))
@@ -433,7 +1030,7 @@ impl BlockStore for Store {
     ) -> Result<Vec<H256>, Error> {
         // Call attempt_head_update SQL function
         select(attempt_head_update(network_name, ancestor_count as i64))
-            .load(&*self.conn.lock().unwrap())
+            .load(&*self.write_conn()?)
             .map_err(Error::from)
 
             // We got a single return value, but it's returned generically as a set of rows
@@ -450,10 +1047,28 @@ impl BlockStore for Store {
             })
             .and_then(|r| r.map_err(Error::from))
     }
+
+    fn chain_head_ptr(&self, network: &str) -> Result<Option<EthereumBlockPointer>, Error> {
+        use db_schema::ethereum_networks::dsl::*;
+
+        let head = ethereum_networks
+            .select((head_block_hash, head_block_number))
+            .filter(name.eq(network))
+            .first::<(Option<String>, Option<i64>)>(&*self.read_conn()?)
+            .optional()?;
+
+        match head {
+            Some((Some(hash), Some(number))) => Ok(Some(EthereumBlockPointer {
+                hash: hash.trim_left_matches("0x").parse()?,
+                number: number as u64,
+            })),
+            _ => Ok(None),
+        }
+    }
 }
 
 impl StoreTrait for Store {
-    fn subscribe(&mut self, entities: Vec<SubgraphEntityPair>) -> EntityChangeStream {
+    fn subscribe(&self, filters: Vec<EntityChangeFilter>) -> EntityChangeStream {
         let subscriptions = self.subscriptions.clone();
 
         // Generate a new (unique) UUID; we're looping just to be sure we avoid collisions
@@ -464,11 +1079,11 @@ impl StoreTrait for Store {
 
         debug!(self.logger, "Subscribe";
                "id" => &id,
-               "entities" => format!("{:?}", entities));
+               "filters" => format!("{:?}", filters));
 
         // Prepare the new subscription by creating a channel and a subscription object
         let (sender, receiver) = channel(100);
-        let subscription = Subscription { entities, sender };
+        let subscription = Subscription { filters, sender };
 
         // Add the new subscription
         let mut subscriptions = subscriptions.write().unwrap();
@@ -477,4 +1092,172 @@ impl StoreTrait for Store {
         // Return the subscription ID and entity change stream
         Box::new(receiver)
     }
+
+    fn create_fulltext_index(
+        &self,
+        subgraph: String,
+        entity: String,
+        attributes: Vec<Attribute>,
+    ) -> Result<(), Error> {
+        let conn = self.write_conn()?;
+        let document = fulltext_document(&attributes);
+
+        // Derive a short index name that is stable across restarts, so that
+        // existing indexes are not created again
+        let index_name = select(
+            sql::<Text>("'fulltext_' || md5(")
+                .bind::<Text, _>(format!("{}/{}/{}", subgraph, entity, document))
+                .sql(")"),
+        ).get_result::<String>(&*conn)?;
+
+        info!(self.logger, "Create full-text search index";
+              "subgraph" => &subgraph,
+              "entity" => &entity,
+              "attributes" => format!("{:?}", attributes),
+              "index" => &index_name);
+
+        // Only index the entities of the given type; queries always filter by
+        // subgraph and entity type, so Postgres can use this partial index
+        sql_query(format!(
+            "CREATE INDEX IF NOT EXISTS {} ON entities USING GIN ({}) \
+             WHERE subgraph = {} AND entity = {}",
+            index_name,
+            document,
+            quote_literal(&subgraph),
+            quote_literal(&entity)
+        )).execute(&*conn)?;
+
+        Ok(())
+    }
+}
+
+impl PersistedQueryStore for Store {
+    fn persisted_query(&self, hash: &str) -> Result<Option<String>, Error> {
+        use db_schema::persisted_queries::dsl::*;
+
+        persisted_queries
+            .select(query)
+            .filter(sha256_hash.eq(hash))
+            .first::<String>(&*self.read_conn()?)
+            .optional()
+            .map_err(Error::from)
+    }
+
+    fn persist_query(&self, hash: &str, query_text: &str) -> Result<(), Error> {
+        use db_schema::persisted_queries::dsl::*;
+
+        insert_into(persisted_queries)
+            .values((sha256_hash.eq(hash), query.eq(query_text)))
+            .on_conflict(sha256_hash)
+            .do_nothing()
+            .execute(&*self.write_conn()?)
+            .map_err(Error::from)
+            .map(|_| ())
+    }
+}
+
+impl SubgraphLogStore for Store {
+    fn append_subgraph_logs(
+        &self,
+        subgraph_id: &str,
+        entries: Vec<SubgraphLogEntry>,
+    ) -> Result<(), Error> {
+        let conn = self.write_conn()?;
+        conn.transaction::<(), result::Error, _>(|| {
+            insert_subgraph_logs(&*conn, subgraph_id, &entries, self.max_subgraph_log_entries)
+        }).map_err(Error::from)
+    }
+
+    fn subgraph_logs(
+        &self,
+        subgraph_id: &str,
+        min_level: SubgraphLogLevel,
+        limit: usize,
+    ) -> Result<Vec<SubgraphLogEntry>, Error> {
+        use db_schema::subgraph_logs::dsl::*;
+
+        subgraph_logs
+            .select((timestamp, level, message, block_number))
+            .filter(subgraph.eq(subgraph_id))
+            .filter(level.ge(log_level_to_i16(min_level)))
+            .order(id.desc())
+            .limit(limit as i64)
+            .load::<(i64, i16, String, Option<i64>)>(&*self.read_conn()?)?
+            .into_iter()
+            .map(|(entry_timestamp, entry_level, entry_message, entry_block)| {
+                Ok(SubgraphLogEntry {
+                    timestamp: entry_timestamp as u64,
+                    level: log_level_from_i16(entry_level)?,
+                    message: entry_message,
+                    block_number: entry_block.map(|number| number as u64),
+                })
+            }).collect()
+    }
+}
+
+/// Appends entries to the log of a subgraph and drops its oldest entries
+/// beyond `max_entries`.
+fn insert_subgraph_logs(
+    conn: &PgConnection,
+    subgraph_id: &str,
+    entries: &[SubgraphLogEntry],
+    max_entries: usize,
+) -> Result<(), result::Error> {
+    use db_schema::subgraph_logs::dsl::*;
+
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            (
+                subgraph.eq(subgraph_id),
+                timestamp.eq(entry.timestamp as i64),
+                level.eq(log_level_to_i16(entry.level)),
+                message.eq(&entry.message),
+                block_number.eq(entry.block_number.map(|number| number as i64)),
+            )
+        }).collect::<Vec<_>>();
+    insert_into(subgraph_logs).values(&rows).execute(conn)?;
+
+    // Keep only the most recent entries of the subgraph
+    let newest_dropped = subgraph_logs
+        .select(id)
+        .filter(subgraph.eq(subgraph_id))
+        .order(id.desc())
+        .offset(max_entries as i64)
+        .first::<i64>(conn)
+        .optional()?;
+    if let Some(newest_dropped) = newest_dropped {
+        delete(
+            subgraph_logs
+                .filter(subgraph.eq(subgraph_id))
+                .filter(id.le(newest_dropped)),
+        ).execute(conn)?;
+    }
+    Ok(())
+}
+
+fn log_level_to_i16(level: SubgraphLogLevel) -> i16 {
+    match level {
+        SubgraphLogLevel::Debug => 0,
+        SubgraphLogLevel::Info => 1,
+        SubgraphLogLevel::Warning => 2,
+        SubgraphLogLevel::Error => 3,
+        SubgraphLogLevel::Critical => 4,
+    }
+}
+
+fn log_level_from_i16(level: i16) -> Result<SubgraphLogLevel, Error> {
+    match level {
+        0 => Ok(SubgraphLogLevel::Debug),
+        1 => Ok(SubgraphLogLevel::Info),
+        2 => Ok(SubgraphLogLevel::Warning),
+        3 => Ok(SubgraphLogLevel::Error),
+        4 => Ok(SubgraphLogLevel::Critical),
+        _ => Err(format_err!("Invalid subgraph log level in store: {}", level)),
+    }
+}
+
+/// Converts a duration into whole milliseconds for logging.
+fn duration_as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
 }
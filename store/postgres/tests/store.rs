@@ -7,13 +7,18 @@ extern crate graph_store_postgres;
 
 use diesel::pg::PgConnection;
 use diesel::*;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::panic;
+use std::str::FromStr;
 use std::sync::Mutex;
+use std::thread;
 
 use graph::components::store::{
-    EventSource, StoreFilter, StoreKey, StoreOrder, StoreQuery, StoreRange,
+    EventSource, StoreAggregate, StoreBlock, StoreFilter, StoreKey, StoreOrder, StoreQuery,
+    StoreRange,
 };
+use graph::data::store::scalar::BigInt;
 use graph::prelude::*;
 use graph::web3::types::H256;
 use graph_store_postgres::{db_schema, Store as DieselStore, StoreConfig};
@@ -30,6 +35,16 @@ lazy_static! {
     static ref TEST_MUTEX: Mutex<()> = Mutex::new(());
 }
 
+/// Creates a pointer to a random block.
+fn random_block() -> EthereumBlockPointer {
+    EthereumBlockPointer::from((H256::random(), 1u64))
+}
+
+/// Creates a pointer to a block whose hash is made up of the bytes of `hash`.
+fn block_from_hash_str(hash: &str) -> EthereumBlockPointer {
+    EthereumBlockPointer::from((H256::from_slice(hash.as_bytes()), 1u64))
+}
+
 /// Test harness for running database integration tests.
 fn run_test<R, F>(test: F) -> ()
 where
@@ -91,7 +106,7 @@ fn create_test_entity(
     (
         test_key,
         test_entity,
-        EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes())),
+        EventSource::EthereumBlock(block_from_hash_str(&block_hash)),
     )
 }
 
@@ -99,7 +114,7 @@ fn create_test_entity(
 fn insert_test_data() {
     let logger = Logger::root(slog::Discard, o!());
     let url = postgres_test_url();
-    let mut store = DieselStore::new(StoreConfig { url }, &logger);
+    let store = DieselStore::new(StoreConfig::new(url), &logger);
 
     let test_entity_1 = create_test_entity(
         String::from("1"),
@@ -167,6 +182,18 @@ fn remove_test_data() {
     delete(entities)
         .execute(&conn)
         .expect("Failed to remove test data");
+    delete(db_schema::subgraphs::table)
+        .execute(&conn)
+        .expect("Failed to remove test subgraphs");
+    delete(db_schema::persisted_queries::table)
+        .execute(&conn)
+        .expect("Failed to remove test persisted queries");
+    delete(db_schema::subgraph_errors::table)
+        .execute(&conn)
+        .expect("Failed to remove test subgraph errors");
+    delete(db_schema::subgraph_logs::table)
+        .execute(&conn)
+        .expect("Failed to remove test subgraph logs");
 }
 
 #[test]
@@ -175,20 +202,20 @@ fn delete_entity() {
         use db_schema::entities::dsl::*;
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
         let test_key = StoreKey {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
             id: String::from("3"),
         };
-        let source = EventSource::EthereumBlock(H256::random());
+        let source = EventSource::EthereumBlock(random_block());
         store.delete(test_key, source).unwrap();
 
         //Get all ids in table
         let all_ids = entities
             .select(id)
-            .load::<String>(&*store.conn.lock().unwrap())
+            .load::<String>(&*store.read_conn().unwrap())
             .unwrap();
 
         // Check that that the deleted entity id is not present
@@ -203,7 +230,7 @@ fn get_entity() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
         let key = StoreKey {
             subgraph: String::from("test_subgraph"),
@@ -229,6 +256,46 @@ fn get_entity() {
     })
 }
 
+#[test]
+fn reads_and_writes_use_separate_connection_pools() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                read_pool_size: 2,
+                write_pool_size: 1,
+                ..StoreConfig::new(url)
+            },
+            &logger,
+        );
+
+        // Creating the store uses a write connection for migrations
+        let read_checkouts = store.read_pool_wait_stats().checkouts;
+        let write_checkouts = store.write_pool_wait_stats().checkouts;
+
+        let key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        store.get(key.clone()).unwrap();
+
+        assert_eq!(store.read_pool_wait_stats().checkouts, read_checkouts + 1);
+        assert_eq!(store.write_pool_wait_stats().checkouts, write_checkouts);
+
+        let entity = Entity::from(vec![("name", Value::from("Johnny"))]);
+        let source = EventSource::EthereumBlock(random_block());
+        store.set(key, entity, source).unwrap();
+
+        assert_eq!(store.read_pool_wait_stats().checkouts, read_checkouts + 1);
+        assert_eq!(store.write_pool_wait_stats().checkouts, write_checkouts + 1);
+        assert_eq!(store.write_pool_wait_stats().timeouts, 0);
+
+        Ok(())
+    })
+}
+
 #[test]
 fn insert_entity() {
     run_test(|| -> Result<(), ()> {
@@ -236,7 +303,7 @@ fn insert_entity() {
 
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
         let test_entity_1 = create_test_entity(
             String::from("7"),
@@ -255,7 +322,7 @@ fn insert_entity() {
         // Check that new record is in the store
         let all_ids = entities
             .select(id)
-            .load::<String>(&*store.conn.lock().unwrap())
+            .load::<String>(&*store.read_conn().unwrap())
             .unwrap();
         assert!(all_ids.iter().any(|x| x == &String::from("7")));
 
@@ -268,7 +335,7 @@ fn update_existing() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
         let entity_key = StoreKey {
             subgraph: String::from("test_subgraph"),
@@ -307,7 +374,7 @@ fn partially_update_existing() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
         let entity_key = StoreKey {
             subgraph: String::from("test_subgraph"),
@@ -322,7 +389,7 @@ fn partially_update_existing() {
         ]);
 
         let original_entity = store.get(entity_key.clone()).unwrap();
-        let event_source = EventSource::EthereumBlock(H256::random());
+        let event_source = EventSource::EthereumBlock(random_block());
         // Verify that the entity before updating is different from what we expect afterwards
         assert_ne!(original_entity, partial_entity);
 
@@ -349,12 +416,58 @@ fn partially_update_existing() {
     })
 }
 
+#[test]
+fn concurrent_partial_updates_are_merged() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = Arc::new(DieselStore::new(StoreConfig::new(url), &logger));
+
+        let entity_key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+
+        // Each writer keeps updating an attribute of its own
+        let writers = vec!["counter_a", "counter_b"]
+            .into_iter()
+            .map(|attribute| {
+                let store = store.clone();
+                let entity_key = entity_key.clone();
+
+                thread::spawn(move || {
+                    for i in 0..20 {
+                        store
+                            .set(
+                                entity_key.clone(),
+                                Entity::from(vec![(attribute, Value::Int(i))]),
+                                EventSource::EthereumBlock(random_block()),
+                            ).expect("Failed to update entity");
+                    }
+                })
+            }).collect::<Vec<_>>();
+
+        for writer in writers {
+            writer.join().expect("Writer panicked");
+        }
+
+        // No update was lost, and untouched attributes remain
+        let entity = store.get(entity_key).unwrap();
+        assert_eq!(entity.get("counter_a"), Some(&Value::Int(19)));
+        assert_eq!(entity.get("counter_b"), Some(&Value::Int(19)));
+        assert_eq!(entity.get("name"), Some(&Value::from("Johnton")));
+
+        Ok(())
+    })
+}
+
 #[test]
 fn find_string_contains() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -362,9 +475,9 @@ fn find_string_contains() {
                 String::from("name"),
                 Value::String(String::from("%ind%")),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -382,7 +495,7 @@ fn find_string_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -390,9 +503,9 @@ fn find_string_equal() {
                 String::from("name"),
                 Value::String(String::from("Cindini")),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -410,7 +523,7 @@ fn find_string_not_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -418,9 +531,9 @@ fn find_string_not_equal() {
                 String::from("name"),
                 Value::String(String::from("Cindini")),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -442,7 +555,7 @@ fn find_string_greater_than() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -450,9 +563,9 @@ fn find_string_greater_than() {
                 String::from("name"),
                 Value::String(String::from("Kundi")),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -474,7 +587,7 @@ fn find_string_less_than() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -482,9 +595,9 @@ fn find_string_less_than() {
                 String::from("name"),
                 Value::String(String::from("Kundi")),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -506,7 +619,7 @@ fn find_string_less_than_order_by_asc() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -514,9 +627,9 @@ fn find_string_less_than_order_by_asc() {
                 String::from("name"),
                 Value::String(String::from("Kundi")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Ascending),
+            order_by: vec![(String::from("name"), StoreOrder::Ascending)],
             range: None,
+            block: None,
         };
         let result = store
             .find(this_query)
@@ -543,12 +656,54 @@ fn find_string_less_than_order_by_asc() {
     })
 }
 
+#[test]
+fn find_order_by_multiple_attributes() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: None,
+            order_by: vec![
+                (String::from("coffee"), StoreOrder::Ascending),
+                (String::from("name"), StoreOrder::Descending),
+            ],
+            range: None,
+            block: None,
+        };
+        let result = store
+            .find(this_query)
+            .expect("Failed to fetch entities from the store");
+
+        // Check that users are ordered by coffee first and by name second
+        let names: Vec<&Value> = result
+            .iter()
+            .map(|entity| {
+                entity
+                    .get(&String::from("name"))
+                    .expect("Entity without \"name\" attribute returned")
+            }).collect();
+        assert_eq!(
+            names,
+            vec![
+                &Value::String(String::from("Shaqueeena")),
+                &Value::String(String::from("Johnton")),
+                &Value::String(String::from("Cindini")),
+            ]
+        );
+
+        Ok(())
+    })
+}
+
 #[test]
 fn find_string_less_than_order_by_desc() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -556,9 +711,9 @@ fn find_string_less_than_order_by_desc() {
                 String::from("name"),
                 Value::String(String::from("Kundi")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let result = store
             .find(this_query)
@@ -590,7 +745,7 @@ fn find_string_less_than_range() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -598,9 +753,9 @@ fn find_string_less_than_range() {
                 String::from("name"),
                 Value::String(String::from("ZZZ")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 1, skip: 1 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -622,7 +777,7 @@ fn find_string_multiple_and() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -630,9 +785,9 @@ fn find_string_multiple_and() {
                 StoreFilter::LessThan(String::from("name"), Value::String(String::from("Cz"))),
                 StoreFilter::Equal(String::from("name"), Value::String(String::from("Cindini"))),
             ])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -654,7 +809,7 @@ fn find_string_ends_with() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -662,9 +817,9 @@ fn find_string_ends_with() {
                 String::from("name"),
                 Value::String(String::from("ini")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -686,7 +841,7 @@ fn find_string_not_ends_with() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -694,9 +849,9 @@ fn find_string_not_ends_with() {
                 String::from("name"),
                 Value::String(String::from("ini")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -718,7 +873,7 @@ fn find_string_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -726,9 +881,9 @@ fn find_string_in() {
                 String::from("name"),
                 vec![Value::String(String::from("Johnton"))],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -750,7 +905,7 @@ fn find_string_not_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -758,9 +913,9 @@ fn find_string_not_in() {
                 String::from("name"),
                 vec![Value::String(String::from("Shaqueeena"))],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -783,7 +938,7 @@ fn find_float_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -791,9 +946,9 @@ fn find_float_equal() {
                 String::from("weight"),
                 Value::Float(184.4 as f32),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -815,7 +970,7 @@ fn find_float_not_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -823,9 +978,9 @@ fn find_float_not_equal() {
                 String::from("weight"),
                 Value::Float(184.4 as f32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -847,7 +1002,7 @@ fn find_float_greater_than() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -855,9 +1010,9 @@ fn find_float_greater_than() {
                 String::from("weight"),
                 Value::Float(160 as f32),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -879,7 +1034,7 @@ fn find_float_less_than() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -887,9 +1042,9 @@ fn find_float_less_than() {
                 String::from("weight"),
                 Value::Float(160 as f32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Ascending),
+            order_by: vec![(String::from("name"), StoreOrder::Ascending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -911,7 +1066,7 @@ fn find_float_less_than_order_by_desc() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -919,9 +1074,9 @@ fn find_float_less_than_order_by_desc() {
                 String::from("weight"),
                 Value::Float(160 as f32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -943,7 +1098,7 @@ fn find_float_less_than_range() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -951,9 +1106,9 @@ fn find_float_less_than_range() {
                 String::from("weight"),
                 Value::Float(161 as f32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 1, skip: 1 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
         // Check if the first user in the result vector is "Cindini"
@@ -974,7 +1129,7 @@ fn find_float_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -982,9 +1137,9 @@ fn find_float_in() {
                 String::from("weight"),
                 vec![Value::Float(184.4 as f32), Value::Float(111.7 as f32)],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 5, skip: 0 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1006,7 +1161,7 @@ fn find_float_not_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1014,9 +1169,9 @@ fn find_float_not_in() {
                 String::from("weight"),
                 vec![Value::Float(184.4 as f32), Value::Float(111.7 as f32)],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 5, skip: 0 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1038,7 +1193,7 @@ fn find_int_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1046,9 +1201,9 @@ fn find_int_equal() {
                 String::from("age"),
                 Value::Int(67 as i32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1070,7 +1225,7 @@ fn find_int_not_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1078,9 +1233,9 @@ fn find_int_not_equal() {
                 String::from("age"),
                 Value::Int(67 as i32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1102,7 +1257,7 @@ fn find_int_greater_than() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1110,9 +1265,9 @@ fn find_int_greater_than() {
                 String::from("age"),
                 Value::Int(43 as i32),
             )])),
-            order_by: None,
-            order_direction: None,
+            order_by: vec![],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1134,7 +1289,7 @@ fn find_int_greater_or_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1142,9 +1297,9 @@ fn find_int_greater_or_equal() {
                 String::from("age"),
                 Value::Int(43 as i32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Ascending),
+            order_by: vec![(String::from("name"), StoreOrder::Ascending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1166,7 +1321,7 @@ fn find_int_less_than() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1174,9 +1329,9 @@ fn find_int_less_than() {
                 String::from("age"),
                 Value::Int(50 as i32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Ascending),
+            order_by: vec![(String::from("name"), StoreOrder::Ascending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1198,7 +1353,7 @@ fn find_int_less_or_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1206,9 +1361,9 @@ fn find_int_less_or_equal() {
                 String::from("age"),
                 Value::Int(43 as i32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Ascending),
+            order_by: vec![(String::from("name"), StoreOrder::Ascending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1230,7 +1385,7 @@ fn find_int_less_than_order_by_desc() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1238,9 +1393,9 @@ fn find_int_less_than_order_by_desc() {
                 String::from("age"),
                 Value::Int(50 as i32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1262,7 +1417,7 @@ fn find_int_less_than_range() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1270,9 +1425,9 @@ fn find_int_less_than_range() {
                 String::from("age"),
                 Value::Int(67 as i32),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 1, skip: 1 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1294,7 +1449,7 @@ fn find_int_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1302,9 +1457,9 @@ fn find_int_in() {
                 String::from("age"),
                 vec![Value::Int(67 as i32), Value::Int(43 as i32)],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 5, skip: 0 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1326,7 +1481,7 @@ fn find_int_not_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1334,9 +1489,9 @@ fn find_int_not_in() {
                 String::from("age"),
                 vec![Value::Int(67 as i32), Value::Int(43 as i32)],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 5, skip: 0 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1358,7 +1513,7 @@ fn find_bool_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1366,9 +1521,9 @@ fn find_bool_equal() {
                 String::from("coffee"),
                 Value::Bool(true),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1390,7 +1545,7 @@ fn find_bool_not_equal() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1398,9 +1553,9 @@ fn find_bool_not_equal() {
                 String::from("coffee"),
                 Value::Bool(true),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Ascending),
+            order_by: vec![(String::from("name"), StoreOrder::Ascending)],
             range: None,
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find query failed");
 
@@ -1422,7 +1577,7 @@ fn find_bool_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1430,9 +1585,9 @@ fn find_bool_in() {
                 String::from("coffee"),
                 vec![Value::Bool(true)],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 5, skip: 0 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1454,7 +1609,7 @@ fn find_bool_not_in() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1462,9 +1617,9 @@ fn find_bool_not_in() {
                 String::from("coffee"),
                 vec![Value::Bool(true)],
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: Some(StoreRange { first: 5, skip: 0 }),
+            block: None,
         };
         let returned_entities = store.find(this_query).expect("store.find operation failed");
 
@@ -1486,7 +1641,7 @@ fn revert_block() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1494,17 +1649,17 @@ fn revert_block() {
                 String::from("name"),
                 Value::String(String::from("Shaqueeena")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
 
-        let block_hash = "znuyjijnezBiGFuZAW9Q";
-        let event_source =
-            EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes())).to_string();
+        let block = block_from_hash_str("znuyjijnezBiGFuZAW9Q");
 
-        // Revert all events associated with event_source, "znuyjijnezBiGFuZAW9Q"
-        store.revert_events(event_source, this_query.subgraph.clone());
+        // Revert all events associated with block "znuyjijnezBiGFuZAW9Q"
+        store
+            .revert_events(block, this_query.subgraph.clone())
+            .expect("Failed to revert events");
 
         let returned_entities = store
             .find(this_query.clone())
@@ -1535,7 +1690,7 @@ fn revert_block_with_delete() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
         let this_query = StoreQuery {
             subgraph: String::from("test_subgraph"),
             entity: String::from("user"),
@@ -1543,9 +1698,9 @@ fn revert_block_with_delete() {
                 String::from("name"),
                 Value::String(String::from("Cindini")),
             )])),
-            order_by: Some(String::from("name")),
-            order_direction: Some(StoreOrder::Descending),
+            order_by: vec![(String::from("name"), StoreOrder::Descending)],
             range: None,
+            block: None,
         };
 
         // Delete an entity using a randomly created event source
@@ -1555,15 +1710,15 @@ fn revert_block_with_delete() {
             id: String::from("2"),
         };
 
-        let block_hash = "test_block_to_revert";
-        let event_source = EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()));
-        let revert_event_source = event_source.to_string();
+        let block = block_from_hash_str("test_block_to_revert");
         store
-            .delete(del_key.clone(), event_source)
+            .delete(del_key.clone(), EventSource::EthereumBlock(block))
             .expect("Store.delete operation failed");
 
         // Revert all events associated with our random event_source
-        store.revert_events(revert_event_source, this_query.subgraph.clone());
+        store
+            .revert_events(block, this_query.subgraph.clone())
+            .expect("Failed to revert events");
 
         let returned_entities = store
             .find(this_query.clone())
@@ -1580,13 +1735,13 @@ fn revert_block_with_delete() {
 
         // Perform revert operation again to confirm idempotent nature of revert_events()
         // Delete an entity using a randomly created event source
-        let block_hash = "test_block_to_revert";
-        let event_source = EventSource::EthereumBlock(H256::from_slice(&block_hash.as_bytes()));
-        let revert_event_source = event_source.to_string();
+        let block = block_from_hash_str("test_block_to_revert");
         store
-            .delete(del_key.clone(), event_source)
+            .delete(del_key.clone(), EventSource::EthereumBlock(block))
             .expect("Store.delete operation failed");
-        store.revert_events(revert_event_source, this_query.subgraph.clone());
+        store
+            .revert_events(block, this_query.subgraph.clone())
+            .expect("Failed to revert events");
         let returned_entities = store
             .find(this_query.clone())
             .expect("store.find operation failed");
@@ -1604,7 +1759,7 @@ fn revert_block_with_partial_update() {
     run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
         let entity_key = StoreKey {
             subgraph: String::from("test_subgraph"),
@@ -1619,19 +1774,23 @@ fn revert_block_with_partial_update() {
         ]);
 
         let original_entity = store.get(entity_key.clone()).unwrap();
-        let event_source = EventSource::EthereumBlock(H256::random());
-        let revert_event_source = event_source.to_string();
+        let block = random_block();
 
         // Verify that the entity before updating is different from what we expect afterwards
         assert_ne!(original_entity, partial_entity);
 
         // Set test entity; as the entity already exists an update should be performed
         store
-            .set(entity_key.clone(), partial_entity, event_source)
-            .expect("Failed to update entity that already exists");
+            .set(
+                entity_key.clone(),
+                partial_entity,
+                EventSource::EthereumBlock(block),
+            ).expect("Failed to update entity that already exists");
 
         // Perform revert operation, reversing the partial update
-        store.revert_events(revert_event_source.clone(), entity_key.subgraph.clone());
+        store
+            .revert_events(block, entity_key.subgraph.clone())
+            .expect("Failed to revert events");
 
         // Obtain the reverted entity from the store
         let reverted_entity = store.get(entity_key.clone()).unwrap();
@@ -1641,7 +1800,9 @@ fn revert_block_with_partial_update() {
 
         // Perform revert operation again and verify the same results to confirm the
         // idempotent nature of the revert_events function
-        store.revert_events(revert_event_source, entity_key.subgraph.clone());
+        store
+            .revert_events(block, entity_key.subgraph.clone())
+            .expect("Failed to revert events");
         let reverted_entity = store.get(entity_key).unwrap();
         assert_eq!(reverted_entity, original_entity);
 
@@ -1650,114 +1811,1063 @@ fn revert_block_with_partial_update() {
 }
 
 #[test]
-fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
-    run_test(|| {
+fn subgraph_latest_block_follows_entity_changes() {
+    run_test(|| -> Result<(), ()> {
         let logger = Logger::root(slog::Discard, o!());
         let url = postgres_test_url();
-        let mut store = DieselStore::new(StoreConfig { url }, &logger);
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
-        // Create a store subscription
-        let subscription =
-            store.subscribe(vec![(String::from("subgraph-id"), String::from("User"))]);
+        let entity_key = StoreKey {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+        let block = random_block();
 
-        // Add two entities to the store
-        let added_entities = vec![
-            (
-                String::from("1"),
-                Entity::from(vec![
-                    ("id", Value::from("1")),
-                    ("name", Value::from("Johnny Boy")),
-                ]),
-            ),
-            (
-                String::from("2"),
-                Entity::from(vec![
-                    ("id", Value::from("2")),
-                    ("name", Value::from("Tessa")),
-                ]),
-            ),
-        ];
-        for (id, entity) in added_entities.iter() {
-            store
-                .set(
-                    StoreKey {
-                        subgraph: String::from("subgraph-id"),
-                        entity: String::from("User"),
-                        id: id.clone(),
-                    },
-                    entity.clone(),
-                    EventSource::EthereumBlock(H256::random()),
-                ).expect("failed to add entity to the store");
-        }
+        // Changing entities makes the block the latest block of the subgraph
+        store
+            .transact_block_operations(
+                block,
+                vec![EntityOperation::Remove { key: entity_key }],
+                HashMap::new(),
+            )
+            .expect("Failed to remove entity");
+        assert_eq!(
+            store.subgraph_latest_block("test_subgraph").unwrap(),
+            Some(block)
+        );
 
-        // Update an entity in the store
-        let updated_entity = Entity::from(vec![
-            ("id", Value::from("1")),
-            ("name", Value::from("Johnny")),
-        ]);
+        // Other subgraphs are not affected
+        assert_eq!(store.subgraph_latest_block("other_subgraph").unwrap(), None);
+
+        // Reverting a block whose parent is unknown forgets the latest block
         store
-            .set(
-                StoreKey {
-                    subgraph: String::from("subgraph-id"),
-                    entity: String::from("User"),
-                    id: String::from("1"),
-                },
-                updated_entity.clone(),
-                EventSource::EthereumBlock(H256::random()),
-            ).expect("failed to update entity in the store");
+            .revert_events(block, String::from("test_subgraph"))
+            .expect("Failed to revert events");
+        assert_eq!(store.subgraph_latest_block("test_subgraph").unwrap(), None);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn persists_queries_by_hash() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        assert_eq!(store.persisted_query("abc").unwrap(), None);
+
+        // Persisting a query twice keeps the first text
+        store.persist_query("abc", "{ users { name } }").unwrap();
+        store.persist_query("abc", "{ users { id } }").unwrap();
+        assert_eq!(
+            store.persisted_query("abc").unwrap(),
+            Some(String::from("{ users { name } }"))
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn chain_head_ptr_follows_the_network_head() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        let conn = store.write_conn().unwrap();
+        delete(
+            db_schema::ethereum_networks::table
+                .filter(db_schema::ethereum_networks::name.eq("chain-head-test")),
+        ).execute(&*conn)
+        .expect("Failed to remove test network");
+
+        assert_eq!(store.chain_head_ptr("chain-head-test").unwrap(), None);
+
+        let head_hash = H256::random();
+        insert_into(db_schema::ethereum_networks::table)
+            .values((
+                db_schema::ethereum_networks::name.eq("chain-head-test"),
+                db_schema::ethereum_networks::head_block_hash.eq(format!("{:#x}", head_hash)),
+                db_schema::ethereum_networks::head_block_number.eq(42i64),
+            )).execute(&*conn)
+            .expect("Failed to insert test network");
+
+        assert_eq!(
+            store.chain_head_ptr("chain-head-test").unwrap(),
+            Some(EthereumBlockPointer::from((head_hash, 42u64)))
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn subgraph_health_is_recorded() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        assert_eq!(
+            store.subgraph_health("test_subgraph").unwrap(),
+            SubgraphHealth::Healthy
+        );
+
+        let failed = SubgraphHealth::Failed(String::from("Failed to transact block operations"));
+        store.set_subgraph_health("test_subgraph", &failed).unwrap();
+        assert_eq!(store.subgraph_health("test_subgraph").unwrap(), failed);
 
-        // Delete an entity in the store
         store
-            .delete(
-                StoreKey {
-                    subgraph: String::from("subgraph-id"),
-                    entity: String::from("User"),
-                    id: String::from("2"),
-                },
-                EventSource::EthereumBlock(H256::random()),
-            ).expect("failed to delete entity from the store");
+            .set_subgraph_health("test_subgraph", &SubgraphHealth::Healthy)
+            .unwrap();
+        assert_eq!(
+            store.subgraph_health("test_subgraph").unwrap(),
+            SubgraphHealth::Healthy
+        );
 
-        // We're expecting four events to be written to the subscription stream
-        subscription
-            .take(4)
-            .collect()
-            .and_then(move |changes| {
-                // Keep the store around until we're done reading from it; otherwise
-                // it would be dropped too early and its entity change listener would
-                // be terminated as well
-                let _store = store;
+        Ok(())
+    })
+}
 
-                assert_eq!(
-                    changes,
-                    vec![
-                        EntityChange {
-                            subgraph: String::from("subgraph-id"),
-                            entity: String::from("User"),
-                            id: added_entities[0].clone().0,
-                            operation: EntityChangeOperation::Added,
-                        },
-                        EntityChange {
-                            subgraph: String::from("subgraph-id"),
-                            entity: String::from("User"),
-                            id: added_entities[1].clone().0,
-                            operation: EntityChangeOperation::Added,
-                        },
-                        EntityChange {
-                            subgraph: String::from("subgraph-id"),
-                            entity: String::from("User"),
-                            id: String::from("1"),
-                            operation: EntityChangeOperation::Updated,
-                        },
-                        EntityChange {
-                            subgraph: String::from("subgraph-id"),
-                            entity: String::from("User"),
-                            id: added_entities[1].clone().0,
-                            operation: EntityChangeOperation::Removed,
-                        },
-                    ]
-                );
+#[test]
+fn subgraph_latest_block_and_health_are_looked_up_together() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
 
-                Ok(())
-            }).and_then(|_| Ok(()))
+        // A subgraph can fail before storing any block
+        let failed = SubgraphHealth::Failed(String::from("Failed to transact block operations"));
+        store.set_subgraph_health("test_subgraph", &failed).unwrap();
+        assert_eq!(
+            store
+                .subgraph_latest_block_and_health("test_subgraph")
+                .unwrap(),
+            (None, failed)
+        );
+
+        let block = random_block();
+        store
+            .transact_block_operations(
+                block,
+                vec![EntityOperation::Remove {
+                    key: StoreKey {
+                        subgraph: String::from("test_subgraph"),
+                        entity: String::from("user"),
+                        id: String::from("1"),
+                    },
+                }],
+                HashMap::new(),
+            ).expect("Failed to remove entity");
+        store
+            .set_subgraph_health("test_subgraph", &SubgraphHealth::Healthy)
+            .unwrap();
+        assert_eq!(
+            store
+                .subgraph_latest_block_and_health("test_subgraph")
+                .unwrap(),
+            (Some(block), SubgraphHealth::Healthy)
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn subgraph_logs_are_filtered_by_level() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        let debug = SubgraphLogEntry::new(SubgraphLogLevel::Debug, String::from("debug"), None);
+        let error = SubgraphLogEntry::new(SubgraphLogLevel::Error, String::from("error"), Some(7));
+        let other = SubgraphLogEntry::new(SubgraphLogLevel::Error, String::from("other"), None);
+        store
+            .append_subgraph_logs("test_subgraph", vec![debug.clone(), error.clone()])
+            .unwrap();
+        store
+            .append_subgraph_logs("other_subgraph", vec![other])
+            .unwrap();
+
+        // Entries are returned most recent first
+        assert_eq!(
+            store
+                .subgraph_logs("test_subgraph", SubgraphLogLevel::Debug, 10)
+                .unwrap(),
+            vec![error.clone(), debug]
+        );
+        assert_eq!(
+            store
+                .subgraph_logs("test_subgraph", SubgraphLogLevel::Warning, 10)
+                .unwrap(),
+            vec![error.clone()]
+        );
+        assert_eq!(
+            store
+                .subgraph_logs("test_subgraph", SubgraphLogLevel::Debug, 1)
+                .unwrap(),
+            vec![error]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn subgraph_logs_are_pruned() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(
+            StoreConfig {
+                max_subgraph_log_entries: 2,
+                ..StoreConfig::new(url)
+            },
+            &logger,
+        );
+
+        let entries = (0..3)
+            .map(|i| SubgraphLogEntry::new(SubgraphLogLevel::Info, format!("{}", i), Some(i)))
+            .collect::<Vec<_>>();
+        store
+            .append_subgraph_logs("test_subgraph", entries[..2].to_vec())
+            .unwrap();
+        store
+            .append_subgraph_logs("test_subgraph", entries[2..].to_vec())
+            .unwrap();
+        store
+            .append_subgraph_logs("other_subgraph", entries[..1].to_vec())
+            .unwrap();
+
+        // Only the most recent entries of each subgraph are kept
+        assert_eq!(
+            store
+                .subgraph_logs("test_subgraph", SubgraphLogLevel::Debug, 10)
+                .unwrap(),
+            vec![entries[2].clone(), entries[1].clone()]
+        );
+        assert_eq!(
+            store
+                .subgraph_logs("other_subgraph", SubgraphLogLevel::Debug, 10)
+                .unwrap(),
+            vec![entries[0].clone()]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn transact_block_operations_appends_log_entries() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        let block = random_block();
+        let entry = SubgraphLogEntry::new(
+            SubgraphLogLevel::Info,
+            String::from("Created user"),
+            Some(block.number),
+        );
+        let mut log_entries = HashMap::new();
+        log_entries.insert(String::from("test_subgraph"), vec![entry.clone()]);
+
+        store
+            .transact_block_operations(
+                block,
+                vec![EntityOperation::Set {
+                    key: StoreKey {
+                        subgraph: String::from("test_subgraph"),
+                        entity: String::from("user"),
+                        id: String::from("logged"),
+                    },
+                    data: Entity::from(vec![("id", Value::from("logged"))]),
+                }],
+                log_entries,
+            ).expect("Failed to apply entity operations");
+
+        // The log entries are stored along with the entity changes
+        assert_eq!(
+            store
+                .subgraph_logs("test_subgraph", SubgraphLogLevel::Debug, 10)
+                .unwrap(),
+            vec![entry]
+        );
+
+        Ok(())
+    })
+}
+
+/// Inserts a block with the given hash and number into the block store.
+fn insert_test_block(conn: &PgConnection, block_hash: H256, block_number: i64) {
+    insert_into(db_schema::ethereum_networks::table)
+        .values(db_schema::ethereum_networks::name.eq("time-travel-test"))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .expect("Failed to insert test network");
+
+    insert_into(db_schema::ethereum_blocks::table)
+        .values((
+            db_schema::ethereum_blocks::hash.eq(format!("{:#x}", block_hash)),
+            db_schema::ethereum_blocks::number.eq(block_number),
+            db_schema::ethereum_blocks::parent_hash.eq(format!("{:#x}", H256::zero())),
+            db_schema::ethereum_blocks::network_name.eq("time-travel-test"),
+            db_schema::ethereum_blocks::data.eq(graph::serde_json::Value::Null),
+        )).execute(conn)
+        .expect("Failed to insert test block");
+}
+
+#[test]
+fn find_at_block() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        let blocks = vec![H256::random(), H256::random(), H256::random()];
+        for (number, block_hash) in blocks.iter().enumerate() {
+            insert_test_block(&*store.write_conn().unwrap(), *block_hash, number as i64 + 1);
+        }
+
+        let key = StoreKey {
+            subgraph: String::from("time_travel_subgraph"),
+            entity: String::from("user"),
+            id: String::from("1"),
+        };
+
+        // Create the entity in block #1, update it in block #2, delete it in block #3
+        store
+            .set(
+                key.clone(),
+                Entity::from(vec![("name", Value::from("Alice"))]),
+                EventSource::EthereumBlock((blocks[0], 1u64).into()),
+            ).expect("Failed to insert entity");
+        store
+            .set(
+                key.clone(),
+                Entity::from(vec![("name", Value::from("Alicia"))]),
+                EventSource::EthereumBlock((blocks[1], 2u64).into()),
+            ).expect("Failed to update entity");
+        store
+            .delete(key.clone(), EventSource::EthereumBlock((blocks[2], 3u64).into()))
+            .expect("Failed to delete entity");
+
+        let find_names = |block: Option<StoreBlock>| -> Vec<Value> {
+            store
+                .find(StoreQuery {
+                    subgraph: key.subgraph.clone(),
+                    entity: key.entity.clone(),
+                    filter: None,
+                    order_by: vec![],
+                    range: None,
+                    block,
+                }).expect("store.find operation failed")
+                .into_iter()
+                .map(|entity| entity.get("name").unwrap().clone())
+                .collect()
+        };
+
+        assert_eq!(find_names(Some(StoreBlock::Number(0))), vec![]);
+        assert_eq!(
+            find_names(Some(StoreBlock::Number(1))),
+            vec![Value::from("Alice")]
+        );
+        assert_eq!(
+            find_names(Some(StoreBlock::Hash(blocks[1]))),
+            vec![Value::from("Alicia")]
+        );
+        assert_eq!(find_names(Some(StoreBlock::Number(3))), vec![]);
+        assert_eq!(find_names(None), vec![]);
+
+        // Remove the test blocks again
+        delete(
+            db_schema::ethereum_blocks::table.filter(
+                db_schema::ethereum_blocks::hash.eq_any(
+                    blocks
+                        .iter()
+                        .map(|block_hash| format!("{:#x}", block_hash))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+        ).execute(&*store.write_conn().unwrap())
+        .expect("Failed to remove test blocks");
+
+        Ok(())
+    })
+}
+
+/// Stores an entity of the test subgraph with the given attributes.
+fn set_test_entity(
+    store: &DieselStore,
+    entity: &str,
+    id: &str,
+    attributes: Vec<(&str, Value)>,
+    event_source: EventSource,
+) {
+    store
+        .set(
+            StoreKey {
+                subgraph: String::from("test_subgraph"),
+                entity: String::from(entity),
+                id: String::from(id),
+            },
+            Entity::from(attributes),
+            event_source,
+        ).expect("Failed to insert test entity");
+}
+
+/// Returns the IDs of the test subgraph's entities of the given type that
+/// match the filter, in ID order.
+fn find_ids(
+    store: &DieselStore,
+    entity: &str,
+    filter: StoreFilter,
+    block: Option<StoreBlock>,
+) -> Vec<Value> {
+    store
+        .find(StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from(entity),
+            filter: Some(StoreFilter::And(vec![filter])),
+            order_by: vec![(String::from("id"), StoreOrder::Ascending)],
+            range: None,
+            block,
+        }).expect("store.find operation failed")
+        .into_iter()
+        .map(|entity| entity.get("id").unwrap().clone())
+        .collect()
+}
+
+#[test]
+fn find_child_of_single_reference() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        for (id, owner) in [("p1", "2"), ("p2", "1"), ("p3", "3")].iter() {
+            set_test_entity(
+                &store,
+                "pet",
+                id,
+                vec![("id", Value::from(*id)), ("owner", Value::from(*owner))],
+                EventSource::EthereumBlock(random_block()),
+            );
+        }
+
+        // Only user 2 drinks coffee
+        let owner_drinks_coffee = StoreFilter::Child(
+            String::from("owner"),
+            String::from("user"),
+            Box::new(StoreFilter::Equal(
+                String::from("coffee"),
+                Value::Bool(true),
+            )),
+        );
+        assert_eq!(
+            find_ids(&store, "pet", owner_drinks_coffee, None),
+            vec![Value::from("p1")]
+        );
+
+        let owner_is_unknown = StoreFilter::Child(
+            String::from("owner"),
+            String::from("user"),
+            Box::new(StoreFilter::Equal(
+                String::from("name"),
+                Value::from("Nobody"),
+            )),
+        );
+        assert_eq!(find_ids(&store, "pet", owner_is_unknown, None), vec![]);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_child_of_list_of_references() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        set_test_entity(
+            &store,
+            "group",
+            "g1",
+            vec![
+                ("id", Value::from("g1")),
+                (
+                    "members",
+                    Value::List(vec![Value::from("1"), Value::from("3")]),
+                ),
+            ],
+            EventSource::EthereumBlock(random_block()),
+        );
+        set_test_entity(
+            &store,
+            "group",
+            "g2",
+            vec![
+                ("id", Value::from("g2")),
+                ("members", Value::List(vec![Value::from("2")])),
+            ],
+            EventSource::EthereumBlock(random_block()),
+        );
+        set_test_entity(
+            &store,
+            "group",
+            "g3",
+            vec![("id", Value::from("g3")), ("members", Value::List(vec![]))],
+            EventSource::EthereumBlock(random_block()),
+        );
+
+        // Only user 3 is younger than 30; groups match if any member matches
+        let has_young_member = StoreFilter::Child(
+            String::from("members"),
+            String::from("user"),
+            Box::new(StoreFilter::LessThan(String::from("age"), Value::Int(30))),
+        );
+        assert_eq!(
+            find_ids(&store, "group", has_young_member, None),
+            vec![Value::from("g1")]
+        );
+
+        let has_member = StoreFilter::Child(
+            String::from("members"),
+            String::from("user"),
+            Box::new(StoreFilter::GreaterThan(String::from("age"), Value::Int(0))),
+        );
+        assert_eq!(
+            find_ids(&store, "group", has_member, None),
+            vec![Value::from("g1"), Value::from("g2")]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_child_of_child() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        for (id, owner) in [("p1", "2"), ("p2", "1")].iter() {
+            set_test_entity(
+                &store,
+                "pet",
+                id,
+                vec![("id", Value::from(*id)), ("owner", Value::from(*owner))],
+                EventSource::EthereumBlock(random_block()),
+            );
+        }
+        for (id, pet) in [("t1", "p1"), ("t2", "p2")].iter() {
+            set_test_entity(
+                &store,
+                "toy",
+                id,
+                vec![("id", Value::from(*id)), ("pet", Value::from(*pet))],
+                EventSource::EthereumBlock(random_block()),
+            );
+        }
+
+        let owned_by_cindini = StoreFilter::Child(
+            String::from("pet"),
+            String::from("pet"),
+            Box::new(StoreFilter::Child(
+                String::from("owner"),
+                String::from("user"),
+                Box::new(StoreFilter::Equal(
+                    String::from("name"),
+                    Value::from("Cindini"),
+                )),
+            )),
+        );
+        assert_eq!(
+            find_ids(&store, "toy", owned_by_cindini, None),
+            vec![Value::from("t1")]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_child_at_block() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        let blocks = vec![H256::random(), H256::random()];
+        for (number, block_hash) in blocks.iter().enumerate() {
+            insert_test_block(&*store.write_conn().unwrap(), *block_hash, number as i64 + 1);
+        }
+
+        // The owner of the pet is renamed in block #2
+        set_test_entity(
+            &store,
+            "owner",
+            "o1",
+            vec![("id", Value::from("o1")), ("name", Value::from("Alice"))],
+            EventSource::EthereumBlock((blocks[0], 1u64).into()),
+        );
+        set_test_entity(
+            &store,
+            "pet",
+            "p1",
+            vec![("id", Value::from("p1")), ("owner", Value::from("o1"))],
+            EventSource::EthereumBlock((blocks[0], 1u64).into()),
+        );
+        set_test_entity(
+            &store,
+            "owner",
+            "o1",
+            vec![("name", Value::from("Bob"))],
+            EventSource::EthereumBlock((blocks[1], 2u64).into()),
+        );
+
+        let owned_by_alice = StoreFilter::Child(
+            String::from("owner"),
+            String::from("owner"),
+            Box::new(StoreFilter::Equal(
+                String::from("name"),
+                Value::from("Alice"),
+            )),
+        );
+        assert_eq!(
+            find_ids(
+                &store,
+                "pet",
+                owned_by_alice.clone(),
+                Some(StoreBlock::Number(1))
+            ),
+            vec![Value::from("p1")]
+        );
+        assert_eq!(
+            find_ids(&store, "pet", owned_by_alice.clone(), Some(StoreBlock::Number(2))),
+            vec![]
+        );
+        assert_eq!(find_ids(&store, "pet", owned_by_alice, None), vec![]);
+
+        // Remove the test blocks again
+        delete(
+            db_schema::ethereum_blocks::table.filter(
+                db_schema::ethereum_blocks::hash.eq_any(
+                    blocks
+                        .iter()
+                        .map(|block_hash| format!("{:#x}", block_hash))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+        ).execute(&*store.write_conn().unwrap())
+        .expect("Failed to remove test blocks");
+
+        Ok(())
+    })
+}
+
+#[test]
+fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        // Create a store subscription
+        let subscription = store.subscribe(vec![EntityChangeFilter::from((
+            String::from("subgraph-id"),
+            String::from("User"),
+        ))]);
+
+        // Add two entities to the store
+        let added_entities = vec![
+            (
+                String::from("1"),
+                Entity::from(vec![
+                    ("id", Value::from("1")),
+                    ("name", Value::from("Johnny Boy")),
+                ]),
+            ),
+            (
+                String::from("2"),
+                Entity::from(vec![
+                    ("id", Value::from("2")),
+                    ("name", Value::from("Tessa")),
+                ]),
+            ),
+        ];
+        for (id, entity) in added_entities.iter() {
+            store
+                .set(
+                    StoreKey {
+                        subgraph: String::from("subgraph-id"),
+                        entity: String::from("User"),
+                        id: id.clone(),
+                    },
+                    entity.clone(),
+                    EventSource::EthereumBlock(random_block()),
+                ).expect("failed to add entity to the store");
+        }
+
+        // Update an entity in the store
+        let updated_entity = Entity::from(vec![
+            ("id", Value::from("1")),
+            ("name", Value::from("Johnny")),
+        ]);
+        store
+            .set(
+                StoreKey {
+                    subgraph: String::from("subgraph-id"),
+                    entity: String::from("User"),
+                    id: String::from("1"),
+                },
+                updated_entity.clone(),
+                EventSource::EthereumBlock(random_block()),
+            ).expect("failed to update entity in the store");
+
+        // Delete an entity in the store
+        store
+            .delete(
+                StoreKey {
+                    subgraph: String::from("subgraph-id"),
+                    entity: String::from("User"),
+                    id: String::from("2"),
+                },
+                EventSource::EthereumBlock(random_block()),
+            ).expect("failed to delete entity from the store");
+
+        // We're expecting four events to be written to the subscription stream,
+        // each in a batch of its own
+        subscription
+            .map(|batch| stream::iter_ok(batch.changes))
+            .flatten()
+            .take(4)
+            .collect()
+            .and_then(move |changes| {
+                // Keep the store around until we're done reading from it; otherwise
+                // it would be dropped too early and its entity change listener would
+                // be terminated as well
+                let _store = store;
+
+                assert_eq!(
+                    changes,
+                    vec![
+                        EntityChange {
+                            subgraph: String::from("subgraph-id"),
+                            entity: String::from("User"),
+                            id: added_entities[0].clone().0,
+                            operation: EntityChangeOperation::Added,
+                        },
+                        EntityChange {
+                            subgraph: String::from("subgraph-id"),
+                            entity: String::from("User"),
+                            id: added_entities[1].clone().0,
+                            operation: EntityChangeOperation::Added,
+                        },
+                        EntityChange {
+                            subgraph: String::from("subgraph-id"),
+                            entity: String::from("User"),
+                            id: String::from("1"),
+                            operation: EntityChangeOperation::Updated,
+                        },
+                        EntityChange {
+                            subgraph: String::from("subgraph-id"),
+                            entity: String::from("User"),
+                            id: added_entities[1].clone().0,
+                            operation: EntityChangeOperation::Removed,
+                        },
+                    ]
+                );
+
+                Ok(())
+            }).and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn entity_changes_are_filtered_by_entity_id() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        // Create a store subscription that is only interested in user 2
+        let subscription = store.subscribe(vec![EntityChangeFilter {
+            subgraph: String::from("subgraph-id"),
+            entity: String::from("User"),
+            ids: Some(vec![String::from("2")].into_iter().collect::<HashSet<_>>()),
+        }]);
+
+        let key = |id: &str| StoreKey {
+            subgraph: String::from("subgraph-id"),
+            entity: String::from("User"),
+            id: String::from(id),
+        };
+
+        // Add two users, update the first and delete the second one
+        for id in ["1", "2"].iter() {
+            store
+                .set(
+                    key(*id),
+                    Entity::from(vec![("id", Value::from(*id))]),
+                    EventSource::EthereumBlock(random_block()),
+                ).expect("failed to add entity to the store");
+        }
+        store
+            .set(
+                key("1"),
+                Entity::from(vec![("name", Value::from("Johnny"))]),
+                EventSource::EthereumBlock(random_block()),
+            ).expect("failed to update entity in the store");
+        store
+            .delete(key("2"), EventSource::EthereumBlock(random_block()))
+            .expect("failed to delete entity from the store");
+
+        // Only the changes to user 2 are written to the subscription stream
+        subscription
+            .map(|batch| stream::iter_ok(batch.changes))
+            .flatten()
+            .take(2)
+            .collect()
+            .and_then(move |changes| {
+                let _store = store;
+
+                assert_eq!(
+                    changes,
+                    vec![
+                        EntityChange {
+                            subgraph: String::from("subgraph-id"),
+                            entity: String::from("User"),
+                            id: String::from("2"),
+                            operation: EntityChangeOperation::Added,
+                        },
+                        EntityChange {
+                            subgraph: String::from("subgraph-id"),
+                            entity: String::from("User"),
+                            id: String::from("2"),
+                            operation: EntityChangeOperation::Removed,
+                        },
+                    ]
+                );
+
+                Ok(())
+            }).and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn entity_changes_of_a_block_are_forwarded_as_one_batch() {
+    run_test(|| {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        let subscription = store.subscribe(vec![EntityChangeFilter::from((
+            String::from("subgraph-id"),
+            String::from("User"),
+        ))]);
+
+        let key = |id: &str| StoreKey {
+            subgraph: String::from("subgraph-id"),
+            entity: String::from("User"),
+            id: String::from(id),
+        };
+
+        // Add two users and remove one of them again in the same block
+        let block = random_block();
+        store
+            .transact_block_operations(
+                block,
+                vec![
+                    EntityOperation::Set {
+                        key: key("1"),
+                        data: Entity::from(vec![("id", Value::from("1"))]),
+                    },
+                    EntityOperation::Set {
+                        key: key("2"),
+                        data: Entity::from(vec![("id", Value::from("2"))]),
+                    },
+                    EntityOperation::Remove { key: key("1") },
+                ],
+                HashMap::new(),
+            ).expect("failed to apply entity operations");
+
+        // All changes are delivered together, along with the block they were made in
+        subscription
+            .take(1)
+            .collect()
+            .and_then(move |batches| {
+                let _store = store;
+
+                let change = |id: &str, operation| EntityChange {
+                    subgraph: String::from("subgraph-id"),
+                    entity: String::from("User"),
+                    id: String::from(id),
+                    operation,
+                };
+
+                assert_eq!(
+                    batches,
+                    vec![EntityChangeBatch {
+                        block,
+                        changes: vec![
+                            change("1", EntityChangeOperation::Added),
+                            change("2", EntityChangeOperation::Added),
+                            change("1", EntityChangeOperation::Removed),
+                        ],
+                    }]
+                );
+
+                Ok(())
+            }).and_then(|_| Ok(()))
+    })
+}
+
+#[test]
+fn aggregate() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: None,
+            order_by: vec![],
+            range: None,
+            block: None,
+        };
+        let aggregates = vec![
+            StoreAggregate::Count,
+            StoreAggregate::Sum(String::from("age")),
+            StoreAggregate::Min(String::from("age")),
+            StoreAggregate::Max(String::from("age")),
+            StoreAggregate::Avg(String::from("age")),
+        ];
+        let values = store
+            .aggregate(this_query, aggregates)
+            .expect("store.aggregate operation failed");
+
+        assert_eq!(
+            values,
+            vec![
+                Value::Int(3),
+                Value::BigInt(BigInt::from_str("138").unwrap()),
+                Value::BigInt(BigInt::from_str("28").unwrap()),
+                Value::BigInt(BigInt::from_str("67").unwrap()),
+                Value::Float(46.0),
+            ]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn aggregate_with_filter() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::Equal(
+                String::from("coffee"),
+                Value::Bool(false),
+            )])),
+            order_by: vec![],
+            range: None,
+            block: None,
+        };
+        let values = store
+            .aggregate(
+                this_query,
+                vec![StoreAggregate::Count, StoreAggregate::Sum(String::from("age"))],
+            ).expect("store.aggregate operation failed");
+
+        assert_eq!(
+            values,
+            vec![
+                Value::Int(2),
+                Value::BigInt(BigInt::from_str("95").unwrap()),
+            ]
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn aggregate_without_matches() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::Equal(
+                String::from("name"),
+                Value::String(String::from("Nobody")),
+            )])),
+            order_by: vec![],
+            range: None,
+            block: None,
+        };
+        let values = store
+            .aggregate(
+                this_query,
+                vec![StoreAggregate::Count, StoreAggregate::Max(String::from("weight"))],
+            ).expect("store.aggregate operation failed");
+
+        assert_eq!(values, vec![Value::Int(0), Value::Null]);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn find_fulltext() {
+    run_test(|| -> Result<(), ()> {
+        let logger = Logger::root(slog::Discard, o!());
+        let url = postgres_test_url();
+        let store = DieselStore::new(StoreConfig::new(url), &logger);
+
+        store
+            .create_fulltext_index(
+                String::from("test_subgraph"),
+                String::from("user"),
+                vec![String::from("name"), String::from("email")],
+            ).expect("Failed to create full-text search index");
+
+        let this_query = StoreQuery {
+            subgraph: String::from("test_subgraph"),
+            entity: String::from("user"),
+            filter: Some(StoreFilter::And(vec![StoreFilter::Fulltext(
+                vec![String::from("name"), String::from("email")],
+                String::from("cindini"),
+            )])),
+            order_by: vec![],
+            range: None,
+            block: None,
+        };
+        let returned_entities = store.find(this_query).expect("store.find operation failed");
+
+        // Make sure "Cindini" is the only user found
+        assert_eq!(returned_entities.len(), 1);
+        let returned_name = returned_entities[0].get(&String::from("name"));
+        let test_value = Value::String(String::from("Cindini"));
+        assert_eq!(&test_value, returned_name.unwrap());
+
+        Ok(())
     })
 }